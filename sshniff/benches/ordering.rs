@@ -0,0 +1,58 @@
+//! Ordering of records into the order they were sent, on synthetic interactive sessions.
+//!
+//! Run with `cargo bench --bench ordering`. The time per record should stay flat as sessions
+//! grow; it used to grow with the session, as every record was removed from the front of a `Vec`.
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use sshniff::analyser::containers::{Direction, PacketInfo};
+use sshniff::analyser::options::Thresholds;
+use sshniff::analyser::utils;
+
+/// Keystroke size under chacha20-poly1305.
+const KEYSTROKE_SIZE: u32 = 36;
+
+/// Keystrokes and their echoes, every third echo arriving after the next keystroke, and a burst
+/// of output after every command.
+fn session(records: usize) -> Vec<PacketInfo> {
+    let mut pattern: Vec<(Direction, i32)> = vec![];
+    while pattern.len() < records {
+        for key in 0..12 {
+            match key % 3 {
+                2 => pattern.extend([(Direction::ClientToServer, 36), (Direction::ClientToServer, 36), (Direction::ServerToClient, 36), (Direction::ServerToClient, 36)]),
+                _ => pattern.extend([(Direction::ClientToServer, 36), (Direction::ServerToClient, 36)]),
+            }
+        }
+        pattern.extend([(Direction::ServerToClient, 1276), (Direction::ServerToClient, 212), (Direction::ServerToClient, 116)]);
+    }
+    pattern.truncate(records);
+
+    pattern.into_iter().enumerate().map(|(index, (direction, length))| PacketInfo {
+        index,
+        seq: index as i64,
+        length,
+        direction,
+        segments: 1,
+        timestamp: index as i64 * 100_000,
+        frame: None,
+        segment_length: length,
+        message_code: None,
+        packet_lengths: vec![],
+        ack_rtt: None,
+    }).collect()
+}
+
+fn ordering(c: &mut Criterion) {
+    let thresholds = Thresholds::default();
+    let mut group = c.benchmark_group("order_keystrokes");
+
+    for records in [1_000, 10_000, 100_000] {
+        let packet_infos = session(records);
+        group.throughput(Throughput::Elements(records as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(records), &packet_infos, |b, packet_infos| {
+            b.iter_batched(|| packet_infos.clone(), |mut packet_infos| utils::order_keystrokes(&mut packet_infos, KEYSTROKE_SIZE, &thresholds), BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, ordering);
+criterion_main!(benches);
@@ -0,0 +1,202 @@
+//! Known vulnerabilities of the client and server versions.
+//!
+//! The implementation and version each side claims in its banner (see
+//! [from_banner](super::software::from_banner)) are looked up in an [AdvisoryDb] of version
+//! ranges. The banner is what the peer claims to be, and distributions backport fixes without
+//! changing it, so a match says the version is affected upstream, not that the host is.
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::fs;
+
+/// The bundled advisory table; see `data/advisories.csv`.
+const BUNDLED: &str = include_str!("../../data/advisories.csv");
+
+/// Which side of a session an advisory affects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Side {
+    Client,
+    Server,
+    Both,
+}
+
+/// A vulnerability affecting a range of versions of one implementation.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Advisory {
+    /// CVE or other advisory identifier
+    pub id: String,
+    /// Implementation as named by [from_banner](super::software::from_banner), e.g. `OpenSSH`
+    pub product: String,
+    pub side: Side,
+    /// First affected version; `None` if all earlier versions are affected
+    pub introduced: Option<String>,
+    /// First fixed version
+    pub fixed: String,
+    /// CVSS base score
+    pub cvss: f32,
+    pub summary: String,
+}
+
+impl Advisory {
+    /// Checks if the advisory covers `version` of `product` on `side`.
+    pub fn affects(&self, product: &str, version: &str, side: Side) -> bool {
+        self.product.eq_ignore_ascii_case(product)
+            && (self.side == side || self.side == Side::Both)
+            && self.introduced.as_deref().is_none_or(|introduced| compare_versions(version, introduced) != Ordering::Less)
+            && compare_versions(version, &self.fixed) == Ordering::Less
+    }
+}
+
+/// Advisories by affected version range.
+#[derive(Clone, Debug, Default)]
+pub struct AdvisoryDb {
+    advisories: Vec<Advisory>,
+}
+
+impl AdvisoryDb {
+    /// The advisory table bundled with SSHniff.
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED).expect("bundled advisory table is valid")
+    }
+
+    /// The bundled advisories, extended by (and overridden with) the entries of the file at
+    /// `path`.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| format!("Failed to read {path}: {err}"))?;
+        let mut db = Self::bundled();
+        let user = Self::parse(&contents).map_err(|err| format!("{path}: {err}"))?;
+        db.advisories.retain(|advisory| !user.advisories.iter().any(|other| other.id == advisory.id && other.product == advisory.product));
+        db.advisories.extend(user.advisories);
+        Ok(db)
+    }
+
+    /// Parses `product,side,introduced,fixed,id,cvss,summary` lines. Empty lines and lines
+    /// starting with `#` are ignored. The summary may contain commas.
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut db = Self::default();
+
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.splitn(7, ',').map(str::trim).collect();
+            let (product, side, introduced, fixed, id, cvss, summary) = match fields[..] {
+                [product, side, introduced, fixed, id, cvss, summary] if !product.is_empty() && !id.is_empty() => (product, side, introduced, fixed, id, cvss, summary),
+                _ => return Err(format!("line {}: expected product,side,introduced,fixed,id,cvss,summary", number + 1)),
+            };
+            let side = match side {
+                "client" => Side::Client,
+                "server" => Side::Server,
+                "both" => Side::Both,
+                _ => return Err(format!("line {}: side must be client, server, or both", number + 1)),
+            };
+            if version_numbers(fixed).is_empty() || (!introduced.is_empty() && version_numbers(introduced).is_empty()) {
+                return Err(format!("line {}: '{introduced}-{fixed}' is not a range of versions", number + 1));
+            }
+            let cvss = match cvss.parse::<f32>() {
+                Ok(cvss) if (0.0..=10.0).contains(&cvss) => cvss,
+                _ => return Err(format!("line {}: '{cvss}' is not a CVSS score", number + 1)),
+            };
+
+            db.advisories.push(Advisory {
+                id: id.to_string(),
+                product: product.to_string(),
+                side,
+                introduced: (!introduced.is_empty()).then(|| introduced.to_string()),
+                fixed: fixed.to_string(),
+                cvss,
+                summary: summary.to_string(),
+            });
+        }
+
+        Ok(db)
+    }
+
+    pub fn len(&self) -> usize {
+        self.advisories.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.advisories.is_empty()
+    }
+
+    /// Advisories affecting the software identified by `software` (e.g. `OpenSSH 8.4p1`, as
+    /// returned by [from_banner](super::software::from_banner)) on `side`, most severe first.
+    pub fn lookup(&self, software: &str, side: Side) -> Vec<Advisory> {
+        let (product, version) = match software.rsplit_once(' ') {
+            Some((product, version)) if !version_numbers(version).is_empty() => (product, version),
+            _ => return vec![],
+        };
+
+        let mut advisories: Vec<Advisory> = self.advisories.iter()
+            .filter(|advisory| advisory.affects(product, version, side))
+            .cloned()
+            .collect();
+        advisories.sort_by(|a, b| b.cvss.total_cmp(&a.cvss).then_with(|| a.id.cmp(&b.id)));
+        advisories
+    }
+
+    /// Advisories affecting the (client, server) banners of a session.
+    pub fn match_banners(&self, banners: (&str, &str)) -> (Vec<Advisory>, Vec<Advisory>) {
+        let lookup = |banner: &str, side: Side| match super::software::from_banner(banner) {
+            Some(software) => self.lookup(&software, side),
+            None => vec![],
+        };
+
+        (lookup(banners.0, Side::Client), lookup(banners.1, Side::Server))
+    }
+}
+
+/// Numeric components of a version, e.g. `[9, 3, 2]` for `9.3p2` and `[2022, 83]` for `2022.83`.
+fn version_numbers(version: &str) -> Vec<u32> {
+    version.split(|c: char| !c.is_ascii_digit())
+        .filter(|part| !part.is_empty())
+        .filter_map(|part| part.parse().ok())
+        .collect()
+}
+
+/// Compares two versions component by component; missing components count as 0, so `9.6` equals
+/// `9.6p0` and precedes `9.6p1`.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a, b) = (version_numbers(a), version_numbers(b));
+    let len = a.len().max(b.len());
+    let pad = |numbers: &[u32], i: usize| numbers.get(i).copied().unwrap_or(0);
+
+    (0..len).map(|i| pad(&a, i).cmp(&pad(&b, i)))
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advisories() {
+        use std::cmp::Ordering;
+
+        assert_eq!(Ordering::Less, compare_versions("9.3p1", "9.3p2"));
+        assert_eq!(Ordering::Equal, compare_versions("9.6", "9.6p0"));
+        assert_eq!(Ordering::Greater, compare_versions("10.0p1", "9.9p2"));
+        assert_eq!(Ordering::Less, compare_versions("2022.83", "2024.84"));
+
+        let db = AdvisoryDb::bundled();
+        let ids = |advisories: Vec<Advisory>| advisories.into_iter().map(|advisory| advisory.id).collect::<Vec<_>>();
+        assert_eq!(vec!["CVE-2024-6387", "CVE-2023-48795"], ids(db.lookup("OpenSSH 9.3p2", Side::Server)));
+        assert!(db.lookup("OpenSSH 9.9p2", Side::Server).is_empty());
+        assert_eq!(vec!["CVE-2023-48795"], ids(db.lookup("Dropbear 2022.83", Side::Server)));
+        assert!(db.lookup("PuTTY 0.78", Side::Client).is_empty());
+        assert!(db.lookup("OpenSSH", Side::Server).is_empty());
+
+        // The banners of known_pass_lsal_id_exit
+        let (client, server) = db.match_banners(("SSH-2.0-OpenSSH_9.6", "SSH-2.0-OpenSSH_8.4p1 Raspbian-5+deb11u3"));
+        assert_eq!(vec!["CVE-2025-26465", "CVE-2025-26466"], ids(client));
+        assert_eq!(vec!["CVE-2021-41617", "CVE-2023-48795"], ids(server));
+
+        let user = AdvisoryDb::parse("OpenSSH,server,,99.0,TEST-1,4.0,Every version, for testing").unwrap();
+        assert_eq!("Every version, for testing", user.lookup("OpenSSH 9.9p2", Side::Server)[0].summary);
+        assert!(AdvisoryDb::parse("OpenSSH,nobody,,9.6,TEST-2,5.0,Bad side").is_err());
+        assert!(AdvisoryDb::parse("OpenSSH,both,,9.6,TEST-3,eleven,Bad score").is_err());
+    }
+}
@@ -0,0 +1,263 @@
+//! Weak-crypto audit of the algorithms each endpoint offers.
+//!
+//! The negotiated algorithms only show what one session ended up with; the KEXINIT lists show
+//! everything an endpoint is still willing to use, which is what a posture assessment is after.
+//! Every offered key exchange, host key, cipher, and MAC algorithm is checked against
+//! [WEAK_ALGORITHMS], separately for the client and the server, and the weaknesses of all sessions
+//! of an endpoint are collected by [audit_endpoints].
+use super::cipher_model;
+use super::core::SshSession;
+use super::packet::Packet;
+use super::utils::{self, ip_of};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Deprecated algorithms and why. A trailing `*` matches any suffix, a leading `*` any prefix.
+pub const WEAK_ALGORITHMS: [(&str, AlgorithmKind, &str); 16] = [
+    ("diffie-hellman-group1-sha1", AlgorithmKind::Kex, "1024-bit group, SHA-1"),
+    ("diffie-hellman-group14-sha1", AlgorithmKind::Kex, "SHA-1"),
+    ("diffie-hellman-group-exchange-sha1", AlgorithmKind::Kex, "SHA-1"),
+    ("rsa1024-sha1", AlgorithmKind::Kex, "1024-bit RSA, SHA-1"),
+    ("ssh-rsa", AlgorithmKind::HostKey, "SHA-1 signatures"),
+    ("ssh-rsa-cert-v01@openssh.com", AlgorithmKind::HostKey, "SHA-1 signatures"),
+    ("ssh-dss", AlgorithmKind::HostKey, "1024-bit DSA"),
+    ("ssh-dss-cert-v01@openssh.com", AlgorithmKind::HostKey, "1024-bit DSA"),
+    ("*-cbc", AlgorithmKind::Cipher, "CBC mode"),
+    ("rijndael-cbc@lysator.liu.se", AlgorithmKind::Cipher, "CBC mode"),
+    ("arcfour*", AlgorithmKind::Cipher, "RC4"),
+    ("3des-ctr", AlgorithmKind::Cipher, "64-bit block cipher"),
+    ("none", AlgorithmKind::Cipher, "no encryption"),
+    ("hmac-md5*", AlgorithmKind::Mac, "MD5"),
+    ("hmac-sha1-96*", AlgorithmKind::Mac, "truncated SHA-1"),
+    ("none", AlgorithmKind::Mac, "no integrity protection"),
+];
+
+/// KEXINIT name-lists audited for each kind of algorithm. Either side offers the ciphers and MACs
+/// of both directions.
+const AUDITED_FIELDS: [(AlgorithmKind, &str); 6] = [
+    (AlgorithmKind::Kex, "ssh.kex_algorithms"),
+    (AlgorithmKind::HostKey, "ssh.server_host_key_algorithms"),
+    (AlgorithmKind::Cipher, "ssh.encryption_algorithms_client_to_server"),
+    (AlgorithmKind::Cipher, "ssh.encryption_algorithms_server_to_client"),
+    (AlgorithmKind::Mac, "ssh.mac_algorithms_client_to_server"),
+    (AlgorithmKind::Mac, "ssh.mac_algorithms_server_to_client"),
+];
+
+/// The name-list of a KEXINIT an algorithm was offered in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum AlgorithmKind {
+    Kex,
+    HostKey,
+    Cipher,
+    Mac,
+}
+
+impl fmt::Display for AlgorithmKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            AlgorithmKind::Kex => "kex",
+            AlgorithmKind::HostKey => "host key",
+            AlgorithmKind::Cipher => "cipher",
+            AlgorithmKind::Mac => "mac",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Weaknesses of the (client, server).
+pub type Weaknesses = (Vec<Weakness>, Vec<Weakness>);
+
+/// A deprecated algorithm offered by an endpoint.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Weakness {
+    pub kind: AlgorithmKind,
+    pub algorithm: String,
+    pub reason: &'static str,
+    /// Whether the session actually negotiated it, rather than only offering it
+    pub negotiated: bool,
+}
+
+impl fmt::Display for Weakness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({}{})", self.algorithm, self.reason, if self.negotiated { ", negotiated" } else { "" })
+    }
+}
+
+/// Weak algorithms offered by one endpoint over all its sessions.
+#[derive(Clone, Debug, Serialize)]
+pub struct EndpointAudit {
+    /// Server IP:port, or client IP
+    pub endpoint: String,
+    /// `client` or `server`
+    pub role: &'static str,
+    /// Version banners the endpoint sent
+    pub banners: BTreeSet<String>,
+    pub streams: Vec<u32>,
+    pub weaknesses: Vec<Weakness>,
+}
+
+/// Checks if an algorithm name matches a [WEAK_ALGORITHMS] pattern.
+fn matches_pattern(pattern: &str, algorithm: &str) -> bool {
+    match (pattern.strip_suffix('*'), pattern.strip_prefix('*')) {
+        (Some(prefix), _) => algorithm.starts_with(prefix),
+        (_, Some(suffix)) => algorithm.ends_with(suffix),
+        _ => algorithm == pattern,
+    }
+}
+
+/// Why an algorithm of the given kind is weak, if it is.
+pub fn weakness_of(kind: AlgorithmKind, algorithm: &str) -> Option<&'static str> {
+    WEAK_ALGORITHMS.iter()
+        .find(|(pattern, weak_kind, _)| *weak_kind == kind && matches_pattern(pattern, algorithm))
+        .map(|(_, _, reason)| *reason)
+}
+
+/// Audits the name-lists of one KEXINIT. `negotiated` holds the session's (KEX, ENC, MAC, CMP)
+/// algorithms, as in [SshSession::algorithms].
+pub fn audit_kexinit(packet: &Packet, negotiated: &(String, String, String, String)) -> Vec<Weakness> {
+    let mut weaknesses: Vec<Weakness> = vec![];
+
+    for (kind, field) in AUDITED_FIELDS {
+        let offered = match utils::find_metadata(packet, field) {
+            Some(offered) => offered,
+            None => continue,
+        };
+        let chosen = match kind {
+            AlgorithmKind::Kex => Some(&negotiated.0),
+            AlgorithmKind::Cipher => Some(&negotiated.1),
+            // AEAD ciphers leave the MAC unused, whatever was agreed on
+            AlgorithmKind::Mac if !cipher_model::is_aead(&negotiated.1) => Some(&negotiated.2),
+            AlgorithmKind::Mac => None,
+            // The signature algorithm is not kept, only the key type
+            AlgorithmKind::HostKey => None,
+        };
+
+        for algorithm in offered.split(',').filter(|algorithm| !algorithm.is_empty()) {
+            if weaknesses.iter().any(|weakness| weakness.kind == kind && weakness.algorithm == algorithm) {
+                continue;
+            }
+            if let Some(reason) = weakness_of(kind, algorithm) {
+                weaknesses.push(Weakness {
+                    kind,
+                    algorithm: algorithm.to_string(),
+                    reason,
+                    negotiated: chosen.is_some_and(|chosen| chosen == algorithm),
+                });
+            }
+        }
+    }
+
+    weaknesses
+}
+
+/// Audits the first client and server KEXINIT of a stream, returning the (client, server)
+/// weaknesses and the positions of the KEXINITs among the packets.
+pub fn audit_offers(packets: &[Packet], negotiated: &(String, String, String, String)) -> (Weaknesses, (Option<usize>, Option<usize>)) {
+    let mut weaknesses = (vec![], vec![]);
+    let mut positions = (None, None);
+
+    for (index, packet) in packets.iter().enumerate().take(50) {
+        if positions.0.is_some() && positions.1.is_some() {
+            break;
+        }
+        if utils::find_metadata(packet, "ssh.message_code") != Some("20") {
+            continue;
+        }
+
+        if utils::is_server_packet(packet) {
+            if positions.1.is_none() {
+                weaknesses.1 = audit_kexinit(packet, negotiated);
+                positions.1 = Some(index);
+            }
+        } else if positions.0.is_none() {
+            weaknesses.0 = audit_kexinit(packet, negotiated);
+            positions.0 = Some(index);
+        }
+    }
+
+    (weaknesses, positions)
+}
+
+/// Collects the weaknesses of every server (IP:port) and client (IP) over their sessions; those
+/// without any are left out. Weaknesses negotiated in any session are marked as such.
+pub fn audit_endpoints(sessions: &BTreeMap<u32, SshSession>) -> Vec<EndpointAudit> {
+    let mut endpoints: BTreeMap<(&'static str, String), EndpointAudit> = BTreeMap::new();
+
+    for session in sessions.values() {
+        let sides = [
+            ("server", session.dst.clone(), &session.protocols.1, &session.weak_crypto.1),
+            ("client", ip_of(&session.src).to_string(), &session.protocols.0, &session.weak_crypto.0),
+        ];
+        for (role, endpoint, banner, weaknesses) in sides {
+            let audit = endpoints.entry((role, endpoint.clone())).or_insert_with(|| EndpointAudit {
+                endpoint,
+                role,
+                banners: BTreeSet::new(),
+                streams: vec![],
+                weaknesses: vec![],
+            });
+            if !banner.is_empty() {
+                audit.banners.insert(banner.clone());
+            }
+            audit.streams.push(session.stream);
+            for weakness in weaknesses {
+                match audit.weaknesses.iter_mut().find(|known| known.kind == weakness.kind && known.algorithm == weakness.algorithm) {
+                    Some(known) => known.negotiated |= weakness.negotiated,
+                    None => audit.weaknesses.push(weakness.clone()),
+                }
+            }
+        }
+    }
+
+    endpoints.into_values()
+        .filter(|audit| !audit.weaknesses.is_empty())
+        .map(|mut audit| {
+            audit.weaknesses.sort_by(|a, b| (a.kind, &a.algorithm).cmp(&(b.kind, &b.algorithm)));
+            audit
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::containers;
+    use crate::analyser::core::analyse;
+    use crate::analyser::options::AnalysisOptions;
+    use crate::analyser::utils;
+
+    #[test]
+    fn test_weak_crypto() {
+        use crate::analyser::options::{Decoding, Engine};
+        use crate::analyser::filter::StreamFilter;
+        use std::collections::BTreeMap;
+
+        assert_eq!(Some("CBC mode"), weakness_of(AlgorithmKind::Cipher, "aes256-cbc"));
+        assert_eq!(Some("RC4"), weakness_of(AlgorithmKind::Cipher, "arcfour128"));
+        assert_eq!(Some("MD5"), weakness_of(AlgorithmKind::Mac, "hmac-md5-etm@openssh.com"));
+        assert_eq!(Some("1024-bit group, SHA-1"), weakness_of(AlgorithmKind::Kex, "diffie-hellman-group1-sha1"));
+        assert_eq!(None, weakness_of(AlgorithmKind::Cipher, "aes256-ctr"));
+        assert_eq!(None, weakness_of(AlgorithmKind::HostKey, "rsa-sha2-512"));
+        // ssh-rsa is only weak as a signature algorithm
+        assert_eq!(None, weakness_of(AlgorithmKind::Kex, "ssh-rsa"));
+
+        let capture = format!("{}/test_captures/known_pass_lsal_id_exit.pcapng", env!("CARGO_MANIFEST_DIR"));
+        let (streams, tcp) = utils::load_capture_reporting(&capture, -1, None, &StreamFilter::default(), Engine::Native, &Decoding::default(), |_, _| {}).unwrap();
+        let session = analyse(0, &streams[&0], &tcp[&0], &AnalysisOptions::default()).unwrap();
+
+        // The Raspbian server still offers SHA-1 RSA signatures; the client offers nothing deprecated
+        assert!(session.weak_crypto.0.is_empty());
+        assert_eq!(1, session.weak_crypto.1.len());
+        assert_eq!("ssh-rsa", session.weak_crypto.1[0].algorithm);
+        assert_eq!(AlgorithmKind::HostKey, session.weak_crypto.1[0].kind);
+        assert!(!session.weak_crypto.1[0].negotiated);
+        let finding = session.results.iter().find(|finding| finding.kind == containers::Event::WeakCrypto).unwrap();
+        assert_eq!(Some("server offers ssh-rsa (SHA-1 signatures)".to_string()), finding.details);
+
+        let audits = audit_endpoints(&BTreeMap::from([(0, session)]));
+        assert_eq!(1, audits.len());
+        assert_eq!(("server", "192.168.0.45:22"), (audits[0].role, audits[0].endpoint.as_str()));
+        assert_eq!(vec![0], audits[0].streams);
+    }
+}
@@ -0,0 +1,183 @@
+//! Expected SSH record lengths for the negotiated cipher and MAC.
+//!
+//! An SSH record is the 4-byte packet length, a padding length byte, the payload, at least 4 bytes
+//! of padding, and the MAC. The padding rounds the record up to the cipher's block size (at least
+//! 8), so the same message is a different length depending on the cipher: a keystroke is 36 bytes
+//! with chacha20-poly1305 but 64 with aes128-ctr and hmac-sha2-256. AEAD ciphers and ETM MACs
+//! leave the packet length unencrypted and out of the padding.
+//!
+//! The sizes SSHniff was researched with were measured on OpenSSH with chacha20-poly1305, the
+//! [reference](CipherModel::reference). Lengths from that research are translated by working back
+//! to the payloads they allow and forward again under the negotiated cipher.
+use super::options::Thresholds;
+use super::utils;
+use std::ops::RangeInclusive;
+
+/// Payload of a keystroke: SSH_MSG_CHANNEL_DATA with the channel, the string length, and one byte.
+pub const KEYSTROKE_PAYLOAD: i32 = 10;
+
+/// Payload of SSH_MSG_USERAUTH_SUCCESS, which is only the message code.
+pub const LOGIN_SUCCESS_PAYLOAD: i32 = 1;
+
+/// Payload of the `keepalive@openssh.com` SSH_MSG_GLOBAL_REQUEST sent by ServerAliveInterval and
+/// ClientAliveInterval: the message code, the request name, and `want_reply`.
+pub const KEEPALIVE_PAYLOAD: i32 = 1 + 4 + 21 + 1;
+
+/// Lengths of SSH_MSG_USERAUTH_SUCCESS under the common ciphers, for when the negotiated cipher is
+/// not known: chacha20-poly1305 and AES-GCM.
+pub const LOGIN_SUCCESS_SIZES: [i32; 2] = [28, 36];
+
+/// Whether an encryption algorithm authenticates records itself, leaving the negotiated MAC unused.
+pub fn is_aead(encryption: &str) -> bool {
+    encryption.contains("chacha20-poly1305") || encryption.contains("-gcm")
+}
+
+/// Smallest amount of padding the sender adds.
+const MIN_PADDING: i32 = 4;
+
+/// Record layout under a cipher and MAC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CipherModel {
+    /// Block size the record is padded to
+    pub block_size: i32,
+    /// Length of the MAC or authentication tag
+    pub mac_length: i32,
+    /// Whether the packet length is left out of the padding (AEAD ciphers and ETM MACs)
+    pub length_excluded: bool,
+}
+
+impl CipherModel {
+    /// Model for the negotiated encryption and MAC algorithms; `None` if the cipher is unknown.
+    pub fn negotiated(encryption: &str, mac: &str) -> Option<Self> {
+        let aead = is_aead(encryption);
+        let block_size = match encryption {
+            _ if encryption.contains("chacha20-poly1305") => 8,
+            _ if encryption.starts_with("aes") || encryption.starts_with("rijndael") => 16,
+            "3des-cbc" | "blowfish-cbc" | "cast128-cbc" | "none" => 8,
+            _ => return None,
+        };
+
+        Some(CipherModel {
+            block_size,
+            mac_length: utils::get_mac_length(encryption, mac),
+            length_excluded: aead || mac.ends_with("-etm@openssh.com"),
+        })
+    }
+
+    /// chacha20-poly1305, which the fixed sizes were measured with.
+    pub fn reference() -> Self {
+        CipherModel { block_size: 8, mac_length: 16, length_excluded: true }
+    }
+
+    /// Length on the wire of a record carrying `payload` bytes, with the least padding.
+    pub fn record_length(&self, payload: i32) -> i32 {
+        let padded = if self.length_excluded {
+            4 + round_up(1 + payload + MIN_PADDING, self.block_size)
+        } else {
+            round_up(4 + 1 + payload + MIN_PADDING, self.block_size)
+        };
+        padded + self.mac_length
+    }
+
+    /// Length on the wire of a record carrying `payload` bytes, padded further to a multiple of
+    /// `extra_pad` bytes as OpenSSH does for passwords, which always adds at least one block.
+    /// An `extra_pad` of 0 adds nothing, as in [record_length](Self::record_length).
+    pub fn padded_record_length(&self, payload: i32, extra_pad: i32) -> i32 {
+        let length = self.record_length(payload);
+        if extra_pad <= 0 {
+            return length;
+        }
+
+        let extra_pad = round_up(extra_pad, self.block_size);
+        let padded = length - self.mac_length - if self.length_excluded { 4 } else { 0 };
+        length + extra_pad - padded % extra_pad
+    }
+
+    /// Payload sizes a record of `length` bytes can carry, padded as in
+    /// [padded_record_length](Self::padded_record_length); `None` if no payload fits.
+    pub fn payloads(&self, length: i32, extra_pad: i32) -> Option<RangeInclusive<i32>> {
+        let mut payloads = (0..length).filter(|&payload| self.padded_record_length(payload, extra_pad) == length);
+        let first = payloads.next()?;
+        Some(first..=payloads.next_back().unwrap_or(first))
+    }
+
+    /// Length of a single keystroke.
+    pub fn keystroke_size(&self) -> i32 {
+        self.record_length(KEYSTROKE_PAYLOAD)
+    }
+
+    /// Length of the server's SSH_MSG_USERAUTH_SUCCESS.
+    pub fn login_success_size(&self) -> i32 {
+        self.record_length(LOGIN_SUCCESS_PAYLOAD)
+    }
+
+    /// Lengths of a keepalive request and of its reply, an SSH_MSG_REQUEST_FAILURE, which is
+    /// only the message code.
+    pub fn keepalive_sizes(&self) -> (i32, i32) {
+        (self.record_length(KEEPALIVE_PAYLOAD), self.record_length(1))
+    }
+
+    /// Record lengths under this model of the payloads that have `lengths` under `from`.
+    pub fn translate(&self, lengths: &RangeInclusive<i32>, from: &CipherModel) -> RangeInclusive<i32> {
+        // Besides the payload, a record holds 5 bytes of header, the MAC, and between 4 and
+        // block size + 3 bytes of padding
+        let (least, most) = (5 + MIN_PADDING + from.mac_length, 5 + from.block_size + MIN_PADDING - 1 + from.mac_length);
+        let payloads = (lengths.start() - most).max(0)..=(lengths.end() - least).max(0);
+        let mut translated = payloads
+            .filter(|&payload| lengths.contains(&from.record_length(payload)))
+            .map(|payload| self.record_length(payload));
+
+        match translated.next() {
+            Some(first) => first..=translated.next_back().unwrap_or(first),
+            None => lengths.clone(),
+        }
+    }
+
+    /// `thresholds` with the key-offer and other message lengths, given for the reference cipher,
+    /// translated to this model.
+    pub fn thresholds(&self, thresholds: &Thresholds) -> Thresholds {
+        let reference = CipherModel::reference();
+        Thresholds {
+            rsa_offer: self.translate(&thresholds.rsa_offer, &reference),
+            ed25519_offer: self.translate(&thresholds.ed25519_offer, &reference),
+            ecdsa_offer: self.translate(&thresholds.ecdsa_offer, &reference),
+            sk_ed25519_offer: self.translate(&thresholds.sk_ed25519_offer, &reference),
+            sk_ecdsa_offer: self.translate(&thresholds.sk_ecdsa_offer, &reference),
+            certificate_offer: self.translate(&thresholds.certificate_offer, &reference),
+            keyboard_interactive_request: self.translate(&thresholds.keyboard_interactive_request, &reference),
+            empty_info_request: self.translate(&thresholds.empty_info_request, &reference),
+            ..thresholds.clone()
+        }
+    }
+}
+
+fn round_up(length: i32, block_size: i32) -> i32 {
+    (length + block_size - 1) / block_size * block_size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::options::Thresholds;
+
+    #[test]
+    fn test_cipher_model() {
+        let chacha = CipherModel::negotiated("chacha20-poly1305@openssh.com", "hmac-sha2-256-etm@openssh.com").unwrap();
+        assert_eq!(CipherModel::reference(), chacha);
+        assert_eq!((36, 28), (chacha.keystroke_size(), chacha.login_success_size()));
+
+        let gcm = CipherModel::negotiated("aes256-gcm@openssh.com", "").unwrap();
+        assert_eq!((36, 36), (gcm.keystroke_size(), gcm.login_success_size()));
+        let ctr = CipherModel::negotiated("aes128-ctr", "hmac-sha2-256").unwrap();
+        assert_eq!(64, ctr.keystroke_size());
+        let etm = CipherModel::negotiated("aes128-ctr", "hmac-sha2-256-etm@openssh.com").unwrap();
+        assert_eq!(52, etm.keystroke_size());
+        assert!(CipherModel::negotiated("twofish-cbc", "hmac-sha1").is_none());
+
+        // The measured ranges hold for the reference and move with the cipher's overhead
+        let defaults = Thresholds::default();
+        assert_eq!(defaults.rsa_offer, chacha.thresholds(&defaults).rsa_offer);
+        assert_eq!(500..=500, gcm.thresholds(&defaults).rsa_offer);
+        assert_eq!(512..=528, ctr.thresholds(&defaults).rsa_offer);
+    }
+}
@@ -0,0 +1,163 @@
+//! Guessing commands from their length and the size of their response.
+//!
+//! Each [Enter](super::containers::KeystrokeType::Enter) keystroke carries the size of the
+//! server's response and its sequence an estimate of the command length. Both are compared against
+//! a [CommandDb] of common commands: the command has to fit the length estimate, and scores best
+//! if its response falls within the fingerprinted range. The few best candidates are attached to
+//! the keystroke as [CommandGuess]es. They narrow down what was run; they do not prove it.
+use super::containers::{CommandGuess, CommandLength, KeystrokeSequence, KeystrokeType};
+use std::fs;
+
+/// The bundled fingerprints; see `data/commands.csv`.
+const BUNDLED: &str = include_str!("../../data/commands.csv");
+
+/// Number of guesses kept per Enter keystroke.
+pub const MAX_GUESSES: usize = 3;
+
+/// Score below which a command is not worth suggesting.
+pub const MIN_GUESS_SCORE: f32 = 0.25;
+
+/// Share of a range's upper bound by which a response may miss it and still score ½.
+const RESPONSE_TOLERANCE: f32 = 0.25;
+
+/// Response-size range of a command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandFingerprint {
+    pub command: String,
+    pub min_response: u128,
+    pub max_response: u128,
+}
+
+/// Fingerprints of common commands.
+#[derive(Clone, Debug, Default)]
+pub struct CommandDb {
+    fingerprints: Vec<CommandFingerprint>,
+}
+
+impl CommandDb {
+    /// The fingerprints bundled with SSHniff.
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED).expect("bundled command fingerprints are valid")
+    }
+
+    /// The bundled fingerprints, extended by (and overridden with) the entries of the file at
+    /// `path`.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| format!("Failed to read {path}: {err}"))?;
+        let mut db = Self::bundled();
+        let user = Self::parse(&contents).map_err(|err| format!("{path}: {err}"))?;
+        db.fingerprints.retain(|fingerprint| !user.fingerprints.iter().any(|other| other.command == fingerprint.command));
+        db.fingerprints.extend(user.fingerprints);
+        Ok(db)
+    }
+
+    /// Parses `command,min_response,max_response` lines. Empty lines and lines starting with `#`
+    /// are ignored. Commands may contain spaces but not commas.
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut db = Self::default();
+
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let (command, min, max) = match fields[..] {
+                [command, min, max] if !command.is_empty() => (command, min, max),
+                _ => return Err(format!("line {}: expected command,min_response,max_response", number + 1)),
+            };
+            let (min_response, max_response) = match (min.parse::<u128>(), max.parse::<u128>()) {
+                (Ok(min), Ok(max)) if min <= max => (min, max),
+                _ => return Err(format!("line {}: '{min}-{max}' is not a range of sizes", number + 1)),
+            };
+            db.fingerprints.push(CommandFingerprint { command: command.to_string(), min_response, max_response });
+        }
+
+        Ok(db)
+    }
+
+    pub fn len(&self) -> usize {
+        self.fingerprints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fingerprints.is_empty()
+    }
+
+    /// Commands that fit a length estimate and response size, best first, at most [MAX_GUESSES].
+    pub fn guess(&self, length: &CommandLength, response_size: u128) -> Vec<CommandGuess> {
+        let mut guesses: Vec<CommandGuess> = self.fingerprints.iter()
+            .map(|fingerprint| CommandGuess {
+                command: fingerprint.command.clone(),
+                score: length_score(&fingerprint.command, length) * response_score(fingerprint, response_size),
+            })
+            .filter(|guess| guess.score >= MIN_GUESS_SCORE)
+            .collect();
+
+        guesses.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.command.cmp(&b.command)));
+        guesses.truncate(MAX_GUESSES);
+        guesses
+    }
+
+    /// Attaches guesses to the Enter keystrokes of each sequence.
+    pub fn annotate(&self, sequences: &mut [KeystrokeSequence]) {
+        for sequence in sequences {
+            let length = sequence.length;
+            for keystroke in sequence.keystrokes.iter_mut().filter(|keystroke| keystroke.k_type == KeystrokeType::Enter) {
+                if let Some(response_size) = keystroke.response_size {
+                    keystroke.guesses = self.guess(&length, response_size);
+                }
+            }
+        }
+    }
+}
+
+/// 1 if the command is as long as estimated, falling off with the difference; 0 if it is outside
+/// the possible lengths. Without an upper bound, any length past the estimate fits, as completions
+/// add characters the estimate does not see.
+fn length_score(command: &str, length: &CommandLength) -> f32 {
+    let chars = command.chars().count();
+    match length.max {
+        _ if chars < length.min => 0.0,
+        Some(max) if chars > max => 0.0,
+        None if chars >= length.estimate => 1.0,
+        _ => 1.0 / (1.0 + chars.abs_diff(length.estimate) as f32),
+    }
+}
+
+/// 1 within the fingerprinted range, falling off with the distance to it.
+fn response_score(fingerprint: &CommandFingerprint, response_size: u128) -> f32 {
+    let distance = if response_size < fingerprint.min_response {
+        fingerprint.min_response - response_size
+    } else {
+        response_size.saturating_sub(fingerprint.max_response)
+    };
+    1.0 / (1.0 + distance as f32 / (fingerprint.max_response as f32 * RESPONSE_TOLERANCE).max(1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::containers;
+
+    #[test]
+    fn test_command_guesses() {
+        let db = CommandDb::bundled();
+        let exact = |length| containers::CommandLength { estimate: length, min: length, max: Some(length), edit_distance: 0 };
+
+        // Two characters and a response just short of `id`'s: `ls` first, `id` close behind
+        let guesses = db.guess(&exact(2), 100);
+        assert_eq!(vec!["ls", "id"], guesses.iter().map(|guess| guess.command.as_str()).collect::<Vec<_>>());
+        assert!(guesses[1].score < 1.0 && guesses[1].score > 0.5);
+        // `cat /etc/pas` completed with Tab leaves the length open
+        let guesses = db.guess(&containers::CommandLength { estimate: 12, min: 12, max: None, edit_distance: 0 }, 2000);
+        assert_eq!("cat /etc/passwd", guesses[0].command);
+        assert!(db.guess(&exact(40), 200).is_empty());
+
+        let custom = CommandDb::parse("# custom\nid,10,20\nls -1,100,200").unwrap();
+        assert_eq!(2, custom.len());
+        assert!(CommandDb::parse("id,20,10").is_err());
+        assert!(CommandDb::parse("id,10").is_err());
+    }
+}
@@ -0,0 +1,215 @@
+//! Comparison of a server's SSH posture between two captures, and of two sessions.
+//!
+//! Postures are meant for before/after checks, e.g. verifying that a hardening change removed weak
+//! algorithms without locking clients out. Sessions are compared to tell whether two connections
+//! came from the same operator or tooling.
+use super::containers::Event;
+use super::core::SshSession;
+use super::utils::ip_of;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Relative difference from which keystroke statistics of two sessions count as changed; the
+/// typing of one person varies about this much between sessions.
+pub const TYPING_TOLERANCE: f64 = 0.2;
+
+/// What a capture reveals about one server.
+#[derive(Debug, Default, Serialize)]
+pub struct Posture {
+    pub sessions: usize,
+    /// Server protocol versions
+    pub versions: BTreeSet<String>,
+    /// Negotiated algorithms, prefixed by their kind (e.g. `enc: aes128-ctr`)
+    pub algorithms: BTreeSet<String>,
+    pub hassh_s: BTreeSet<String>,
+    /// Client IPs
+    pub clients: BTreeSet<String>,
+    pub hassh_c: BTreeSet<String>,
+    /// Number of each authentication event
+    pub auth: BTreeMap<String, usize>,
+}
+
+/// Difference in one attribute of a [Posture].
+#[derive(Debug, Serialize)]
+pub struct PostureChange {
+    pub attribute: &'static str,
+    pub removed: Vec<String>,
+    pub added: Vec<String>,
+}
+
+/// Authentication outcomes that are part of the posture.
+const AUTH_EVENTS: [Event; 5] = [Event::WrongPassword, Event::CorrectPassword, Event::RejectedKey, Event::AcceptedKey, Event::NoneAuth];
+
+impl Posture {
+    /// Collects the posture of `server` (IP) from the sessions it took part in as the server.
+    pub fn from_sessions(server: &str, sessions: &BTreeMap<u32, SshSession>) -> Self {
+        let mut posture = Posture::default();
+
+        for session in sessions.values().filter(|session| ip_of(&session.dst) == server) {
+            posture.sessions += 1;
+            posture.versions.insert(session.protocols.1.clone());
+            posture.algorithms.insert(format!("kex: {}", session.algorithms.0));
+            posture.algorithms.insert(format!("enc: {}", session.algorithms.1));
+            posture.algorithms.insert(format!("mac: {}", session.algorithms.2));
+            posture.algorithms.insert(format!("cmp: {}", session.algorithms.3));
+            posture.hassh_s.insert(session.hassh_s.clone());
+            posture.clients.insert(ip_of(&session.src).to_string());
+            posture.hassh_c.insert(session.hassh_c.clone());
+
+            for finding in session.results.iter().filter(|finding| AUTH_EVENTS.contains(&finding.kind)) {
+                *posture.auth.entry(finding.kind.to_string()).or_default() += 1;
+            }
+        }
+
+        posture
+    }
+}
+
+/// Lists the attributes that differ between the `old` and `new` posture.
+pub fn compare_postures(old: &Posture, new: &Posture) -> Vec<PostureChange> {
+    let auth = |posture: &Posture| posture.auth.iter().map(|(event, count)| format!("{event}: {count}")).collect::<BTreeSet<_>>();
+
+    [
+        ("sessions", BTreeSet::from([old.sessions.to_string()]), BTreeSet::from([new.sessions.to_string()])),
+        ("versions", old.versions.clone(), new.versions.clone()),
+        ("algorithms", old.algorithms.clone(), new.algorithms.clone()),
+        ("hassh_s", old.hassh_s.clone(), new.hassh_s.clone()),
+        ("clients", old.clients.clone(), new.clients.clone()),
+        ("hassh_c", old.hassh_c.clone(), new.hassh_c.clone()),
+        ("auth", auth(old), auth(new)),
+    ]
+    .into_iter()
+    .filter(|(_, before, after)| before != after)
+    .map(|(attribute, before, after)| PostureChange {
+        attribute,
+        removed: before.difference(&after).cloned().collect(),
+        added: after.difference(&before).cloned().collect(),
+    })
+    .collect()
+}
+
+/// One attribute of two sessions side by side; see [compare_sessions].
+#[derive(Debug, Serialize)]
+pub struct SessionComparison {
+    /// `metadata`, `timeline`, or `typing`
+    pub section: &'static str,
+    pub attribute: String,
+    /// Value in the first and second session; empty if missing
+    pub values: (String, String),
+    pub changed: bool,
+}
+
+/// Session metadata compared, by JSON pointer into a serialised [SshSession].
+const METADATA: [(&str, &str); 17] = [
+    ("client", "/src"),
+    ("server", "/dst"),
+    ("client version", "/protocols/0"),
+    ("server version", "/protocols/1"),
+    ("hassh_c", "/hassh_c"),
+    ("hassh_s", "/hassh_s"),
+    ("client software", "/software/0/name"),
+    ("server software", "/software/1/name"),
+    ("kex", "/algorithms/0"),
+    ("encryption", "/algorithms/1"),
+    ("mac", "/algorithms/2"),
+    ("compression", "/algorithms/3"),
+    ("host key", "/host_key/sha256"),
+    ("ja4ssh", "/ja4ssh"),
+    ("keystroke size", "/keystroke_size"),
+    ("obfuscated", "/obfuscated"),
+    ("tags", "/tags"),
+];
+
+/// Keystroke statistics compared, by JSON pointer; see [TYPING_TOLERANCE].
+const TYPING: [(&str, &str); 8] = [
+    ("keystrokes", "/typing/samples"),
+    ("median latency (μs)", "/typing/median_us"),
+    ("mean latency (μs)", "/typing/mean_us"),
+    ("latency stddev (μs)", "/typing/stddev_us"),
+    ("p10 latency (μs)", "/typing/p10_us"),
+    ("p75 latency (μs)", "/typing/p75_us"),
+    ("error rate", "/error_rate"),
+    ("client RTT (μs)", "/jitter/0/mean_rtt"),
+];
+
+/// Compares the metadata, event timeline, and keystroke statistics of two sessions.
+///
+/// Sessions are compared as serialised with `--json` or to the output directory, so that sessions
+/// saved by earlier runs compare as well as freshly analysed ones. Timelines are compared by the
+/// number of events of each kind, since two connections never line up packet by packet.
+pub fn compare_sessions(first: &Value, second: &Value) -> Vec<SessionComparison> {
+    let mut comparisons = Vec::new();
+
+    for (attribute, pointer) in METADATA {
+        let values = (value_text(first.pointer(pointer)), value_text(second.pointer(pointer)));
+        comparisons.push(SessionComparison { section: "metadata", attribute: attribute.to_string(), changed: values.0 != values.1, values });
+    }
+
+    let (first_events, second_events) = (event_counts(first), event_counts(second));
+    let kinds: BTreeSet<&String> = first_events.keys().chain(second_events.keys()).collect();
+    for kind in kinds {
+        let counts = (first_events.get(kind).copied().unwrap_or(0), second_events.get(kind).copied().unwrap_or(0));
+        comparisons.push(SessionComparison { section: "timeline", attribute: kind.clone(), values: (counts.0.to_string(), counts.1.to_string()), changed: counts.0 != counts.1 });
+    }
+
+    let commands = |session: &Value| session.get("keystroke_data").and_then(Value::as_array).map(Vec::len);
+    let typing = TYPING.iter()
+        .map(|&(attribute, pointer)| (attribute, first.pointer(pointer).and_then(Value::as_f64), second.pointer(pointer).and_then(Value::as_f64)))
+        .chain([("commands", commands(first).map(|count| count as f64), commands(second).map(|count| count as f64))]);
+    for (attribute, first, second) in typing {
+        let changed = match (first, second) {
+            (Some(first), Some(second)) => (first - second).abs() > first.abs().max(second.abs()) * TYPING_TOLERANCE,
+            (first, second) => first.is_some() != second.is_some(),
+        };
+        let text = |value: Option<f64>| value.map_or(String::new(), |value| if value.fract() == 0.0 { format!("{value}") } else { format!("{value:.3}") });
+        comparisons.push(SessionComparison { section: "typing", attribute: attribute.to_string(), values: (text(first), text(second)), changed });
+    }
+
+    comparisons
+}
+
+/// Number of findings of each kind in a serialised session.
+fn event_counts(session: &Value) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for finding in session.get("results").and_then(Value::as_array).into_iter().flatten() {
+        if let Some(kind) = finding.get("kind") {
+            *counts.entry(value_text(Some(kind))).or_default() += 1;
+        }
+    }
+    counts
+}
+
+/// A JSON value as plain text; arrays are comma-separated, missing values and `null` empty.
+fn value_text(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(values)) => values.iter().map(|value| value_text(Some(value))).collect::<Vec<_>>().join(", "),
+        Some(value) => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_comparison() {
+        let first = serde_json::json!({"hassh_c": "aaa", "algorithms": ["curve25519-sha256", "aes128-ctr", "hmac-sha2-256", "none"],
+            "results": [{"kind": "WrongPassword"}, {"kind": "CorrectPassword"}], "typing": {"median_us": 180_000, "mean_us": 200_000}});
+        let second = serde_json::json!({"hassh_c": "aaa", "algorithms": ["curve25519-sha256", "aes256-gcm@openssh.com", "", "none"],
+            "results": [{"kind": "CorrectPassword"}], "typing": {"median_us": 120_000, "mean_us": 190_000}});
+
+        let comparisons = compare_sessions(&first, &second);
+        let changed = |attribute: &str| comparisons.iter().find(|comparison| comparison.attribute == attribute).unwrap().changed;
+        assert!(!changed("hassh_c"));
+        assert!(changed("encryption"));
+        assert!(!changed("kex"));
+        assert!(changed("WrongPassword"));
+        assert!(!changed("CorrectPassword"));
+        // Within the typing tolerance
+        assert!(!changed("mean latency (μs)"));
+        assert!(changed("median latency (μs)"));
+    }
+}
@@ -0,0 +1,169 @@
+//! Transparent decompression of gzip-, zstd-, and xz-compressed captures.
+//!
+//! tshark needs a seekable file, so a compressed capture (e.g. `capture.pcap.gz`) is decompressed
+//! in a streaming fashion into a temporary file, which is removed once the [CaptureFile] is
+//! dropped. The format is recognised by its magic bytes, not the extension, so renamed evidence
+//! files are handled too.
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Capture path standing for standard input, e.g. `tcpdump -w - | sshniff -f -`.
+pub const STDIN: &str = "-";
+
+/// Path tshark reads standard input from; rtshark insists on an existing input path, so `-` cannot
+/// be passed on.
+const STDIN_PATH: &str = "/dev/stdin";
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const XZ_MAGIC: [u8; 6] = [0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// Distinguishes the temporary files of captures decompressed concurrently.
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Compression formats recognised in captures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Compression {
+    /// Recognises a compression format by the first bytes of a file.
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        if header.starts_with(&GZIP_MAGIC) {
+            Some(Compression::Gzip)
+        } else if header.starts_with(&ZSTD_MAGIC) {
+            Some(Compression::Zstd)
+        } else if header.starts_with(&XZ_MAGIC) {
+            Some(Compression::Xz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Capture ready to be read by tshark: either the original file or its decompressed copy.
+#[derive(Debug)]
+pub struct CaptureFile {
+    path: PathBuf,
+    temporary: bool,
+}
+
+impl CaptureFile {
+    /// Opens a capture, decompressing it first if it is compressed. [STDIN] is handed to tshark
+    /// uncompressed, to read from the pipe while it is written.
+    pub fn open(filepath: &str) -> Result<Self, String> {
+        if filepath == STDIN {
+            return Ok(Self { path: PathBuf::from(STDIN_PATH), temporary: false });
+        }
+
+        let mut header = [0u8; XZ_MAGIC.len()];
+        let read = File::open(filepath)
+            .and_then(|mut file| file.read(&mut header))
+            .map_err(|err| format!("Failed to read {filepath}: {err}"))?;
+
+        match Compression::detect(&header[..read]) {
+            Some(compression) => {
+                let path = decompress(Path::new(filepath), compression).map_err(|err| format!("Failed to decompress {filepath}: {err}"))?;
+                Ok(Self { path, temporary: true })
+            },
+            None => Ok(Self { path: PathBuf::from(filepath), temporary: false }),
+        }
+    }
+
+    /// Takes ownership of a temporary copy of a capture, e.g. a download, which is removed when
+    /// dropped.
+    pub fn temporary(path: PathBuf) -> Self {
+        Self { path, temporary: true }
+    }
+
+    /// Path to hand to tshark.
+    pub fn path(&self) -> &str {
+        self.path.to_str().expect("capture paths are UTF-8")
+    }
+}
+
+impl Drop for CaptureFile {
+    fn drop(&mut self) {
+        if self.temporary {
+            if let Err(err) = fs::remove_file(&self.path) {
+                log::warn!("Failed to remove {}: {err}", self.path.display());
+            }
+        }
+    }
+}
+
+/// Unique path in the temporary directory for a copy of the capture `name`.
+pub fn temporary_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("sshniff-{}-{}-{name}", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed)))
+}
+
+/// Decompresses `source` into a new temporary file and returns its path.
+fn decompress(source: &Path, compression: Compression) -> io::Result<PathBuf> {
+    let name = source.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let path = temporary_path(name.trim_end_matches(".gz").trim_end_matches(".zst").trim_end_matches(".xz"));
+    log::info!("Decompressing {} ({compression:?}) to {}", source.display(), path.display());
+
+    let mut input = BufReader::new(File::open(source)?);
+    let mut output = BufWriter::new(File::create_new(&path)?);
+    let copied = match compression {
+        Compression::Gzip => io::copy(&mut flate2::read::MultiGzDecoder::new(input), &mut output),
+        Compression::Zstd => zstd::stream::read::Decoder::with_buffer(input).and_then(|mut decoder| io::copy(&mut decoder, &mut output)),
+        Compression::Xz => lzma_rs::xz_decompress(&mut input, &mut output).map(|()| 0).map_err(|err| match err {
+            lzma_rs::error::Error::IoError(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+        }),
+    };
+
+    match copied.and_then(|_| output.into_inner().map_err(|err| err.into_error())) {
+        Ok(_) => Ok(path),
+        Err(err) => {
+            let _ = fs::remove_file(&path);
+            Err(err)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression() {
+        use std::io::Write;
+
+        // pcapng section header block magic
+        let capture = [0x0a, 0x0d, 0x0d, 0x0a, 0x1c, 0x00, 0x00, 0x00];
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&capture).unwrap();
+        let compressed = std::env::temp_dir().join(format!("sshniff-test-{}.pcapng.gz", std::process::id()));
+        std::fs::write(&compressed, encoder.finish().unwrap()).unwrap();
+
+        let file = CaptureFile::open(compressed.to_str().unwrap()).unwrap();
+        let decompressed = std::path::PathBuf::from(file.path());
+        assert_eq!(capture.to_vec(), std::fs::read(&decompressed).unwrap());
+        drop(file);
+        assert!(!decompressed.exists());
+        std::fs::remove_file(&compressed).unwrap();
+
+        assert_eq!(Some(Compression::Zstd), Compression::detect(&zstd::encode_all(&capture[..], 0).unwrap()));
+        assert_eq!(None, Compression::detect(&capture));
+
+        let mut xz = vec![];
+        lzma_rs::xz_compress(&mut &capture[..], &mut xz).unwrap();
+        assert_eq!(Some(Compression::Xz), Compression::detect(&xz));
+        let compressed = std::env::temp_dir().join(format!("sshniff-test-{}.pcapng.xz", std::process::id()));
+        std::fs::write(&compressed, xz).unwrap();
+        let file = CaptureFile::open(compressed.to_str().unwrap()).unwrap();
+        assert_eq!(capture.to_vec(), std::fs::read(file.path()).unwrap());
+        drop(file);
+        std::fs::remove_file(&compressed).unwrap();
+
+        // Standard input is left to tshark
+        assert_eq!("/dev/stdin", CaptureFile::open(crate::analyser::compression::STDIN).unwrap().path());
+    }
+}
@@ -1,5 +1,8 @@
 //! Contains custom structs, enums, and impls.
-use rtshark::Packet;
+use super::packet::Packet;
+use super::stats::LatencyStats;
+use super::utils;
+use crate::ui::catalog::Catalog;
 use serde::{ser::SerializeStruct, Serialize};
 use std::fmt;
 
@@ -8,12 +11,93 @@ use std::fmt;
 pub struct Keystroke {
     /// Inferred type of keystroke
     pub k_type: KeystrokeType,
-    /// UNIX timestamp taken from [rtshark] [Packet]
+    /// UNIX timestamp (μs) taken from the [Packet]
     pub timestamp: i64,
+    /// `timestamp` as UTC date and time
+    pub timestamp_utc: String,
+    /// Time (μs) since the previous keystroke in the sequence; 0 for the first keystroke
+    pub latency_us: i64,
     /// Returned bytes; `None` for typical keystrokes, `Some()` for [Enter](KeystrokeType::Enter)
     pub response_size: Option<u128>,
     /// tcp.seq
     pub seq: i64,
+    /// Index of the evidence packet in the analysed record list
+    pub index: Option<usize>,
+    /// Frame number of the evidence packet in the capture (frame.number)
+    pub frame: Option<u32>,
+    /// ID of the [KeystrokeSequence] the keystroke belongs to, once grouped
+    pub sequence: Option<usize>,
+    /// How certain the classification is, from 0 to 1; see [KeystrokeType::confidence]
+    pub confidence: f32,
+    /// Likely commands, best first; only for [Enter](KeystrokeType::Enter)
+    pub guesses: Vec<CommandGuess>,
+}
+
+/// Keystrokes up to and including a Return, i.e. a single command.
+#[derive(Clone, Debug, Serialize)]
+pub struct KeystrokeSequence {
+    /// Command number, starting at 1
+    pub id: usize,
+    /// UNIX timestamp (μs) of the first keystroke
+    pub start: i64,
+    /// `start` as UTC date and time
+    pub start_utc: String,
+    /// Share of [corrective](KeystrokeType::is_corrective) keystrokes
+    pub error_rate: f32,
+    /// Whether the command is dominated by shifted characters and punctuation; see
+    /// [is_symbol_heavy](utils::is_symbol_heavy)
+    pub symbol_heavy: bool,
+    /// Whether the command was typed by a person, by a script, or pasted; see
+    /// [classify_input](utils::classify_input)
+    pub input: InputSource,
+    /// Estimated length of the submitted command; see [command_length](utils::command_length)
+    pub length: CommandLength,
+    /// Keystrokes of the command
+    pub keystrokes: Vec<Keystroke>,
+}
+
+impl Keystroke {
+    /// Creates a [Keystroke] of `k_type` from its evidence packet. Not yet part of a sequence.
+    pub fn new(k_type: KeystrokeType, packet_info: &PacketInfo, response_size: Option<u128>) -> Self {
+        let timestamp = packet_info.timestamp;
+
+        Self {
+            timestamp,
+            timestamp_utc: utils::format_timestamp(timestamp),
+            latency_us: 0,
+            response_size,
+            seq: packet_info.seq,
+            index: Some(packet_info.index),
+            frame: packet_info.frame,
+            sequence: None,
+            confidence: k_type.confidence(),
+            k_type,
+            guesses: vec![],
+        }
+    }
+
+    /// Creates a [Keystroke] of `k_type` known only by its latency, such as one read back from a
+    /// corpus.
+    pub fn with_latency(k_type: KeystrokeType, latency_us: i64) -> Self {
+        Self {
+            timestamp: 0,
+            timestamp_utc: String::new(),
+            latency_us,
+            response_size: None,
+            seq: 0,
+            index: None,
+            frame: None,
+            sequence: None,
+            confidence: k_type.confidence(),
+            k_type,
+            guesses: vec![],
+        }
+    }
+
+    /// Coarse level of the [confidence](Keystroke::confidence).
+    pub fn level(&self) -> Confidence {
+        Confidence::of(self.confidence)
+    }
 }
 
 /// Types of Keystroke
@@ -31,23 +115,125 @@ pub enum KeystrokeType {
     ArrowHorizontal,
     /// Up/Down arrow key
     ArrowVertical,
+    /// Non-ASCII character, sent as several UTF-8 bytes and echoed as such
+    Multibyte,
     /// Unknown Keystroke
     Unknown,
     /// Chaff/Fake Traffic
     Chaff,
 }
 
-/// Things that we are looking for before successful login.
-#[derive(Debug, PartialEq, Eq)]
+impl KeystrokeType {
+    /// How reliably the size patterns identify this type, from 0 to 1.
+    ///
+    /// Deletions share their echo with Ctrl+a/Ctrl+e, arrows and multibyte characters share
+    /// sizes with other escape sequences, and Tab detection is known to be unreliable.
+    pub fn confidence(&self) -> f32 {
+        match self {
+            KeystrokeType::Keystroke | KeystrokeType::Enter => 1.0,
+            KeystrokeType::Delete | KeystrokeType::ArrowHorizontal => 0.8,
+            KeystrokeType::ArrowVertical | KeystrokeType::Multibyte => 0.6,
+            KeystrokeType::Tab => 0.5,
+            KeystrokeType::Unknown => 0.3,
+            KeystrokeType::Chaff => 0.0,
+        }
+    }
+
+    /// Checks if the keystroke likely corrects a typing error: deletions, and horizontal cursor
+    /// movement to get to the error. [Unknown](KeystrokeType::Unknown) keystrokes are deletions or
+    /// keystrokes after such movement, so they count, too.
+    pub fn is_corrective(&self) -> bool {
+        matches!(self, KeystrokeType::Delete | KeystrokeType::Unknown | KeystrokeType::ArrowHorizontal)
+    }
+}
+
+/// Things that we are looking for in a session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
 pub enum Event {
+    /// Client's acceptance of the server host key
+    HostKeyAccepted,
+    /// New Keys (21) message concluding the KEX
+    NewKeys,
+    /// Packet following New Keys, whose size indicates the keystroke size
+    KeystrokeSizeIndicator,
+    /// First login prompt sent by the server
+    FirstLoginPrompt,
     WrongPassword,
     CorrectPassword,
     OfferRSAKey,
     OfferECDSAKey,
     OfferED25519Key,
+    /// FIDO security key offer (sk-ecdsa-sha2-nistp256@openssh.com)
+    OfferSkECDSAKey,
+    /// FIDO security key offer (sk-ssh-ed25519@openssh.com)
+    OfferSkED25519Key,
+    /// OpenSSH certificate offer (*-cert-v01@openssh.com), which carries the key, its principals,
+    /// and the CA's key and signature
+    OfferCertificate,
     OfferUnknownKey,
     RejectedKey,
     AcceptedKey,
+    /// Login accepted on the client's first request, without credentials: `none` authentication
+    NoneAuth,
+    /// Client's keyboard-interactive request, answered by rounds of INFO_REQUEST prompts
+    KeyboardInteractive,
+    /// Second round of keyboard-interactive prompts, e.g. a verification code after the password
+    SecondFactor,
+    /// Password sent in a single burst right after the prompt (password manager, paste)
+    PastedPassword,
+    /// Password sent after a human-scale delay
+    TypedPassword,
+    /// Timestamps running backwards were corrected before computing latencies
+    TimestampCorrection,
+    /// Abrupt, sustained RTT shift on one side of the connection
+    PathChange,
+    /// Key re-exchange (KEXINIT to NEWKEYS) after the initial one
+    Rekey,
+    /// Client's pty-req; its size grows with the TERM name and terminal modes
+    PtyRequest,
+    /// Server output refreshed at a fixed interval without input, e.g. a tmux/screen status line
+    StatusRefresh,
+    /// Text pasted into the terminal in bracketed paste mode
+    BracketedPaste,
+    /// Inputs too fast to be typed: mouse reporting or a TUI application (htop, mc)
+    InputBurst,
+    /// Keystrokes without echoes after login: a password typed at a sudo, su, or nested ssh prompt
+    PasswordEntry,
+    /// Keystrokes echoed markedly later after a command: an SSH session opened onward from the server
+    NestedSession,
+    /// A direction carried far more data than typing explains; see [exfil](super::exfil)
+    Exfiltration,
+    /// Client's window-change request, i.e. the terminal was resized
+    WindowChange,
+    /// Client's env requests (SendEnv/SetEnv) sent between pty-req and shell
+    EnvRequests,
+    /// Bulk file transfer (SCP/SFTP) or bulk shell output
+    FileTransfer,
+    /// Channels opened for port forwarding (-L, -D, or -R)
+    PortForwarding,
+    /// HASSH or IP listed in the threat-intel indicators; see [intel](super::intel)
+    ThreatMatch,
+    /// Server host key is listed for the server in the known_hosts file; see [knownhosts](super::knownhosts)
+    HostKeyMatch,
+    /// Server host key differs from the known ones, or is revoked: possible man-in-the-middle
+    HostKeyMismatch,
+    /// No key is known for the server
+    HostKeyUnknown,
+    /// Server presented a different host key than in an earlier session: possible man-in-the-middle
+    HostKeyChanged,
+    /// Client tried a few passwords on each of many servers; see [spray](super::spray)
+    PasswordSpray,
+    /// Client or server offered deprecated algorithms in its KEXINIT; see [audit](super::audit)
+    WeakCrypto,
+}
+
+impl Event {
+    /// English description for display; see [Catalog] for other languages.
+    ///
+    /// Serialised output uses the variant name instead, so that it stays stable.
+    pub fn description(&self) -> String {
+        Catalog::english().event(*self)
+    }
 }
 
 impl fmt::Display for Event {
@@ -56,56 +242,521 @@ impl fmt::Display for Event {
     }
 }
 
+/// Reference to the packet a [Finding] was inferred from.
+#[derive(Clone, Debug, Serialize)]
+pub struct PacketRef {
+    /// Index in the analysed packet stream
+    pub index: usize,
+    /// tcp.seq
+    pub seq: i64,
+    /// Frame number in the capture (frame.number)
+    pub frame: Option<u32>,
+}
+
+/// A typed result of the analysis, placed on the session's timeline.
+#[derive(Clone, Debug, Serialize)]
+pub struct Finding {
+    /// What was found
+    pub kind: Event,
+    /// Evidence packet
+    pub packet_ref: PacketRef,
+    /// UNIX timestamp (μs) of the evidence packet
+    pub timestamp: i64,
+    /// How certain the inference is, from 0 to 1
+    pub confidence: f32,
+    /// Optional free-text context, e.g. measured values
+    pub details: Option<String>,
+}
+
+impl Finding {
+    /// Creates a [Finding] of `kind` for the given packet, with full confidence and no details.
+    pub fn new(kind: Event, packet_info: &PacketInfo) -> Self {
+        Self {
+            kind,
+            packet_ref: PacketRef {
+                index: packet_info.index,
+                seq: packet_info.seq,
+                frame: packet_info.frame,
+            },
+            timestamp: packet_info.timestamp,
+            confidence: 1.0,
+            details: None,
+        }
+    }
+
+    /// Sets how certain the inference is, from 0 to 1.
+    pub fn with_confidence(mut self, confidence: f32) -> Self {
+        self.confidence = confidence;
+        self
+    }
+
+    /// Coarse level of the [confidence](Finding::confidence).
+    pub fn level(&self) -> Confidence {
+        Confidence::of(self.confidence)
+    }
+
+    /// Attaches free-text details.
+    pub fn with_details(mut self, details: String) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// English description for display, including the details if there are any.
+    pub fn description(&self) -> String {
+        Catalog::english().describe(self)
+    }
+}
+
+/// Stretch of a session without idle gaps, e.g. one attachment to a long-lived tmux session.
+///
+/// Keepalives do not count as activity, so a session kept open by ServerAliveInterval still falls
+/// apart at its idle gaps.
+#[derive(Clone, Debug, Serialize)]
+pub struct Activity {
+    /// Period number, starting at 1
+    pub id: usize,
+    /// UNIX timestamp (μs) of the first record
+    pub start: i64,
+    /// UNIX timestamp (μs) of the last record
+    pub end: i64,
+    pub start_utc: String,
+    pub end_utc: String,
+    /// Bytes (SSH record lengths) sent by the (client, server)
+    pub bytes: (u64, u64),
+    /// IDs of the [KeystrokeSequence]s started in the period
+    pub sequences: Vec<usize>,
+    /// Keystrokes of those sequences
+    pub keystrokes: usize,
+    /// Time (μs) since the end of the previous period; 0 for the first
+    pub idle_before: i64,
+    /// Share of [corrective](KeystrokeType::is_corrective) keystrokes in the period
+    pub error_rate: f32,
+    /// Latencies of those keystrokes
+    pub typing: LatencyStats,
+    /// Kinds of the findings in the period, in order
+    pub findings: Vec<Event>,
+}
+
+/// Algorithm name-lists of a KEXINIT, in order of preference.
+///
+/// Directional lists are (client to server, server to client).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct KexInit {
+    pub kex: Vec<String>,
+    pub host_key: Vec<String>,
+    pub encryption: (Vec<String>, Vec<String>),
+    pub mac: (Vec<String>, Vec<String>),
+    pub compression: (Vec<String>, Vec<String>),
+}
+
+impl KexInit {
+    /// Reads the name-lists of a KEXINIT packet; `None` if one is missing.
+    pub fn from_packet(packet: &Packet) -> Option<Self> {
+        let list = |field: &str| utils::find_metadata(packet, field)
+            .map(|names| names.split(',').filter(|name| !name.is_empty()).map(str::to_string).collect::<Vec<_>>());
+
+        Some(Self {
+            kex: list("ssh.kex_algorithms")?,
+            host_key: list("ssh.server_host_key_algorithms")?,
+            encryption: (list("ssh.encryption_algorithms_client_to_server")?, list("ssh.encryption_algorithms_server_to_client")?),
+            mac: (list("ssh.mac_algorithms_client_to_server")?, list("ssh.mac_algorithms_server_to_client")?),
+            compression: (list("ssh.compression_algorithms_client_to_server")?, list("ssh.compression_algorithms_server_to_client")?),
+        })
+    }
+}
+
+/// Round-trip latency and jitter observed on one leg of the connection.
+///
+/// Derived from tshark's `tcp.analysis.ack_rtt`; all values are in μs.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct JitterStats {
+    /// Number of RTT samples
+    pub samples: usize,
+    /// Mean RTT
+    pub mean_rtt: i64,
+    /// Lowest observed RTT
+    pub min_rtt: i64,
+    /// Highest observed RTT
+    pub max_rtt: i64,
+    /// Mean absolute difference between consecutive RTT samples
+    pub jitter: i64,
+}
+
+/// TCP packets of a stream that carry no SSH, and packets past the sampling threshold, collected
+/// while loading the capture.
+#[derive(Clone, Debug, Default)]
+pub struct TcpMeta {
+    /// MSS announced in the (client SYN, server SYN/ACK)
+    pub mss: (Option<u32>, Option<u32>),
+    /// Whether the handshake was captured, i.e. a SYN or SYN/ACK of the stream
+    pub handshake: bool,
+    /// Timestamp (μs) and direction (`true` for server) of every bare ACK
+    pub acks: Vec<(i64, bool)>,
+    /// Packets past the sampling threshold, if the stream reached it; see [sampling](super::sampling)
+    pub tail: Option<SampledTail>,
+    /// Index of the first packet of each part a stream was stitched from after the first, and
+    /// the (client, server) tcp.seq offset of that part; see [rollover](super::rollover)
+    pub rollovers: Vec<(usize, (i64, i64))>,
+}
+
+impl TcpMeta {
+    /// Shifts the tcp.seq of packets in stitched parts to continue those of the first part.
+    pub fn rebase_seq(&self, packet_infos: &mut [PacketInfo]) {
+        if self.rollovers.is_empty() {
+            return;
+        }
+        for packet_info in packet_infos {
+            if let Some((_, offsets)) = self.rollovers.iter().rev().find(|(index, _)| packet_info.index >= *index) {
+                packet_info.seq += match packet_info.direction {
+                    Direction::ClientToServer => offsets.0,
+                    Direction::ServerToClient => offsets.1,
+                };
+            }
+        }
+    }
+}
+
+/// Packets of a stream past the sampling threshold, reduced to counters and a sample.
+#[derive(Clone, Debug)]
+pub struct SampledTail {
+    /// One in `rate` packets is sampled
+    pub rate: usize,
+    /// Packets sent by the (client, server)
+    pub packets: (u64, u64),
+    /// TCP payload bytes sent by the (client, server)
+    pub bytes: (u64, u64),
+    /// UNIX timestamps (μs) of the first and last packet
+    pub first: i64,
+    pub last: i64,
+    /// Timestamp (μs) and direction (`true` for server) of every `rate`-th packet
+    pub samples: Vec<(i64, bool)>,
+}
+
+impl SampledTail {
+    pub fn new(rate: usize) -> Self {
+        Self {
+            rate: rate.max(1),
+            packets: (0, 0),
+            bytes: (0, 0),
+            first: 0,
+            last: 0,
+            samples: vec![],
+        }
+    }
+
+    /// Counts a packet, and samples it if it is due.
+    pub fn add(&mut self, timestamp: i64, is_server: bool, length: u64) {
+        let count = self.packets.0 + self.packets.1;
+        if count == 0 {
+            self.first = timestamp;
+        }
+        if count.is_multiple_of(self.rate as u64) {
+            self.samples.push((timestamp, is_server));
+        }
+        self.last = self.last.max(timestamp);
+
+        if is_server {
+            self.packets.1 += 1;
+            self.bytes.1 += length;
+        } else {
+            self.packets.0 += 1;
+            self.bytes.0 += length;
+        }
+    }
+}
+
+/// TCP segmentation characteristics of a session.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Segmentation {
+    /// MSS announced in the (client SYN, server SYN/ACK); `None` if the handshake was not captured
+    /// or the SYN carried no MSS option
+    pub mss: (Option<u32>, Option<u32>),
+    /// Whether the TCP handshake was captured
+    pub handshake: bool,
+    /// Largest observed tcp.len of (client, server) segments
+    pub max_segment: (u32, u32),
+    /// Number of SSH records that were reassembled from multiple TCP segments
+    pub split_records: usize,
+    /// Number of TCP segments that carried multiple SSH records
+    pub coalesced_segments: usize,
+}
+
+/// How reliably the sizes the analysis rests on were inferred, each from 0 to 1.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SizeConfidence {
+    /// Keystroke size; full if given, or if both methods found the size the cipher predicts
+    pub keystroke_size: f32,
+    /// Prompt size; full if given, or if the login scan followed the prompts up to the login
+    pub prompt_size: f32,
+    /// Size of the server's USERAUTH_SUCCESS; full if the [cipher model](super::cipher_model)
+    /// predicted it
+    pub login_success: f32,
+}
+
+/// Smallest confidence considered medium.
+pub const MEDIUM_CONFIDENCE: f32 = 0.5;
+
+/// Smallest confidence considered high.
+pub const HIGH_CONFIDENCE: f32 = 0.8;
+
+/// Coarse level of a confidence score, for display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl Confidence {
+    /// Level of a score from 0 to 1.
+    pub fn of(score: f32) -> Self {
+        if score >= HIGH_CONFIDENCE {
+            Confidence::High
+        } else if score >= MEDIUM_CONFIDENCE {
+            Confidence::Medium
+        } else {
+            Confidence::Low
+        }
+    }
+}
+
+impl fmt::Display for Confidence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+/// Estimated length of the command a [KeystrokeSequence] submits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CommandLength {
+    /// Most likely length in characters
+    pub estimate: usize,
+    /// Shortest possible length
+    pub min: usize,
+    /// Longest possible length; None if Tab completion or history recall added an unknown number
+    /// of characters
+    pub max: Option<usize>,
+    /// Characters typed and deleted again, i.e. the edit distance between everything typed and
+    /// the submitted command
+    pub edit_distance: usize,
+}
+
+impl fmt::Display for CommandLength {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f, "{}", self.estimate),
+            Some(max) => write!(f, "~{} ({}-{max})", self.estimate, self.min),
+            None => write!(f, "~{} ({}+)", self.estimate, self.min),
+        }
+    }
+}
+
+/// Command an [Enter](KeystrokeType::Enter) keystroke may have submitted, judged by the command
+/// length and the size of the response; see [commands](super::commands).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CommandGuess {
+    pub command: String,
+    /// How well the command fits, from 0 to 1
+    pub score: f32,
+}
+
+impl fmt::Display for CommandGuess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({:.0}%)", self.command, self.score * 100.0)
+    }
+}
+
+/// Origin of a [KeystrokeSequence]'s input, judged by its latencies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum InputSource {
+    /// Irregular latencies of a person typing
+    Human,
+    /// Near-constant latencies of a tool sending one key at a time (expect, Ansible, xdotool)
+    Scripted,
+    /// Keys arriving within milliseconds of each other, faster than anyone types
+    Pasted,
+    /// Too few keystrokes to tell
+    Unknown,
+}
+
+impl fmt::Display for InputSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+/// Direction a packet was sent in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Direction {
+    /// Client to Server (CTS)
+    ClientToServer,
+    /// Server to Client (STC)
+    ServerToClient,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
 /// Packet representation for easier access.
+///
+/// Owns everything the scans need from the [Packet], so results don't borrow from the capture.
 #[derive(Clone, Debug)]
-pub struct PacketInfo<'a> {
+pub struct PacketInfo {
     /// Index in the stream array/slice.
     pub index: usize,
     /// tcp.seq.
     pub seq: i64,
-    /// tcp.len - We use [i32] to indicate STC packets with a negative length.
+    /// SSH record length (tcp.len, unless reassembled).
     pub length: i32,    
-    /// Reference to "original" [Packet].
-    pub packet: &'a Packet,
-    /// Optional description for later printing.
-    pub description: Option<String>,  
+    /// Whether the client or the server sent the packet.
+    pub direction: Direction,
+    /// Number of TCP segments the SSH record was reassembled from.
+    pub segments: u32,
+    /// UNIX timestamp (μs) of the packet that completed the record.
+    pub timestamp: i64,
+    /// Frame number (frame.number) of the packet that completed the record.
+    pub frame: Option<u32>,
+    /// tcp.len of the segment that completed the record.
+    pub segment_length: i32,
+    /// SSH message code, if tshark could read it (i.e. before encryption).
+    pub message_code: Option<u32>,
+    /// `ssh.packet_length` of each SSH record tshark found in the packet.
+    pub packet_lengths: Vec<i32>,
+    /// tcp.analysis.ack_rtt (μs), if the packet acknowledges data.
+    pub ack_rtt: Option<i64>,
 }
 
-impl<'a> PacketInfo<'a> {
+impl PacketInfo {
     /// Constructor that does most of the heavy lifting using an existing [Packet].
-    pub fn new(packet: &'a Packet, index: usize, description: Option<String>) -> Self {
+    pub fn new(packet: &Packet, index: usize) -> Self {
         let tcp_layer = packet.layer_name("tcp").unwrap();
         let seq = tcp_layer.metadata("tcp.seq").unwrap().value().parse::<i64>().unwrap();
-        let mut length = tcp_layer.metadata("tcp.len").unwrap().value().parse::<i32>().unwrap();
-
-        let srcport: u32 = tcp_layer.metadata("tcp.srcport").unwrap().value().parse().unwrap();
-        let dstport: u32 = tcp_layer.metadata("tcp.dstport").unwrap().value().parse().unwrap();
-
-        // Server-to-Client indicated by negative length
-        if dstport > srcport {
-            length = -length;
-        }
+        let length = utils::get_record_length(packet);
 
         Self {
             index,
             seq,
             length,
-            packet,
-            description,
+            direction: utils::get_direction(packet),
+            segments: utils::get_segment_count(packet),
+            timestamp: packet.timestamp_micros().unwrap(),
+            frame: utils::get_frame_number(packet),
+            segment_length: utils::get_segment_length(packet),
+            message_code: utils::get_message_code(packet),
+            packet_lengths: utils::get_ssh_packet_lengths(packet),
+            ack_rtt: utils::get_ack_rtt(packet),
         }
     }
+
+    /// Returns the on-wire size of the SSH record starting in the packet, if tshark exposes its
+    /// `ssh.packet_length`.
+    ///
+    /// The record consists of the 4-byte length field, the packet itself, and the MAC (or AEAD tag)
+    /// of `mac_length` bytes.
+    pub fn record_length(&self, mac_length: i32) -> Option<i32> {
+        self.packet_lengths.first().map(|packet_length| 4 + packet_length + mac_length)
+    }
+
+    /// Returns the on-wire sizes of all SSH records in the packet. See [PacketInfo::record_length].
+    pub fn record_lengths(&self, mac_length: i32) -> Vec<i32> {
+        self.packet_lengths.iter().map(|packet_length| 4 + packet_length + mac_length).collect()
+    }
+    /// Checks if the packet was sent by the client.
+    pub fn is_client(&self) -> bool {
+        self.direction == Direction::ClientToServer
+    }
+
+    /// Checks if the packet was sent by the server.
+    pub fn is_server(&self) -> bool {
+        self.direction == Direction::ServerToClient
+    }
+
+    /// Checks if the packet was sent by the client and is exactly `length` long.
+    pub fn is_client_len(&self, length: i32) -> bool {
+        self.is_client() && self.length == length
+    }
+
+    /// Checks if the packet was sent by the server and is exactly `length` long.
+    pub fn is_server_len(&self, length: i32) -> bool {
+        self.is_server() && self.length == length
+    }
 }
 
 /// [Serde](serde) serialiser for output/saving.
-impl Serialize for PacketInfo<'_> {
+impl Serialize for PacketInfo {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where
             S: serde::Serializer {
         let mut state = serializer.serialize_struct("PacketInfo", 3)?;
         state.serialize_field("tcp.seq", &self.index)?;
         state.serialize_field("tcp.len", &self.length)?;
-        state.serialize_field("description", &self.description.clone().unwrap_or("".to_string()))?;
+        state.serialize_field("direction", &self.direction)?;
         state.end()
     }
 }
 
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Single-segment records of the given directions and lengths, 200ms apart.
+    pub(crate) fn records(layout: &[(Direction, i32)]) -> Vec<PacketInfo> {
+        let timed: Vec<(Direction, i32, i64)> = layout.iter().enumerate().map(|(index, &(direction, length))| (direction, length, index as i64 * 200_000)).collect();
+        timed_records(&timed)
+    }
+
+    /// Keystroke of `k_type` with a latency of `latency_us`, outside any capture or sequence.
+    pub(crate) fn keystroke(k_type: KeystrokeType, latency_us: i64) -> Keystroke {
+        Keystroke::with_latency(k_type, latency_us)
+    }
+
+    /// Single-segment records of the given directions, lengths and timestamps (μs).
+    pub(crate) fn timed_records(layout: &[(Direction, i32, i64)]) -> Vec<PacketInfo> {
+        layout.iter().enumerate().map(|(index, &(direction, length, timestamp))| PacketInfo {
+            index,
+            seq: index as i64,
+            length,
+            direction,
+            segments: 1,
+            timestamp,
+            frame: None,
+            segment_length: length,
+            message_code: None,
+            packet_lengths: vec![],
+            ack_rtt: None,
+        }).collect()
+    }
+
+    #[test]
+    fn test_rollover_seq() {
+        use Direction::{ClientToServer, ServerToClient};
+
+        // Two parts of a stream: tcp.seq restarts at 1 in the second, which begins at packet 3
+        let mut packet_infos = timed_records(&[(ClientToServer, 36, 0), (ServerToClient, 36, 1), (ClientToServer, 36, 2), (ClientToServer, 36, 3), (ServerToClient, 36, 4)]);
+        for (packet_info, seq) in packet_infos.iter_mut().zip([1000, 2000, 1036, 1, 1]) {
+            packet_info.seq = seq;
+        }
+        let tcp = TcpMeta { rollovers: vec![(3, (1071, 2035))], ..Default::default() };
+        tcp.rebase_seq(&mut packet_infos);
+
+        let seqs: Vec<i64> = packet_infos.iter().map(|packet_info| packet_info.seq).collect();
+        assert_eq!(vec![1000, 2000, 1036, 1072, 2036], seqs);
+    }
+
+    #[test]
+    fn test_keystroke_json() {
+        let packet_infos = records(&[(Direction::ClientToServer, 36)]);
+        let mut keystroke = Keystroke::new(KeystrokeType::Keystroke, &packet_infos[0], None);
+        keystroke.timestamp = 1_706_702_400_250_000;
+        keystroke.latency_us = 250_000;
+
+        // Same names as the CSV columns: the absolute time, and the latency next to it
+        let json = serde_json::to_value(&keystroke).unwrap();
+        assert_eq!(1_706_702_400_250_000_i64, json["timestamp"]);
+        assert_eq!(250_000, json["latency_us"]);
+        assert!(json.get("absolute_timestamp").is_none());
+    }
+}
@@ -2,11 +2,30 @@
 //! Calls all [scan](super::scan) functions and aggregates them into a single [SshSession]. 
 use crate::analyser::utils::is_server_packet;
 
-use super::scan::{scan_for_host_key_accepts, scan_for_keystrokes, scan_login_data, find_successful_login, scan_for_reverse_session_r_option, scan_for_obfuscated_keystrokes};
+use super::scan::{scan_for_host_key_accepts, scan_for_keystrokes, scan_login_data, scan_failed_login, find_successful_login, scan_for_reverse_session_r_option, scan_for_obfuscated_keystrokes, scan_for_path_changes, scan_for_terminal_events, scan_for_forwarding, scan_password_entry, scan_for_rekeys, scan_for_status_refreshes, scan_for_pastes, scan_for_unechoed_input, scan_none_auth};
 use super::containers;
+use super::records;
+use super::tags;
+use super::transfers;
+use super::stats;
+use super::rhythm;
+use super::password;
+use super::ja4ssh;
+use super::software;
+use super::advisories;
+use super::audit;
+use super::hostkey;
+use super::tunnel;
+use super::nested;
+use super::exfil;
+use super::sampling;
+use super::tuning;
+use super::cipher_model::{self, CipherModel};
+use crate::error::Error;
+use super::options::{AnalysisOptions, Scan, Thresholds};
 use super::utils;
-use core::{panic, fmt};
-use rtshark::Packet;
+use core::fmt;
+use super::packet::Packet;
 use serde::Serialize;
 use chrono::{DateTime, TimeZone, Utc};
 
@@ -15,7 +34,7 @@ use chrono::{DateTime, TimeZone, Utc};
 /// Contains markers to optimise packet iteration as well as containers for results and keystroke
 /// data. Passed from function to function during analysis and aggregates data.
 #[derive(Debug, Serialize)]
-pub struct SshSession<'a> {
+pub struct SshSession {
     pub stream: u32,
     pub new_keys_at: usize,
     pub keystroke_size: u32,
@@ -25,15 +44,112 @@ pub struct SshSession<'a> {
     pub dst: String,
     pub hassh_s: String,
     pub hassh_c: String,
+    /// Likely (client, server) implementations; see [software](super::software).
+    pub software: (Option<software::Software>, Option<software::Software>),
+    /// Known vulnerabilities of the (client, server) banner versions; see [advisories].
+    pub advisories: (Vec<advisories::Advisory>, Vec<advisories::Advisory>),
+    /// Deprecated algorithms the (client, server) offered in their KEXINIT; see [audit].
+    pub weak_crypto: audit::Weaknesses,
+    /// Public host key sent by the server during the KEX.
+    pub host_key: Option<hostkey::HostKey>,
+    /// VLAN tags and tunnels the session was captured in; see [tunnel](super::tunnel).
+    pub tunnel: Option<tunnel::Tunnel>,
+    /// JA4SSH fingerprint of every window of 200 packets.
+    pub ja4ssh: Vec<String>,
     pub algorithms: (String, String, String, String),
+    /// Full algorithm lists of the (client, server) KEXINIT, if `--verbose-kex` was given.
+    pub kexinit: (Option<containers::KexInit>, Option<containers::KexInit>),
+    /// Position of the login success among the ordered records; their count if the login failed.
     pub logged_in_at: usize,
+    /// Whether the client uses keystroke obfuscation.
+    pub obfuscated: bool,
+    /// Bytes (SSH record lengths) sent by the (client, server) after login.
+    pub bytes: (u64, u64),
+    /// How much of the (client, server) bytes interactive use explains; see [exfil](super::exfil).
+    pub volume: (exfil::VolumeScore, exfil::VolumeScore),
     pub start_utc: String,
     pub end_utc: String,
-    pub results: Vec<containers::PacketInfo<'a>>,
-    pub keystroke_data: Vec<Vec<containers::Keystroke>>,
+    /// RTT jitter of the (client, server) side of the connection.
+    pub jitter: (containers::JitterStats, containers::JitterStats),
+    pub segmentation: containers::Segmentation,
+    pub results: Vec<containers::Finding>,
+    pub keystroke_data: Vec<containers::KeystrokeSequence>,
+    /// Share of corrective keystrokes over all sequences.
+    pub error_rate: f32,
+    /// Confidence-weighted keystroke latencies over all sequences.
+    pub typing: stats::LatencyStats,
+    /// Digraph latency distributions over all sequences; see [rhythm].
+    pub rhythm: rhythm::TypingRhythm,
+    /// Bulk transfers after login.
+    pub transfers: Vec<transfers::Transfer>,
+    pub tags: Vec<tags::Tag>,
+    /// Sizes and directions of the first records; see [size_signature](utils::size_signature).
+    pub signature: String,
+    /// Activity periods after login, if splitting at idle gaps was requested.
+    pub activity: Vec<containers::Activity>,
+    /// Statistics of the packets past the sampling threshold, if the stream was sampled.
+    pub sampled: Option<sampling::SampledStats>,
+    /// Highest severity (0-10) of the threat-intel indicators the session matched; 0 if none.
+    pub severity: u8,
+    /// How reliably the keystroke, prompt, and login success sizes were inferred.
+    pub size_confidence: containers::SizeConfidence,
+    /// Whether the capture started after the KEX, leaving only the keystrokes to be analysed; see
+    /// [is_mid_session].
+    pub partial: bool,
 }
 
-impl<'a> fmt::Display for SshSession<'a> {
+impl SshSession {
+    /// Creates an empty session of `stream`, to be filled in by [analyse].
+    pub(crate) fn new(stream: u32) -> Self {
+        Self {
+            stream,
+            new_keys_at: 0,
+            keystroke_size: 0,
+            prompt_size: 0,
+            protocols: (String::new(), String::new()),
+            src: String::new(),
+            dst: String::new(),
+            hassh_s: String::new(),
+            hassh_c: String::new(),
+            software: (None, None),
+            advisories: (vec![], vec![]),
+            weak_crypto: (vec![], vec![]),
+            host_key: None,
+            tunnel: None,
+            ja4ssh: vec![],
+            algorithms: (String::new(), String::new(), String::new(), String::new()),
+            kexinit: (None, None),
+            logged_in_at: 0,
+            obfuscated: false,
+            bytes: (0, 0),
+            volume: (exfil::VolumeScore::default(), exfil::VolumeScore::default()),
+            start_utc: String::new(),
+            end_utc: String::new(),
+            jitter: (containers::JitterStats::default(), containers::JitterStats::default()),
+            segmentation: containers::Segmentation::default(),
+            results: vec![],
+            keystroke_data: vec![],
+            error_rate: 0.0,
+            typing: stats::LatencyStats::default(),
+            rhythm: rhythm::TypingRhythm::default(),
+            transfers: vec![],
+            tags: vec![],
+            signature: String::new(),
+            activity: vec![],
+            sampled: None,
+            severity: 0,
+            size_confidence: containers::SizeConfidence::default(),
+            partial: false,
+        }
+    }
+
+    /// Analyses the packets of a single stream; see [analyse].
+    pub fn analyse(stream_id: u32, packet_stream: &[Packet], tcp: &containers::TcpMeta, options: &AnalysisOptions) -> Result<Self, Error> {
+        analyse(stream_id, packet_stream, tcp, options)
+    }
+}
+
+impl fmt::Display for SshSession {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "SshSession '{}' SRC '{}' DST '{}' HASSH_C '{}' HASSH_S '{}' KEX '{}' ENC '{}' MAC '{}' CMP '{}' NK '{}' KS '{}' PS '{}' LIA '{}' Protocols '{:?}'", self.stream, self.src, self.dst, self.hassh_c, self.hassh_s, self.algorithms.0, self.algorithms.1, self.algorithms.2, self.algorithms.3,  self.new_keys_at, self.keystroke_size, self.prompt_size, self.logged_in_at, self.protocols)
     }
@@ -42,157 +158,424 @@ impl<'a> fmt::Display for SshSession<'a> {
 /// Core analysis function creating the SshSession object with all extracted data.
 ///
 /// Operates on a single packet stream; will have to be called iteratively for multiple streams.
-/// The `only_meta` parameter allows the caller to skip keystroke analysis.
-///     By default, the full analysis will run, unless only_meta = true.
-pub fn analyse(stream_id: u32, packet_stream: &[Packet], only_meta: bool) -> SshSession {
+/// The `tcp` parameter holds the MSS from the TCP handshake and the bare ACKs of the stream, as far
+/// as they were captured.
+/// The `options` parameter selects the optional scans, their thresholds and any values that
+/// should not be inferred from the capture; see [AnalysisOptions].
+///
+/// The [results](SshSession::results) are in the order they were seen: by timestamp, then by
+/// sequence number.
+pub fn analyse(stream_id: u32, packet_stream: &[Packet], tcp: &containers::TcpMeta, options: &AnalysisOptions) -> Result<SshSession, Error> {
+    let mut session = analyse_stream(stream_id, packet_stream, tcp, options)?;
+    session.results.sort_by_key(|finding| (finding.timestamp, finding.packet_ref.seq));
+    Ok(session)
+}
+
+/// Runs the scans of [analyse], leaving the findings in the order they were scanned for.
+fn analyse_stream(stream_id: u32, packet_stream: &[Packet], tcp: &containers::TcpMeta, options: &AnalysisOptions) -> Result<SshSession, Error> {
+    let thresholds = &options.thresholds;
+    let overrides = &options.overrides;
+
     log::info!("Starting analysis.");
 
-    let mut session = SshSession {
-        stream: stream_id,
-        new_keys_at: 0,
-        keystroke_size: 0,
-        prompt_size: 0,
-        protocols: (String::new(), String::new()),
-        src: String::new(),
-        dst: String::new(),
-        hassh_s: String::new(),
-        hassh_c: String::new(),
-        algorithms: (String::new(), String::new(), String::new(), String::new()),
-        logged_in_at: 0,
-        start_utc: String::new(),
-        end_utc: String::new(),
-        results: vec![],
-        keystroke_data: vec![],
-    };
+    let mut session = SshSession::new(stream_id);
 
     // Get start and end
     let timeframe = get_start_and_end(&packet_stream);
     session.start_utc = timeframe.0;
     session.end_utc = timeframe.1;
 
+    session.segmentation = find_meta_segmentation(packet_stream);
+    session.segmentation.mss = tcp.mss;
+    session.segmentation.handshake = tcp.handshake;
+    session.ja4ssh = ja4ssh::fingerprints(packet_stream, &tcp.acks);
+    if session.segmentation.split_records > 0 {
+        log::info!("{} SSH records were split across TCP segments.", session.segmentation.split_records);
+    }
+
     // Get NewKeys, Keystroke Indicator, Login Prompt
     let kex = match find_meta_size(&packet_stream) {
-        Ok(infos) => infos,
-        Err(err) => {
-            log::error!("{err}");
-            panic!();
+        Ok(infos) => Some(infos),
+        // Without the KEX, only the keystrokes are left to analyse
+        Err(err) if is_mid_session(packet_stream) => {
+            log::warn!("Stream {stream_id} was captured mid-session ({err}); analysing keystrokes only.");
+            session.partial = true;
+            None
         },
+        Err(err) => return Err(Error::Metadata(err)),
     };
 
-    session.results.push(kex[0].clone());
-    session.results.push(kex[1].clone());
-    session.results.push(kex[2].clone());
-    session.new_keys_at = kex[0].index;
-    //session.keystroke_size = kex[1].length as u32 - 8;
-    session.prompt_size = kex[2].length;
-    log::debug!("{session}");
+    if let Some(kex) = &kex {
+        session.results.push(containers::Finding::new(containers::Event::NewKeys, &kex[0]));
+        session.results.push(containers::Finding::new(containers::Event::KeystrokeSizeIndicator, &kex[1]));
+        session.results.push(containers::Finding::new(containers::Event::FirstLoginPrompt, &kex[2]));
+        session.new_keys_at = kex[0].index;
+        //session.keystroke_size = kex[1].length as u32 - 8;
+        session.prompt_size = overrides.prompt_size.unwrap_or(kex[2].length);
+        log::debug!("{session}");
+
+        let hassh_server: String;
+        let hassh_client: String;
+        let algorithms: (String, String, String, String);
+        match find_meta_hassh(&packet_stream) {
+            Ok(vals) => {
+                hassh_client = String::from(&vals[0]);
+                hassh_server = String::from(&vals[1]);
+                algorithms = (String::from(&vals[2]), String::from(&vals[3]), String::from(&vals[4]), String::from(&vals[5]))
+            }
+            Err(err) => return Err(Error::Metadata(err)),
+        }
+
+        session.hassh_s = hassh_server;
+        session.hassh_c = hassh_client;
+        session.algorithms = algorithms;
+        log::debug!("{session}");
+
+        if options.verbose_kex {
+            session.kexinit = find_kexinits(packet_stream);
+        }
+    }
+
+    // Expected record lengths under the negotiated cipher
+    let model = CipherModel::negotiated(&session.algorithms.1, &session.algorithms.2);
+    if model.is_none() && !session.partial {
+        log::warn!("No record length model for cipher {}; using the measured sizes.", session.algorithms.1);
+    }
+    let keepalive = model.unwrap_or_else(CipherModel::reference).keepalive_sizes();
 
     // Temporary measure to identify other ciphers
     let verify = alt_find_keystroke_size(&packet_stream);
-    if verify == kex[1].length as u32 - 8 {
+    let expected = model.map(|model| model.keystroke_size() as u32);
+    // Without a model, agreement of both methods is as good as it gets
+    let predicted = |size: u32| expected.is_none_or(|expected| expected == size);
+    // Keystrokes of a partial capture can only be sized by the traffic
+    let indicated = kex.as_ref().map_or(verify, |kex| kex[1].length as u32 - 8);
+    if let Some(keystroke_size) = overrides.keystroke_size {
+        session.keystroke_size = keystroke_size;
+        session.size_confidence.keystroke_size = 1.0;
+    } else if session.partial {
+        session.keystroke_size = verify;
+        session.size_confidence.keystroke_size = 0.5;
+    } else if verify == indicated {
         session.keystroke_size = verify;
+        session.size_confidence.keystroke_size = if predicted(verify) { 1.0 } else { 0.8 };
+    } else if expected == Some(indicated) {
+        log::warn!("Disagreement when finding keystroke size. Relying on the cipher's keystroke size.");
+        log::debug!("Alternative size: {}", verify);
+        session.keystroke_size = indicated;
+        session.size_confidence.keystroke_size = 0.7;
     } else {
         log::warn!("Disagreement when finding keystroke size. Relying on alternative method.");
         log::debug!("Alternative size: {}", verify);
         session.keystroke_size = verify;
+        session.size_confidence.keystroke_size = if expected == Some(verify) { 0.6 } else { 0.3 };
     }
 
-    let hassh_server: String;
-    let hassh_client: String;
-    let algorithms: (String, String, String, String);
-    match find_meta_hassh(&packet_stream) {
-        Ok(vals) => {
-            hassh_server = String::from(&vals[0]);
-            hassh_client = String::from(&vals[1]);
-            algorithms = (String::from(&vals[2]), String::from(&vals[3]), String::from(&vals[4]), String::from(&vals[5]))
-        }
-        Err(err) => {
-            log::error!("{err}");
-            panic!();
-        }
-    }
+    // The key-offer lengths were measured with chacha20-poly1305
+    let modelled;
+    let thresholds = match &model {
+        Some(model) => {
+            modelled = model.thresholds(thresholds);
+            &modelled
+        },
+        None => thresholds,
+    };
 
-    session.hassh_s = hassh_server;
-    session.hassh_c = hassh_client;
-    session.algorithms = algorithms;
-    log::debug!("{session}");
 
+    // Partial captures have no banners, but their endpoints all the same
     let protocols = match find_meta_protocol(packet_stream) {
         Ok(protocols) => protocols,
-        Err(err) => {
-            log::error!("{err}");
-            panic!();
-        }
+        Err(_) if session.partial => match find_meta_endpoints(&packet_stream[0]) {
+            Ok([client_ip, client_port, server_ip, server_port]) => [String::new(), String::new(), client_ip, client_port, server_ip, server_port],
+            Err(err) => return Err(Error::Metadata(err)),
+        },
+        Err(err) => return Err(Error::Metadata(err)),
     };
     log::debug!("{protocols:?}");
     session.protocols = (String::from(protocols[0].clone()), String::from(protocols[1].clone()));
-    session.src = String::from(format!("{}:{}", protocols[2], protocols[3]));
-    session.dst = String::from(format!("{}:{}", protocols[4], protocols[5]));
+    session.src = utils::format_endpoint(&protocols[2], &protocols[3]);
+    session.dst = utils::format_endpoint(&protocols[4], &protocols[5]);
+    session.host_key = hostkey::find_host_key(packet_stream);
+    session.tunnel = tunnel::find_tunnel(packet_stream);
+    session.software = options.hassh_db.identify((&session.hassh_c, &session.hassh_s), (&session.protocols.0, &session.protocols.1));
+    session.advisories = options.advisory_db.match_banners((&session.protocols.0, &session.protocols.1));
 
     let mut size_matrix = utils::create_size_matrix(packet_stream);
+    tcp.rebase_seq(&mut size_matrix);
+
+    let (corrected, duplicated) = utils::correct_timestamps(&mut size_matrix);
+    if let Some(&first) = corrected.first() {
+        log::warn!("Corrected {} timestamps running backwards; latencies around them are unreliable.", corrected.len());
+        session.results.push(containers::Finding::new(containers::Event::TimestampCorrection, &size_matrix[first])
+            .with_details(format!("{} moved forward, {duplicated} duplicated", corrected.len())));
+    }
+
+    if let Some(first) = size_matrix.first() {
+        for threat in options.indicators.matches(&session) {
+            log::warn!("Threat indicator matched: {}", threat.details());
+            session.severity = session.severity.max(threat.indicator.severity);
+            session.results.push(containers::Finding::new(containers::Event::ThreatMatch, first).with_details(threat.details()));
+        }
+    }
+
+    // Deprecated algorithms are reported with the KEXINIT offering them
+    let (weak_crypto, kexinits) = audit::audit_offers(packet_stream, &session.algorithms);
+    for (side, weaknesses, kexinit) in [("client", &weak_crypto.0, kexinits.0), ("server", &weak_crypto.1, kexinits.1)] {
+        if let (Some(kexinit), false) = (kexinit, weaknesses.is_empty()) {
+            let offered = weaknesses.iter().map(|weakness| weakness.to_string()).collect::<Vec<_>>().join(", ");
+            session.results.push(containers::Finding::new(containers::Event::WeakCrypto, &size_matrix[kexinit])
+                .with_details(format!("{side} offers {offered}")));
+        }
+    }
+    session.weak_crypto = weak_crypto;
+
+    // The KEX reply carrying the host key directly precedes New Keys
+    if let (Some(known_hosts), Some(host_key), Some(kex)) = (&options.known_hosts, &session.host_key, &kex) {
+        let (event, details) = known_hosts.verify(&session.dst, host_key);
+        if event == containers::Event::HostKeyMismatch {
+            log::warn!("Host key mismatch: {details}");
+        }
+        session.results.push(containers::Finding::new(event, &kex[0]).with_details(details));
+    }
+
+    // Jitter needs capture order, so it runs before the keystroke ordering below.
+    let mut path_changes = vec![];
+    if options.runs(Scan::PathChanges) {
+        let (jitter, findings) = scan_for_path_changes(&size_matrix, thresholds);
+        session.jitter = jitter;
+        path_changes = findings;
+    }
+
+    // From here on, analysis operates on SSH records rather than TCP segments.
+    let mac_length = overrides.mac_length.unwrap_or_else(|| utils::get_mac_length(&session.algorithms.1, &session.algorithms.2));
+    let (size_matrix, merged) = if options.runs(Scan::Reassembly) {
+        records::reassemble_records(size_matrix, mac_length, thresholds)
+    } else {
+        (size_matrix, 0)
+    };
+    session.segmentation.split_records += merged;
+    session.signature = utils::size_signature(&size_matrix, options.signature_length);
+
+    // Merging renumbers packets, so the New Keys index has to follow.
+    if let (true, Some(kex)) = (merged > 0, &kex) {
+        session.new_keys_at = size_matrix.iter()
+            .position(|record| record.seq == kex[0].seq && record.direction == kex[0].direction)
+            .unwrap_or(session.new_keys_at);
+    }
 
     // Hacky fix to accommodate Patch Bypass PoC
     // Once we know the protocol versions, we can account for chaff and find spikes
-    let is_obfuscated = utils::is_obfuscated(&session.protocols.0,  &session.protocols.1);
+    let is_obfuscated = overrides.obfuscated.unwrap_or_else(|| utils::is_obfuscated(&session.protocols.0,  &session.protocols.1));
+    session.obfuscated = is_obfuscated;
+
+    // Slim and fat keystrokes are multiples of each other in obfuscated sessions, so splitting
+    // by keystroke size would tear fat keystrokes apart.
+    let split_size = if is_obfuscated { 0 } else { session.keystroke_size as i32 };
+    let (mut size_matrix, coalesced) = if options.runs(Scan::Reassembly) {
+        records::split_coalesced_records(size_matrix, session.new_keys_at, split_size, mac_length, thresholds)
+    } else {
+        (size_matrix, 0)
+    };
+    session.segmentation.coalesced_segments = coalesced;
+
+    // Rekey records follow New Keys, so dropping them leaves the New Keys index in place.
+    let mut rekeys = vec![];
+    if options.runs(Scan::Rekey) {
+        let (bursts, findings) = scan_for_rekeys(&size_matrix, session.new_keys_at, mac_length);
+        records::drop_records(&mut size_matrix, bursts);
+        rekeys = findings;
+    }
+
+    // Chaff blurs the size distributions, so obfuscated sessions keep the fixed thresholds.
+    let tuned;
+    let thresholds = if options.self_tune && !is_obfuscated {
+        tuned = tuning::tune(&size_matrix, session.keystroke_size as i32, session.prompt_size, session.new_keys_at, thresholds);
+        &tuned
+    } else {
+        thresholds
+    };
+
     let ordered: Vec<containers::PacketInfo>;
 
     if  is_obfuscated {
         log::warn!("Session uses obfuscation! Metadata extraction is experimental.");
         session.keystroke_size *= 2;
-        ordered = utils::order_obfuscated_keystrokes(&mut size_matrix, session.keystroke_size);
+        ordered = utils::order_obfuscated_keystrokes(&mut size_matrix, session.keystroke_size, thresholds);
     } else {
-        ordered = utils::order_keystrokes(&mut size_matrix, session.keystroke_size);
+        ordered = utils::order_keystrokes(&mut size_matrix, session.keystroke_size, thresholds);
     }
 
-    let logged_in_at = match find_successful_login(&ordered) {
+    let login_sizes = match &model {
+        Some(model) => vec![model.login_success_size()],
+        None => cipher_model::LOGIN_SUCCESS_SIZES.to_vec(),
+    };
+    session.size_confidence.login_success = if model.is_some() { 1.0 } else { 0.6 };
+    // A partial capture starts after the login, so all of it is taken as the session
+    let logged_in_at = match find_successful_login(&ordered, &login_sizes) {
+        _ if session.partial => {
+            session.size_confidence.login_success = 0.0;
+            0
+        },
         Some(index) => index,
-        None => {
-            log::error!("Failed to find login packet.");
-            panic!();
-        }
+        None => return failed_login(session, &ordered, thresholds, model.as_ref()),
     };
 
     session.logged_in_at = logged_in_at;
+    session.bytes = utils::count_bytes(&ordered[logged_in_at..]);
+
+    // Sampled packets follow everything analysed exactly, so they only extend the totals.
+    if let Some(tail) = &tcp.tail {
+        let stats = sampling::summarise(tail, packet_stream.len());
+        log::warn!("Stream {stream_id} was sampled: {}", stats.accuracy);
+        session.bytes.0 += tail.bytes.0;
+        session.bytes.1 += tail.bytes.1;
+        if let Some(end) = Utc.timestamp_micros(tail.last).single() {
+            session.end_utc = end.format("%Y-%m-%d %H:%M:%S").to_string();
+        }
+        session.sampled = Some(stats);
+    }
 
-    let login_events = scan_login_data(&ordered, session.prompt_size, session.new_keys_at, session.logged_in_at);
+    let first_prompt = session.results.iter().find(|finding| finding.kind == containers::Event::FirstLoginPrompt).map(|finding| finding.packet_ref.index);
+    let login_events = match (session.partial, first_prompt.and_then(|first_prompt| scan_none_auth(&ordered, first_prompt, session.logged_in_at))) {
+        (true, _) => vec![],
+        (false, Some(none_auth)) => vec![none_auth],
+        (false, None) => scan_login_data(&ordered, session.prompt_size, session.new_keys_at, session.logged_in_at, thresholds),
+    };
+    // The prompts led up to the login as expected
+    let followed = login_events.iter().any(|finding| matches!(finding.kind, containers::Event::CorrectPassword | containers::Event::AcceptedKey));
+    session.size_confidence.prompt_size = match session.partial {
+        true => 0.0,
+        false => if overrides.prompt_size.is_some() || followed { 1.0 } else { 0.5 },
+    };
     session.results.extend(login_events);
 
-    match scan_for_host_key_accepts(&ordered, session.logged_in_at) {
-        Some(pinfo) => {
-            // Hostkey acceptance occurs before the other events, so we set it first.
-            session.results.insert(0, pinfo);
-        },
-        None => {
-            log::error!("Failed to find Hostkey Acceptance.");
-        }
-    };
+    // Keyboard-interactive logins end in an exchange of empty prompts, not the password
+    let keyboard_interactive = session.results.iter().any(|finding| finding.kind == containers::Event::KeyboardInteractive);
+    if options.runs(Scan::PasswordEntry) && session.results.iter().any(|finding| finding.kind == containers::Event::CorrectPassword) && !keyboard_interactive {
+        session.results.extend(scan_password_entry(&ordered, session.logged_in_at, session.jitter.0.mean_rtt));
+    }
 
-    // Skip keystroke analysis and processing if `only_meta` is true.
-    if only_meta {
-        return session;
+    if options.runs(Scan::HostKey) && !session.partial {
+        match scan_for_host_key_accepts(&ordered, session.logged_in_at) {
+            Some(finding) => {
+                let finding = match &session.host_key {
+                    Some(host_key) => finding.with_details(format!("{} {}", host_key.key_type, host_key.sha256)),
+                    None => finding,
+                };
+                session.results.push(finding);
+            },
+            None => {
+                log::error!("Failed to find Hostkey Acceptance.");
+            }
+        };
     }
 
-    let keystrokes;
+    // Obfuscated sessions track the doubled keystroke size; channel requests are sized like a
+    // single keystroke.
+    let single_keystroke_size = if is_obfuscated { session.keystroke_size / 2 } else { session.keystroke_size } as i32;
 
-    if is_obfuscated {
-        keystrokes = scan_for_obfuscated_keystrokes(&ordered, session.keystroke_size as i32, session.logged_in_at);
-    } else {
-        keystrokes = scan_for_keystrokes(&ordered, session.keystroke_size as i32, session.logged_in_at);
-    
+    if options.runs(Scan::Terminal) {
+        session.results.extend(scan_for_terminal_events(&ordered, single_keystroke_size, session.logged_in_at));
+        session.results.extend(scan_for_status_refreshes(&ordered, session.logged_in_at));
     }
 
-    if keystrokes.len() == 0 {
-        log::warn!("Failed to find keystrokes using conventional method.");
-        let keystroke_size = alt_find_keystroke_size(&packet_stream);
-        let keystrokes_2 = scan_for_keystrokes(&ordered, keystroke_size as i32, session.logged_in_at);
-        let processed = process_keystrokes(keystrokes);
-        session.keystroke_data = processed;
-    } else {
-        let processed = process_keystrokes(keystrokes);
-        session.keystroke_data = processed;
+    if options.runs(Scan::Forwarding) {
+        session.results.extend(scan_for_forwarding(&ordered, single_keystroke_size, session.logged_in_at, thresholds));
+    }
+
+    if options.runs(Scan::Transfers) {
+        let interactive = session.results.iter().any(|finding| finding.kind == containers::Event::PtyRequest);
+        let (found, events) = transfers::scan_for_transfers(&ordered, session.logged_in_at, interactive);
+        session.transfers = found;
+        session.results.extend(events);
+    }
+
+    session.results.extend(path_changes);
+    session.results.extend(rekeys);
+
+    // Keystroke analysis and processing can be switched off
+    if options.runs(Scan::Keystrokes) {
+        let keystrokes;
+
+        if is_obfuscated {
+            keystrokes = scan_for_obfuscated_keystrokes(&ordered, session.keystroke_size as i32, session.logged_in_at, thresholds);
+        } else {
+            keystrokes = scan_for_keystrokes(&ordered, session.keystroke_size as i32, session.logged_in_at, thresholds);
+        }
+
+        if options.runs(Scan::Pastes) && !is_obfuscated {
+            session.results.extend(scan_for_pastes(&ordered, session.keystroke_size as i32, session.logged_in_at));
+        }
+        if options.runs(Scan::UnechoedInput) && !is_obfuscated {
+            session.results.extend(scan_for_unechoed_input(&ordered, session.keystroke_size as i32, session.logged_in_at));
+        }
+
+        // Mouse reporting and TUI navigation would otherwise show up as floods of keystrokes. Chaff
+        // is sent just as fast, so obfuscated sessions are left as they are.
+        let (keystrokes, bursts) = if is_obfuscated || !options.runs(Scan::InputBursts) { (keystrokes, vec![]) } else { utils::separate_input_bursts(keystrokes) };
+        for burst in bursts {
+            let (first, last) = (&burst[0], &burst[burst.len() - 1]);
+            if let Some(packet_info) = ordered.iter().find(|packet_info| Some(packet_info.index) == first.index) {
+                session.results.push(containers::Finding::new(containers::Event::InputBurst, packet_info)
+                    .with_details(format!("{} inputs over {:.1}s", burst.len(), (last.timestamp - first.timestamp) as f64 / 1_000_000.0))
+                    .with_confidence(0.7));
+            }
+        }
+
+        if keystrokes.len() == 0 {
+            log::warn!("Failed to find keystrokes using conventional method.");
+            let keystroke_size = alt_find_keystroke_size(&packet_stream);
+            let keystrokes_2 = scan_for_keystrokes(&ordered, keystroke_size as i32, session.logged_in_at, thresholds);
+            let processed = process_keystrokes(keystrokes);
+            session.keystroke_data = processed;
+        } else {
+            let processed = process_keystrokes(keystrokes);
+            session.keystroke_data = processed;
+        }
+        options.command_db.annotate(&mut session.keystroke_data);
+        if options.runs(Scan::Nested) && !is_obfuscated {
+            let nested = nested::scan_for_nested_sessions(&ordered, &session.keystroke_data, &session.results);
+            session.results.extend(nested);
+        }
+        session.error_rate = utils::error_rate(session.keystroke_data.iter().flat_map(|sequence| &sequence.keystrokes));
+        session.typing = stats::latency_stats(session.keystroke_data.iter().flat_map(|sequence| &sequence.keystrokes), stats::MIN_LATENCY_CONFIDENCE);
+        session.rhythm = rhythm::TypingRhythm::new(&session.keystroke_data);
+        password::estimate_password_lengths(&ordered, &mut session, model.as_ref());
+    }
+
+    if options.runs(Scan::Exfil) {
+        let (volume, findings) = exfil::score_volume(&ordered[logged_in_at..], session.bytes, session.keystroke_size, &session.keystroke_data);
+        session.volume = volume;
+        session.results.extend(findings);
     }
 
-    session
+    // Periods are split last, so that each one summarises its own keystrokes and findings
+    if let Some(gap) = options.idle_split {
+        session.activity = split_activity(&ordered[logged_in_at..], &session.keystroke_data, &session.results, gap, keepalive);
+    }
+    session.tags = tags::infer_tags(&session);
+    Ok(session)
+}
+
+/// Finishes a session whose login never succeeded, keeping its rejected passwords and keys, e.g.
+/// for [spray](super::spray) detection. Nothing follows the login, so
+/// [logged_in_at](SshSession::logged_in_at) is past the last record.
+///
+/// Errors with [LoginNotFound](Error::LoginNotFound) if no rejected attempt was found either.
+fn failed_login(mut session: SshSession, ordered: &[containers::PacketInfo], thresholds: &Thresholds, model: Option<&CipherModel>) -> Result<SshSession, Error> {
+    let attempts = scan_failed_login(ordered, session.prompt_size, session.new_keys_at, thresholds);
+    let rejected = attempts.iter().filter(|finding| matches!(finding.kind, containers::Event::WrongPassword | containers::Event::RejectedKey)).count();
+    if rejected == 0 {
+        return Err(Error::LoginNotFound);
+    }
+    log::warn!("Stream {}: login failed after {rejected} rejected attempts.", session.stream);
+
+    session.logged_in_at = ordered.len();
+    session.results.extend(attempts);
+    password::estimate_password_lengths(ordered, &mut session, model);
+    session.tags = tags::infer_tags(&session);
+    session.tags.push(tags::Tag::FailedLogin);
+    Ok(session)
 }
 
 /// Gets the start and end datetime (UTC) of a packet stream as a tuple of Strings.
@@ -215,6 +598,30 @@ pub fn get_start_and_end(packets: &[Packet]) -> (String, String) {
     (start_datetime.format("%Y-%m-%d %H:%M:%S").to_string(), end_datetime.format("%Y-%m-%d %H:%M:%S").to_string())
 }
 
+/// Gets the largest observed (client, server) segment sizes and the number of SSH records that
+/// were split across multiple TCP segments.
+///
+/// The MSS is not known from the SSH packets alone and is left for the caller to fill in.
+pub fn find_meta_segmentation(packets: &[Packet]) -> containers::Segmentation {
+    let mut segmentation = containers::Segmentation::default();
+
+    for packet in packets {
+        let length = utils::get_segment_length(packet) as u32;
+
+        if is_server_packet(packet) {
+            segmentation.max_segment.1 = segmentation.max_segment.1.max(length);
+        } else {
+            segmentation.max_segment.0 = segmentation.max_segment.0.max(length);
+        }
+
+        if utils::get_segment_count(packet) > 1 {
+            segmentation.split_records += 1;
+        }
+    }
+
+    segmentation
+}
+
 /// Finds keystrokes via an alternative brute-forcy method.
 /// 
 /// When NewKeys+1 cannot be used to find keystroke len, this ought to do the trick.
@@ -236,7 +643,12 @@ pub fn alt_find_keystroke_size(packets: &[Packet]) -> u32 {
                     .map(|meta| meta.value().parse::<u32>())
                     .ok_or("TCP layer or length metadata not found")
                     .and_then(|res| res.map_err(|_| "Parsing TCP length failed")) 
-            }).collect::<Result<Vec<u32>, _>>().unwrap();
+            }).collect::<Result<Vec<u32>, _>>();
+        // Too close to the end of the stream
+        let sizes = match sizes {
+            Ok(sizes) => sizes,
+            Err(_) => break,
+        };
         
         if sizes[0] == sizes[1] && sizes[1] == sizes[2] && sizes[2] == sizes[3] {
             return sizes[0];
@@ -315,9 +727,9 @@ pub fn find_meta_size(packets: &[Packet]) -> Result<[containers::PacketInfo; 3],
             // i+1: Keystroke indicator (length - 8 = keystroke_size)
             // i+4: First login prompt (size indicator)
             let out: [containers::PacketInfo; 3] = [
-                containers::PacketInfo::new(&packet, i, Some("New Keys (21)".to_string())),
-                containers::PacketInfo::new(packets.get(i+1).unwrap(), i+1, Some("Keystroke Size Indicator".to_string())),
-                containers::PacketInfo::new(packets.get(i+4).unwrap(), i+4, Some("First login prompt".to_string())),
+                containers::PacketInfo::new(&packet, i),
+                containers::PacketInfo::new(packets.get(i+1).unwrap(), i+1),
+                containers::PacketInfo::new(packets.get(i+4).unwrap(), i+4),
             ];
 
             return Ok(out);
@@ -340,8 +752,6 @@ pub fn find_meta_hassh(packets: &[Packet]) -> Result<[String; 6], &'static str>
 
     let mut hassh_client_found: bool = false;
     let mut hassh_server_found: bool = false;
-    let mut sport: u32;
-    let mut dport: u32;
 
     // Client to Server (cts) -> hassh
     let mut client_kex: &str = "";
@@ -376,13 +786,9 @@ pub fn find_meta_hassh(packets: &[Packet]) -> Result<[String; 6], &'static str>
             continue;
         }
 
-        let tcp_layer = packet.layer_name("tcp").unwrap();
-
-        // Get Source/Destination port to determine if client (>22) or server (22) 
-        sport = tcp_layer.metadata("tcp.srcport").unwrap().value().parse().unwrap();
-        dport = tcp_layer.metadata("tcp.dstport").unwrap().value().parse().unwrap();
+        let from_server = utils::is_server_packet(packet);
 
-        if (sport > dport) && !hassh_client_found {
+        if !from_server && !hassh_client_found {
             client_kex = ssh_layer.metadata("ssh.kex_algorithms")
                 .ok_or("ssh.kex_algorithms not found")?.value();
             client_enc_algs_cts = ssh_layer.metadata("ssh.encryption_algorithms_client_to_server")
@@ -395,7 +801,7 @@ pub fn find_meta_hassh(packets: &[Packet]) -> Result<[String; 6], &'static str>
             hassh_algorithms = [client_kex, client_enc_algs_cts, client_mac_algs_cts, client_cmp_algs_cts].join(";");
             hassh = Some(utils::get_md5_hash(hassh_algorithms));
             hassh_client_found = true;
-        } else if (dport > sport) && !hassh_server_found {
+        } else if from_server && !hassh_server_found {
             server_kex = ssh_layer.metadata("ssh.kex_algorithms")
                 .ok_or("ssh.kex_algorithms not found")?.value();
             server_enc_algs_stc = ssh_layer.metadata("ssh.encryption_algorithms_server_to_client")
@@ -422,6 +828,27 @@ pub fn find_meta_hassh(packets: &[Packet]) -> Result<[String; 6], &'static str>
     ])
 }
 
+/// Finds the first client and server KEXINIT and reads their full algorithm lists.
+pub fn find_kexinits(packets: &[Packet]) -> (Option<containers::KexInit>, Option<containers::KexInit>) {
+    let mut kexinits = (None, None);
+
+    for packet in packets.iter().take(50) {
+        if kexinits.0.is_some() && kexinits.1.is_some() {
+            break;
+        }
+        if utils::find_metadata(packet, "ssh.message_code") != Some("20") {
+            continue;
+        }
+
+        let kexinit = if utils::is_server_packet(packet) { &mut kexinits.1 } else { &mut kexinits.0 };
+        if kexinit.is_none() {
+            *kexinit = containers::KexInit::from_packet(packet);
+        }
+    }
+
+    kexinits
+}
+
 /// Find the protocols in use by server and client. Protocol means version/type of SSH
 /// client/server, as well as source IP:PORT, destination IP:PORT.
 pub fn find_meta_protocol(packets: &[Packet]) -> Result<[String; 6], &'static str> {
@@ -430,10 +857,7 @@ pub fn find_meta_protocol(packets: &[Packet]) -> Result<[String; 6], &'static st
     let mut protocol_client = None;
     let mut protocol_server = None;
 
-    let mut sport = 0;
-    let mut dport = 0;
-    let mut sip: &str = "";
-    let mut dip: &str = "";
+    let mut endpoints: [String; 4] = Default::default();
 
     for packet in packets.iter().take(50) {
         if protocol_server.is_some() && protocol_client.is_some() {
@@ -448,54 +872,60 @@ pub fn find_meta_protocol(packets: &[Packet]) -> Result<[String; 6], &'static st
             None => continue,
         };
 
-        let ip_layer = packet.layer_name("ip").unwrap();
-        let tcp_layer = packet.layer_name("tcp").unwrap();
-
-        // Get source/dest IP/port
-        sip = ip_layer.metadata("ip.src").ok_or("Source IP not found")?.value();
-        dip = ip_layer.metadata("ip.dst").ok_or("Destination IP not found")?.value();
-        sport = tcp_layer.metadata("tcp.srcport").ok_or("Source port not found")?.value()
-            .parse().map_err(|_| "Parsing source port failed")?;
-        dport = tcp_layer.metadata("tcp.dstport").ok_or("Destination port not found")?.value()
-            .parse().map_err(|_| "Parsing destination port failed")?;
-
-        if sport > dport && protocol_client.is_none() {
+        if !utils::is_server_packet(packet) && protocol_client.is_none() {
             protocol_client = Some(protocol.to_string());
-        } else if dport > sport && protocol_server.is_none() {
+            endpoints = find_meta_endpoints(packet)?;
+        } else if utils::is_server_packet(packet) && protocol_server.is_none() {
             protocol_server = Some(protocol.to_string());
+            endpoints = find_meta_endpoints(packet)?;
         }
     }
 
-    // Under the assumption that the Client Protocol packet always comes before the Server Protocol
-    // packet, the final sip/dip sport/dport will be swapped, since they will be assigned from the
-    // Server packet. Hence, we swap the values.
-    if dport > sport {
-        let tmp_ip = sip;
-        let tmp_port = sport;
-        sip = dip;
-        sport = dport;
-        dip = tmp_ip;
-        dport = tmp_port;
-    }
-
+    let [client_ip, client_port, server_ip, server_port] = endpoints;
     Ok([
         protocol_client.ok_or("Failed to get client protocol")?,
         protocol_server.ok_or("Failed to get server protocol")?,
-        sip.to_string(),
-        sport.to_string(),
-        dip.to_string(),
-        dport.to_string()
+        client_ip,
+        client_port,
+        server_ip,
+        server_port,
     ])
 }
 
-/// Orders collected keystrokes into sequence groups and relativises their timestamps into a
-/// processable format.
+/// Finds the client IP, client port, server IP, and server port of a packet's stream, from its
+/// IPv4 or IPv6 layer and its direction.
+pub fn find_meta_endpoints(packet: &Packet) -> Result<[String; 4], &'static str> {
+    let tcp_layer = packet.layer_name("tcp").ok_or("TCP layer not found")?;
+
+    let (source_ip, destination_ip) = utils::find_ip_addresses(packet).ok_or("Source or destination IP not found")?;
+    let source_port: u32 = tcp_layer.metadata("tcp.srcport").ok_or("Source port not found")?.value()
+        .parse().map_err(|_| "Parsing source port failed")?;
+    let destination_port: u32 = tcp_layer.metadata("tcp.dstport").ok_or("Destination port not found")?.value()
+        .parse().map_err(|_| "Parsing destination port failed")?;
+
+    // Endpoints are kept as (client, server)
+    Ok(match utils::is_server_packet(packet) {
+        true => [destination_ip, destination_port.to_string(), source_ip, source_port.to_string()],
+        false => [source_ip, source_port.to_string(), destination_ip, destination_port.to_string()],
+    })
+}
+
+/// Whether a stream was captured after its KEX: none of its first packets carries a banner or an
+/// unencrypted message.
+pub fn is_mid_session(packets: &[Packet]) -> bool {
+    !packets.iter().take(50).any(|packet| {
+        packet.layer_name("ssh").is_some_and(|ssh| ssh.metadata("ssh.protocol").is_some() || ssh.metadata("ssh.message_code").is_some())
+    })
+}
+
+/// Orders collected keystrokes into sequence groups and computes the latency between them.
 ///
 /// To produce the output, group keystroke sequences together.
 /// A sequence is the first keystroke up to the return, including the returned size.
-pub fn process_keystrokes(keystrokes: Vec<containers::Keystroke>) -> Vec<Vec<containers::Keystroke>> {
+/// Sequences are numbered from 1 and keep the absolute time of their first keystroke.
+pub fn process_keystrokes(keystrokes: Vec<containers::Keystroke>) -> Vec<containers::KeystrokeSequence> {
     log::info!("Grouping keystroke sequences.");
-    let mut out: Vec<Vec<containers::Keystroke>> = Vec::new();
+    let mut out: Vec<containers::KeystrokeSequence> = Vec::new();
     let mut itr = 0;
 
     let mut tmp_vec: Vec<containers::Keystroke> = Vec::new();
@@ -503,13 +933,23 @@ pub fn process_keystrokes(keystrokes: Vec<containers::Keystroke>) -> Vec<Vec<con
 
     while itr < keystrokes.len() {
         curr = &keystrokes[itr];
-        tmp_vec.push(curr.clone());
+        tmp_vec.push(containers::Keystroke { sequence: Some(out.len() + 1), ..curr.clone() });
 
         // Sequences are delimited by Enter (Return), or in edge cases if we reach the last
         // keystroke without encountering a Return.
         if curr.k_type == containers::KeystrokeType::Enter || itr == keystrokes.len()-1 {
-            make_relative(&mut tmp_vec);
-            out.push(tmp_vec.clone());
+            let start = tmp_vec[0].timestamp;
+            set_latencies(&mut tmp_vec);
+            out.push(containers::KeystrokeSequence {
+                id: out.len() + 1,
+                start,
+                start_utc: utils::format_timestamp(start),
+                error_rate: utils::error_rate(&tmp_vec),
+                symbol_heavy: utils::is_symbol_heavy(&tmp_vec),
+                input: utils::classify_input(&tmp_vec),
+                length: utils::command_length(&tmp_vec),
+                keystrokes: tmp_vec.clone(),
+            });
             tmp_vec.clear();
         }
 
@@ -519,17 +959,75 @@ pub fn process_keystrokes(keystrokes: Vec<containers::Keystroke>) -> Vec<Vec<con
     out
 }
 
-/// Transform timestamps into latencies for a given sequence
-fn make_relative(sequence: &mut Vec<containers::Keystroke>) {
+/// Splits records into activity periods wherever nothing but keepalives was sent for at least
+/// `gap` μs.
+///
+/// Each period gets the keystroke sequences that started in it, their typing statistics, and the
+/// findings within it, so that e.g. a tmux session reattached hours later can be read separately
+/// from the previous attachment. Keepalives are
+/// recognised as a record of the request length of `keepalive` ([keepalive_sizes]) directly
+/// answered by one of the reply length, in either direction.
+///
+/// [keepalive_sizes]: CipherModel::keepalive_sizes
+pub fn split_activity(records: &[containers::PacketInfo], sequences: &[containers::KeystrokeSequence], findings: &[containers::Finding], gap: i64, keepalive: (i32, i32)) -> Vec<containers::Activity> {
+    let mut activity: Vec<containers::Activity> = Vec::new();
+
+    let mut keepalives = vec![false; records.len()];
+    for (i, pair) in records.windows(2).enumerate() {
+        if pair[0].length == keepalive.0 && pair[1].length == keepalive.1 && pair[0].direction != pair[1].direction {
+            keepalives[i] = true;
+            keepalives[i + 1] = true;
+        }
+    }
+    let active: Vec<&containers::PacketInfo> = records.iter().zip(&keepalives)
+        .filter(|(_, &keepalive)| !keepalive)
+        .map(|(record, _)| record)
+        .collect();
+
+    let mut previous_end = None;
+    for period in active.chunk_by(|previous, next| next.timestamp - previous.timestamp < gap) {
+        let start = period[0].timestamp;
+        let end = period[period.len() - 1].timestamp;
+        let started: Vec<&containers::KeystrokeSequence> = sequences.iter()
+            .filter(|sequence| sequence.start >= start && sequence.start <= end)
+            .collect();
+
+        activity.push(containers::Activity {
+            id: activity.len() + 1,
+            start,
+            end,
+            start_utc: utils::format_timestamp(start),
+            end_utc: utils::format_timestamp(end),
+            bytes: utils::count_bytes(period.iter().copied()),
+            sequences: started.iter().map(|sequence| sequence.id).collect(),
+            keystrokes: started.iter().map(|sequence| sequence.keystrokes.len()).sum(),
+            idle_before: previous_end.map_or(0, |previous_end| start - previous_end),
+            error_rate: utils::error_rate(started.iter().flat_map(|sequence| &sequence.keystrokes)),
+            typing: stats::latency_stats(started.iter().flat_map(|sequence| &sequence.keystrokes), stats::MIN_LATENCY_CONFIDENCE),
+            findings: findings.iter()
+                .filter(|finding| finding.timestamp >= start && finding.timestamp <= end)
+                .map(|finding| finding.kind)
+                .collect(),
+        });
+        previous_end = Some(end);
+    }
+    log::debug!("{} activity periods", activity.len());
+
+    activity
+}
+
+/// Sets the latency of each keystroke in a given sequence, relative to the previous keystroke.
+///
+/// Absolute timestamps are kept, so the keystrokes can still be placed on a timeline.
+fn set_latencies(sequence: &mut [containers::Keystroke]) {
     let mut prev_time = sequence[0].timestamp;
 
     for keystroke in sequence.iter_mut().skip(1) {
-        let tmp = keystroke.timestamp;
-        keystroke.timestamp = tmp - prev_time;
-        prev_time = tmp;
+        keystroke.latency_us = keystroke.timestamp - prev_time;
+        prev_time = keystroke.timestamp;
     }
 
-    sequence[0].timestamp = 0;
+    sequence[0].latency_us = 0;
 }
 
 // TODO: 
@@ -537,23 +1035,82 @@ fn make_relative(sequence: &mut Vec<containers::Keystroke>) {
 // Kept the monolith, but maybe do away with file-loading (except for a single test) and then just
 // use serialised vector objects with the state? 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use lazy_static::lazy_static;
     use std::collections::HashMap;
     use std::env;
+    use crate::analyser::options::Thresholds;
 
     lazy_static!(
         static ref LSAL_STREAM: HashMap<u32, Vec<Packet>> = {
             let base = env!("CARGO_MANIFEST_DIR");
-            utils::load_file(format!("{base}/test_captures/known_pass_lsal_id_exit.pcapng").to_string(), -1)
+            utils::load_capture(&format!("{base}/test_captures/known_pass_lsal_id_exit.pcapng"), -1).unwrap().0
         };
         static ref ARROW_STREAM: HashMap<u32, Vec<Packet>> = {
             let base = env!("CARGO_MANIFEST_DIR");
-            utils::load_file(format!("{base}/test_captures/lstlpn_to_ss_tlpn_nopass_exit.pcapng").to_string(), -1)
+            utils::load_capture(&format!("{base}/test_captures/lstlpn_to_ss_tlpn_nopass_exit.pcapng"), -1).unwrap().0
         };
     );
 
+    /// A hand-made session for the golden tests of the output formats: a failed password, a
+    /// forwarding with details that need escaping in every format, and one corrected command.
+    pub(crate) fn session() -> SshSession {
+        use containers::{Event, Finding, InputSource, KeystrokeType, PacketRef};
+
+        const START: i64 = 1_706_702_400_000_000;
+        let keystroke = |k_type: KeystrokeType, seq, frame, offset, latency_us, response_size| containers::Keystroke {
+            confidence: k_type.confidence(),
+            k_type,
+            timestamp: START + offset,
+            timestamp_utc: utils::format_timestamp(START + offset),
+            latency_us,
+            response_size,
+            seq,
+            index: None,
+            frame: Some(frame),
+            sequence: Some(1),
+            guesses: vec![],
+        };
+
+        SshSession {
+            protocols: ("SSH-2.0-OpenSSH_9.6".to_string(), "SSH-2.0-OpenSSH_8.9p1 Ubuntu-3ubuntu0.6".to_string()),
+            src: "192.168.1.10:51514".to_string(),
+            dst: "10.0.0.1:22".to_string(),
+            hassh_c: "ec7378c1a92f5a8dde7e8b7a1ddf33d1".to_string(),
+            hassh_s: "b12d2871a1189eff20364cf5333619ee".to_string(),
+            algorithms: ("curve25519-sha256".to_string(), "chacha20-poly1305@openssh.com".to_string(), "hmac-sha2-256".to_string(), "none".to_string()),
+            start_utc: "2024-01-31 12:00:00".to_string(),
+            end_utc: "2024-01-31 12:00:10".to_string(),
+            results: vec![
+                Finding { kind: Event::WrongPassword, packet_ref: PacketRef { index: 14, seq: 1342, frame: Some(21) }, timestamp: START + 2_500_000, confidence: 0.5, details: None },
+                Finding {
+                    kind: Event::PortForwarding,
+                    packet_ref: PacketRef { index: 40, seq: 3718, frame: Some(66) },
+                    timestamp: START + 9_000_000,
+                    confidence: 0.75,
+                    details: Some("to \"db|main\"=5432\tvia C:\\tmp <x> & y\nnext".to_string()),
+                },
+            ],
+            keystroke_data: vec![containers::KeystrokeSequence {
+                id: 1,
+                start: START + 4_000_000,
+                start_utc: utils::format_timestamp(START + 4_000_000),
+                error_rate: 0.25,
+                symbol_heavy: false,
+                input: InputSource::Human,
+                length: containers::CommandLength { estimate: 0, min: 0, max: Some(0), edit_distance: 1 },
+                keystrokes: vec![
+                    keystroke(KeystrokeType::Keystroke, 2000, 30, 4_000_000, 0, None),
+                    keystroke(KeystrokeType::Delete, 2036, 32, 4_200_000, 200_000, None),
+                    keystroke(KeystrokeType::Enter, 2072, 34, 4_500_000, 300_000, Some(512)),
+                ],
+            }],
+            tags: vec![tags::Tag::Interactive, tags::Tag::Tunneled],
+            ..SshSession::new(3)
+        }
+    }
+
     #[test]
     fn test_meta_sizes() {
         let meta_size = find_meta_size(&LSAL_STREAM.get(&0).unwrap()).unwrap();
@@ -566,7 +1123,8 @@ mod tests {
         // keystroke size 
         assert_eq!(36, keysize.length-8);
         // Prompt size
-        assert_eq!(-52, prompt.length);
+        assert_eq!(52, prompt.length);
+        assert!(prompt.is_server());
     }
 
     #[test]
@@ -598,7 +1156,7 @@ mod tests {
         // Ordered packets are as many as before sorting
         let mut size_matrix = utils::create_size_matrix(&LSAL_STREAM.get(&0).unwrap());
         let original_size = size_matrix.len();
-        let ordered = utils::order_keystrokes(&mut size_matrix, 36);
+        let ordered = utils::order_keystrokes(&mut size_matrix, 36, &Thresholds::default());
         assert_eq!(original_size, ordered.len());
     }
 
@@ -606,10 +1164,10 @@ mod tests {
     fn test_reverse_r() {
         // Needs ordered packets
         let mut size_matrix = utils::create_size_matrix(&LSAL_STREAM.get(&0).unwrap());
-        let ordered = utils::order_keystrokes(&mut size_matrix, 36);
+        let ordered = utils::order_keystrokes(&mut size_matrix, 36, &Thresholds::default());
 
         // No -R was used
-        let reverse_r = scan_for_reverse_session_r_option(&ordered, -52);
+        let reverse_r = scan_for_reverse_session_r_option(&ordered, 52);
         assert!(reverse_r.is_none());
     }
 
@@ -617,10 +1175,10 @@ mod tests {
     fn test_login() {
         // Needs ordered packets
         let mut size_matrix = utils::create_size_matrix(&LSAL_STREAM.get(&0).unwrap());
-        let ordered = utils::order_keystrokes(&mut size_matrix, 36);
+        let ordered = utils::order_keystrokes(&mut size_matrix, 36, &Thresholds::default());
 
         // One login attempt- login successful
-        let login_index = find_successful_login(&ordered);
+        let login_index = find_successful_login(&ordered, &cipher_model::LOGIN_SUCCESS_SIZES);
         assert!(login_index.is_some());
 
         // Server login prompt preceding successful login
@@ -632,10 +1190,10 @@ mod tests {
     fn test_keystrokes() {
         // Needs ordered packets
         let mut size_matrix = utils::create_size_matrix(&LSAL_STREAM.get(&0).unwrap());
-        let ordered = utils::order_keystrokes(&mut size_matrix, 36);
+        let ordered = utils::order_keystrokes(&mut size_matrix, 36, &Thresholds::default());
 
         // TODO: better keystroke checking (check for type?)
-        let keystrokes = scan_for_keystrokes(&ordered, 36, 20);
+        let keystrokes = scan_for_keystrokes(&ordered, 36, 20, &Thresholds::default());
         assert_eq!(15, keystrokes.len());
     }
 
@@ -643,9 +1201,9 @@ mod tests {
     fn test_arrows() {
         // Needs ordered packets
         let mut size_matrix = utils::create_size_matrix(&ARROW_STREAM.get(&0).unwrap());
-        let ordered = utils::order_keystrokes(&mut size_matrix, 36);
+        let ordered = utils::order_keystrokes(&mut size_matrix, 36, &Thresholds::default());
 
-        let keystrokes = scan_for_keystrokes(&ordered, 36, 20);
+        let keystrokes = scan_for_keystrokes(&ordered, 36, 20, &Thresholds::default());
         let mut typevec: Vec<containers::KeystrokeType> = Vec::new();
         for keystroke in &keystrokes {
             typevec.push(keystroke.k_type.clone());
@@ -683,11 +1241,173 @@ mod tests {
     fn test_key_login() {
         // Needs ordered packets
         let mut size_matrix = utils::create_size_matrix(&LSAL_STREAM.get(&0).unwrap());
-        let ordered = utils::order_keystrokes(&mut size_matrix, 36);
+        let ordered = utils::order_keystrokes(&mut size_matrix, 36, &Thresholds::default());
 
         // No key was used
-        let key_log = scan_login_data(&ordered, -52, 7, 17);
-        let events: Vec<String> = vec![key_log[0].description.clone().unwrap(), key_log[1].description.clone().unwrap(), key_log[2].description.clone().unwrap(), key_log[3].description.clone().unwrap(), key_log[4].description.clone().unwrap()];
-        assert_eq!(events, vec![containers::Event::OfferRSAKey.to_string(), containers::Event::AcceptedKey.to_string(), containers::Event::OfferED25519Key.to_string(), containers::Event::RejectedKey.to_string(), containers::Event::CorrectPassword.to_string()]);
+        let key_log = scan_login_data(&ordered, 52, 7, 17, &Thresholds::default());
+        let events: Vec<containers::Event> = key_log.iter().take(5).map(|finding| finding.kind).collect();
+        assert_eq!(events, vec![containers::Event::OfferRSAKey, containers::Event::AcceptedKey, containers::Event::OfferED25519Key, containers::Event::RejectedKey, containers::Event::CorrectPassword]);
+    }
+
+    #[test]
+    fn test_session_is_owned() {
+        fn assert_owned<T: Send + Sync + 'static>() {}
+        assert_owned::<SshSession>();
+        assert_owned::<containers::PacketInfo>();
+    }
+
+    #[test]
+    fn test_results_chronological() {
+        use crate::analyser::options::{Decoding, Engine};
+        use crate::analyser::filter::StreamFilter;
+
+        let capture = format!("{}/test_captures/known_pass_lsal_id_exit.pcapng", env!("CARGO_MANIFEST_DIR"));
+        let (streams, tcp) = utils::load_capture_reporting(&capture, -1, None, &StreamFilter::default(), Engine::Native, &Decoding::default(), |_, _| {}).unwrap();
+        let session = analyse(0, &streams[&0], &tcp[&0], &AnalysisOptions::default()).unwrap();
+
+        assert!(session.results.len() > 1);
+        let order: Vec<(i64, i64)> = session.results.iter().map(|finding| (finding.timestamp, finding.packet_ref.seq)).collect();
+        let mut sorted = order.clone();
+        sorted.sort();
+        assert_eq!(sorted, order);
     }
+
+    #[test]
+    fn test_ipv6_session() {
+        use crate::analyser::options::{Decoding, Engine};
+        use crate::analyser::filter::StreamFilter;
+
+        // known_pass_lsal_id_exit, moved to 2001:db8::/96
+        let capture = format!("{}/test_captures/ipv6_known_pass_lsal_id_exit.pcap", env!("CARGO_MANIFEST_DIR"));
+        let (streams, tcp) = utils::load_capture_reporting(&capture, -1, None, &StreamFilter::default(), Engine::Native, &Decoding::default(), |_, _| {}).unwrap();
+        let session = analyse(0, &streams[&0], &tcp[&0], &AnalysisOptions::default()).unwrap();
+        assert_eq!("[2001:db8::c0a8:d4]:50502", session.src);
+        assert_eq!("[2001:db8::c0a8:2d]:22", session.dst);
+        assert_eq!(36, session.keystroke_size);
+
+        assert_eq!(("2001:db8::c0a8:d4", Some("50502")), utils::split_endpoint(&session.src));
+        assert_eq!("2001:db8::c0a8:2d", utils::ip_of(&session.dst));
+        assert_eq!(("10.0.0.1", Some("22")), utils::split_endpoint("10.0.0.1:22"));
+        assert_eq!(("fe80::1", None), utils::split_endpoint("fe80::1"));
+    }
+
+    #[test]
+    fn test_encapsulated_session() {
+        use crate::analyser::options::{Decoding, Engine};
+        use crate::analyser::filter::StreamFilter;
+        use crate::analyser::tunnel::Tunnel;
+
+        // known_pass_lsal_id_exit, mirrored over ERSPAN type II (session 7) from VLAN 100
+        let capture = format!("{}/test_captures/erspan_known_pass_lsal_id_exit.pcap", env!("CARGO_MANIFEST_DIR"));
+        let (streams, tcp) = utils::load_capture_reporting(&capture, -1, None, &StreamFilter::default(), Engine::Native, &Decoding::default(), |_, _| {}).unwrap();
+        let session = analyse(0, &streams[&0], &tcp[&0], &AnalysisOptions::default()).unwrap();
+        assert_eq!("192.168.0.212:50502", session.src);
+        assert_eq!("192.168.0.45:22", session.dst);
+        assert_eq!(36, session.keystroke_size);
+
+        let tunnel = session.tunnel.unwrap();
+        assert_eq!(Tunnel {
+            encapsulations: vec!["vlan".to_string(), "gre".to_string(), "erspan".to_string()],
+            outer: Some(("10.1.1.1".to_string(), "10.1.1.2".to_string())),
+            vlan_ids: vec![100],
+            gre_key: None,
+            erspan_id: Some(7),
+            vxlan_vni: None,
+        }, tunnel);
+        assert_eq!("VLAN 100 / GRE / ERSPAN 7 via 10.1.1.1 -> 10.1.1.2", tunnel.to_string());
+    }
+
+    #[test]
+    fn test_mid_session() {
+        use crate::analyser::options::{Decoding, Engine};
+        use crate::analyser::filter::StreamFilter;
+
+        // known_pass_lsal_id_exit without its first 50 frames, from just after the login
+        let capture = format!("{}/test_captures/midsession_known_pass_lsal_id_exit.pcap", env!("CARGO_MANIFEST_DIR"));
+        let (streams, tcp) = utils::load_capture_reporting(&capture, -1, None, &StreamFilter::default(), Engine::Native, &Decoding::default(), |_, _| {}).unwrap();
+        assert!(is_mid_session(&streams[&0]));
+        let session = analyse(0, &streams[&0], &tcp[&0], &AnalysisOptions::default()).unwrap();
+
+        assert!(session.partial);
+        assert!(session.tags.contains(&tags::Tag::PartialCapture));
+        assert_eq!("192.168.0.212:50502", session.src);
+        assert_eq!("192.168.0.45:22", session.dst);
+        assert_eq!(36, session.keystroke_size);
+        assert_eq!(0, session.logged_in_at);
+        assert!(session.results.iter().all(|finding| !matches!(finding.kind, containers::Event::NewKeys | containers::Event::CorrectPassword)));
+        assert!(!session.keystroke_data.is_empty());
+
+        let full = format!("{}/test_captures/known_pass_lsal_id_exit.pcapng", env!("CARGO_MANIFEST_DIR"));
+        let (streams, _) = utils::load_capture_reporting(&full, -1, None, &StreamFilter::default(), Engine::Native, &Decoding::default(), |_, _| {}).unwrap();
+        assert!(!is_mid_session(&streams[&0]));
+    }
+
+    #[test]
+    fn test_idle_segmentation() {
+        use containers::Direction::*;
+        let second = 1_000_000;
+        let keepalive = CipherModel::reference().keepalive_sizes();
+        assert_eq!((52, 28), keepalive);
+
+        // Three keystrokes, ServerAliveInterval keepalives every 15s for two minutes, two more
+        // keystrokes, and a server-side keepalive just after them
+        let mut records: Vec<(containers::Direction, i32, i64)> = vec![];
+        for time in [0, second, 2 * second] {
+            records.extend([(ClientToServer, 36, time), (ServerToClient, 36, time + 1000)]);
+        }
+        for time in (1..=8).map(|n| 2 * second + n * 15 * second) {
+            records.extend([(ClientToServer, keepalive.0, time), (ServerToClient, keepalive.1, time + 1000)]);
+        }
+        for time in [125 * second, 126 * second] {
+            records.extend([(ClientToServer, 36, time), (ServerToClient, 36, time + 1000)]);
+        }
+        records.extend([(ServerToClient, keepalive.0, 127 * second), (ClientToServer, keepalive.1, 127 * second + 1000)]);
+
+        let ordered = containers::tests::timed_records(&records);
+        let sequences = process_keystrokes(scan_for_keystrokes(&ordered, 36, 0, &Thresholds::default()));
+
+        let findings = [containers::Finding::new(containers::Event::InputBurst, &ordered[ordered.len() - 3])];
+
+        let activity = split_activity(&ordered, &sequences, &findings, 60 * second, keepalive);
+        assert_eq!(2, activity.len());
+        assert_eq!((0, 2 * second + 1000), (activity[0].start, activity[0].end));
+        assert_eq!(0, activity[0].idle_before);
+        assert_eq!((125 * second, 126 * second + 1000), (activity[1].start, activity[1].end));
+        assert_eq!(125 * second - (2 * second + 1000), activity[1].idle_before);
+        assert_eq!((72, 72), activity[1].bytes);
+        assert_eq!(sequences.iter().map(|sequence| sequence.keystrokes.len()).sum::<usize>(), activity[0].keystrokes + activity[1].keystrokes);
+        assert!(activity[0].typing.samples > 0);
+        assert!(activity[0].findings.is_empty());
+        assert_eq!(vec![containers::Event::InputBurst], activity[1].findings);
+
+        // Counted as activity, the keepalives would bridge the gap
+        assert_eq!(1, split_activity(&ordered, &sequences, &findings, 60 * second, (0, 0)).len());
+    }
+
+    #[test]
+    fn test_verbose_kex() {
+        use crate::analyser::options::{Decoding, Engine};
+        use crate::analyser::filter::StreamFilter;
+
+        let capture = format!("{}/test_captures/known_pass_lsal_id_exit.pcapng", env!("CARGO_MANIFEST_DIR"));
+        let (streams, tcp) = utils::load_capture_reporting(&capture, -1, None, &StreamFilter::default(), Engine::Native, &Decoding::default(), |_, _| {}).unwrap();
+        let session = analyse(0, &streams[&0], &tcp[&0], &AnalysisOptions::default()).unwrap();
+        assert_eq!((None, None), session.kexinit);
+
+        let options = AnalysisOptions::builder().verbose_kex().build();
+        let session = analyse(0, &streams[&0], &tcp[&0], &options).unwrap();
+        let (client, server) = match &session.kexinit {
+            (Some(client), Some(server)) => (client, server),
+            _ => panic!("KEXINIT lists not kept"),
+        };
+        assert_eq!(vec!["rsa-sha2-512", "rsa-sha2-256", "ssh-rsa", "ecdsa-sha2-nistp256", "ssh-ed25519"], server.host_key);
+        assert!(client.kex.contains(&session.algorithms.0) && server.kex.contains(&session.algorithms.0));
+        assert_eq!(client.encryption.0, client.encryption.1);
+
+        // The lists are complete: HASSH is computed from them
+        let hassh = |lists: [&Vec<String>; 4]| utils::get_md5_hash(lists.map(|list| list.join(",")).join(";"));
+        assert_eq!(session.hassh_c, hassh([&client.kex, &client.encryption.0, &client.mac.0, &client.compression.0]));
+        assert_eq!(session.hassh_s, hassh([&server.kex, &server.encryption.1, &server.mac.1, &server.compression.1]));
+    }
+
 }
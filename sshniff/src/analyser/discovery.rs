@@ -0,0 +1,79 @@
+//! Quick enumeration of the SSH streams in a capture, without analysing them.
+//!
+//! One pass over the SSH packets collects each stream's endpoints, packet count, start time, and
+//! protocol banners, which is enough to pick the stream to analyse with `-n`. Packets are counted
+//! and dropped rather than collected per stream, so large captures list in the time tshark takes
+//! to dissect them.
+use crate::error::Error;
+use super::compression::CaptureFile;
+use super::source::{CaptureSource, TsharkSource};
+use super::options::Decoding;
+use super::utils;
+use super::packet::Packet;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// An SSH stream as seen in [list_streams].
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct StreamSummary {
+    pub stream: u32,
+    /// Client IP and port, e.g. `10.0.0.1:51234`
+    pub client: String,
+    /// Server IP and port
+    pub server: String,
+    /// SSH packets in both directions
+    pub packets: usize,
+    /// UNIX timestamp (μs) of the first SSH packet
+    pub start: i64,
+    pub start_utc: String,
+    /// (client, server) protocol banners, e.g. `SSH-2.0-OpenSSH_9.6`; `None` if not captured
+    pub protocols: (Option<String>, Option<String>),
+}
+
+/// Lists the SSH streams of a capture, ordered by stream ID.
+///
+/// Errors only if the capture cannot be decompressed or tshark cannot be run; packets tshark fails
+/// to parse end the listing early.
+pub fn list_streams(filepath: &str, decoding: &Decoding) -> Result<Vec<StreamSummary>, Error> {
+    log::info!("Listing streams.");
+
+    let capture = CaptureFile::open(filepath).map_err(Error::Capture)?;
+    let mut source: Box<dyn CaptureSource> = Box::new(TsharkSource::spawn(capture.path(), "ssh", decoding).map_err(|err| Error::Capture(err.to_string()))?);
+
+    let mut streams = BTreeMap::new();
+    let mut servers = utils::ServerMap::new();
+    while let Some(mut packet) = source.next_packet() {
+        if let Some(stream) = utils::find_metadata(&packet, "tcp.stream").and_then(|stream| stream.parse::<u32>().ok()) {
+            utils::mark_direction(&mut servers, stream, &mut packet);
+        }
+        add_packet(&mut streams, &packet);
+    }
+    // Packets tshark fails to parse end the listing early, as documented
+    let _ = source.finish();
+
+    Ok(streams.into_values().collect())
+}
+
+/// Adds a single SSH packet to the summary of its stream.
+fn add_packet(streams: &mut BTreeMap<u32, StreamSummary>, packet: &Packet) {
+    let stream = match utils::find_metadata(packet, "tcp.stream").and_then(|stream| stream.parse::<u32>().ok()) {
+        Some(stream) => stream,
+        None => return,
+    };
+
+    let summary = streams.entry(stream).or_insert_with(|| {
+        let (source_ip, destination_ip) = utils::find_ip_addresses(packet).unwrap_or_default();
+        let port = |field| utils::find_metadata(packet, field).unwrap_or_default();
+        let (source, destination) = (utils::format_endpoint(&source_ip, port("tcp.srcport")), utils::format_endpoint(&destination_ip, port("tcp.dstport")));
+        let start = packet.timestamp_micros().unwrap_or_default();
+        let (client, server) = if utils::is_server_packet(packet) { (destination, source) } else { (source, destination) };
+
+        StreamSummary { stream, client, server, start, start_utc: utils::format_timestamp(start), ..Default::default() }
+    });
+    summary.packets += 1;
+
+    if let Some(protocol) = packet.layer_name("ssh").and_then(|ssh| ssh.metadata("ssh.protocol")) {
+        let banner = if utils::is_server_packet(packet) { &mut summary.protocols.1 } else { &mut summary.protocols.0 };
+        banner.get_or_insert_with(|| protocol.value().to_string());
+    }
+}
@@ -0,0 +1,148 @@
+//! Exfiltration scoring from the volume each direction carries beyond interactive use.
+//!
+//! Typing accounts for little traffic: a record per keystroke, its echo, and the output of each
+//! command. Whatever a direction carries after login beyond that (and a baseline for banners,
+//! prompts, and window adjusts) is unexplained; the unexplained share of the direction's bytes is
+//! its score. Command output only counts up to [EXFIL_COMMAND_ALLOWANCE], so `cat`-ing a large
+//! file through the shell scores like a download. Sessions without keystrokes explain nothing
+//! beyond the baseline, so SCP/SFTP transfers score high as well; see
+//! [transfers](super::transfers) to tell them apart.
+use super::containers::{Direction, Event, Finding, KeystrokeSequence, PacketInfo};
+use super::transfers::TransferDirection;
+use super::utils;
+use serde::Serialize;
+
+/// Bytes per direction explained without any typing: banners, prompts, MOTD, window adjusts.
+pub const EXFIL_BASELINE_BYTES: u64 = 64 << 10;
+
+/// Most bytes of a single command's output that count as interactive use.
+pub const EXFIL_COMMAND_ALLOWANCE: u64 = 16 << 10;
+
+/// Fewest unexplained bytes for a direction to be flagged.
+pub const EXFIL_MIN_BYTES: u64 = 1 << 20;
+
+/// Lowest score for a direction to be flagged.
+pub const EXFIL_MIN_SCORE: f64 = 0.8;
+
+/// Share of a direction's bytes left out at either end of its bulk window.
+pub const EXFIL_WINDOW_TRIM: f64 = 0.05;
+
+/// Volume one direction carried after login, and how much of it interactive use explains.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct VolumeScore {
+    /// Bytes (SSH record lengths) sent after login
+    pub bytes: u64,
+    /// Bytes accounted for by typing and command output
+    pub explained: u64,
+    /// Share of `bytes` that is unexplained, from 0 to 1
+    pub score: f64,
+    /// UNIX timestamp (μs) of the start of the window holding the bulk (90%) of the bytes
+    pub start: i64,
+    /// UNIX timestamp (μs) of its end
+    pub end: i64,
+    pub start_utc: String,
+    pub end_utc: String,
+}
+
+/// Scores the (client, server) directions of a session and adds an
+/// [Exfiltration](Event::Exfiltration) finding for each flagged one.
+///
+/// `packet_infos` are the records after login, `bytes` the session's totals, which may include
+/// sampled records past them.
+pub fn score_volume(packet_infos: &[PacketInfo], bytes: (u64, u64), keystroke_size: u32, sequences: &[KeystrokeSequence]) -> ((VolumeScore, VolumeScore), Vec<Finding>) {
+    log::info!("Scoring traffic volume.");
+
+    let keystrokes = sequences.iter().map(|sequence| sequence.keystrokes.len() as u64).sum::<u64>();
+    let output: u64 = sequences.iter()
+        .flat_map(|sequence| &sequence.keystrokes)
+        .filter_map(|keystroke| keystroke.response_size)
+        .map(|size| (size as u64).min(EXFIL_COMMAND_ALLOWANCE))
+        .sum();
+    let typed = keystrokes * keystroke_size as u64;
+
+    let client = score(packet_infos, Direction::ClientToServer, bytes.0, EXFIL_BASELINE_BYTES + typed);
+    let server = score(packet_infos, Direction::ServerToClient, bytes.1, EXFIL_BASELINE_BYTES + typed + output);
+
+    let mut findings = Vec::new();
+    for (volume, direction, transfer) in [(&client, Direction::ClientToServer, TransferDirection::Upload), (&server, Direction::ServerToClient, TransferDirection::Download)] {
+        if volume.score < EXFIL_MIN_SCORE || volume.bytes - volume.explained < EXFIL_MIN_BYTES {
+            continue;
+        }
+        let first = match packet_infos.iter().find(|packet_info| packet_info.direction == direction && packet_info.timestamp >= volume.start) {
+            Some(first) => first,
+            None => continue,
+        };
+        log::debug!("{transfer:?} of {} bytes at {}, score {:.2}", volume.bytes, first.seq, volume.score);
+        findings.push(Finding::new(Event::Exfiltration, first)
+            .with_details(format!("{transfer:?} of {:.1} MiB, {:.0}% unexplained, {} - {}", volume.bytes as f64 / (1 << 20) as f64, volume.score * 100.0, volume.start_utc, volume.end_utc))
+            .with_confidence(volume.score as f32));
+    }
+
+    ((client, server), findings)
+}
+
+fn score(packet_infos: &[PacketInfo], direction: Direction, bytes: u64, explained: u64) -> VolumeScore {
+    let explained = explained.min(bytes);
+    let (start, end) = bulk_window(packet_infos, direction);
+
+    VolumeScore {
+        bytes,
+        explained,
+        score: if bytes == 0 { 0.0 } else { (bytes - explained) as f64 / bytes as f64 },
+        start,
+        end,
+        start_utc: utils::format_timestamp(start),
+        end_utc: utils::format_timestamp(end),
+    }
+}
+
+/// Window from the record that completes the first [EXFIL_WINDOW_TRIM] of a direction's bytes to
+/// the one that completes all but the last; (0, 0) without records.
+fn bulk_window(packet_infos: &[PacketInfo], direction: Direction) -> (i64, i64) {
+    let records: Vec<&PacketInfo> = packet_infos.iter().filter(|packet_info| packet_info.direction == direction).collect();
+    let total: u64 = records.iter().map(|packet_info| packet_info.length as u64).sum();
+    let (low, high) = (total as f64 * EXFIL_WINDOW_TRIM, total as f64 * (1.0 - EXFIL_WINDOW_TRIM));
+
+    let mut cumulative = 0;
+    let mut window: Option<(i64, i64)> = None;
+    for packet_info in records {
+        cumulative += packet_info.length as u64;
+        if cumulative as f64 >= low {
+            let start = window.map_or(packet_info.timestamp, |(start, _)| start);
+            window = Some((start, packet_info.timestamp));
+        }
+        if cumulative as f64 >= high {
+            break;
+        }
+    }
+
+    window.unwrap_or((0, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::containers;
+    use crate::analyser::containers::tests::timed_records;
+
+    #[test]
+    fn test_volume_score() {
+        use containers::Direction::*;
+        // 30s of typing, then 8 MiB downloaded in 2 KiB records over 8s
+        let mut records: Vec<(containers::Direction, i32, i64)> = (0..100).flat_map(|index| [(ClientToServer, 36, index * 300_000), (ServerToClient, 36, index * 300_000 + 1000)]).collect();
+        records.extend((0..4096).map(|index| (ServerToClient, 2048, 30_000_000 + index * 2000)));
+        let ordered = timed_records(&records);
+
+        let bytes = utils::count_bytes(&ordered);
+        let ((client, server), findings) = score_volume(&ordered, bytes, 36, &[]);
+        assert_eq!(0.0, client.score);
+        assert!(server.score > 0.99);
+        assert!(server.start >= 30_000_000 && server.end <= 38_200_000);
+        assert_eq!(1, findings.len());
+        assert!(findings[0].details.as_deref().is_some_and(|details| details.starts_with("Download of 8.0 MiB")));
+
+        // Typing alone is explained
+        let (_, findings) = score_volume(&ordered[..200], utils::count_bytes(&ordered[..200]), 36, &[]);
+        assert!(findings.is_empty());
+    }
+}
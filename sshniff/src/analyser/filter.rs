@@ -0,0 +1,117 @@
+//! Endpoint and time-range filters applied while loading a capture.
+//!
+//! Narrowing a multi-GB capture to the hosts and window of interest used to take a tshark
+//! pre-pass. With a [StreamFilter], every stream is decided on its first packet instead, and the
+//! packets of streams that do not match are dropped as they are read rather than kept until the
+//! analysis. Endpoints are matched by role, i.e. `src` is the client and `dst` the server, so a
+//! filter selects whole sessions.
+use super::intel;
+use super::utils;
+use super::packet::Packet;
+use std::net::IpAddr;
+
+/// Which streams to load. The default lets every stream through.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StreamFilter {
+    /// Client networks; empty for any
+    pub src: Vec<(IpAddr, u8)>,
+    /// Server networks; empty for any
+    pub dst: Vec<(IpAddr, u8)>,
+    /// Ports either endpoint has to use; empty for any
+    pub ports: Vec<u16>,
+    /// Earliest start of a session, as UNIX timestamp (μs)
+    pub from: Option<i64>,
+    /// Latest start of a session, as UNIX timestamp (μs)
+    pub to: Option<i64>,
+}
+
+impl StreamFilter {
+    /// Whether the filter lets every stream through.
+    pub fn is_empty(&self) -> bool {
+        *self == StreamFilter::default()
+    }
+
+    /// Whether the stream that `packet` is the first packet of should be loaded.
+    pub fn matches(&self, packet: &Packet) -> bool {
+        let (source, destination) = match utils::find_ip_addresses(packet) {
+            Some((source, destination)) => (source.parse::<IpAddr>().ok(), destination.parse::<IpAddr>().ok()),
+            None => (None, None),
+        };
+        let ports = (utils::find_metadata(packet, "tcp.srcport"), utils::find_metadata(packet, "tcp.dstport"));
+        let ((client, server), (client_port, server_port)) = if utils::is_server_packet(packet) {
+            ((destination, source), (ports.1, ports.0))
+        } else {
+            ((source, destination), ports)
+        };
+
+        let in_any = |networks: &[(IpAddr, u8)], ip: Option<IpAddr>| {
+            networks.is_empty() || ip.is_some_and(|ip| networks.iter().any(|&(network, prefix)| intel::in_network(ip, network, prefix)))
+        };
+        let uses_port = self.ports.is_empty() || [client_port, server_port].iter()
+            .any(|port| port.and_then(|port| port.parse::<u16>().ok()).is_some_and(|port| self.ports.contains(&port)));
+        let start = packet.timestamp_micros().unwrap_or_default();
+
+        in_any(&self.src, client) && in_any(&self.dst, server) && uses_port
+            && self.from.is_none_or(|from| start >= from)
+            && self.to.is_none_or(|to| start <= to)
+    }
+}
+
+/// Parses an IP address or CIDR range for `--src`/`--dst`; a plain address matches only itself.
+pub fn parse_network(value: &str) -> Result<(IpAddr, u8), String> {
+    if value.contains('/') {
+        return intel::parse_cidr(value);
+    }
+    match value.parse::<IpAddr>() {
+        Ok(ip) => Ok((ip, if ip.is_ipv4() { 32 } else { 128 })),
+        Err(_) => Err(format!("'{value}' is neither an IP address nor a CIDR range")),
+    }
+}
+
+/// Parses a point in time for `--from`/`--to` into a UNIX timestamp (μs): UTC date and time as
+/// `2024-01-31 12:00:00` (or with a `T`), a date alone, RFC 3339, or UNIX seconds.
+pub fn parse_time(value: &str) -> Result<i64, String> {
+    if let Ok(seconds) = value.parse::<i64>() {
+        return Ok(seconds * 1_000_000);
+    }
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(time.timestamp_micros());
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(time) = chrono::NaiveDateTime::parse_from_str(value, format) {
+            return Ok(time.and_utc().timestamp_micros());
+        }
+    }
+    match chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => Ok(date.and_hms_opt(0, 0, 0).expect("midnight exists").and_utc().timestamp_micros()),
+        Err(_) => Err(format!("'{value}' is not a date and time (e.g. 2024-01-31 12:00:00) or UNIX timestamp")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_filter() {
+        use std::net::IpAddr;
+
+        let host: IpAddr = "10.0.0.5".parse().unwrap();
+        assert_eq!(Ok((host, 32)), parse_network("10.0.0.5"));
+        assert_eq!(Ok(("10.0.0.0".parse().unwrap(), 8)), parse_network("10.0.0.0/8"));
+        assert_eq!(128, parse_network("fe80::1").unwrap().1);
+        assert!(parse_network("10.0.0.0/33").is_err());
+        assert!(parse_network("example.org").is_err());
+
+        let start = 1_706_702_400_000_000;
+        assert_eq!(Ok(start), parse_time("2024-01-31 12:00:00"));
+        assert_eq!(Ok(start), parse_time("2024-01-31T12:00:00"));
+        assert_eq!(Ok(start), parse_time("2024-01-31T13:00:00+01:00"));
+        assert_eq!(Ok(start), parse_time("1706702400"));
+        assert_eq!(Ok(start - 12 * 3600 * 1_000_000), parse_time("2024-01-31"));
+        assert!(parse_time("yesterday").is_err());
+
+        assert!(StreamFilter::default().is_empty());
+        assert!(!StreamFilter { ports: vec![22], ..Default::default() }.is_empty());
+    }
+}
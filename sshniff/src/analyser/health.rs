@@ -0,0 +1,290 @@
+//! Pre-flight checks of a capture, run before committing to a full analysis.
+//!
+//! A capture can be readable and still be useless for SSHniff: truncated by a small snaplen,
+//! captured on one side of an asymmetric route, or lossy. These checks make one quick pass over
+//! the TCP packets and report what would get in the way.
+use crate::error::Error;
+use super::compression::{self, CaptureFile};
+use super::source::{self, CaptureSource, SourceError, TsharkSource};
+use super::options::{Decoding, Engine};
+use super::utils;
+use super::packet::Packet;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Share of lost segments from which a capture is considered lossy.
+pub const LOSS_WARN_RATIO: f64 = 0.01;
+
+/// Outcome of a single check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Status {
+    Pass,
+    /// Analysis runs, but results may be incomplete
+    Warn,
+    /// Analysis will fail or produce nothing
+    Fail,
+}
+
+/// A single check and its outcome.
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub details: String,
+}
+
+/// Counters collected in the pass over the capture.
+#[derive(Debug, Default, Serialize)]
+pub struct CaptureStats {
+    /// TCP packets
+    pub packets: usize,
+    /// Packets tshark dissected as SSH
+    pub ssh_packets: usize,
+    /// Number of (client, server) SSH packets per stream
+    pub streams: BTreeMap<u32, (usize, usize)>,
+    /// Packets timestamped earlier than their predecessor
+    pub backwards_timestamps: usize,
+    /// Segments tshark flagged as lost (tcp.analysis.lost_segment)
+    pub lost_segments: usize,
+    /// Retransmitted segments
+    pub retransmissions: usize,
+    /// Packets captured shorter than their length on the wire (frame.cap_len < frame.len)
+    pub truncated: usize,
+}
+
+/// Result of [check_capture].
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    pub stats: CaptureStats,
+    pub checks: Vec<Check>,
+}
+
+impl HealthReport {
+    /// Whether no check failed.
+    pub fn is_usable(&self) -> bool {
+        self.checks.iter().all(|check| check.status != Status::Fail)
+    }
+}
+
+/// Reads all TCP packets of a capture with `engine` and checks whether it is fit for analysis,
+/// dissecting SSH wherever `decoding` says besides port 22.
+///
+/// tshark reads every TCP packet. The native engine only returns those the analysis would see and
+/// does not flag lost segments, and falls back to tshark for captures it cannot read, as the
+/// analysis does.
+///
+/// Errors only if the capture cannot be decompressed or tshark cannot be run at all; everything else is reported as a failed [Check].
+pub fn check_capture(filepath: &str, engine: Engine, decoding: &Decoding) -> Result<HealthReport, Error> {
+    log::info!("Checking capture file.");
+
+    let capture = CaptureFile::open(filepath).map_err(Error::Capture)?;
+    if engine == Engine::Native {
+        match source::open(&capture, Engine::Native, decoding) {
+            Ok(mut native) => {
+                let stats = read_stats(native.as_mut());
+                match native.finish() {
+                    Err(SourceError::Unsupported(reason)) if filepath != compression::STDIN => log::warn!("{filepath}: {reason}; reading it with tshark."),
+                    result => return Ok(report(stats, result.err())),
+                }
+            },
+            Err(SourceError::Unsupported(reason)) if filepath != compression::STDIN => log::warn!("{filepath}: {reason}; reading it with tshark."),
+            Err(err) => return Err(Error::Capture(format!("{filepath}: {err}"))),
+        }
+    }
+
+    let mut source: Box<dyn CaptureSource> = Box::new(TsharkSource::spawn(capture.path(), "tcp", decoding).map_err(|err| Error::Capture(err.to_string()))?);
+    let stats = read_stats(source.as_mut());
+    Ok(report(stats, source.finish().err()))
+}
+
+/// Counts the packets of a source.
+fn read_stats(source: &mut dyn CaptureSource) -> CaptureStats {
+    let mut stats = CaptureStats::default();
+    let mut last_timestamp = i64::MIN;
+    let mut servers = utils::ServerMap::new();
+    while let Some(mut packet) = source.next_packet() {
+        if let Some(stream) = utils::find_metadata(&packet, "tcp.stream").and_then(|stream| stream.parse::<u32>().ok()) {
+            utils::mark_direction(&mut servers, stream, &mut packet);
+        }
+        count_packet(&mut stats, &packet, &mut last_timestamp);
+    }
+    stats
+}
+
+/// Runs the checks on the counters, and the error that ended reading early, if any.
+fn report(stats: CaptureStats, read_error: Option<SourceError>) -> HealthReport {
+    let checks = evaluate(&stats, read_error.map(|err| err.to_string()));
+    HealthReport { stats, checks }
+}
+
+/// Adds a single packet to the counters.
+fn count_packet(stats: &mut CaptureStats, packet: &Packet, last_timestamp: &mut i64) {
+    stats.packets += 1;
+
+    if let Some(timestamp) = packet.timestamp_micros() {
+        if timestamp < *last_timestamp {
+            stats.backwards_timestamps += 1;
+        }
+        *last_timestamp = timestamp;
+    }
+
+    let number = |name| utils::find_metadata(packet, name).and_then(|value| value.parse::<u64>().ok());
+    if let (Some(length), Some(captured)) = (number("frame.len"), number("frame.cap_len")) {
+        if captured < length {
+            stats.truncated += 1;
+        }
+    }
+
+    if utils::find_metadata(packet, "tcp.analysis.lost_segment").is_some() {
+        stats.lost_segments += 1;
+    }
+    if utils::find_metadata(packet, "tcp.analysis.retransmission").is_some()
+        || utils::find_metadata(packet, "tcp.analysis.fast_retransmission").is_some() {
+        stats.retransmissions += 1;
+    }
+
+    if packet.layer_name("ssh").is_none() {
+        return;
+    }
+    stats.ssh_packets += 1;
+
+    if let Some(stream_id) = number("tcp.stream") {
+        let entry = stats.streams.entry(stream_id as u32).or_default();
+        if utils::is_server_packet(packet) {
+            entry.1 += 1;
+        } else {
+            entry.0 += 1;
+        }
+    }
+}
+
+/// Turns the counters into checks.
+fn evaluate(stats: &CaptureStats, read_error: Option<String>) -> Vec<Check> {
+    let check = |name, status, details: String| Check { name, status, details };
+    let mut checks = Vec::new();
+
+    checks.push(match (&read_error, stats.packets) {
+        (Some(err), _) => check("tshark", Status::Fail, format!("dissection stopped after {} packets: {err}", stats.packets)),
+        (None, 0) => check("tshark", Status::Fail, "no TCP packets dissected".to_string()),
+        (None, packets) => check("tshark", Status::Pass, format!("{packets} TCP packets dissected")),
+    });
+
+    checks.push(if stats.ssh_packets == 0 {
+        check("ssh", Status::Fail, "no SSH traffic found; non-standard ports need to be decoded as SSH (--ssh-port)".to_string())
+    } else {
+        check("ssh", Status::Pass, format!("{} SSH packets in {} streams", stats.ssh_packets, stats.streams.len()))
+    });
+
+    let one_sided: Vec<String> = stats.streams.iter()
+        .filter(|(_, (client, server))| *client == 0 || *server == 0)
+        .map(|(stream_id, _)| stream_id.to_string())
+        .collect();
+    checks.push(match one_sided.len() {
+        0 => check("directions", Status::Pass, "both directions captured".to_string()),
+        count if count == stats.streams.len() => check("directions", Status::Fail, "only one direction captured".to_string()),
+        _ => check("directions", Status::Warn, format!("only one direction captured in streams {}", one_sided.join(", "))),
+    });
+
+    checks.push(if stats.backwards_timestamps == 0 {
+        check("timestamps", Status::Pass, "monotonic".to_string())
+    } else {
+        check("timestamps", Status::Warn, format!("{} packets timestamped before their predecessor; latencies will be off", stats.backwards_timestamps))
+    });
+
+    let loss = if stats.packets == 0 { 0.0 } else { stats.lost_segments as f64 / stats.packets as f64 };
+    checks.push(if loss < LOSS_WARN_RATIO {
+        check("loss", Status::Pass, format!("{} lost, {} retransmitted segments", stats.lost_segments, stats.retransmissions))
+    } else {
+        check("loss", Status::Warn, format!("~{:.1}% of segments lost; keystrokes may be missing", loss * 100.0))
+    });
+
+    checks.push(if stats.truncated == 0 {
+        check("snaplen", Status::Pass, "packets captured in full".to_string())
+    } else {
+        check("snaplen", Status::Fail, format!("{} packets truncated by the snaplen; recapture with a larger one (e.g. -s 0)", stats.truncated))
+    });
+
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::packet::Layer;
+
+    #[test]
+    fn test_count_packet() {
+        let packet = |timestamp, stream, from_server, fields: &[(&str, &str)], ssh: bool| {
+            let mut packet = Packet::new(Some(timestamp));
+            packet.set_from_server(from_server);
+            let mut tcp = Layer::new("tcp");
+            tcp.add("tcp.stream", stream);
+            for (name, value) in fields {
+                tcp.add(name, value);
+            }
+            packet.push(tcp);
+            if ssh {
+                packet.push(Layer::new("ssh"));
+            }
+            packet
+        };
+
+        let mut stats = CaptureStats::default();
+        let mut last_timestamp = i64::MIN;
+        for packet in [
+            packet(100, 0, false, &[("frame.len", "100"), ("frame.cap_len", "100")], true),
+            // Timestamped before the previous packet, and cut short by the snaplen
+            packet(50, 0, true, &[("frame.len", "1500"), ("frame.cap_len", "96")], true),
+            packet(200, 1, false, &[("tcp.analysis.lost_segment", ""), ("tcp.analysis.retransmission", "")], false),
+            packet(300, 1, false, &[("tcp.analysis.fast_retransmission", "")], false),
+        ] {
+            count_packet(&mut stats, &packet, &mut last_timestamp);
+        }
+
+        assert_eq!((4, 2), (stats.packets, stats.ssh_packets));
+        assert_eq!(BTreeMap::from([(0, (1, 1))]), stats.streams);
+        assert_eq!((1, 1, 1, 2), (stats.backwards_timestamps, stats.truncated, stats.lost_segments, stats.retransmissions));
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let statuses = |checks: &[Check]| checks.iter().map(|check| (check.name, check.status)).collect::<Vec<_>>();
+        let healthy = CaptureStats {
+            packets: 1000,
+            ssh_packets: 900,
+            streams: BTreeMap::from([(0, (450, 450))]),
+            retransmissions: 3,
+            ..Default::default()
+        };
+        let checks = evaluate(&healthy, None);
+        assert!(checks.iter().all(|check| check.status == Status::Pass));
+        assert_eq!("900 SSH packets in 1 streams", checks[1].details);
+        assert!(HealthReport { stats: CaptureStats::default(), checks }.is_usable());
+
+        // tshark gave up, and nothing was read
+        let checks = evaluate(&CaptureStats::default(), Some("tshark exited with 2".to_string()));
+        assert_eq!((Status::Fail, "dissection stopped after 0 packets: tshark exited with 2"), (checks[0].status, checks[0].details.as_str()));
+        assert_eq!(Status::Fail, checks[1].status);
+        assert!(!HealthReport { stats: CaptureStats::default(), checks }.is_usable());
+        assert_eq!(Status::Fail, evaluate(&CaptureStats::default(), None)[0].status);
+
+        // One of two streams seen from one side only, out-of-order timestamps, 2% loss, a truncated packet
+        let degraded = CaptureStats {
+            streams: BTreeMap::from([(0, (450, 450)), (1, (30, 0))]),
+            backwards_timestamps: 4,
+            lost_segments: 20,
+            truncated: 1,
+            ..healthy
+        };
+        let checks = evaluate(&degraded, None);
+        assert_eq!(vec![
+            ("tshark", Status::Pass), ("ssh", Status::Pass), ("directions", Status::Warn),
+            ("timestamps", Status::Warn), ("loss", Status::Warn), ("snaplen", Status::Fail),
+        ], statuses(&checks));
+        assert_eq!("only one direction captured in streams 1", checks[2].details);
+        assert_eq!("~2.0% of segments lost; keystrokes may be missing", checks[4].details);
+
+        let one_sided = CaptureStats { streams: BTreeMap::from([(0, (30, 0)), (1, (0, 12))]), ..Default::default() };
+        assert_eq!(Status::Fail, evaluate(&one_sided, None)[2].status);
+    }
+}
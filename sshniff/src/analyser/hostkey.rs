@@ -0,0 +1,240 @@
+//! Server host key extraction.
+//!
+//! The server sends its public host key in cleartext in the KEX reply (KEXDH_REPLY,
+//! KEX_ECDH_REPLY, or the group exchange reply). tshark splits it into its components, which are
+//! put back together into the wire-format key blob so that the fingerprints match the ones
+//! `ssh-keygen -l` prints for the server's key.
+//!
+//! Keys are also compared between sessions to the same server; see [find_key_changes].
+use super::containers::Event;
+use super::core::SshSession;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use md5::Md5;
+use super::packet::Packet;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+/// Components of each key type, in wire order, after the type name.
+const COMPONENTS: [(&str, &[&str]); 4] = [
+    ("ssh-rsa", &["ssh.host_key.rsa.e", "ssh.host_key.rsa.n"]),
+    ("ssh-dss", &["ssh.host_key.dsa.p", "ssh.host_key.dsa.q", "ssh.host_key.dsa.g", "ssh.host_key.dsa.y"]),
+    ("ssh-ed25519", &["ssh.host_key.eddsa_key"]),
+    ("ecdsa-sha2-", &["ssh.host_key.ecdsa.curve_id", "ssh.host_key.ecdsa.q"]),
+];
+
+/// Public host key of the server.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct HostKey {
+    /// Key type, e.g. `ssh-ed25519`
+    pub key_type: String,
+    /// `SHA256:` followed by the unpadded base64 digest, as printed by OpenSSH
+    pub sha256: String,
+    /// `MD5:` followed by the colon-separated hex digest, as printed by older OpenSSH
+    pub md5: String,
+}
+
+impl HostKey {
+    /// Fingerprints a key blob (`string type, ...`), as sent in the KEX reply.
+    pub fn from_blob(blob: &[u8]) -> Option<Self> {
+        let length = u32::from_be_bytes(blob.get(..4)?.try_into().ok()?) as usize;
+        let key_type = String::from_utf8(blob.get(4..4 + length)?.to_vec()).ok()?;
+
+        let md5 = Md5::digest(blob).iter().map(|byte| format!("{byte:02x}")).collect::<Vec<_>>().join(":");
+
+        Some(Self {
+            key_type,
+            sha256: format!("SHA256:{}", STANDARD_NO_PAD.encode(Sha256::digest(blob))),
+            md5: format!("MD5:{md5}"),
+        })
+    }
+}
+
+/// Finds the server's host key in the KEX reply among the first packets of a stream.
+pub fn find_host_key(packets: &[Packet]) -> Option<HostKey> {
+    for packet in packets.iter().take(50) {
+        let ssh_layer = match packet.layer_name("ssh") {
+            Some(layer) => layer,
+            None => continue,
+        };
+        let key_type = match ssh_layer.metadata("ssh.host_key.type") {
+            Some(metadata) => metadata.value(),
+            None => continue,
+        };
+
+        let mut blob = vec![];
+        put_string(&mut blob, key_type.as_bytes());
+        match COMPONENTS.iter().find(|(prefix, _)| key_type == *prefix || (prefix.ends_with('-') && key_type.starts_with(prefix))) {
+            Some((_, fields)) => {
+                for field in *fields {
+                    match ssh_layer.metadata(field).and_then(|metadata| raw_bytes(metadata.raw_value())) {
+                        Some(bytes) => put_string(&mut blob, &bytes),
+                        None => {
+                            log::warn!("Host key of type {key_type} is missing {field}.");
+                            return None;
+                        },
+                    }
+                }
+            },
+            // Unknown types (e.g. certificates) are passed through as a single blob
+            None => {
+                let data = ssh_layer.metadata("ssh.host_key.data").and_then(|metadata| raw_bytes(metadata.raw_value()))?;
+                blob.extend(data);
+            },
+        }
+
+        return HostKey::from_blob(&blob);
+    }
+
+    None
+}
+
+/// Splits a key blob into the fields tshark dissects it into, the inverse of [find_host_key]:
+/// `ssh.host_key.type`, then the [COMPONENTS] of the type, or `ssh.host_key.data` for other types.
+///
+/// `None` if the blob does not start with a key type, e.g. the group of a group exchange, which
+/// shares its message code with the KEX reply.
+pub fn blob_fields(blob: &[u8]) -> Option<Vec<(&'static str, Vec<u8>)>> {
+    let (key_type, mut rest) = take_string(blob)?;
+    let key_type = std::str::from_utf8(key_type).ok()?;
+    if key_type.is_empty() || !key_type.bytes().all(|byte| byte.is_ascii_graphic()) {
+        return None;
+    }
+
+    let mut fields = vec![("ssh.host_key.type", key_type.as_bytes().to_vec())];
+    match COMPONENTS.iter().find(|(prefix, _)| key_type == *prefix || (prefix.ends_with('-') && key_type.starts_with(prefix))) {
+        Some((_, names)) => {
+            for name in *names {
+                let (component, remainder) = take_string(rest)?;
+                fields.push((name, component.to_vec()));
+                rest = remainder;
+            }
+        },
+        None => fields.push(("ssh.host_key.data", rest.to_vec())),
+    }
+    Some(fields)
+}
+
+/// Splits an SSH `string` off the front of `data`: its contents, then the rest.
+pub fn take_string(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let length = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    let contents = data.get(4..4usize.checked_add(length)?)?;
+    Some((contents, &data[4 + length..]))
+}
+
+/// Appends an SSH `string` (or `mpint`, which tshark keeps in wire form): length, then data.
+fn put_string(blob: &mut Vec<u8>, data: &[u8]) {
+    blob.extend((data.len() as u32).to_be_bytes());
+    blob.extend(data);
+}
+
+/// Decodes the hex a PDML field carries its raw bytes in.
+fn raw_bytes(value: &str) -> Option<Vec<u8>> {
+    hex::decode(value.replace(':', "")).ok()
+}
+
+/// Host key a server presented in an earlier session.
+#[derive(Clone, Debug)]
+pub struct KeySighting {
+    /// Server address, `ip:port`
+    pub server: String,
+    pub key_type: String,
+    pub sha256: String,
+    pub capture: String,
+    pub stream: u32,
+    pub start_utc: String,
+}
+
+/// Adds a [HostKeyChanged](Event::HostKeyChanged) finding to every session whose server presented
+/// a different key of the same type in an earlier session, in this capture or in `earlier` ones.
+///
+/// Keys of different types are not compared: a server usually has several, and which one is used
+/// depends on the client's preferences.
+pub fn find_key_changes(sessions: &mut BTreeMap<u32, SshSession>, capture: &str, earlier: &[KeySighting]) {
+    let mut seen: Vec<KeySighting> = earlier.to_vec();
+
+    let mut order: Vec<u32> = sessions.keys().copied().collect();
+    order.sort_by(|a, b| sessions[a].start_utc.cmp(&sessions[b].start_utc));
+
+    for stream in order {
+        let session = sessions.get_mut(&stream).expect("stream taken from the map");
+        let host_key = match &session.host_key {
+            Some(host_key) => host_key.clone(),
+            None => continue,
+        };
+
+        if let Some(previous) = previous_key(&seen, &session.dst, &host_key) {
+            let details = format!(
+                "{} {}, previously {} in {} stream {} at {}",
+                host_key.key_type, host_key.sha256, previous.sha256, previous.capture, previous.stream, previous.start_utc,
+            );
+            log::warn!("Host key of {} changed: {details}", session.dst);
+
+            // Placed with New Keys, which directly follows the KEX reply carrying the key
+            if let Some(new_keys) = session.results.iter().find(|finding| finding.kind == Event::NewKeys) {
+                let mut finding = new_keys.clone().with_details(details);
+                finding.kind = Event::HostKeyChanged;
+                session.results.push(finding);
+            }
+        }
+
+        seen.push(KeySighting {
+            server: session.dst.clone(),
+            key_type: host_key.key_type,
+            sha256: host_key.sha256,
+            capture: capture.to_string(),
+            stream,
+            start_utc: session.start_utc.clone(),
+        });
+    }
+}
+
+/// Latest sighting of `server` with a different key of the same type as `key`.
+pub fn previous_key<'a>(seen: &'a [KeySighting], server: &str, key: &HostKey) -> Option<&'a KeySighting> {
+    seen.iter().rev().find(|sighting| sighting.server == server && sighting.key_type == key.key_type && sighting.sha256 != key.sha256)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_key() {
+        // ssh-ed25519 key of the server in the test captures
+        let mut blob = vec![0, 0, 0, 11];
+        blob.extend(b"ssh-ed25519");
+        blob.extend([0, 0, 0, 32]);
+        blob.extend(hex::decode("e8a7746eeba085e34affd083da9cde434795c734ebe24df71c77cd40414f72d0").unwrap());
+
+        let host_key = HostKey::from_blob(&blob).unwrap();
+        assert_eq!("ssh-ed25519", host_key.key_type);
+        assert_eq!("SHA256:t56EKSEYh7BngyQ3748Dm4lID63UbQ/nFrrezLxcA7o", host_key.sha256);
+        assert_eq!("MD5:bb:28:57:b7:8a:a0:1a:74:cd:09:01:4b:97:fa:d3:66", host_key.md5);
+
+        assert_eq!(None, HostKey::from_blob(&[0, 0, 0, 11, b's']));
+    }
+
+    #[test]
+    fn test_host_key_changes() {
+        let sighting = |server: &str, key_type: &str, sha256: &str, stream| KeySighting {
+            server: server.to_string(),
+            key_type: key_type.to_string(),
+            sha256: sha256.to_string(),
+            capture: "earlier.pcapng".to_string(),
+            stream,
+            start_utc: String::new(),
+        };
+        let seen = vec![
+            sighting("10.0.0.1:22", "ssh-ed25519", "SHA256:old", 0),
+            sighting("10.0.0.1:22", "ssh-rsa", "SHA256:rsa", 1),
+            sighting("10.0.0.2:22", "ssh-ed25519", "SHA256:other", 2),
+        ];
+
+        let key = |key_type: &str, sha256: &str| HostKey { key_type: key_type.to_string(), sha256: sha256.to_string(), md5: String::new() };
+        assert_eq!(Some(0), previous_key(&seen, "10.0.0.1:22", &key("ssh-ed25519", "SHA256:new")).map(|previous| previous.stream));
+        assert!(previous_key(&seen, "10.0.0.1:22", &key("ssh-ed25519", "SHA256:old")).is_none());
+        assert!(previous_key(&seen, "10.0.0.1:22", &key("ecdsa-sha2-nistp256", "SHA256:new")).is_none());
+        assert!(previous_key(&seen, "10.0.0.1:2222", &key("ssh-ed25519", "SHA256:new")).is_none());
+    }
+}
@@ -0,0 +1,357 @@
+//! Statistical inference of typed text from keystroke latencies, behind the `inference` feature.
+//!
+//! Two keys typed with alternating hands follow each other faster than two keys typed with the same
+//! hand, and a repeated key is slower still (Song, Wagner, and Tian, "Timing Analysis of Keystrokes
+//! and Timing Attacks on SSH", 2001). Latencies are divided by the session's median latency, so the
+//! model does not depend on how fast the operator types, and scored under a log-normal distribution
+//! per kind of [Transition]. Two kinds of hypotheses are proposed per keystroke sequence:
+//!
+//! - the most likely [CharClass] of each keystroke, decoded with a hidden Markov model whose class
+//!   transitions are trained on the dictionary (Viterbi);
+//! - dictionary entries of the right length, ranked by their posterior probability.
+//!
+//! The hypotheses are weak evidence: they tell which of a handful of common commands was more
+//! likely typed, not what was typed.
+use super::containers::{KeystrokeSequence, KeystrokeType};
+use super::core::SshSession;
+use serde::Serialize;
+use std::sync::OnceLock;
+
+/// The bundled dictionary; see `data/inference/words.txt`.
+const BUNDLED: &str = include_str!("../../data/inference/words.txt");
+
+/// Number of dictionary hypotheses kept per sequence.
+pub const MAX_HYPOTHESES: usize = 5;
+
+/// Keys typed with the left hand on a US QWERTY keyboard, shifted or not.
+const LEFT_HAND: &str = "`12345qwertasdfgzxcvb~!@#$%QWERTASDFGZXCVB";
+
+/// Latencies this many times the median are pauses to think, not typing, and are not scored.
+const PAUSE_RATIO: f64 = 4.0;
+
+/// Standard deviation of the log latency ratio around each transition's mean.
+const LOG_RATIO_STDDEV: f64 = 0.35;
+
+/// Class of a typed character, by the hand typing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum CharClass {
+    Left,
+    Right,
+    /// Space bar, typed with a thumb
+    Space,
+}
+
+impl CharClass {
+    const ALL: [CharClass; 3] = [CharClass::Left, CharClass::Right, CharClass::Space];
+
+    /// Class of a character on a US QWERTY keyboard with the usual fingering.
+    pub fn of(c: char) -> Self {
+        if c == ' ' {
+            CharClass::Space
+        } else if LEFT_HAND.contains(c) {
+            CharClass::Left
+        } else {
+            CharClass::Right
+        }
+    }
+
+    /// Class of a keystroke whose key is known from its type; None for typed characters.
+    fn of_keystroke(k_type: &KeystrokeType) -> Option<Self> {
+        match k_type {
+            KeystrokeType::Tab => Some(CharClass::Left),
+            KeystrokeType::Enter | KeystrokeType::Delete | KeystrokeType::ArrowHorizontal | KeystrokeType::ArrowVertical => Some(CharClass::Right),
+            _ => None,
+        }
+    }
+
+    /// `L`, `R`, or `_` in class strings.
+    fn symbol(self) -> char {
+        match self {
+            CharClass::Left => 'L',
+            CharClass::Right => 'R',
+            CharClass::Space => '_',
+        }
+    }
+}
+
+/// Kinds of key pairs, which differ in how fast the second key follows the first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transition {
+    /// Different hands, or the space bar
+    Alternating,
+    /// Different keys of the same hand
+    SameHand,
+    /// The same key twice
+    SameKey,
+}
+
+impl Transition {
+    /// Transition between two classes, assuming different keys.
+    pub fn between(from: CharClass, to: CharClass) -> Self {
+        match (from, to) {
+            (CharClass::Left, CharClass::Left) | (CharClass::Right, CharClass::Right) => Transition::SameHand,
+            _ => Transition::Alternating,
+        }
+    }
+
+    /// Mean of the log ratio of a latency to the median latency.
+    fn mean_log_ratio(self) -> f64 {
+        match self {
+            Transition::Alternating => -0.2,
+            Transition::SameHand => 0.1,
+            Transition::SameKey => 0.25,
+        }
+    }
+
+    /// Log-likelihood of a latency ratio; 0 for pauses and missing latencies, which carry no
+    /// information about the keys.
+    fn log_likelihood(self, ratio: Option<f64>) -> f64 {
+        match ratio {
+            Some(ratio) => {
+                let z = (ratio.ln() - self.mean_log_ratio()) / LOG_RATIO_STDDEV;
+                -0.5 * z * z - (LOG_RATIO_STDDEV * (2.0 * std::f64::consts::PI).sqrt()).ln()
+            },
+            None => 0.0,
+        }
+    }
+}
+
+/// Most likely character classes of a sequence, one per keystroke, e.g. `RL_RRR` for `ls -l` and
+/// its Enter.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ClassHypothesis {
+    pub classes: String,
+    pub log_likelihood: f64,
+}
+
+/// Dictionary entry a sequence may have typed.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct WordHypothesis {
+    pub text: String,
+    /// Posterior probability among the entries of the same length
+    pub probability: f64,
+}
+
+/// Hypotheses for one keystroke sequence.
+#[derive(Clone, Debug, Serialize)]
+pub struct SequenceInference {
+    pub stream: u32,
+    /// ID of the [KeystrokeSequence]
+    pub sequence: usize,
+    pub classes: ClassHypothesis,
+    /// Best first; empty unless the sequence consists of typed characters and an Enter only
+    pub words: Vec<WordHypothesis>,
+}
+
+/// Dictionary and the class transitions trained on it.
+#[derive(Clone, Debug)]
+pub struct Model {
+    /// Entries and their log prior probabilities
+    words: Vec<(String, f64)>,
+    /// log P(first class)
+    initial: [f64; 3],
+    /// log P(class | previous class)
+    transitions: [[f64; 3]; 3],
+}
+
+impl Model {
+    /// The model trained on the bundled dictionary.
+    pub fn bundled() -> &'static Model {
+        static BUNDLED_MODEL: OnceLock<Model> = OnceLock::new();
+        BUNDLED_MODEL.get_or_init(|| Self::parse(BUNDLED).expect("bundled dictionary is valid"))
+    }
+
+    /// Trains a model on `weight,text` lines. Empty lines and lines starting with `#` are ignored.
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut words = vec![];
+        for (number, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once(',').map(|(weight, text)| (weight.trim().parse::<f64>(), text)) {
+                Some((Ok(weight), text)) if weight > 0.0 && !text.is_empty() => words.push((text.to_string(), weight)),
+                _ => return Err(format!("line {}: expected weight,text with a positive weight", number + 1)),
+            }
+        }
+        if words.is_empty() {
+            return Err("the dictionary is empty".to_string());
+        }
+
+        // Class counts with add-one smoothing; every entry is followed by an Enter
+        let mut initial = [1.0; 3];
+        let mut transitions = [[1.0; 3]; 3];
+        for (text, weight) in &words {
+            let classes: Vec<CharClass> = text.chars().map(CharClass::of).chain([CharClass::Right]).collect();
+            initial[classes[0] as usize] += weight;
+            for pair in classes.windows(2) {
+                transitions[pair[0] as usize][pair[1] as usize] += weight;
+            }
+        }
+
+        let total: f64 = words.iter().map(|(_, weight)| weight).sum();
+        Ok(Self {
+            words: words.into_iter().map(|(text, weight)| (text, (weight / total).ln())).collect(),
+            initial: normalise(initial),
+            transitions: transitions.map(normalise),
+        })
+    }
+
+    /// Hypotheses for every keystroke sequence of a session. Empty if the session has no typing
+    /// statistics to normalise the latencies with.
+    pub fn infer(&self, session: &SshSession) -> Vec<SequenceInference> {
+        if session.typing.median_us <= 0 {
+            return vec![];
+        }
+
+        session.keystroke_data.iter()
+            .filter(|sequence| !sequence.keystrokes.is_empty())
+            .map(|sequence| self.infer_sequence(session.stream, sequence, session.typing.median_us))
+            .collect()
+    }
+
+    /// Hypotheses for one non-empty keystroke sequence, with latencies relative to `median_us`.
+    pub fn infer_sequence(&self, stream: u32, sequence: &KeystrokeSequence, median_us: i64) -> SequenceInference {
+        let ratios = ratios(sequence, median_us);
+        SequenceInference {
+            stream,
+            sequence: sequence.id,
+            classes: self.decode(sequence, &ratios),
+            words: self.words(sequence, &ratios),
+        }
+    }
+
+    /// Most likely class of each keystroke (Viterbi). Keystrokes of a known key are fixed to its
+    /// class.
+    fn decode(&self, sequence: &KeystrokeSequence, ratios: &[Option<f64>]) -> ClassHypothesis {
+        let allowed = |index: usize, class: CharClass| {
+            CharClass::of_keystroke(&sequence.keystrokes[index].k_type).is_none_or(|known| known == class)
+        };
+
+        // Best log-likelihood of ending in each class, and the class before it
+        let mut scores = CharClass::ALL.map(|class| if allowed(0, class) { self.initial[class as usize] } else { f64::NEG_INFINITY });
+        let mut back: Vec<[usize; 3]> = vec![];
+        for (index, &ratio) in ratios.iter().enumerate().skip(1) {
+            let mut next = [f64::NEG_INFINITY; 3];
+            let mut from = [0; 3];
+            for to in CharClass::ALL.into_iter().filter(|&class| allowed(index, class)) {
+                for prev in CharClass::ALL {
+                    let score = scores[prev as usize]
+                        + self.transitions[prev as usize][to as usize]
+                        + Transition::between(prev, to).log_likelihood(ratio);
+                    if score > next[to as usize] {
+                        next[to as usize] = score;
+                        from[to as usize] = prev as usize;
+                    }
+                }
+            }
+            scores = next;
+            back.push(from);
+        }
+
+        let (mut best, log_likelihood) = scores.iter().copied().enumerate().fold((0, f64::NEG_INFINITY), |best, (class, score)| if score > best.1 { (class, score) } else { best });
+        let mut path = vec![best];
+        for from in back.iter().rev() {
+            best = from[best];
+            path.push(best);
+        }
+
+        ClassHypothesis {
+            classes: path.iter().rev().map(|&class| CharClass::ALL[class].symbol()).collect(),
+            log_likelihood,
+        }
+    }
+
+    /// Dictionary entries with as many characters as the sequence typed, best first. Sequences with
+    /// corrections, completions, or keys other than characters and a final Enter are not matched.
+    fn words(&self, sequence: &KeystrokeSequence, ratios: &[Option<f64>]) -> Vec<WordHypothesis> {
+        let keystrokes = &sequence.keystrokes;
+        let enter = keystrokes.last().is_some_and(|keystroke| keystroke.k_type == KeystrokeType::Enter);
+        let typed = if enter { keystrokes.len() - 1 } else { keystrokes.len() };
+        if typed == 0 || keystrokes[..typed].iter().any(|keystroke| keystroke.k_type != KeystrokeType::Keystroke) {
+            return vec![];
+        }
+
+        let mut scored: Vec<(&str, f64)> = self.words.iter()
+            .filter(|(text, _)| text.chars().count() == typed)
+            .map(|(text, prior)| {
+                let chars: Vec<char> = text.chars().collect();
+                let mut score = *prior;
+                for index in 1..typed {
+                    let transition = match (chars[index - 1], chars[index]) {
+                        (a, b) if a == b => Transition::SameKey,
+                        (a, b) => Transition::between(CharClass::of(a), CharClass::of(b)),
+                    };
+                    score += transition.log_likelihood(ratios[index]);
+                }
+                if enter {
+                    score += Transition::between(CharClass::of(chars[typed - 1]), CharClass::Right).log_likelihood(ratios[typed]);
+                }
+                (text.as_str(), score)
+            })
+            .collect();
+        if scored.is_empty() {
+            return vec![];
+        }
+
+        // Softmax, shifted by the best score to stay within range
+        let best = scored.iter().map(|(_, score)| *score).fold(f64::NEG_INFINITY, f64::max);
+        let total: f64 = scored.iter().map(|(_, score)| (score - best).exp()).sum();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        scored.into_iter()
+            .take(MAX_HYPOTHESES)
+            .map(|(text, score)| WordHypothesis { text: text.to_string(), probability: (score - best).exp() / total })
+            .collect()
+    }
+}
+
+/// Latency of each keystroke relative to `median_us`; None for the first keystroke and for pauses.
+fn ratios(sequence: &KeystrokeSequence, median_us: i64) -> Vec<Option<f64>> {
+    sequence.keystrokes.iter()
+        .map(|keystroke| Some(keystroke.latency_us as f64 / median_us as f64).filter(|&ratio| ratio > 0.0 && ratio < PAUSE_RATIO))
+        .collect()
+}
+
+/// Log probabilities from counts.
+fn normalise(counts: [f64; 3]) -> [f64; 3] {
+    let total: f64 = counts.iter().sum();
+    counts.map(|count| (count / total).ln())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::containers;
+    use crate::analyser::containers::tests::keystroke;
+    use crate::analyser::core::process_keystrokes;
+
+    #[test]
+    fn test_inference() {
+        use containers::KeystrokeType::*;
+
+        // Keystrokes at the given times (ms)
+        let sequence = |keys: Vec<(containers::KeystrokeType, i64)>| process_keystrokes(keys.into_iter().map(|(k_type, ms)| containers::Keystroke {
+            timestamp: ms * 1000,
+            ..keystroke(k_type, 0)
+        }).collect()).remove(0);
+        let model = Model::bundled();
+
+        // A quick second key and a slow Enter: hands alternate, then the same hand (`ls`). Which
+        // hand starts is left to the prior, as latencies cannot tell.
+        let inference = model.infer_sequence(0, &sequence(vec![(Keystroke, 0), (Keystroke, 110), (Enter, 300)]), 150_000);
+        assert_eq!("LRR", inference.classes.classes);
+        assert_eq!("ls", inference.words[0].text);
+        assert!(inference.words.windows(2).all(|pair| pair[0].probability >= pair[1].probability));
+        assert!(inference.words.iter().map(|word| word.probability).sum::<f64>() <= 1.0 + 1e-9);
+
+        // A slow second key and a quick Enter: the same hand, then alternating (`cd`)
+        let inference = model.infer_sequence(0, &sequence(vec![(Keystroke, 0), (Keystroke, 230), (Enter, 330)]), 150_000);
+        assert_eq!("cd", inference.words[0].text);
+
+        // Corrections rule out dictionary matches, but not classes; Enter is always a right-hand key
+        let inference = model.infer_sequence(0, &sequence(vec![(Keystroke, 0), (Delete, 150), (Keystroke, 300), (Enter, 450)]), 150_000);
+        assert!(inference.words.is_empty());
+        assert!(inference.classes.classes.ends_with('R') && inference.classes.classes.chars().nth(1) == Some('R'));
+        assert!(Model::parse("ls").is_err());
+        assert!(Model::parse("0,ls").is_err());
+    }
+}
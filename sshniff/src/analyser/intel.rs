@@ -0,0 +1,222 @@
+//! Matching sessions against threat-intelligence indicators.
+//!
+//! Indicators are HASSH values, IP addresses, and CIDR ranges, loaded from a user-provided CSV or
+//! JSON file. A session matches if its client or server HASSH, or the IP of either end, is listed.
+//! Each match is placed on the timeline as a [ThreatMatch](super::containers::Event::ThreatMatch)
+//! and raises the session's severity to that of the indicator.
+use super::core::SshSession;
+use super::utils;
+use serde::Deserialize;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Severity (0-10) of indicators that do not set one.
+pub const DEFAULT_SEVERITY: u8 = 8;
+
+/// What an [Indicator] matches.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IndicatorKind {
+    Hassh(String),
+    Ip(IpAddr),
+    /// Network address and prefix length
+    Cidr(IpAddr, u8),
+}
+
+/// Single entry of an indicator file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Indicator {
+    pub kind: IndicatorKind,
+    /// 0 to 10
+    pub severity: u8,
+    /// Free text from the file, e.g. the campaign or feed the indicator comes from
+    pub description: Option<String>,
+}
+
+/// Indicator as written in a JSON file.
+#[derive(Deserialize)]
+struct RawIndicator {
+    indicator: String,
+    severity: Option<u8>,
+    description: Option<String>,
+}
+
+/// A session attribute that matched an [Indicator].
+#[derive(Clone, Debug)]
+pub struct ThreatMatch<'a> {
+    /// The matched attribute, e.g. `client HASSH` or `server IP`
+    pub field: &'static str,
+    /// Its value in the session
+    pub value: String,
+    pub indicator: &'a Indicator,
+}
+
+impl ThreatMatch<'_> {
+    /// Description for the timeline.
+    pub fn details(&self) -> String {
+        match &self.indicator.description {
+            Some(description) => format!("{} {}: {description}", self.field, self.value),
+            None => format!("{} {}", self.field, self.value),
+        }
+    }
+}
+
+/// Indicators to match sessions against.
+#[derive(Clone, Debug, Default)]
+pub struct IndicatorSet {
+    indicators: Vec<Indicator>,
+}
+
+impl IndicatorSet {
+    /// Loads indicators from a `.json` file, or from a CSV file with any other extension.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| format!("Failed to read {path}: {err}"))?;
+        let is_json = Path::new(path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+        let parsed = if is_json { Self::parse_json(&contents) } else { Self::parse_csv(&contents) };
+        parsed.map_err(|err| format!("{path}: {err}"))
+    }
+
+    /// Parses `indicator[,severity[,description]]` lines. Empty lines and lines starting with `#`
+    /// are ignored.
+    pub fn parse_csv(contents: &str) -> Result<Self, String> {
+        let mut indicators = vec![];
+
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.splitn(3, ',').map(str::trim);
+            let value = fields.next().unwrap_or_default();
+            let severity = match fields.next() {
+                Some(severity) if !severity.is_empty() => Some(severity.parse().map_err(|_| format!("line {}: '{severity}' is not a severity", number + 1))?),
+                _ => None,
+            };
+            let description = fields.next().filter(|description| !description.is_empty()).map(str::to_string);
+
+            indicators.push(indicator(value, severity, description).map_err(|err| format!("line {}: {err}", number + 1))?);
+        }
+
+        Ok(Self { indicators })
+    }
+
+    /// Parses a JSON array of `{"indicator": ..., "severity": ..., "description": ...}` objects;
+    /// only `indicator` is required.
+    pub fn parse_json(contents: &str) -> Result<Self, String> {
+        let raw: Vec<RawIndicator> = serde_json::from_str(contents).map_err(|err| err.to_string())?;
+        let indicators = raw.into_iter()
+            .enumerate()
+            .map(|(index, raw)| indicator(&raw.indicator, raw.severity, raw.description).map_err(|err| format!("entry {}: {err}", index + 1)))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { indicators })
+    }
+
+    pub fn len(&self) -> usize {
+        self.indicators.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indicators.is_empty()
+    }
+
+    /// Indicators matching the HASSH values or IPs of a session.
+    pub fn matches(&self, session: &SshSession) -> Vec<ThreatMatch<'_>> {
+        let attributes = [
+            ("client HASSH", session.hassh_c.as_str()),
+            ("server HASSH", session.hassh_s.as_str()),
+            ("client IP", utils::ip_of(&session.src)),
+            ("server IP", utils::ip_of(&session.dst)),
+        ];
+
+        attributes.into_iter()
+            .flat_map(|(field, value)| self.lookup(value).map(move |indicator| ThreatMatch { field, value: value.to_string(), indicator }))
+            .collect()
+    }
+
+    /// Indicators matching a single HASSH or IP.
+    pub fn lookup<'a>(&'a self, value: &str) -> impl Iterator<Item = &'a Indicator> + 'a {
+        let ip = value.trim_matches(['[', ']']).parse::<IpAddr>().ok();
+        let value = value.to_ascii_lowercase();
+        self.indicators.iter().filter(move |indicator| match &indicator.kind {
+            IndicatorKind::Hassh(hassh) => *hassh == value,
+            IndicatorKind::Ip(address) => ip == Some(*address),
+            IndicatorKind::Cidr(network, prefix) => ip.is_some_and(|ip| in_network(ip, *network, *prefix)),
+        })
+    }
+}
+
+/// Classifies an indicator value as a HASSH, IP, or CIDR range.
+fn indicator(value: &str, severity: Option<u8>, description: Option<String>) -> Result<Indicator, String> {
+    let severity = severity.unwrap_or(DEFAULT_SEVERITY);
+    if severity > 10 {
+        return Err(format!("severity {severity} is above 10"));
+    }
+
+    let kind = if value.contains('/') {
+        let (network, prefix) = parse_cidr(value)?;
+        IndicatorKind::Cidr(network, prefix)
+    } else if let Ok(ip) = value.parse::<IpAddr>() {
+        IndicatorKind::Ip(ip)
+    } else if value.len() == 32 && value.chars().all(|c| c.is_ascii_hexdigit()) {
+        IndicatorKind::Hassh(value.to_ascii_lowercase())
+    } else {
+        return Err(format!("'{value}' is neither a HASSH, an IP, nor a CIDR range"));
+    };
+
+    Ok(Indicator { kind, severity, description })
+}
+
+/// Parses a CIDR range such as `10.0.0.0/8` into its network and prefix length.
+pub fn parse_cidr(value: &str) -> Result<(IpAddr, u8), String> {
+    let (network, prefix) = value.split_once('/').ok_or_else(|| format!("'{value}' is not a CIDR range"))?;
+    let network: IpAddr = network.parse().map_err(|_| format!("'{value}' is not a CIDR range"))?;
+    let max = if network.is_ipv4() { 32 } else { 128 };
+    match prefix.parse::<u8>() {
+        Ok(prefix) if prefix <= max => Ok((network, prefix)),
+        _ => Err(format!("'{value}' is not a CIDR range")),
+    }
+}
+
+/// Checks if `ip` lies in `network/prefix`; addresses of different families never match.
+pub fn in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    let (ip, network, bits) = match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => (u32::from(ip) as u128, u32::from(network) as u128, 32),
+        (IpAddr::V6(ip), IpAddr::V6(network)) => (u128::from(ip), u128::from(network), 128),
+        _ => return false,
+    };
+    if prefix == 0 {
+        return true;
+    }
+
+    let shift = bits - prefix as u32;
+    ip >> shift == network >> shift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indicators() {
+        let csv = "# feed\nAAE6B9604F6F3356543709A376D7F657,9,scanner\n203.0.113.7\n10.8.0.0/16,5,VPN pool\n2001:db8::/32";
+        let indicators = IndicatorSet::parse_csv(csv).unwrap();
+        assert_eq!(4, indicators.len());
+
+        let lookup = |value| indicators.lookup(value).map(|indicator| indicator.severity).collect::<Vec<_>>();
+        assert_eq!(vec![9], lookup("aae6b9604f6f3356543709a376d7f657"));
+        assert_eq!(vec![DEFAULT_SEVERITY], lookup("203.0.113.7"));
+        assert_eq!(vec![5], lookup("10.8.254.1"));
+        assert!(lookup("10.9.0.1").is_empty());
+        assert_eq!(1, lookup("2001:db8::1").len());
+
+        let json = r#"[{"indicator": "198.51.100.0/24", "description": "C2"}]"#;
+        let indicators = IndicatorSet::parse_json(json).unwrap();
+        assert_eq!(Some("C2".to_string()), indicators.lookup("198.51.100.20").next().unwrap().description.clone());
+
+        assert!(IndicatorSet::parse_csv("10.0.0.0/33").is_err());
+        assert!(IndicatorSet::parse_csv("203.0.113.7,11").is_err());
+        assert!(IndicatorSet::parse_csv("example.com").is_err());
+    }
+}
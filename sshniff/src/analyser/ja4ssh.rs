@@ -0,0 +1,89 @@
+//! JA4SSH fingerprints of a stream.
+//!
+//! The stream is cut into windows of [WINDOW] TCP packets (SSH packets and bare ACKs), and every
+//! window is described as `c{mode}s{mode}_c{packets}s{packets}_c{acks}s{acks}`: the most common
+//! TCP payload length, the number of SSH packets, and the number of bare ACKs of the client and
+//! the server. An interactive session typically reads `c36s36_c51s80_c69s0`, a file transfer
+//! `c112s1460_c0s179_c21s0`.
+//!
+//! Bare ACKs are only known when the capture was loaded with [load_capture](super::utils::load_capture);
+//! without them, the last part reads `c0s0`.
+use super::utils;
+use super::packet::Packet;
+use std::collections::HashMap;
+
+/// Packets per fingerprint.
+pub const WINDOW: usize = 200;
+
+/// Returns one fingerprint per window of the stream, the last of which may be shorter.
+pub fn fingerprints(packets: &[Packet], acks: &[(i64, bool)]) -> Vec<String> {
+    // (timestamp, is_server, payload length; None for bare ACKs)
+    let mut events: Vec<(i64, bool, Option<i32>)> = packets.iter()
+        .filter_map(|packet| packet.timestamp_micros().map(|timestamp| (timestamp, utils::is_server_packet(packet), Some(utils::get_segment_length(packet)))))
+        .chain(acks.iter().map(|(timestamp, is_server)| (*timestamp, *is_server, None)))
+        .collect();
+    events.sort_by_key(|(timestamp, _, _)| *timestamp);
+
+    events.chunks(WINDOW).map(fingerprint).collect()
+}
+
+/// Fingerprint of a single window.
+fn fingerprint(window: &[(i64, bool, Option<i32>)]) -> String {
+    let lengths = |server: bool| window.iter()
+        .filter(|(_, is_server, _)| *is_server == server)
+        .filter_map(|(_, _, length)| *length)
+        .collect::<Vec<i32>>();
+    let acks = |server: bool| window.iter()
+        .filter(|(_, is_server, length)| *is_server == server && length.is_none())
+        .count();
+    let (client, server) = (lengths(false), lengths(true));
+
+    format!("c{}s{}_c{}s{}_c{}s{}", mode(&client), mode(&server), client.len(), server.len(), acks(false), acks(true))
+}
+
+/// Most common length; the smallest one on ties, and 0 if there are none.
+fn mode(lengths: &[i32]) -> i32 {
+    let mut counts: HashMap<i32, usize> = HashMap::new();
+    for length in lengths {
+        *counts.entry(*length).or_default() += 1;
+    }
+
+    counts.into_iter()
+        .max_by_key(|(length, count)| (*count, -length))
+        .map_or(0, |(length, _)| length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::packet::Layer;
+
+    #[test]
+    fn test_fingerprints() {
+        let packet = |timestamp, from_server, length: i32| {
+            let mut packet = Packet::new(timestamp);
+            packet.set_from_server(from_server);
+            let mut tcp = Layer::new("tcp");
+            tcp.add("tcp.len", length);
+            packet.push(tcp);
+            packet
+        };
+
+        // Ties go to the smaller length, packets without a timestamp are left out, and ACKs count
+        // towards the side that sent them
+        let packets = [
+            packet(Some(1), false, 36), packet(Some(2), true, 100), packet(Some(3), false, 36),
+            packet(Some(4), true, 36), packet(Some(5), false, 52), packet(None, false, 52),
+        ];
+        let acks = [(6, false), (7, false), (8, true)];
+        assert_eq!(vec!["c36s36_c3s2_c2s1"], fingerprints(&packets, &acks));
+        assert_eq!(vec!["c36s36_c3s2_c0s0"], fingerprints(&packets, &[]));
+
+        // Windows are cut in capture order, with ACKs sorted in between the packets
+        let packets = (0..WINDOW as i64).map(|n| packet(Some(n * 2), n % 2 == 1, 48)).collect::<Vec<_>>();
+        let acks = (0..WINDOW as i64 / 2).map(|n| (n * 4 + 1, false)).collect::<Vec<_>>();
+        assert_eq!(vec!["c48s48_c67s66_c67s0", "c48s48_c33s34_c33s0"], fingerprints(&packets, &acks));
+
+        assert!(fingerprints(&[], &[]).is_empty());
+    }
+}
@@ -0,0 +1,270 @@
+//! Verification of server host keys against an OpenSSH `known_hosts` file.
+//!
+//! The server's [HostKey] is looked up the way `ssh` would: entries are matched by host pattern
+//! (plain, wildcard, negated, or hashed with `HashKnownHosts`), with `[host]:port` for servers not
+//! on port 22. A key that differs from every known key of the host is a strong sign of a
+//! man-in-the-middle; so is a key marked `@revoked`.
+use super::containers::Event;
+use super::hostkey::HostKey;
+use super::utils;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::fs;
+
+/// Entry of a known_hosts file.
+#[derive(Clone, Debug)]
+struct Entry {
+    hosts: Hosts,
+    revoked: bool,
+    key: HostKey,
+}
+
+#[derive(Clone, Debug)]
+enum Hosts {
+    /// Comma-separated patterns, possibly with wildcards and negations
+    Patterns(Vec<String>),
+    /// `|1|salt|hash`: HMAC-SHA1 of the host name, keyed with the salt
+    Hashed { salt: Vec<u8>, hash: Vec<u8> },
+}
+
+/// Parsed known_hosts file.
+#[derive(Clone, Debug, Default)]
+pub struct KnownHosts {
+    entries: Vec<Entry>,
+}
+
+impl KnownHosts {
+    /// Reads a known_hosts file; see [parse](Self::parse).
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| format!("Failed to read {path}: {err}"))?;
+        Ok(Self::parse(&contents))
+    }
+
+    /// Parses known_hosts lines. Comments, `@cert-authority` lines, and lines that cannot be
+    /// parsed are skipped, as `ssh` does.
+    pub fn parse(contents: &str) -> Self {
+        let mut entries = vec![];
+
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace().peekable();
+            let revoked = match fields.peek() {
+                Some(&"@revoked") => true,
+                Some(&"@cert-authority") => continue,
+                _ => false,
+            };
+            if revoked {
+                fields.next();
+            }
+
+            let entry = match (fields.next(), fields.next(), fields.next()) {
+                (Some(hosts), Some(_), Some(key)) => parse_hosts(hosts)
+                    .zip(STANDARD.decode(key).ok().and_then(|blob| HostKey::from_blob(&blob)))
+                    .map(|(hosts, key)| Entry { hosts, revoked, key }),
+                _ => None,
+            };
+            match entry {
+                Some(entry) => entries.push(entry),
+                None => log::warn!("known_hosts line {}: not a host key entry.", number + 1),
+            }
+        }
+
+        Self { entries }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Checks the host key a server at `address` (`ip:port`) presented.
+    ///
+    /// Returns [HostKeyMatch](Event::HostKeyMatch), [HostKeyMismatch](Event::HostKeyMismatch), or
+    /// [HostKeyUnknown](Event::HostKeyUnknown), with details for the timeline.
+    pub fn verify(&self, address: &str, key: &HostKey) -> (Event, String) {
+        let name = host_name(address);
+        let known: Vec<&Entry> = self.entries.iter().filter(|entry| entry.hosts.matches(&name)).collect();
+
+        if known.iter().any(|entry| entry.revoked && entry.key.sha256 == key.sha256) {
+            return (Event::HostKeyMismatch, format!("{name}: {} {} is revoked", key.key_type, key.sha256));
+        }
+        if known.iter().any(|entry| !entry.revoked && entry.key.sha256 == key.sha256) {
+            return (Event::HostKeyMatch, format!("{name}: {} {}", key.key_type, key.sha256));
+        }
+
+        let expected: Vec<String> = known.iter()
+            .filter(|entry| !entry.revoked)
+            .map(|entry| format!("{} {}", entry.key.key_type, entry.key.sha256))
+            .collect();
+        if expected.is_empty() {
+            (Event::HostKeyUnknown, format!("{name}: {} {}", key.key_type, key.sha256))
+        } else {
+            (Event::HostKeyMismatch, format!("{name}: got {} {}, known {}", key.key_type, key.sha256, expected.join(", ")))
+        }
+    }
+}
+
+impl Hosts {
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            Hosts::Hashed { salt, hash } => hmac_sha1(salt, name.as_bytes()).as_slice() == hash.as_slice(),
+            Hosts::Patterns(patterns) => {
+                let mut matched = false;
+                for pattern in patterns {
+                    match pattern.strip_prefix('!') {
+                        // A matching negation excludes the host, whatever else matches
+                        Some(negated) if glob(negated, name) => return false,
+                        Some(_) => {},
+                        None => matched |= glob(pattern, name),
+                    }
+                }
+                matched
+            },
+        }
+    }
+}
+
+fn parse_hosts(hosts: &str) -> Option<Hosts> {
+    match hosts.strip_prefix("|1|") {
+        Some(hashed) => {
+            let (salt, hash) = hashed.split_once('|')?;
+            Some(Hosts::Hashed { salt: STANDARD.decode(salt).ok()?, hash: STANDARD.decode(hash).ok()? })
+        },
+        None => Some(Hosts::Patterns(hosts.split(',').map(str::to_ascii_lowercase).collect())),
+    }
+}
+
+/// Name `ssh` looks the server up by: the IP, or `[ip]:port` if the port is not 22.
+fn host_name(address: &str) -> String {
+    match utils::split_endpoint(address) {
+        (ip, Some("22") | None) => ip.to_string(),
+        (ip, Some(port)) => format!("[{ip}]:{port}"),
+    }
+}
+
+/// Matches `name` against a pattern with `*` and `?` wildcards.
+fn glob(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// HMAC-SHA1, as used for hashed host names.
+fn hmac_sha1(key: &[u8], message: &[u8]) -> [u8; 20] {
+    const BLOCK: usize = 64;
+    let mut padded = [0u8; BLOCK];
+    if key.len() > BLOCK {
+        padded[..20].copy_from_slice(&sha1(key));
+    } else {
+        padded[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner: Vec<u8> = padded.iter().map(|byte| byte ^ 0x36).collect();
+    inner.extend(message);
+    let mut outer: Vec<u8> = padded.iter().map(|byte| byte ^ 0x5c).collect();
+    outer.extend(sha1(&inner));
+    sha1(&outer)
+}
+
+/// SHA-1 (FIPS 180-4); only used for known_hosts hashing, where it is mandated by the format.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend((data.len() as u64 * 8).to_be_bytes());
+
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in chunk.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, value) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::containers;
+    use crate::analyser::hostkey;
+
+    #[test]
+    fn test_known_hosts() {
+        use containers::Event;
+
+        let key = "AAAAC3NzaC1lZDI1NTE5AAAAIOindG7roIXjSv/Qg9qc3kNHlcc06+JN9xx3zUBBT3LQ";
+        let other = "AAAAC3NzaC1lZDI1NTE5AAAAIAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+        let known_hosts = KnownHosts::parse(&format!(
+            "# hashed entry for 192.0.2.10\n|1|AAECAwQFBgcICQoLDA0ODxAREhM=|TTB3WJSKiWPCN9rvqYmXex4Tsl4= ssh-ed25519 {key}\n10.0.0.*,!10.0.0.9 ssh-ed25519 {other} host\n2001:db8::5,[2001:db8::6]:2222 ssh-ed25519 {key}\nnot an entry"
+        ));
+        assert_eq!(3, known_hosts.len());
+
+        let blob = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, key).unwrap();
+        let host_key = hostkey::HostKey::from_blob(&blob).unwrap();
+        assert_eq!(Event::HostKeyMatch, known_hosts.verify("192.0.2.10:22", &host_key).0);
+        assert_eq!(Event::HostKeyMismatch, known_hosts.verify("10.0.0.5:22", &host_key).0);
+        assert_eq!(Event::HostKeyUnknown, known_hosts.verify("10.0.0.9:22", &host_key).0);
+        assert_eq!(Event::HostKeyUnknown, known_hosts.verify("10.0.0.5:2222", &host_key).0);
+        assert_eq!(Event::HostKeyMatch, known_hosts.verify("[2001:db8::5]:22", &host_key).0);
+        assert_eq!(Event::HostKeyMatch, known_hosts.verify("[2001:db8::6]:2222", &host_key).0);
+        assert_eq!(Event::HostKeyUnknown, known_hosts.verify("[2001:db8::6]:22", &host_key).0);
+    }
+}
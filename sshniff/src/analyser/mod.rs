@@ -4,3 +4,38 @@ pub mod utils;
 pub mod core;
 pub mod scan;
 pub mod containers;
+pub mod records;
+pub mod options;
+pub mod tags;
+pub mod compare;
+pub mod transfers;
+pub mod nat;
+pub mod spray;
+pub mod password;
+pub mod health;
+pub mod discovery;
+pub mod stats;
+pub mod ja4ssh;
+pub mod software;
+pub mod advisories;
+pub mod audit;
+pub mod tuning;
+pub mod cipher_model;
+pub mod operators;
+pub mod rhythm;
+pub mod intel;
+pub mod hostkey;
+pub mod knownhosts;
+pub mod sampling;
+pub mod filter;
+pub mod packet;
+pub mod native;
+pub mod source;
+pub mod tunnel;
+pub mod rollover;
+pub mod compression;
+pub mod commands;
+pub mod nested;
+pub mod exfil;
+#[cfg(feature = "inference")]
+pub mod inference;
@@ -0,0 +1,224 @@
+//! Disambiguation of sessions sharing a client IP, e.g. behind NAT.
+//!
+//! Sessions are grouped by client software (HASSH and version banner), and each group is split
+//! further by typing speed, since distinct typists rarely share a median keystroke latency.
+//! Overlapping sessions are reported alongside, but are not counted as separate machines, since
+//! a single user can keep several sessions open.
+use super::containers::KeystrokeType;
+use super::core::SshSession;
+use super::stats;
+use super::utils;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Factor by which median keystroke latencies may differ and still be attributed to one typist.
+pub const TYPIST_LATENCY_RATIO: f64 = 1.3;
+
+/// Sessions of a client IP attributed to one client machine or user.
+#[derive(Debug, Serialize)]
+pub struct ClientGroup {
+    pub hassh_c: String,
+    /// Client protocol version banner
+    pub version: String,
+    /// Median keystroke latency (μs) over the group's sessions; `None` without keystrokes
+    pub typing_median_us: Option<i64>,
+    /// Largest number of the group's sessions open at the same time
+    pub concurrent: usize,
+    /// Streams of the group's sessions
+    pub sessions: Vec<u32>,
+}
+
+/// Estimate of the clients behind one IP.
+#[derive(Debug, Serialize)]
+pub struct NatReport {
+    pub client_ip: String,
+    pub sessions: usize,
+    pub estimated_clients: usize,
+    pub groups: Vec<ClientGroup>,
+}
+
+/// Builds a [NatReport] for every client IP with at least `min_sessions` sessions.
+pub fn nat_reports(sessions: &BTreeMap<u32, SshSession>, min_sessions: usize) -> Vec<NatReport> {
+    let mut by_ip: BTreeMap<&str, Vec<&SshSession>> = BTreeMap::new();
+    for session in sessions.values() {
+        by_ip.entry(utils::ip_of(&session.src)).or_default().push(session);
+    }
+
+    by_ip.into_iter()
+        .filter(|(_, sessions)| sessions.len() >= min_sessions)
+        .map(|(ip, sessions)| {
+            let mut by_software: BTreeMap<(&str, &str), Vec<&SshSession>> = BTreeMap::new();
+            for session in &sessions {
+                by_software.entry((&session.hassh_c, &session.protocols.0)).or_default().push(session);
+            }
+
+            let groups: Vec<ClientGroup> = by_software.into_iter()
+                .flat_map(|((hassh_c, version), sessions)| {
+                    split_by_typist(sessions).into_iter().map(move |typist| ClientGroup {
+                        hassh_c: hassh_c.to_string(),
+                        version: version.to_string(),
+                        typing_median_us: typing_median(&typist),
+                        concurrent: max_concurrent(&typist),
+                        sessions: typist.iter().map(|session| session.stream).collect(),
+                    })
+                })
+                .collect();
+
+            NatReport {
+                client_ip: ip.to_string(),
+                sessions: sessions.len(),
+                estimated_clients: groups.len(),
+                groups,
+            }
+        })
+        .collect()
+}
+
+/// Splits sessions into groups of similar typing speed.
+///
+/// Sessions without keystrokes cannot be told apart and join the first group.
+fn split_by_typist(sessions: Vec<&SshSession>) -> Vec<Vec<&SshSession>> {
+    let (mut typed, untyped): (Vec<_>, Vec<_>) = sessions.into_iter()
+        .map(|session| (typing_median(&[session]), session))
+        .partition(|(median, _)| median.is_some());
+    typed.sort_by_key(|(median, _)| *median);
+
+    let mut groups: Vec<(i64, Vec<&SshSession>)> = Vec::new();
+    for (median, session) in typed {
+        let median = median.unwrap();
+        match groups.last_mut() {
+            Some((first, group)) if median as f64 <= *first as f64 * TYPIST_LATENCY_RATIO => group.push(session),
+            _ => groups.push((median, vec![session])),
+        }
+    }
+
+    let mut groups: Vec<Vec<&SshSession>> = groups.into_iter().map(|(_, group)| group).collect();
+    let untyped: Vec<&SshSession> = untyped.into_iter().map(|(_, session)| session).collect();
+    match groups.first_mut() {
+        Some(first) => first.extend(untyped),
+        None => groups.push(untyped),
+    }
+
+    groups
+}
+
+/// Confidence-weighted median latency of regular keystrokes over all sequences of the given
+/// sessions. Enter, deletions and arrow keys follow pauses or repeat, so they would skew it.
+fn typing_median(sessions: &[&SshSession]) -> Option<i64> {
+    let keystrokes = sessions.iter()
+        .flat_map(|session| &session.keystroke_data)
+        .flat_map(|sequence| &sequence.keystrokes)
+        .filter(|keystroke| keystroke.k_type == KeystrokeType::Keystroke);
+
+    let typing = stats::latency_stats(keystrokes, stats::MIN_LATENCY_CONFIDENCE);
+    (typing.samples > 0).then_some(typing.median_us)
+}
+
+/// Largest number of sessions open at the same time.
+fn max_concurrent(sessions: &[&SshSession]) -> usize {
+    // Start and end are formatted timestamps, which sort chronologically. Starts sort before
+    // ends at the same time, so that touching sessions count as overlapping.
+    let mut edges: Vec<(&str, bool)> = sessions.iter()
+        .flat_map(|session| [(session.start_utc.as_str(), false), (session.end_utc.as_str(), true)])
+        .collect();
+    edges.sort();
+
+    let mut open = 0usize;
+    let mut most = 0;
+    for (_, is_end) in edges {
+        if is_end {
+            open = open.saturating_sub(1);
+        } else {
+            open += 1;
+            most = most.max(open);
+        }
+    }
+
+    most
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::containers::tests::keystroke;
+    use crate::analyser::core::tests::session;
+
+    /// Session from `src`, open from `start` to `end` minutes past noon, with five keystrokes
+    /// `latency_us` apart if given.
+    fn session_at(stream: u32, src: &str, (start, end): (u32, u32), latency_us: Option<i64>) -> SshSession {
+        let mut session = session();
+        session.stream = stream;
+        session.src = src.to_string();
+        session.start_utc = format!("2024-01-31 12:{start:02}:00");
+        session.end_utc = format!("2024-01-31 12:{end:02}:00");
+        session.keystroke_data[0].keystrokes = latency_us.map_or(vec![], |latency_us| {
+            (0..5).map(|index| keystroke(KeystrokeType::Keystroke, if index == 0 { 0 } else { latency_us })).collect()
+        });
+        session
+    }
+
+    #[test]
+    fn test_nat_reports() {
+        let sessions: BTreeMap<u32, SshSession> = [
+            // Two typists behind one IP, in overlapping sessions
+            session_at(1, "192.168.1.10:50000", (0, 10), Some(100_000)),
+            session_at(2, "192.168.1.10:50001", (5, 15), Some(200_000)),
+            // One typist at a similar speed, and a session without typing
+            session_at(3, "10.1.1.1:50000", (0, 10), Some(100_000)),
+            session_at(4, "10.1.1.1:50001", (20, 30), Some(120_000)),
+            session_at(5, "10.1.1.1:50002", (40, 50), None),
+            // A single session, below min_sessions
+            session_at(6, "172.16.0.1:50000", (0, 10), Some(100_000)),
+        ].into_iter().map(|session| (session.stream, session)).collect();
+
+        let reports = nat_reports(&sessions, 2);
+        let ips: Vec<&str> = reports.iter().map(|report| report.client_ip.as_str()).collect();
+        assert_eq!(vec!["10.1.1.1", "192.168.1.10"], ips);
+
+        let single = &reports[0];
+        assert_eq!((3, 1), (single.sessions, single.estimated_clients));
+        assert_eq!(vec![3, 4, 5], single.groups[0].sessions);
+        assert_eq!(1, single.groups[0].concurrent);
+
+        let shared = &reports[1];
+        assert_eq!((2, 2), (shared.sessions, shared.estimated_clients));
+        let groups: Vec<(Vec<u32>, Option<i64>)> = shared.groups.iter().map(|group| (group.sessions.clone(), group.typing_median_us)).collect();
+        assert_eq!(vec![(vec![1], Some(100_000)), (vec![2], Some(200_000))], groups);
+    }
+
+    #[test]
+    fn test_split_by_typist() {
+        let sessions = [
+            session_at(1, "10.1.1.1:1", (0, 1), Some(200_000)),
+            session_at(2, "10.1.1.1:2", (0, 1), None),
+            session_at(3, "10.1.1.1:3", (0, 1), Some(100_000)),
+            session_at(4, "10.1.1.1:4", (0, 1), Some(125_000)),
+        ];
+        let streams = |groups: Vec<Vec<&SshSession>>| groups.iter().map(|group| group.iter().map(|session| session.stream).collect()).collect::<Vec<Vec<u32>>>();
+
+        // Sorted by speed, within TYPIST_LATENCY_RATIO of the fastest of each group; sessions
+        // without keystrokes join the first
+        assert_eq!(vec![vec![3, 4, 2], vec![1]], streams(split_by_typist(sessions.iter().collect())));
+        assert_eq!(vec![vec![2]], streams(split_by_typist(vec![&sessions[1]])));
+    }
+
+    #[test]
+    fn test_typing_median() {
+        let mut session = session_at(1, "10.1.1.1:1", (0, 1), Some(150_000));
+        // Pauses before Enter are left out
+        session.keystroke_data[0].keystrokes.push(keystroke(KeystrokeType::Enter, 2_000_000));
+        assert_eq!(Some(150_000), typing_median(&[&session]));
+        assert_eq!(None, typing_median(&[&session_at(2, "10.1.1.1:2", (0, 1), None)]));
+    }
+
+    #[test]
+    fn test_max_concurrent() {
+        let concurrent = [session_at(1, "10.1.1.1:1", (0, 10), None), session_at(2, "10.1.1.1:2", (5, 15), None), session_at(3, "10.1.1.1:3", (8, 20), None)];
+        let back_to_back = [session_at(1, "10.1.1.1:1", (0, 10), None), session_at(2, "10.1.1.1:2", (11, 20), None), session_at(3, "10.1.1.1:3", (21, 30), None)];
+        let touching = [session_at(1, "10.1.1.1:1", (0, 10), None), session_at(2, "10.1.1.1:2", (10, 20), None)];
+
+        assert_eq!(3, max_concurrent(&concurrent.iter().collect::<Vec<_>>()));
+        assert_eq!(1, max_concurrent(&back_to_back.iter().collect::<Vec<_>>()));
+        assert_eq!(2, max_concurrent(&touching.iter().collect::<Vec<_>>()));
+    }
+}
@@ -0,0 +1,1041 @@
+//! Built-in capture reader, the `native` [engine](super::options::Engine).
+//!
+//! Reads pcap and pcapng captures and dissects them into the [Packet]s the tshark engine would
+//! produce, as far as the scans look at them, without tshark installed. Only the packets tshark's
+//! display filter would let through are returned: those with an SSH layer, SYNs, and bare ACKs,
+//! leaving out retransmissions.
+//!
+//! Links: Ethernet (with VLAN tags), Linux cooked (SLL and SLL2), BSD loopback, and raw IP.
+//! Anything else, e.g. 802.11 or other capture formats, is [Unsupported](NativeError::Unsupported),
+//! for the caller to fall back to tshark. Fragmented IP packets are skipped. Traffic mirrored
+//! through GRE, ERSPAN (types I to III), or VXLAN on its standard port is unwrapped, keeping the
+//! `vlan`, outer `ip`, `gre`, `erspan`, and `vxlan` layers before the inner IP layer, as tshark
+//! does.
+//!
+//! TCP streams are numbered in order of their first packet, like tcp.stream, and sequence numbers
+//! are made relative to the first packet of each direction. SSH is recognised on port 22, on the
+//! [ssh_ports](Decoding::ssh_ports) given, and by its banner. Plaintext records up to New Keys are
+//! reassembled, so that the segment completing one carries its message code and, for KEXINITs and
+//! KEX replies, the offered algorithms and the host key; every segment after New Keys carries an
+//! empty SSH layer.
+use super::hostkey;
+use super::options::Decoding;
+use super::packet::{Layer, Packet};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const SECTION_HEADER: u32 = 0x0A0D0D0A;
+const INTERFACE_DESCRIPTION: u32 = 1;
+/// Obsolete Packet Block, with the layout of the enhanced one but a 16-bit interface ID
+const PACKET: u32 = 2;
+const SIMPLE_PACKET: u32 = 3;
+const ENHANCED_PACKET: u32 = 6;
+/// UDP port VXLAN is dissected on, as by tshark
+const VXLAN_PORT: u16 = 4789;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B3C4D;
+
+const IF_TSRESOL: u16 = 9;
+
+const PCAP_MICROSECONDS: u32 = 0xA1B2C3D4;
+const PCAP_NANOSECONDS: u32 = 0xA1B23C4D;
+
+/// Largest block or record accepted, so that a corrupt length does not exhaust memory.
+const MAX_RECORD: usize = 1 << 28;
+
+/// Largest plaintext SSH packet before New Keys; larger lengths mean the stream is not SSH, or not
+/// from its start.
+const MAX_PLAINTEXT_PACKET: usize = 35000;
+
+/// Longest banner, including the lines servers may send before it.
+const MAX_BANNER: usize = 8192;
+
+const SSH_MSG_NEWKEYS: u8 = 21;
+const SSH_MSG_KEXINIT: u8 = 20;
+/// KEXDH_REPLY and KEX_ECDH_REPLY, but also the group of a group exchange
+const SSH_MSG_KEX_REPLY: u8 = 31;
+const SSH_MSG_KEX_DH_GEX_REPLY: u8 = 33;
+
+/// Name-lists of a KEXINIT, in wire order, under their tshark field names.
+const KEXINIT_FIELDS: [&str; 10] = [
+    "ssh.kex_algorithms",
+    "ssh.server_host_key_algorithms",
+    "ssh.encryption_algorithms_client_to_server",
+    "ssh.encryption_algorithms_server_to_client",
+    "ssh.mac_algorithms_client_to_server",
+    "ssh.mac_algorithms_server_to_client",
+    "ssh.compression_algorithms_client_to_server",
+    "ssh.compression_algorithms_server_to_client",
+    "ssh.languages_client_to_server",
+    "ssh.languages_server_to_client",
+];
+
+const FLAG_FIN: u16 = 0x001;
+const FLAG_SYN: u16 = 0x002;
+const FLAG_RST: u16 = 0x004;
+const FLAG_ACK: u16 = 0x010;
+
+/// Why a capture could not be read natively.
+#[derive(Debug)]
+pub enum NativeError {
+    /// The capture has a format or link type only tshark reads
+    Unsupported(String),
+    /// The capture could not be read
+    Io(io::Error),
+}
+
+impl fmt::Display for NativeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NativeError::Unsupported(reason) => write!(f, "{reason}"),
+            NativeError::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl From<io::Error> for NativeError {
+    fn from(err: io::Error) -> Self {
+        NativeError::Io(err)
+    }
+}
+
+/// Reads the packets of a capture, dissected like tshark would; see the [module](self) docs.
+///
+/// Reading stops at the first error, which [finish](NativeReader::finish) returns.
+pub struct NativeReader {
+    frames: FrameReader<BufReader<File>>,
+    tracker: TcpTracker,
+    frame_number: u64,
+    error: Option<NativeError>,
+}
+
+impl NativeReader {
+    /// Opens a pcap or pcapng capture, recognising SSH on the ports of `decoding` too.
+    pub fn open(path: &str, decoding: Decoding) -> Result<Self, NativeError> {
+        let frames = FrameReader::new(BufReader::new(File::open(path)?))?;
+        let tracker = TcpTracker { decoding, ..Default::default() };
+        Ok(Self { frames, tracker, frame_number: 0, error: None })
+    }
+
+    /// Ends reading, returning the error that stopped it early, if any.
+    pub fn finish(self) -> Result<(), NativeError> {
+        match self.error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Iterator for NativeReader {
+    type Item = Packet;
+
+    fn next(&mut self) -> Option<Packet> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        loop {
+            let frame = match self.frames.next_frame() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return None,
+                Err(err) => {
+                    self.error = Some(err);
+                    return None;
+                },
+            };
+            self.frame_number += 1;
+
+            let mut encapsulation = vec![];
+            let ip = match link_payload(frame.link_type, &frame.data, &mut encapsulation) {
+                Ok(Some(ip)) => ip,
+                Ok(None) => continue,
+                Err(err) => {
+                    self.error = Some(err);
+                    return None;
+                },
+            };
+            let segment = match decapsulate(ip, &mut encapsulation, 0).and_then(parse_tcp) {
+                Some(segment) => segment,
+                None => continue,
+            };
+            if let Some(packet) = self.tracker.add(&segment, &frame, self.frame_number, encapsulation) {
+                return Some(packet);
+            }
+        }
+    }
+}
+
+/// A captured frame, as stored in the capture.
+struct Frame {
+    /// UNIX timestamp (μs)
+    timestamp: i64,
+    link_type: u32,
+    data: Vec<u8>,
+    original_length: u32,
+}
+
+/// Interface of a pcapng section.
+struct Interface {
+    link_type: u32,
+    /// Timestamp units per second
+    resolution: u64,
+}
+
+/// Capture file format, with what is needed to read its records.
+enum Format {
+    Pcap { big_endian: bool, nanos: bool, link_type: u32 },
+    Pcapng { big_endian: bool, interfaces: Vec<Interface> },
+}
+
+/// Reads the frames of a pcap or pcapng capture one by one.
+struct FrameReader<R: Read> {
+    reader: R,
+    format: Format,
+    /// Timestamp of the last frame, for simple packet blocks, which have none
+    last_timestamp: i64,
+}
+
+impl<R: Read> FrameReader<R> {
+    fn new(mut reader: R) -> Result<Self, NativeError> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        let (little, big) = (u32::from_le_bytes(magic), u32::from_be_bytes(magic));
+        let is_pcap = |magic: u32| magic == PCAP_MICROSECONDS || magic == PCAP_NANOSECONDS;
+
+        let format = if little == SECTION_HEADER {
+            Format::Pcapng { big_endian: read_section_header(&mut reader)?, interfaces: vec![] }
+        } else if is_pcap(little) || is_pcap(big) {
+            let big_endian = is_pcap(big);
+            let mut header = [0u8; 20];
+            reader.read_exact(&mut header)?;
+            Format::Pcap {
+                big_endian,
+                nanos: little == PCAP_NANOSECONDS || big == PCAP_NANOSECONDS,
+                link_type: read_u32(&header[16..20], big_endian) & 0x0fff_ffff,
+            }
+        } else {
+            return Err(NativeError::Unsupported("not a pcap or pcapng capture".to_string()));
+        };
+
+        Ok(Self { reader, format, last_timestamp: 0 })
+    }
+
+    /// Next frame; `None` at the end of the capture. A capture cut off in the middle of a record
+    /// is an error once the frames before it were read.
+    fn next_frame(&mut self) -> Result<Option<Frame>, NativeError> {
+        match self.format {
+            Format::Pcap { big_endian, nanos, link_type } => {
+                let mut header = [0u8; 16];
+                if !read_or_eof(&mut self.reader, &mut header)? {
+                    return Ok(None);
+                }
+                let seconds = i64::from(read_u32(&header[0..4], big_endian));
+                let fraction = i64::from(read_u32(&header[4..8], big_endian));
+                let captured = read_u32(&header[8..12], big_endian) as usize;
+                if captured > MAX_RECORD {
+                    return Err(invalid("pcap record too long"));
+                }
+                let mut data = vec![0u8; captured];
+                read_rest(&mut self.reader, &mut data)?;
+
+                let timestamp = seconds * 1_000_000 + if nanos { fraction / 1000 } else { fraction };
+                Ok(Some(Frame { timestamp, link_type, data, original_length: read_u32(&header[12..16], big_endian) }))
+            },
+            Format::Pcapng { .. } => self.next_pcapng_frame(),
+        }
+    }
+
+    fn next_pcapng_frame(&mut self) -> Result<Option<Frame>, NativeError> {
+        loop {
+            let mut header = [0u8; 8];
+            if !read_or_eof(&mut self.reader, &mut header)? {
+                return Ok(None);
+            }
+            let (big_endian, interfaces) = match &mut self.format {
+                Format::Pcapng { big_endian, interfaces } => (big_endian, interfaces),
+                Format::Pcap { .. } => unreachable!("only called on pcapng captures"),
+            };
+
+            if u32::from_le_bytes(header[0..4].try_into().expect("4 bytes")) == SECTION_HEADER {
+                // The length has to be read in the byte order of the new section
+                let mut rest = [0u8; 4];
+                self.reader.read_exact(&mut rest)?;
+                *big_endian = u32::from_be_bytes(rest) == BYTE_ORDER_MAGIC;
+                let length = read_u32(&header[4..8], *big_endian) as usize;
+                skip(&mut self.reader, length.saturating_sub(12))?;
+                interfaces.clear();
+                continue;
+            }
+
+            let big_endian = *big_endian;
+            let block_type = read_u32(&header[0..4], big_endian);
+            let length = read_u32(&header[4..8], big_endian) as usize;
+            if !(12..=MAX_RECORD).contains(&length) {
+                return Err(invalid("invalid pcapng block length"));
+            }
+            let mut body = vec![0u8; length - 8];
+            read_rest(&mut self.reader, &mut body)?;
+            let body = &body[..body.len() - 4];
+
+            match block_type {
+                INTERFACE_DESCRIPTION => {
+                    let link_type = u32::from(read_u16(body.get(0..2).ok_or_else(|| invalid("short interface block"))?, big_endian));
+                    let resolution = body.get(8..).map_or(1_000_000, |options| timestamp_resolution(options, big_endian));
+                    interfaces.push(Interface { link_type, resolution });
+                },
+                ENHANCED_PACKET | PACKET => {
+                    let fields = body.get(0..20).ok_or_else(|| invalid("short packet block"))?;
+                    let interface = if block_type == ENHANCED_PACKET { read_u32(&fields[0..4], big_endian) } else { u32::from(read_u16(&fields[0..2], big_endian)) };
+                    let interface = interfaces.get(interface as usize).ok_or_else(|| invalid("packet on an undescribed interface"))?;
+                    let units = (u64::from(read_u32(&fields[4..8], big_endian)) << 32) | u64::from(read_u32(&fields[8..12], big_endian));
+                    let captured = read_u32(&fields[12..16], big_endian) as usize;
+                    let data = body.get(20..20 + captured).ok_or_else(|| invalid("packet block shorter than its packet"))?;
+
+                    let timestamp = (u128::from(units) * 1_000_000 / u128::from(interface.resolution.max(1))) as i64;
+                    self.last_timestamp = timestamp;
+                    return Ok(Some(Frame { timestamp, link_type: interface.link_type, data: data.to_vec(), original_length: read_u32(&fields[16..20], big_endian) }));
+                },
+                SIMPLE_PACKET => {
+                    let interface = interfaces.first().ok_or_else(|| invalid("packet on an undescribed interface"))?;
+                    let original_length = read_u32(body.get(0..4).ok_or_else(|| invalid("short packet block"))?, big_endian);
+                    let data = &body[4..body.len().min(4 + original_length as usize)];
+                    return Ok(Some(Frame { timestamp: self.last_timestamp, link_type: interface.link_type, data: data.to_vec(), original_length }));
+                },
+                _ => continue,
+            }
+        }
+    }
+}
+
+/// Reads the rest of a pcapng section header after its type, returning whether it is big-endian.
+fn read_section_header(reader: &mut impl Read) -> Result<bool, NativeError> {
+    let mut fields = [0u8; 8];
+    reader.read_exact(&mut fields)?;
+    let big_endian = u32::from_be_bytes(fields[4..8].try_into().expect("4 bytes")) == BYTE_ORDER_MAGIC;
+    let length = read_u32(&fields[0..4], big_endian) as usize;
+    skip(reader, length.saturating_sub(12))?;
+    Ok(big_endian)
+}
+
+/// Timestamp units per second from the `if_tsresol` option of an interface; microseconds if absent.
+fn timestamp_resolution(mut options: &[u8], big_endian: bool) -> u64 {
+    while options.len() >= 4 {
+        let code = read_u16(&options[0..2], big_endian);
+        let length = read_u16(&options[2..4], big_endian) as usize;
+        if code == 0 {
+            break;
+        }
+        if code == IF_TSRESOL && length == 1 {
+            if let Some(&resolution) = options.get(4) {
+                return if resolution & 0x80 != 0 {
+                    1u64.checked_shl(u32::from(resolution & 0x7f)).unwrap_or(u64::MAX)
+                } else {
+                    10u64.checked_pow(u32::from(resolution)).unwrap_or(u64::MAX)
+                };
+            }
+        }
+        options = options.get(4 + length.div_ceil(4) * 4..).unwrap_or_default();
+    }
+    1_000_000
+}
+
+/// The IP packet a frame carries; `None` for frames of other protocols. VLAN tags are added to
+/// `encapsulation`.
+fn link_payload<'a>(link_type: u32, data: &'a [u8], encapsulation: &mut Vec<Layer>) -> Result<Option<&'a [u8]>, NativeError> {
+    let ip = match link_type {
+        // BSD loopback: the address family, in either byte order, which the IP version makes up for
+        0 | 108 => data.get(4..),
+        1 => ethernet_payload(data, encapsulation),
+        // Raw IP, under its various numbers
+        12 | 14 | 101 | 228 | 229 => Some(data),
+        // Linux cooked capture
+        113 => data.get(14..16).filter(|protocol| is_ip(read_u16(protocol, true))).and_then(|_| data.get(16..)),
+        276 => data.get(0..2).filter(|protocol| is_ip(read_u16(protocol, true))).and_then(|_| data.get(20..)),
+        other => return Err(NativeError::Unsupported(format!("link type {other} is not supported natively"))),
+    };
+    Ok(ip)
+}
+
+/// The IP packet an Ethernet frame carries, adding a `vlan` layer per VLAN tag to `encapsulation`.
+fn ethernet_payload<'a>(data: &'a [u8], encapsulation: &mut Vec<Layer>) -> Option<&'a [u8]> {
+    let mut offset = 12;
+    let mut ether_type = data.get(offset..offset + 2).map(|bytes| read_u16(bytes, true));
+    while let Some(0x8100 | 0x88a8 | 0x9100) = ether_type {
+        let mut vlan = Layer::new("vlan");
+        vlan.add("vlan.id", read_u16(data.get(offset + 2..offset + 4)?, true) & 0x0fff);
+        encapsulation.push(vlan);
+        offset += 4;
+        ether_type = data.get(offset..offset + 2).map(|bytes| read_u16(bytes, true));
+    }
+    ether_type.filter(|ether_type| is_ip(*ether_type)).and_then(|_| data.get(offset + 2..))
+}
+
+/// Unwraps an IP packet tunnelling another through GRE, ERSPAN, or VXLAN, adding the outer IP
+/// layer and those of the tunnel to `encapsulation`; other packets are returned as they are.
+/// `None` if the tunnel does not carry IP.
+fn decapsulate<'a>(ip: &'a [u8], encapsulation: &mut Vec<Layer>, depth: usize) -> Option<&'a [u8]> {
+    let (source, destination, protocol, payload) = ip_header(ip)?;
+    let inner = match protocol {
+        47 if depth < 4 => {
+            let flags = read_u16(payload.get(0..2)?, true);
+            let protocol = read_u16(payload.get(2..4)?, true);
+            let mut offset = 4 + if flags & 0x8000 != 0 { 4 } else { 0 };
+            let mut gre = Layer::new("gre");
+            gre.add("gre.proto", format!("0x{protocol:04x}"));
+            if flags & 0x2000 != 0 {
+                gre.add("gre.key", read_u32(payload.get(offset..offset + 4)?, true));
+                offset += 4;
+            }
+            let sequenced = flags & 0x1000 != 0;
+            if sequenced {
+                offset += 4;
+            }
+            let tunnelled = payload.get(offset..)?;
+            let mut layers = vec![gre];
+            let inner = match protocol {
+                0x0800 | 0x86dd => Some(tunnelled),
+                // Transparent Ethernet bridging
+                0x6558 => ethernet_payload(tunnelled, &mut layers),
+                // ERSPAN type I has no header of its own, and no sequence number to tell it by
+                0x88be | 0x22eb => {
+                    let mut erspan = Layer::new("erspan");
+                    let header_length = match (protocol, sequenced) {
+                        (0x88be, false) => 0,
+                        (0x88be, true) => 8,
+                        // Type III, with the platform specific subheader if the O flag is set
+                        _ => 12 + if tunnelled.get(11)? & 0x01 != 0 { 8 } else { 0 },
+                    };
+                    if header_length > 0 {
+                        erspan.add("erspan.spanid", read_u16(tunnelled.get(2..4)?, true) & 0x03ff);
+                    }
+                    layers.push(erspan);
+                    ethernet_payload(tunnelled.get(header_length..)?, &mut layers)
+                },
+                _ => None,
+            };
+            (layers, inner?)
+        },
+        17 if depth < 4 && payload.get(2..4).is_some_and(|port| read_u16(port, true) == VXLAN_PORT) => {
+            let header = payload.get(8..16)?;
+            let mut vxlan = Layer::new("vxlan");
+            vxlan.add("vxlan.vni", read_u32(&header[4..8], true) >> 8);
+            let mut layers = vec![vxlan];
+            let inner = ethernet_payload(payload.get(16..)?, &mut layers)?;
+            (layers, inner)
+        },
+        _ => return Some(ip),
+    };
+
+    let (name, source_field, destination_field) = match source {
+        IpAddr::V4(_) => ("ip", "ip.src", "ip.dst"),
+        IpAddr::V6(_) => ("ipv6", "ipv6.src", "ipv6.dst"),
+    };
+    let mut outer = Layer::new(name);
+    outer.add(source_field, source);
+    outer.add(destination_field, destination);
+    encapsulation.push(outer);
+    encapsulation.extend(inner.0);
+    decapsulate(inner.1, encapsulation, depth + 1)
+}
+
+/// Whether an EtherType is IPv4 or IPv6.
+fn is_ip(ether_type: u16) -> bool {
+    ether_type == 0x0800 || ether_type == 0x86dd
+}
+
+/// Endpoint of a TCP connection: address and port.
+type Endpoint = (IpAddr, u16);
+
+/// The parts of a TCP segment that are dissected.
+struct Segment<'a> {
+    source: Endpoint,
+    destination: Endpoint,
+    seq: u32,
+    ack: u32,
+    flags: u16,
+    mss: Option<u16>,
+    payload: &'a [u8],
+}
+
+/// Splits an IP packet into its source, destination, protocol, and payload; `None` for fragments.
+fn ip_header(ip: &[u8]) -> Option<(IpAddr, IpAddr, u8, &[u8])> {
+    match ip.first()? >> 4 {
+        4 => {
+            let header_length = usize::from(ip[0] & 0x0f) * 4;
+            let total_length = usize::from(read_u16(ip.get(2..4)?, true));
+            // More fragments, or a fragment offset
+            if read_u16(ip.get(6..8)?, true) & 0x3fff != 0 {
+                return None;
+            }
+            // Segmentation offload leaves the total length at 0
+            let end = if total_length == 0 { ip.len() } else { total_length.min(ip.len()) };
+            let source: [u8; 4] = ip.get(12..16)?.try_into().ok()?;
+            let destination: [u8; 4] = ip.get(16..20)?.try_into().ok()?;
+            Some((IpAddr::V4(Ipv4Addr::from(source)), IpAddr::V4(Ipv4Addr::from(destination)), *ip.get(9)?, ip.get(header_length..end)?))
+        },
+        6 => {
+            let payload_length = usize::from(read_u16(ip.get(4..6)?, true));
+            let end = if payload_length == 0 { ip.len() } else { (40 + payload_length).min(ip.len()) };
+            let mut next_header = *ip.get(6)?;
+            let mut offset = 40;
+            // Hop-by-hop, routing, and destination options headers
+            while matches!(next_header, 0 | 43 | 60) {
+                let header = ip.get(offset..offset + 2)?;
+                next_header = header[0];
+                offset += (usize::from(header[1]) + 1) * 8;
+            }
+            let source: [u8; 16] = ip.get(8..24)?.try_into().ok()?;
+            let destination: [u8; 16] = ip.get(24..40)?.try_into().ok()?;
+            Some((IpAddr::V6(Ipv6Addr::from(source)), IpAddr::V6(Ipv6Addr::from(destination)), next_header, ip.get(offset..end)?))
+        },
+        _ => None,
+    }
+}
+
+/// Dissects the TCP segment in an IP packet; `None` for other protocols and fragments.
+fn parse_tcp(ip: &[u8]) -> Option<Segment<'_>> {
+    let (source, destination, protocol, tcp) = ip_header(ip)?;
+    if protocol != 6 {
+        return None;
+    }
+
+    let header_length = usize::from(tcp.get(12)? >> 4) * 4;
+    let options = tcp.get(20..header_length)?;
+    let mut mss = None;
+    let mut index = 0;
+    while let Some(&kind) = options.get(index) {
+        match kind {
+            0 => break,
+            1 => index += 1,
+            _ => {
+                let length = usize::from(*options.get(index + 1)?);
+                if kind == 2 && length == 4 {
+                    mss = Some(read_u16(options.get(index + 2..index + 4)?, true));
+                }
+                index += length.max(2);
+            },
+        }
+    }
+
+    Some(Segment {
+        source: (source, read_u16(&tcp[0..2], true)),
+        destination: (destination, read_u16(&tcp[2..4], true)),
+        seq: read_u32(&tcp[4..8], true),
+        ack: read_u32(&tcp[8..12], true),
+        flags: read_u16(&tcp[12..14], true) & 0x01ff,
+        mss,
+        payload: tcp.get(header_length..)?,
+    })
+}
+
+/// Whether sequence number `a` comes after `b`, allowing for wraparound.
+fn seq_after(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+/// TCP conversations seen so far, by their endpoints in sorted order.
+#[derive(Default)]
+struct TcpTracker {
+    conversations: HashMap<(Endpoint, Endpoint), Conversation>,
+    next_id: u32,
+    decoding: Decoding,
+}
+
+/// A TCP conversation: tcp.stream and both directions.
+struct Conversation {
+    id: u32,
+    /// Source of the first packet; its direction is `flows[0]`
+    first: Endpoint,
+    flows: [Flow; 2],
+    /// Whether the conversation carries SSH; `None` until its first payload
+    ssh: Option<bool>,
+    /// Whether a FIN or RST was seen, so that a new SYN starts a new conversation
+    closing: bool,
+}
+
+/// One direction of a TCP conversation.
+#[derive(Default)]
+struct Flow {
+    /// Sequence number tcp.seq is relative to
+    base: Option<u32>,
+    /// Initial sequence number, from the SYN
+    isn: Option<u32>,
+    /// Sequence number following the highest one sent, to recognise retransmissions
+    next_seq: Option<u32>,
+    /// Segments not acknowledged yet: the sequence number following each, and when it was sent
+    unacked: VecDeque<(u32, i64)>,
+    ssh: SshFlow,
+}
+
+impl TcpTracker {
+    /// Tracks a segment, returning its packet if tshark's display filter would let it through,
+    /// with the `encapsulation` layers the segment was found under.
+    fn add(&mut self, segment: &Segment, frame: &Frame, frame_number: u64, encapsulation: Vec<Layer>) -> Option<Packet> {
+        let key = if segment.source <= segment.destination { (segment.source, segment.destination) } else { (segment.destination, segment.source) };
+        let opening = segment.flags & (FLAG_SYN | FLAG_ACK) == FLAG_SYN;
+        let reused = self.conversations.get(&key).is_some_and(|conversation| {
+            let isn = conversation.flows[conversation.direction(segment.source)].isn;
+            opening && (conversation.closing || isn.is_some_and(|isn| isn != segment.seq))
+        });
+        if reused || !self.conversations.contains_key(&key) {
+            let conversation = Conversation { id: self.next_id, first: segment.source, flows: Default::default(), ssh: None, closing: false };
+            self.conversations.insert(key, conversation);
+            self.next_id += 1;
+        }
+        let conversation = self.conversations.get_mut(&key).expect("inserted above");
+        let direction = conversation.direction(segment.source);
+
+        let length = segment.payload.len() as u32;
+        let syn = segment.flags & FLAG_SYN != 0;
+        let seq_end = segment.seq.wrapping_add(length + u32::from(syn) + u32::from(segment.flags & FLAG_FIN != 0));
+
+        // Round trip time of the oldest segments this one acknowledges
+        let mut ack_rtt = None;
+        let peer_base = conversation.flows[1 - direction].base;
+        if segment.flags & FLAG_ACK != 0 {
+            let unacked = &mut conversation.flows[1 - direction].unacked;
+            while let Some(&(end, sent)) = unacked.front() {
+                if seq_after(end, segment.ack) {
+                    break;
+                }
+                ack_rtt = Some(frame.timestamp - sent);
+                unacked.pop_front();
+            }
+        }
+        if segment.flags & (FLAG_FIN | FLAG_RST) != 0 {
+            conversation.closing = true;
+        }
+
+        let flow = &mut conversation.flows[direction];
+        if syn {
+            flow.isn = Some(segment.seq);
+        }
+        let base = *flow.base.get_or_insert(if syn { segment.seq } else { segment.seq.wrapping_sub(1) });
+        let retransmission = length > 0 && flow.next_seq.is_some_and(|next| !seq_after(seq_end, next));
+        let gap = flow.next_seq.is_some_and(|next| seq_after(segment.seq, next));
+        if retransmission {
+            return None;
+        }
+        if flow.next_seq.is_none_or(|next| seq_after(seq_end, next)) {
+            flow.next_seq = Some(seq_end);
+        }
+        if seq_end != segment.seq {
+            flow.unacked.push_back((seq_end, frame.timestamp));
+        }
+
+        let ssh = if length > 0 {
+            let decoding = &self.decoding;
+            let is_ssh = *conversation.ssh.get_or_insert_with(|| {
+                decoding.is_ssh_port(segment.source.1) || decoding.is_ssh_port(segment.destination.1) || segment.payload.starts_with(b"SSH-")
+            });
+            match is_ssh {
+                true => conversation.flows[direction].ssh.dissect(segment.payload, gap),
+                false => None,
+            }
+        } else {
+            None
+        };
+        if ssh.is_none() && !syn && !(segment.flags == FLAG_ACK && length == 0) {
+            return None;
+        }
+
+        let mut packet = Packet::new(Some(frame.timestamp));
+        let mut frame_layer = Layer::new("frame");
+        frame_layer.add("frame.number", frame_number);
+        frame_layer.add("frame.len", frame.original_length);
+        frame_layer.add("frame.cap_len", frame.data.len());
+        packet.push(frame_layer);
+        for layer in encapsulation {
+            packet.push(layer);
+        }
+
+        let (ip_name, source_field, destination_field) = match segment.source.0 {
+            IpAddr::V4(_) => ("ip", "ip.src", "ip.dst"),
+            IpAddr::V6(_) => ("ipv6", "ipv6.src", "ipv6.dst"),
+        };
+        let mut ip_layer = Layer::new(ip_name);
+        ip_layer.add(source_field, segment.source.0);
+        ip_layer.add(destination_field, segment.destination.0);
+        packet.push(ip_layer);
+
+        let mut tcp = Layer::new("tcp");
+        tcp.add("tcp.srcport", segment.source.1);
+        tcp.add("tcp.dstport", segment.destination.1);
+        tcp.add("tcp.stream", conversation.id);
+        tcp.add("tcp.len", length);
+        tcp.add("tcp.seq", segment.seq.wrapping_sub(base));
+        tcp.add("tcp.seq_raw", segment.seq);
+        if segment.flags & FLAG_ACK != 0 {
+            tcp.add("tcp.ack", segment.ack.wrapping_sub(peer_base.unwrap_or(0)));
+            tcp.add("tcp.ack_raw", segment.ack);
+        }
+        tcp.add("tcp.flags", format!("0x{:04x}", segment.flags));
+        tcp.add("tcp.flags.syn", u8::from(syn));
+        tcp.add("tcp.flags.ack", u8::from(segment.flags & FLAG_ACK != 0));
+        if let Some(mss) = segment.mss {
+            tcp.add("tcp.options.mss_val", mss);
+        }
+        if let Some(rtt) = ack_rtt {
+            tcp.add("tcp.analysis.ack_rtt", format!("{:.9}", rtt as f64 / 1_000_000.0));
+        }
+        if let Some((_, Some((reassembled, segments)))) = &ssh {
+            tcp.add("tcp.reassembled.length", reassembled);
+            tcp.add("tcp.segment.count", segments);
+        }
+        packet.push(tcp);
+
+        if let Some((ssh, _)) = ssh {
+            packet.push(ssh);
+        }
+        Some(packet)
+    }
+}
+
+impl Conversation {
+    /// Index of the flow `source` sends in.
+    fn direction(&self, source: Endpoint) -> usize {
+        usize::from(self.first != source)
+    }
+}
+
+/// Where one direction of an SSH connection is at.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Phase {
+    /// Before the banner line
+    #[default]
+    Banner,
+    /// Unencrypted binary packets, up to New Keys
+    Plaintext,
+    /// Encrypted, or no longer followed
+    Encrypted,
+}
+
+/// One direction of an SSH connection, as far as it is dissected.
+#[derive(Default)]
+struct SshFlow {
+    phase: Phase,
+    /// Bytes of an incomplete banner or record
+    buffer: Vec<u8>,
+    /// Segments the buffered bytes came from
+    segments: u32,
+}
+
+impl SshFlow {
+    /// Dissects the payload of a segment, returning its SSH layer if it completes the banner or a
+    /// record, with the reassembled length and segment count if the first of those started in an
+    /// earlier segment. Parsing stops when segments went missing (`gap`) before New Keys.
+    fn dissect(&mut self, payload: &[u8], gap: bool) -> Option<(Layer, Option<(usize, u32)>)> {
+        let mut ssh = Layer::new("ssh");
+        if self.phase == Phase::Encrypted {
+            return Some((ssh, None));
+        }
+        if gap {
+            log::debug!("Segments missing before New Keys; no longer dissecting the plaintext.");
+            self.phase = Phase::Encrypted;
+            self.buffer.clear();
+            return Some((ssh, None));
+        }
+
+        let carried = self.buffer.len();
+        let earlier_segments = self.segments;
+        self.buffer.extend_from_slice(payload);
+        self.segments += 1;
+        let mut consumed = 0;
+
+        // Binary data before any banner: the connection was captured after the KEX
+        let line = payload.split(|&byte| byte == b'\n').next().unwrap_or_default();
+        if self.phase == Phase::Banner && carried == 0 && line.iter().any(|&byte| byte.is_ascii_control() && !b"\t\r".contains(&byte)) {
+            log::debug!("No banner before binary data; treating the connection as encrypted.");
+            self.phase = Phase::Encrypted;
+            self.buffer.clear();
+            self.segments = 0;
+            return Some((ssh, None));
+        }
+
+        if self.phase == Phase::Banner {
+            while let Some(newline) = self.buffer[consumed..].iter().position(|&byte| byte == b'\n') {
+                let line = String::from_utf8_lossy(&self.buffer[consumed..consumed + newline]).trim_end_matches('\r').to_string();
+                consumed += newline + 1;
+                if line.starts_with("SSH-") {
+                    ssh.add("ssh.protocol", line);
+                    self.phase = Phase::Plaintext;
+                    break;
+                }
+            }
+            if self.phase == Phase::Banner && self.buffer.len() > MAX_BANNER {
+                self.phase = Phase::Encrypted;
+            }
+        }
+
+        while self.phase == Phase::Plaintext {
+            let record = &self.buffer[consumed..];
+            let packet_length = match record.get(0..4) {
+                Some(bytes) => read_u32(bytes, true) as usize,
+                None => break,
+            };
+            if !(5..=MAX_PLAINTEXT_PACKET).contains(&packet_length) {
+                log::debug!("Implausible SSH packet length {packet_length}; no longer dissecting the plaintext.");
+                self.phase = Phase::Encrypted;
+                break;
+            }
+            let record = match record.get(..4 + packet_length) {
+                Some(record) => record,
+                None => break,
+            };
+
+            let padding = usize::from(record[4]);
+            let payload = record.get(5..record.len().saturating_sub(padding)).unwrap_or_default();
+            ssh.add("ssh.packet_length", packet_length);
+            if let Some(&code) = payload.first() {
+                ssh.add("ssh.message_code", code);
+                dissect_message(&mut ssh, code, &payload[1..]);
+                if code == SSH_MSG_NEWKEYS {
+                    self.phase = Phase::Encrypted;
+                }
+            }
+            consumed += record.len();
+        }
+
+        let completed = consumed > 0 || self.phase == Phase::Encrypted;
+        let reassembled = (carried > 0 && consumed > carried).then_some((consumed, earlier_segments + 1));
+        if self.phase == Phase::Encrypted {
+            self.buffer.clear();
+            self.segments = 0;
+        } else if consumed > 0 {
+            self.buffer.drain(..consumed);
+            self.segments = u32::from(!self.buffer.is_empty());
+        }
+
+        completed.then_some((ssh, reassembled))
+    }
+}
+
+/// Adds the fields of a KEXINIT or KEX reply, given its payload after the message code.
+fn dissect_message(ssh: &mut Layer, code: u8, mut payload: &[u8]) {
+    match code {
+        SSH_MSG_KEXINIT => {
+            // Cookie
+            payload = payload.get(16..).unwrap_or_default();
+            for field in KEXINIT_FIELDS {
+                match hostkey::take_string(payload) {
+                    Some((list, rest)) => {
+                        ssh.add(field, String::from_utf8_lossy(list));
+                        payload = rest;
+                    },
+                    None => return,
+                }
+            }
+        },
+        SSH_MSG_KEX_REPLY | SSH_MSG_KEX_DH_GEX_REPLY => {
+            let fields = hostkey::take_string(payload).and_then(|(blob, _)| hostkey::blob_fields(blob));
+            for (name, value) in fields.unwrap_or_default() {
+                match name {
+                    "ssh.host_key.type" => ssh.add(name, String::from_utf8_lossy(&value)),
+                    _ => ssh.add_raw(name, hex::encode(&value), &value),
+                }
+            }
+        },
+        _ => {},
+    }
+}
+
+fn read_u16(bytes: &[u8], big_endian: bool) -> u16 {
+    let bytes: [u8; 2] = bytes[..2].try_into().expect("2 bytes");
+    if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) }
+}
+
+fn read_u32(bytes: &[u8], big_endian: bool) -> u32 {
+    let bytes: [u8; 4] = bytes[..4].try_into().expect("4 bytes");
+    if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+}
+
+/// Fills `buffer` with the start of a record, returning `false` if the capture ends before it.
+fn read_or_eof(reader: &mut impl Read, buffer: &mut [u8]) -> Result<bool, NativeError> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(cut_off()),
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(true)
+}
+
+/// Fills `buffer` with the rest of a record whose start was read.
+fn read_rest(reader: &mut impl Read, buffer: &mut [u8]) -> Result<(), NativeError> {
+    reader.read_exact(buffer).map_err(|err| match err.kind() {
+        io::ErrorKind::UnexpectedEof => cut_off(),
+        _ => err.into(),
+    })
+}
+
+fn skip(reader: &mut impl Read, length: usize) -> io::Result<()> {
+    io::copy(&mut reader.take(length as u64), &mut io::sink())?;
+    Ok(())
+}
+
+fn invalid(reason: &str) -> NativeError {
+    NativeError::Io(io::Error::new(io::ErrorKind::InvalidData, reason))
+}
+
+fn cut_off() -> NativeError {
+    NativeError::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "capture cut off in the middle of a record"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::utils;
+    use std::io::Cursor;
+
+    /// 2024-01-31 12:00:00 UTC
+    const SECONDS: u32 = 1_706_702_400;
+
+    /// IPv4 packet with a TCP SYN from 10.0.0.1:50000 to 10.0.0.2:22.
+    fn syn() -> Vec<u8> {
+        let mut ip = vec![0x45, 0, 0, 40, 0, 0, 0x40, 0, 64, 6, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2];
+        ip.extend([0xc3, 0x50, 0, 22, 0, 0, 0, 1, 0, 0, 0, 0, 0x50, 0x02, 0xff, 0xff, 0, 0, 0, 0]);
+        ip
+    }
+
+    /// `ip` in an Ethernet frame.
+    fn ethernet(ip: &[u8]) -> Vec<u8> {
+        [[0u8; 12].as_slice(), &[0x08, 0x00], ip].concat()
+    }
+
+    /// Classic pcap capture of `link_type` with the given (seconds, fraction, data) records.
+    fn pcap(big_endian: bool, nanos: bool, link_type: u32, records: &[(u32, u32, &[u8])]) -> Vec<u8> {
+        let word = |value: u32| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        let half = |value: u16| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        let mut capture = word(if nanos { PCAP_NANOSECONDS } else { PCAP_MICROSECONDS }).to_vec();
+        capture.extend(half(2).into_iter().chain(half(4)));
+        for value in [0, 0, 65535, link_type] {
+            capture.extend(word(value));
+        }
+        for (seconds, fraction, data) in records {
+            for value in [*seconds, *fraction, data.len() as u32, data.len() as u32] {
+                capture.extend(word(value));
+            }
+            capture.extend(*data);
+        }
+        capture
+    }
+
+    /// All frames of a capture, up to the first error.
+    fn read_frames(capture: Vec<u8>) -> (Vec<Frame>, Option<NativeError>) {
+        let mut reader = FrameReader::new(Cursor::new(capture)).unwrap();
+        let mut frames = vec![];
+        loop {
+            match reader.next_frame() {
+                Ok(Some(frame)) => frames.push(frame),
+                Ok(None) => return (frames, None),
+                Err(err) => return (frames, Some(err)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_pcap() {
+        let frame = ethernet(&syn());
+        for (big_endian, nanos) in [(false, false), (true, false), (false, true), (true, true)] {
+            let fraction = if nanos { 250_000_000 } else { 250_000 };
+            let (frames, error) = read_frames(pcap(big_endian, nanos, 1, &[(SECONDS, fraction, &frame), (SECONDS + 1, 0, &frame)]));
+            assert!(error.is_none());
+            let timestamps: Vec<i64> = frames.iter().map(|frame| frame.timestamp).collect();
+            assert_eq!(vec![1_706_702_400_250_000, 1_706_702_401_000_000], timestamps, "big endian {big_endian}, nanoseconds {nanos}");
+            assert_eq!((1, frame.len() as u32), (frames[0].link_type, frames[0].original_length));
+
+            let ip = link_payload(frames[0].link_type, &frames[0].data, &mut vec![]).unwrap().unwrap();
+            let segment = parse_tcp(ip).unwrap();
+            assert_eq!((50000, 22, FLAG_SYN), (segment.source.1, segment.destination.1, segment.flags));
+        }
+    }
+
+    #[test]
+    fn test_linux_cooked() {
+        // Packet type, ARPHRD, address length, address, protocol
+        let header = |protocol: [u8; 2]| [[0, 0, 0, 1, 0, 6].as_slice(), &[0; 8], &protocol].concat();
+        let frame = [header([0x08, 0x00]), syn()].concat();
+        let (frames, _) = read_frames(pcap(false, false, 113, &[(SECONDS, 0, &frame)]));
+        assert_eq!(113, frames[0].link_type);
+
+        let ip = link_payload(113, &frames[0].data, &mut vec![]).unwrap().unwrap();
+        let segment = parse_tcp(ip).unwrap();
+        assert_eq!((IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 50000), segment.source);
+        // ARP and other protocols carry no IP
+        assert!(link_payload(113, &[header([0x08, 0x06]), vec![0; 28]].concat(), &mut vec![]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_truncated_record() {
+        let frame = ethernet(&syn());
+        let capture = pcap(false, false, 1, &[(SECONDS, 0, &frame), (SECONDS + 1, 0, &frame)]);
+
+        // Cut off in the data, and in the header, of the final record
+        for cut in [5, frame.len() + 8] {
+            let (frames, error) = read_frames(capture[..capture.len() - cut].to_vec());
+            assert_eq!(1, frames.len());
+            assert!(matches!(error, Some(NativeError::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof), "cut {cut}");
+        }
+
+        // The reader returns the packets before the cut, then the error
+        let path = std::env::temp_dir().join(format!("sshniff-test-{}-truncated.pcap", std::process::id()));
+        std::fs::write(&path, &capture[..capture.len() - 5]).unwrap();
+        let mut reader = NativeReader::open(path.to_str().unwrap(), Decoding::default()).unwrap();
+        assert_eq!(1, reader.by_ref().count());
+        assert!(reader.finish().is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_pcapng_resolution() {
+        let block = |block_type: u32, body: &[u8]| {
+            let length = (12 + body.len()) as u32;
+            [block_type.to_le_bytes().as_slice(), &length.to_le_bytes(), body, &length.to_le_bytes()].concat()
+        };
+        let frame = ethernet(&syn());
+        // if_tsresol as a power of ten or, with the high bit set, of two
+        let capture = |resolution: u8, units: u64| {
+            let section = block(SECTION_HEADER, &[BYTE_ORDER_MAGIC.to_le_bytes().as_slice(), &[1, 0, 0, 0], &[0xff; 8]].concat());
+            let interface = block(INTERFACE_DESCRIPTION, &[[1, 0, 0, 0].as_slice(), &65535u32.to_le_bytes(), &IF_TSRESOL.to_le_bytes(), &[1, 0, resolution, 0, 0, 0], &[0; 4]].concat());
+            let mut packet = [[0; 4].as_slice(), &((units >> 32) as u32).to_le_bytes(), &(units as u32).to_le_bytes(), &(frame.len() as u32).to_le_bytes(), &(frame.len() as u32).to_le_bytes(), &frame].concat();
+            packet.resize(packet.len().div_ceil(4) * 4, 0);
+            [section, interface, block(ENHANCED_PACKET, &packet)].concat()
+        };
+
+        let (frames, error) = read_frames(capture(9, u64::from(SECONDS) * 1_000_000_000 + 250_000_000));
+        assert!(error.is_none());
+        assert_eq!((1_706_702_400_250_000, &frame), (frames[0].timestamp, &frames[0].data));
+        let (frames, _) = read_frames(capture(0x80 | 20, (u64::from(SECONDS) << 20) + (1 << 19)));
+        assert_eq!(1_706_702_400_500_000, frames[0].timestamp);
+
+        // Microseconds without the option
+        assert_eq!(1_000_000, timestamp_resolution(&[0, 0, 0, 0], false));
+    }
+
+    #[test]
+    fn test_native_engine() {
+        use crate::analyser::options::{Decoding, Engine};
+        use crate::analyser::filter::StreamFilter;
+
+        // Read without tshark
+        let capture = format!("{}/test_captures/known_pass_lsal_id_exit.pcapng", env!("CARGO_MANIFEST_DIR"));
+        let (streams, tcp) = utils::load_capture_reporting(&capture, -1, None, &StreamFilter::default(), Engine::Native, &Decoding::default(), |_, _| {}).unwrap();
+        assert_eq!(vec![&0], streams.keys().collect::<Vec<_>>());
+        assert_eq!((Some(1460), Some(1460)), tcp[&0].mss);
+        assert!(tcp[&0].handshake);
+
+        let packets = &streams[&0];
+        let banner = packets.iter().find_map(|packet| utils::find_metadata(packet, "ssh.protocol"));
+        assert_eq!(Some("SSH-2.0-OpenSSH_9.6"), banner);
+        let kex = packets.iter().find_map(|packet| utils::find_metadata(packet, "ssh.kex_algorithms")).unwrap();
+        assert!(kex.contains("curve25519-sha256"));
+        let host_key = hostkey::find_host_key(packets).unwrap();
+        assert_eq!("SHA256:t56EKSEYh7BngyQ3748Dm4lID63UbQ/nFrrezLxcA7o", host_key.sha256);
+    }
+}
@@ -0,0 +1,139 @@
+//! Detection of SSH sessions started from within the observed session (SSH inside SSH).
+//!
+//! Once the user connects onward from the server, the inner SSH client runs in the observed
+//! terminal and every keystroke travels on to the next host before it is echoed. The time from a
+//! keystroke to its echo therefore grows by the inner hop's round trip, while the TCP round trip
+//! of the observed session stays the same. A command after which the echo delay jumps is taken as
+//! the start of a nested session, all the more if a password was entered right after it (see
+//! [scan_for_unechoed_input](super::scan::scan_for_unechoed_input)).
+use super::containers::{Event, Finding, KeystrokeSequence, KeystrokeType, PacketInfo};
+use std::collections::HashMap;
+
+/// Keystrokes compared on either side of a candidate command.
+pub const NESTED_WINDOW: usize = 8;
+
+/// Fewest echo delays on either side of a candidate command to compare them.
+pub const NESTED_MIN_SAMPLES: usize = 3;
+
+/// Factor by which the median echo delay has to grow after the command.
+pub const NESTED_DELAY_FACTOR: f64 = 1.5;
+
+/// Minimum growth (μs) of the median echo delay; smaller changes are within a LAN's jitter.
+pub const NESTED_MIN_EXTRA_DELAY: i64 = 2_000;
+
+/// Shortest command that can open a session, e.g. `ssh h`.
+pub const NESTED_MIN_COMMAND_LENGTH: usize = 5;
+
+/// Flags the commands after which keystrokes are echoed markedly later, i.e. probably travel
+/// through a nested SSH session. `findings` are the session's findings so far, searched for
+/// password entries following the command.
+pub fn scan_for_nested_sessions(packet_infos: &[PacketInfo], sequences: &[KeystrokeSequence], findings: &[Finding]) -> Vec<Finding> {
+    log::info!("Looking for nested SSH sessions.");
+
+    let positions: HashMap<usize, usize> = packet_infos.iter().enumerate().map(|(position, packet_info)| (packet_info.index, position)).collect();
+    let delays: Vec<Vec<i64>> = sequences.iter().map(|sequence| echo_delays(packet_infos, &positions, sequence)).collect();
+
+    let mut nested = Vec::new();
+    // Sequences before the last detection were typed on another host and are not compared
+    let mut floor = 0;
+    for (index, sequence) in sequences.iter().enumerate() {
+        let enter = match sequence.keystrokes.last() {
+            Some(keystroke) if keystroke.k_type == KeystrokeType::Enter && sequence.length.estimate >= NESTED_MIN_COMMAND_LENGTH => keystroke,
+            _ => continue,
+        };
+
+        let before: Vec<i64> = delays[floor..=index].iter().rev().flatten().take(NESTED_WINDOW).copied().collect();
+        let after: Vec<i64> = delays[index + 1..].iter().flatten().take(NESTED_WINDOW).copied().collect();
+        if before.len() < NESTED_MIN_SAMPLES || after.len() < NESTED_MIN_SAMPLES {
+            continue;
+        }
+
+        let (before, after) = (median(before), median(after));
+        if after < before + NESTED_MIN_EXTRA_DELAY || (after as f64) < before as f64 * NESTED_DELAY_FACTOR {
+            continue;
+        }
+
+        let packet_info = match enter.index.and_then(|index| positions.get(&index)) {
+            Some(&position) => &packet_infos[position],
+            None => continue,
+        };
+        let next_start = sequences.get(index + 1).map_or(i64::MAX, |next| next.start);
+        let password = findings.iter().any(|finding| finding.kind == Event::PasswordEntry && (enter.timestamp..next_start).contains(&finding.timestamp));
+
+        log::debug!("Nested session at {}: echo delay {before}μs to {after}μs", packet_info.seq);
+        nested.push(Finding::new(Event::NestedSession, packet_info)
+            .with_details(format!("after command #{}, echo delay {:.1}ms to {:.1}ms{}", sequence.id, before as f64 / 1000.0, after as f64 / 1000.0, if password { ", password entered" } else { "" }))
+            .with_confidence(if password { 0.7 } else { 0.5 }));
+        floor = index + 1;
+    }
+
+    nested
+}
+
+/// Time (μs) from each typed character of a sequence to the next server record, its echo.
+fn echo_delays(packet_infos: &[PacketInfo], positions: &HashMap<usize, usize>, sequence: &KeystrokeSequence) -> Vec<i64> {
+    sequence.keystrokes.iter()
+        .filter(|keystroke| keystroke.k_type == KeystrokeType::Keystroke)
+        .filter_map(|keystroke| positions.get(&keystroke.index?))
+        .filter_map(|&position| {
+            let echo = packet_infos[position + 1..].iter().find(|packet_info| packet_info.is_server())?;
+            Some(echo.timestamp - packet_infos[position].timestamp)
+        })
+        .collect()
+}
+
+fn median(mut values: Vec<i64>) -> i64 {
+    values.sort_unstable();
+    values[values.len() / 2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::containers;
+    use crate::analyser::containers::tests::timed_records;
+    use crate::analyser::core::process_keystrokes;
+    use crate::analyser::options::Thresholds;
+    use crate::analyser::scan::scan_for_keystrokes;
+    use crate::analyser::scan::scan_for_unechoed_input;
+
+    #[test]
+    fn test_nested_sessions() {
+        use containers::Direction::*;
+        // Records as (direction, length, time in μs)
+        let mut records: Vec<(containers::Direction, i32, i64)> = vec![(ServerToClient, 60, 0)];
+        let mut time = 1_000_000;
+        let mut typed = |records: &mut Vec<_>, characters: usize, echo_delay: i64, echoed: bool| {
+            for _ in 0..characters {
+                records.push((ClientToServer, 36, time));
+                if echoed {
+                    records.push((ServerToClient, 36, time + echo_delay));
+                }
+                time += 150_000;
+            }
+            // Return, answered by a newline and output (or a prompt)
+            records.extend([(ClientToServer, 36, time), (ServerToClient, 40, time + echo_delay), (ServerToClient, 300, time + echo_delay + 1000)]);
+            time += 1_000_000;
+        };
+        // `ssh h1` with local echoes, the password for h1, then two commands on h1
+        typed(&mut records, 6, 1000, true);
+        typed(&mut records, 8, 25_000, false);
+        typed(&mut records, 4, 25_000, true);
+        typed(&mut records, 4, 25_000, true);
+        records.extend([(ClientToServer, 36, time), (ServerToClient, 36, time + 25_000)]);
+
+        let ordered = timed_records(&records);
+        let findings = scan_for_unechoed_input(&ordered, 36, 0);
+        assert_eq!(Some("8 characters".to_string()), findings[0].details);
+        let sequences = process_keystrokes(scan_for_keystrokes(&ordered, 36, 0, &Thresholds::default()));
+        assert_eq!(3, sequences.len());
+
+        let nested = scan_for_nested_sessions(&ordered, &sequences, &findings);
+        assert_eq!(1, nested.len());
+        assert_eq!(containers::Event::NestedSession, nested[0].kind);
+        assert_eq!(0.7, nested[0].confidence);
+        assert_eq!(Some("after command #1, echo delay 1.0ms to 25.0ms, password entered".to_string()), nested[0].details);
+        // Without the delay growing, a password alone (e.g. for sudo) is no nested session
+        assert!(scan_for_nested_sessions(&ordered, &sequences[..1], &findings).is_empty());
+    }
+}
@@ -0,0 +1,149 @@
+//! Linking sessions across captures to the operator behind them.
+//!
+//! Every session is reduced to an [OperatorFingerprint]: the client software (HASSH and banner),
+//! typing speed and [rhythm](super::rhythm), the hour of day it started, and how commands are
+//! typed. Pairs of sessions from different captures or client IPs are then scored on how well
+//! these agree. No single component is conclusive; a shared HASSH only means the same client
+//! build, and typing speed drifts with fatigue. Together they narrow down which sessions deserve a
+//! closer look.
+use super::core::SshSession;
+use super::rhythm::TypingRhythm;
+use super::stats::{self, LatencyStats};
+use super::utils;
+use chrono::{TimeZone, Timelike, Utc};
+use serde::Serialize;
+
+/// Score from which a pair of sessions is reported as linked.
+pub const LINK_MIN_SCORE: f64 = 0.7;
+
+/// Components of a [Link] score and their weights.
+pub const LINK_WEIGHTS: [(&str, f64); 6] = [("hassh", 0.2), ("banner", 0.1), ("typing", 0.15), ("rhythm", 0.3), ("hours", 0.1), ("commands", 0.15)];
+
+/// Behavioural and software traits of the operator behind one session.
+#[derive(Clone, Debug, Serialize)]
+pub struct OperatorFingerprint {
+    pub capture: String,
+    pub stream: u32,
+    pub client_ip: String,
+    pub hassh_c: String,
+    pub banner: String,
+    pub typing: LatencyStats,
+    pub rhythm: TypingRhythm,
+    /// Hour of day (UTC) the session started
+    pub start_hour: Option<u32>,
+    /// Mean number of keystrokes per command
+    pub command_length: Option<f64>,
+    pub error_rate: f32,
+    /// Share of symbol-heavy commands
+    pub symbol_share: Option<f64>,
+}
+
+/// Scored pair of sessions.
+#[derive(Debug, Serialize)]
+pub struct Link {
+    /// (capture, stream) of the two sessions
+    pub sessions: ((String, u32), (String, u32)),
+    pub score: f64,
+    /// Similarity (0 to 1) per component; components missing on either side are left out
+    pub components: Vec<(&'static str, f64)>,
+}
+
+impl OperatorFingerprint {
+    /// Fingerprints a session of the given capture.
+    pub fn new(capture: &str, session: &SshSession) -> Self {
+        let sequences = &session.keystroke_data;
+        let (command_length, symbol_share) = if sequences.is_empty() {
+            (None, None)
+        } else {
+            let count = sequences.len() as f64;
+            (
+                Some(sequences.iter().map(|sequence| sequence.keystrokes.len()).sum::<usize>() as f64 / count),
+                Some(sequences.iter().filter(|sequence| sequence.symbol_heavy).count() as f64 / count),
+            )
+        };
+
+        Self {
+            capture: capture.to_string(),
+            stream: session.stream,
+            client_ip: utils::ip_of(&session.src).to_string(),
+            hassh_c: session.hassh_c.clone(),
+            banner: session.protocols.0.clone(),
+            typing: stats::latency_stats(sequences.iter().flat_map(|sequence| &sequence.keystrokes), stats::MIN_LATENCY_CONFIDENCE),
+            rhythm: TypingRhythm::new(sequences),
+            start_hour: utils::parse_session_time(&session.start_utc)
+                .and_then(|timestamp| Utc.timestamp_micros(timestamp).single())
+                .map(|datetime| datetime.hour()),
+            command_length,
+            error_rate: session.error_rate,
+            symbol_share,
+        }
+    }
+}
+
+/// Scores how likely two sessions belong to the same operator, from 0 to 1.
+pub fn score(a: &OperatorFingerprint, b: &OperatorFingerprint) -> Link {
+    let equal = |a: &str, b: &str| if a == b { 1.0 } else { 0.0 };
+    let mut components = vec![
+        ("hassh", equal(&a.hassh_c, &b.hassh_c)),
+        ("banner", equal(&a.banner, &b.banner)),
+    ];
+
+    if a.typing.samples > 0 && b.typing.samples > 0 {
+        let median = ratio(a.typing.median_us as f64, b.typing.median_us as f64);
+        let spread = ratio(a.typing.stddev_us as f64, b.typing.stddev_us as f64);
+        components.push(("typing", (median * 2.0 + spread) / 3.0));
+    }
+    if let Some(rhythm) = a.rhythm.similarity(&b.rhythm) {
+        components.push(("rhythm", rhythm));
+    }
+    if let (Some(a), Some(b)) = (a.start_hour, b.start_hour) {
+        let distance = a.abs_diff(b).min(24 - a.abs_diff(b));
+        components.push(("hours", 1.0 - distance as f64 / 12.0));
+    }
+    if let (Some(length_a), Some(length_b)) = (a.command_length, b.command_length) {
+        let length = ratio(length_a, length_b);
+        let errors = 1.0 - (a.error_rate - b.error_rate).abs() as f64;
+        // Sessions from a corpus have no symbol share
+        let mut parts = vec![length, errors];
+        if let (Some(symbols_a), Some(symbols_b)) = (a.symbol_share, b.symbol_share) {
+            parts.push(1.0 - (symbols_a - symbols_b).abs());
+        }
+        components.push(("commands", parts.iter().sum::<f64>() / parts.len() as f64));
+    }
+
+    let weight = |name: &str| LINK_WEIGHTS.iter()
+        .find(|(component, _)| *component == name)
+        .map_or(0.0, |(_, weight)| *weight);
+    let total: f64 = components.iter().map(|(name, _)| weight(name)).sum();
+    let score = components.iter().map(|(name, similarity)| weight(name) * similarity).sum::<f64>() / total;
+
+    Link {
+        sessions: ((a.capture.clone(), a.stream), (b.capture.clone(), b.stream)),
+        score,
+        components,
+    }
+}
+
+/// Scores all pairs of sessions from different captures or client IPs and returns those scoring
+/// at least `min_score`, best first. With `same_client`, sessions of one client IP within a capture
+/// are paired as well, e.g. to tell apart the operators behind a jump host.
+pub fn link_operators(fingerprints: &[OperatorFingerprint], min_score: f64, same_client: bool) -> Vec<Link> {
+    let mut links: Vec<Link> = fingerprints.iter().enumerate()
+        .flat_map(|(index, a)| fingerprints[index + 1..].iter().map(move |b| (a, b)))
+        .filter(|(a, b)| same_client || a.capture != b.capture || a.client_ip != b.client_ip)
+        .map(|(a, b)| score(a, b))
+        .filter(|link| link.score >= min_score)
+        .collect();
+
+    links.sort_by(|a, b| b.score.total_cmp(&a.score));
+    links
+}
+
+/// Ratio of the smaller to the larger value; 1 if both are 0.
+fn ratio(a: f64, b: f64) -> f64 {
+    if a.max(b) <= 0.0 {
+        1.0
+    } else {
+        a.min(b) / a.max(b)
+    }
+}
@@ -0,0 +1,389 @@
+//! Options controlling which scans [analyse](super::core::analyse) runs and with which parameters.
+//!
+//! Built via [AnalysisOptions::builder]; anything not set falls back to the defaults, which are
+//! the values SSHniff has been tuned with.
+use super::records;
+use super::advisories::AdvisoryDb;
+use super::commands::CommandDb;
+use super::intel::IndicatorSet;
+use super::knownhosts::KnownHosts;
+use super::sampling::Sampling;
+use super::filter::StreamFilter;
+use super::software::HasshDb;
+use super::utils;
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+/// Optional scans that can be switched off.
+///
+/// The core metadata and login scans always run, since everything else depends on them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scan {
+    /// Keystroke ordering, classification and grouping
+    Keystrokes,
+    /// RTT jitter and path-change detection
+    PathChanges,
+    /// SSH record reassembly and splitting of coalesced records
+    Reassembly,
+    /// Host key acceptance by the client
+    HostKey,
+    /// Key re-exchanges, whose records are then kept out of the other scans
+    Rekey,
+    /// Terminal (pty-req), env, and resize requests, and multiplexer status lines
+    Terminal,
+    /// SCP/SFTP file transfers
+    Transfers,
+    /// Local, dynamic, and remote port forwarding
+    Forwarding,
+    /// Text pasted into the shell in bracketed paste mode
+    Pastes,
+    /// Passwords typed without echo after login, e.g. at sudo prompts
+    UnechoedInput,
+    /// Whether the login password was pasted or typed by hand
+    PasswordEntry,
+    /// Transfer volume unexplained by typing or known file transfers
+    Exfil,
+    /// SSH sessions opened from within the session
+    Nested,
+    /// Mouse reports and held keys, which are set apart from the typed keystrokes
+    InputBursts,
+}
+
+/// Dissector the packets of a capture are read with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Engine {
+    /// tshark, through rtshark
+    #[default]
+    Tshark,
+    /// Built-in pcap/pcapng reader, without tshark; see [native](super::native)
+    Native,
+}
+
+/// Where SSH is dissected besides port 22.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Decoding {
+    /// Further TCP ports carrying SSH, e.g. 2222 or 443
+    pub ssh_ports: Vec<u16>,
+    /// tshark decode-as rules, e.g. `tcp.port==8443,ssh`; the native engine recognises SSH by its
+    /// banner instead
+    pub decode_as: Vec<String>,
+}
+
+impl Decoding {
+    /// The decode-as rules passed to tshark: SSH on each of the [ssh_ports](Decoding::ssh_ports),
+    /// then the given ones.
+    pub fn tshark_rules(&self) -> Vec<String> {
+        self.ssh_ports.iter().map(|port| format!("tcp.port=={port},ssh")).chain(self.decode_as.iter().cloned()).collect()
+    }
+
+    /// Whether SSH is expected on `port`.
+    pub fn is_ssh_port(&self, port: u16) -> bool {
+        port == 22 || self.ssh_ports.contains(&port)
+    }
+}
+
+/// Heuristic thresholds used by the scans.
+#[derive(Clone, Debug)]
+pub struct Thresholds {
+    /// Largest size difference between a keystroke and an arrow key or an enlarged echo.
+    pub keystroke_upper_bound: i32,
+    /// Size difference between a keystroke and the echo of a deletion.
+    pub echo_offset: i32,
+    /// Largest size difference between a login prompt and the prompt size.
+    pub prompt_tolerance: i32,
+    /// Number of RTT samples on either side of a candidate path change.
+    pub path_change_window: usize,
+    /// Factor by which the median RTT has to change to be considered a path change.
+    pub path_change_ratio: f64,
+    /// Minimum absolute median RTT change (μs) to be considered a path change.
+    pub path_change_min_delta: i64,
+    /// Smallest segment size that is considered to fill the MSS.
+    pub min_full_segment: i32,
+    /// Largest number of keystroke records expected to be coalesced into one TCP segment.
+    pub max_coalesced_keystrokes: i32,
+    /// Smallest pause (μs) between chaff records that ends a run of chaff.
+    pub chaff_gap: i64,
+    /// Client record lengths of an RSA public key offer, with chacha20-poly1305; other ciphers
+    /// are translated by the [cipher model](super::cipher_model).
+    pub rsa_offer: RangeInclusive<i32>,
+    /// Client record lengths of an ED25519 public key offer.
+    pub ed25519_offer: RangeInclusive<i32>,
+    /// Client record lengths of an ECDSA public key offer.
+    pub ecdsa_offer: RangeInclusive<i32>,
+    /// Client record lengths of a FIDO ED25519 key offer.
+    pub sk_ed25519_offer: RangeInclusive<i32>,
+    /// Client record lengths of a FIDO ECDSA key offer.
+    pub sk_ecdsa_offer: RangeInclusive<i32>,
+    /// Client record lengths of an OpenSSH certificate offer.
+    pub certificate_offer: RangeInclusive<i32>,
+    /// Client record lengths of a keyboard-interactive SSH_MSG_USERAUTH_REQUEST.
+    pub keyboard_interactive_request: RangeInclusive<i32>,
+    /// Server record lengths of a keyboard-interactive INFO_REQUEST without prompts, which
+    /// OpenSSH concludes a PAM conversation with.
+    pub empty_info_request: RangeInclusive<i32>,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            keystroke_upper_bound: utils::KEYSTROKE_UPPER_BOUND,
+            echo_offset: utils::ECHO_OFFSET,
+            prompt_tolerance: utils::PROMPT_TOLERANCE,
+            path_change_window: utils::PATH_CHANGE_WINDOW,
+            path_change_ratio: utils::PATH_CHANGE_RATIO,
+            path_change_min_delta: utils::PATH_CHANGE_MIN_DELTA,
+            min_full_segment: records::MIN_FULL_SEGMENT,
+            max_coalesced_keystrokes: records::MAX_COALESCED_KEYSTROKES,
+            chaff_gap: utils::CHAFF_GAP,
+            rsa_offer: utils::RSA_OFFER_SIZE,
+            ed25519_offer: utils::ED25519_OFFER_SIZE,
+            ecdsa_offer: utils::ECDSA_OFFER_SIZE,
+            sk_ed25519_offer: utils::SK_ED25519_OFFER_SIZE,
+            sk_ecdsa_offer: utils::SK_ECDSA_OFFER_SIZE,
+            certificate_offer: utils::CERTIFICATE_OFFER_SIZE,
+            keyboard_interactive_request: utils::KEYBOARD_INTERACTIVE_REQUEST_SIZE,
+            empty_info_request: utils::EMPTY_INFO_REQUEST_SIZE,
+        }
+    }
+}
+
+/// Values that replace what the analysis would otherwise infer from the capture.
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    /// Keystroke size (SSH record length)
+    pub keystroke_size: Option<u32>,
+    /// Login prompt size (SSH record length)
+    pub prompt_size: Option<i32>,
+    /// MAC/AEAD tag length appended to each record, instead of deriving it from the negotiated algorithms
+    pub mac_length: Option<i32>,
+    /// Whether the client sends chaff (OpenSSH >= 9.5 keystroke obfuscation), instead of guessing from the banners
+    pub obfuscated: Option<bool>,
+}
+
+/// Everything [analyse](super::core::analyse) needs to know besides the packets.
+#[derive(Clone, Debug)]
+pub struct AnalysisOptions {
+    /// Optional scans that are skipped
+    pub skipped: Vec<Scan>,
+    pub thresholds: Thresholds,
+    pub overrides: Overrides,
+    /// Number of records encoded in the session's size signature
+    pub signature_length: usize,
+    /// Idle gap (μs) at which a session is split into activity periods; `None` keeps it whole
+    pub idle_split: Option<i64>,
+    /// Known HASSH values to identify clients and servers by
+    pub hassh_db: Arc<HasshDb>,
+    /// Known vulnerabilities to match the banner versions against
+    pub advisory_db: Arc<AdvisoryDb>,
+    /// Response-size fingerprints to guess submitted commands with
+    pub command_db: Arc<CommandDb>,
+    /// Whether to derive the size thresholds from each session before scanning; see [tuning](super::tuning)
+    pub self_tune: bool,
+    /// Whether to keep the full KEXINIT algorithm lists of both sides, not just the negotiated ones
+    pub verbose_kex: bool,
+    /// Threat-intel indicators to flag sessions with
+    pub indicators: Arc<IndicatorSet>,
+    /// known_hosts file to verify server host keys against
+    pub known_hosts: Option<Arc<KnownHosts>>,
+    /// Approximate mode for very large streams; `None` analyses every packet
+    pub sampling: Option<Sampling>,
+    /// Endpoints and time range of the streams to load; see [filter](super::filter)
+    pub filter: StreamFilter,
+    /// Dissector to read captures with
+    pub engine: Engine,
+    /// Ports and tshark rules to dissect SSH on besides port 22
+    pub decoding: Decoding,
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        Self {
+            skipped: vec![],
+            thresholds: Thresholds::default(),
+            overrides: Overrides::default(),
+            signature_length: utils::SIGNATURE_LENGTH,
+            idle_split: None,
+            hassh_db: Arc::new(HasshDb::bundled()),
+            advisory_db: Arc::new(AdvisoryDb::bundled()),
+            command_db: Arc::new(CommandDb::bundled()),
+            self_tune: false,
+            verbose_kex: false,
+            indicators: Arc::new(IndicatorSet::default()),
+            known_hosts: None,
+            sampling: None,
+            filter: StreamFilter::default(),
+            engine: Engine::default(),
+            decoding: Decoding::default(),
+        }
+    }
+}
+
+impl AnalysisOptions {
+    /// Starts building options from the defaults.
+    pub fn builder() -> AnalysisOptionsBuilder {
+        AnalysisOptionsBuilder { options: AnalysisOptions::default() }
+    }
+
+    /// Checks if an optional scan should run.
+    pub fn runs(&self, scan: Scan) -> bool {
+        !self.skipped.contains(&scan)
+    }
+}
+
+/// Builder for [AnalysisOptions].
+pub struct AnalysisOptionsBuilder {
+    options: AnalysisOptions,
+}
+
+impl AnalysisOptionsBuilder {
+    /// Skips an optional scan.
+    pub fn skip(mut self, scan: Scan) -> Self {
+        if !self.options.skipped.contains(&scan) {
+            self.options.skipped.push(scan);
+        }
+        self
+    }
+
+    /// Uses a fixed keystroke size instead of inferring it.
+    pub fn keystroke_size(mut self, keystroke_size: u32) -> Self {
+        self.options.overrides.keystroke_size = Some(keystroke_size);
+        self
+    }
+
+    /// Uses a fixed login prompt size instead of inferring it.
+    pub fn prompt_size(mut self, prompt_size: i32) -> Self {
+        self.options.overrides.prompt_size = Some(prompt_size);
+        self
+    }
+
+    /// Uses a fixed MAC length instead of deriving it from the negotiated algorithms.
+    pub fn mac_length(mut self, mac_length: i32) -> Self {
+        self.options.overrides.mac_length = Some(mac_length);
+        self
+    }
+
+    /// Forces obfuscation handling on or off.
+    pub fn obfuscated(mut self, obfuscated: bool) -> Self {
+        self.options.overrides.obfuscated = Some(obfuscated);
+        self
+    }
+
+    /// Sets the number of records encoded in the size signature.
+    pub fn signature_length(mut self, signature_length: usize) -> Self {
+        self.options.signature_length = signature_length;
+        self
+    }
+
+    /// Splits sessions into activity periods at idle gaps of at least `gap_us` μs.
+    pub fn idle_split(mut self, gap_us: i64) -> Self {
+        self.options.idle_split = Some(gap_us);
+        self
+    }
+
+    /// Identifies clients and servers with the given HASSH database instead of the bundled one.
+    pub fn hassh_db(mut self, hassh_db: HasshDb) -> Self {
+        self.options.hassh_db = Arc::new(hassh_db);
+        self
+    }
+
+    /// Matches the banner versions against the given advisories instead of the bundled ones.
+    pub fn advisory_db(mut self, advisory_db: AdvisoryDb) -> Self {
+        self.options.advisory_db = Arc::new(advisory_db);
+        self
+    }
+
+    /// Guesses submitted commands with the given fingerprints instead of the bundled ones.
+    pub fn command_db(mut self, command_db: CommandDb) -> Self {
+        self.options.command_db = Arc::new(command_db);
+        self
+    }
+
+    /// Derives the size thresholds from each session instead of using the fixed ones.
+    pub fn self_tune(mut self) -> Self {
+        self.options.self_tune = true;
+        self
+    }
+
+    /// Keeps the full KEXINIT algorithm lists of both sides.
+    pub fn verbose_kex(mut self) -> Self {
+        self.options.verbose_kex = true;
+        self
+    }
+
+    /// Flags sessions matching the given threat-intel indicators.
+    pub fn indicators(mut self, indicators: IndicatorSet) -> Self {
+        self.options.indicators = Arc::new(indicators);
+        self
+    }
+
+    /// Verifies server host keys against the given known_hosts file.
+    pub fn known_hosts(mut self, known_hosts: KnownHosts) -> Self {
+        self.options.known_hosts = Some(Arc::new(known_hosts));
+        self
+    }
+
+    /// Uses the given heuristic thresholds instead of the defaults.
+    pub fn thresholds(mut self, thresholds: Thresholds) -> Self {
+        self.options.thresholds = thresholds;
+        self
+    }
+
+    /// Analyses only the first `after` SSH packets of each stream exactly, and samples one in
+    /// `rate` packets past that; see [sampling](super::sampling).
+    pub fn sample(mut self, after: usize, rate: usize) -> Self {
+        self.options.sampling = Some(Sampling { after, rate });
+        self
+    }
+
+    /// Loads only the streams matching `filter`; see [filter](super::filter).
+    pub fn filter(mut self, filter: StreamFilter) -> Self {
+        self.options.filter = filter;
+        self
+    }
+
+    /// Reads captures with the given engine instead of tshark.
+    pub fn engine(mut self, engine: Engine) -> Self {
+        self.options.engine = engine;
+        self
+    }
+
+    /// Dissects SSH on `port` too, e.g. 2222.
+    pub fn ssh_port(mut self, port: u16) -> Self {
+        if !self.options.decoding.ssh_ports.contains(&port) {
+            self.options.decoding.ssh_ports.push(port);
+        }
+        self
+    }
+
+    /// Passes a decode-as rule (`tcp.port==8443,ssh`) to tshark.
+    pub fn decode_as(mut self, rule: &str) -> Self {
+        self.options.decoding.decode_as.push(rule.to_string());
+        self
+    }
+
+    /// Returns the options, with the defaults for anything not set.
+    pub fn build(self) -> AnalysisOptions {
+        self.options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoding() {
+        let options = AnalysisOptions::builder().ssh_port(2222).ssh_port(443).ssh_port(2222).decode_as("tcp.port==8443,ssh").build();
+
+        assert_eq!(vec![2222, 443], options.decoding.ssh_ports);
+        assert_eq!(vec!["tcp.port==2222,ssh", "tcp.port==443,ssh", "tcp.port==8443,ssh"], options.decoding.tshark_rules());
+        assert!(options.decoding.is_ssh_port(22));
+        assert!(options.decoding.is_ssh_port(443));
+        assert!(!options.decoding.is_ssh_port(8443));
+        assert!(AnalysisOptions::default().decoding.tshark_rules().is_empty());
+    }
+}
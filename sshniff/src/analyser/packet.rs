@@ -0,0 +1,131 @@
+//! Dissected packets, as produced by either [engine](super::options::Engine).
+//!
+//! Mirrors the layers and fields tshark exposes through rtshark, keeping only the field names and
+//! values, so that the scans work the same on packets dissected by tshark and by the
+//! [native](super::native) engine. Fields carry tshark's names (`tcp.len`, `ssh.message_code`, ...)
+//! and values as tshark shows them.
+
+/// A packet: its capture time and its protocol layers, outermost first.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Packet {
+    layers: Vec<Layer>,
+    timestamp_micros: Option<i64>,
+    /// Whether the server sent the packet, once known from the stream; see
+    /// [is_server_packet](super::utils::is_server_packet)
+    from_server: Option<bool>,
+}
+
+impl Packet {
+    /// Creates a packet without layers, captured at the given UNIX timestamp (μs).
+    pub fn new(timestamp_micros: Option<i64>) -> Self {
+        Self { layers: vec![], timestamp_micros, from_server: None }
+    }
+
+    /// Capture time as UNIX timestamp (μs).
+    pub fn timestamp_micros(&self) -> Option<i64> {
+        self.timestamp_micros
+    }
+
+    /// Whether the server sent the packet, if it was [marked](Packet::set_from_server).
+    pub fn from_server(&self) -> Option<bool> {
+        self.from_server
+    }
+
+    /// Marks the packet as sent by the server or the client.
+    pub fn set_from_server(&mut self, from_server: bool) {
+        self.from_server = Some(from_server);
+    }
+
+    /// Appends a layer on top of the existing ones.
+    pub fn push(&mut self, layer: Layer) {
+        self.layers.push(layer);
+    }
+
+    /// First layer of the given protocol, e.g. `tcp`.
+    pub fn layer_name(&self, name: &str) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    /// Layers, outermost first.
+    pub fn iter(&self) -> impl Iterator<Item = &Layer> {
+        self.layers.iter()
+    }
+}
+
+impl From<rtshark::Packet> for Packet {
+    fn from(packet: rtshark::Packet) -> Self {
+        let mut converted = Packet::new(packet.timestamp_micros());
+        for layer in packet.iter() {
+            let mut converted_layer = Layer::new(layer.name());
+            for metadata in layer.iter() {
+                let raw_value = (metadata.raw_value() != metadata.value()).then(|| metadata.raw_value().to_string());
+                converted_layer.metadata.push(Metadata { name: metadata.name().to_string(), value: metadata.value().to_string(), raw_value });
+            }
+            converted.push(converted_layer);
+        }
+        converted
+    }
+}
+
+/// A protocol layer and its fields.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Layer {
+    name: String,
+    metadata: Vec<Metadata>,
+}
+
+impl Layer {
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), metadata: vec![] }
+    }
+
+    /// Protocol name, as tshark calls it.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Adds a field.
+    pub fn add(&mut self, name: &str, value: impl ToString) {
+        self.metadata.push(Metadata { name: name.to_string(), value: value.to_string(), raw_value: None });
+    }
+
+    /// Adds a field that tshark shows differently from its raw bytes, given in hex.
+    pub fn add_raw(&mut self, name: &str, value: impl ToString, raw_value: &[u8]) {
+        self.metadata.push(Metadata { name: name.to_string(), value: value.to_string(), raw_value: Some(hex::encode(raw_value)) });
+    }
+
+    /// First field with the given name.
+    pub fn metadata(&self, name: &str) -> Option<&Metadata> {
+        self.metadata.iter().find(|metadata| metadata.name == name)
+    }
+
+    /// Fields, in the order they were dissected.
+    pub fn iter(&self) -> impl Iterator<Item = &Metadata> {
+        self.metadata.iter()
+    }
+}
+
+/// A field of a layer.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    name: String,
+    value: String,
+    raw_value: Option<String>,
+}
+
+impl Metadata {
+    /// Field name, e.g. `tcp.len`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Value as tshark shows it.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Raw bytes of the field in hex, where they differ from the shown value.
+    pub fn raw_value(&self) -> &str {
+        self.raw_value.as_deref().unwrap_or(&self.value)
+    }
+}
@@ -0,0 +1,188 @@
+//! Password length inference at login.
+//!
+//! Two independent estimates are made for every submitted password. The size estimate compares
+//! the SSH_MSG_USERAUTH_REQUEST carrying the password with the `none` request the client opens
+//! the login with: both carry the same username and service, so the difference in payload is the
+//! method name and the password. Block padding blurs it by up to a block, and OpenSSH pads
+//! password requests to a multiple of [OPENSSH_PASSWORD_PADDING] bytes, which only leaves a
+//! bound. The timing estimate divides the time from the prompt to the submission, net of the
+//! client's RTT and a reaction time, by the operator's keystroke latencies.
+use super::cipher_model::CipherModel;
+use super::containers::{Event, PacketInfo};
+use super::core::SshSession;
+use super::stats::LatencyStats;
+use super::utils;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Payload a password request carries beyond the `none` request of the same user, besides the
+/// password: `password` instead of `none` as the method name, the FALSE byte, and the length of
+/// the password string.
+pub const PASSWORD_PAYLOAD_OVERHEAD: i32 = 4 + 1 + 4;
+
+/// Granularity (bytes) OpenSSH clients pad password requests to, to hide the password's length.
+pub const OPENSSH_PASSWORD_PADDING: i32 = 64;
+
+/// Range of the time (μs) between a prompt being shown and the first key of the answer.
+pub const PASSWORD_REACTION_US: (i64, i64) = (200_000, 1_500_000);
+
+/// Interquartile keystroke latency (μs) assumed when the session has too few keystrokes of its own.
+pub const DEFAULT_PASSWORD_LATENCY_US: (i64, i64) = (120_000, 300_000);
+
+/// Keystrokes a session needs for its own latencies to time the password with.
+pub const PASSWORD_LATENCY_MIN_SAMPLES: usize = 8;
+
+/// Estimated length of a submitted password.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PasswordLength {
+    /// Lengths the request's size allows
+    pub by_size: Option<RangeInclusive<usize>>,
+    /// Lengths the time taken to type it allows
+    pub by_timing: Option<RangeInclusive<usize>>,
+}
+
+impl PasswordLength {
+    /// Lengths both estimates allow. If they disagree, the size is trusted: the operator may have
+    /// paused, but the padding holds.
+    pub fn estimate(&self) -> Option<RangeInclusive<usize>> {
+        match (&self.by_size, &self.by_timing) {
+            (Some(size), Some(timing)) => {
+                let (start, end) = (*size.start().max(timing.start()), *size.end().min(timing.end()));
+                Some(if start <= end { start..=end } else { size.clone() })
+            },
+            (Some(range), None) | (None, Some(range)) => Some(range.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+impl fmt::Display for PasswordLength {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let range = |range: &RangeInclusive<usize>| match range.start() == range.end() {
+            true => range.start().to_string(),
+            false => format!("{}-{}", range.start(), range.end()),
+        };
+        let estimate = match self.estimate() {
+            Some(estimate) => estimate,
+            None => return write!(f, "password length unknown"),
+        };
+
+        write!(f, "password length {}", range(&estimate))?;
+        let sources: Vec<String> = [("size", &self.by_size), ("timing", &self.by_timing)].into_iter()
+            .filter_map(|(source, estimate)| estimate.as_ref().map(|estimate| format!("{source} {}", range(estimate))))
+            .collect();
+        write!(f, " ({})", sources.join(", "))
+    }
+}
+
+/// Password lengths a request of `length` bytes allows, given the `none` request of `none_length`
+/// bytes, under `model` and the client's `extra_pad` (0 if it only pads to the block size).
+pub fn length_by_size(length: i32, none_length: i32, model: &CipherModel, extra_pad: i32) -> Option<RangeInclusive<usize>> {
+    let none = model.payloads(none_length, 0)?;
+    let password = model.payloads(length, extra_pad)?;
+    let least = password.start() - none.end() - PASSWORD_PAYLOAD_OVERHEAD;
+    let most = password.end() - none.start() - PASSWORD_PAYLOAD_OVERHEAD;
+    match most >= 0 {
+        true => Some(least.max(0) as usize..=most as usize),
+        false => None,
+    }
+}
+
+/// Password lengths typed in `delay` μs (net of the client's RTT) at the interquartile latencies
+/// of `typing`; `None` if the password was pasted. The Enter that submits it counts as a key.
+pub fn length_by_timing(delay: i64, typing: &LatencyStats) -> Option<RangeInclusive<usize>> {
+    if delay <= utils::PASTED_PASSWORD_MAX_DELAY {
+        return None;
+    }
+
+    let (fast, slow) = match typing.samples >= PASSWORD_LATENCY_MIN_SAMPLES && typing.p25_us > 0 {
+        true => (typing.p25_us, typing.p75_us.max(typing.p25_us)),
+        false => DEFAULT_PASSWORD_LATENCY_US,
+    };
+    let least = ((delay - PASSWORD_REACTION_US.1).max(0) as f64 / slow as f64).ceil().max(1.0) as usize;
+    let most = ((delay - PASSWORD_REACTION_US.0).max(0) as f64 / fast as f64).floor().max(1.0) as usize;
+    Some(least.min(most)..=most)
+}
+
+/// Attaches a [PasswordLength] to the details of every [WrongPassword](Event::WrongPassword) and
+/// [CorrectPassword](Event::CorrectPassword) finding of a session, each of which points at the
+/// server's answer with the prompt and the password the two records before it.
+///
+/// The `none` request is the client record answered by the first prompt after the new keys.
+/// Without it or a `model` only the timing is estimated, with the session's
+/// [typing](SshSession::typing) latencies.
+pub fn estimate_password_lengths(packet_infos: &[PacketInfo], session: &mut SshSession, model: Option<&CipherModel>) {
+    let none_request = packet_infos.windows(2).skip(session.new_keys_at)
+        .find(|pair| pair[1].is_server_len(session.prompt_size))
+        .map(|pair| &pair[0])
+        .filter(|packet_info| packet_info.is_client());
+    let extra_pad = if session.protocols.0.contains("OpenSSH") { OPENSSH_PASSWORD_PADDING } else { 0 };
+    let (typing, client_rtt) = (&session.typing, session.jitter.0.mean_rtt);
+
+    for finding in session.results.iter_mut().filter(|finding| matches!(finding.kind, Event::WrongPassword | Event::CorrectPassword)) {
+        let position = match packet_infos.iter().position(|packet_info| packet_info.index == finding.packet_ref.index) {
+            Some(position) if position >= 2 => position,
+            _ => continue,
+        };
+        let (prompt, password) = (&packet_infos[position - 2], &packet_infos[position - 1]);
+        // Keyboard-interactive answers follow INFO_REQUESTs, not prompts, and are laid out differently
+        if !prompt.is_server() || !password.is_client() || (prompt.length - session.prompt_size).abs() > utils::PROMPT_TOLERANCE {
+            continue;
+        }
+
+        let length = PasswordLength {
+            by_size: match (model, none_request) {
+                (Some(model), Some(none_request)) => length_by_size(password.length, none_request.length, model, extra_pad),
+                _ => None,
+            },
+            by_timing: length_by_timing(password.timestamp - prompt.timestamp - client_rtt, typing),
+        };
+        if length.estimate().is_none() {
+            continue;
+        }
+        log::debug!("Password at {}: {length}", password.seq);
+
+        finding.details = Some(match finding.details.take() {
+            Some(details) => format!("{details}, {length}"),
+            None => length.to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::cipher_model::CipherModel;
+    use crate::analyser::containers;
+    use crate::analyser::core::analyse;
+    use crate::analyser::options::AnalysisOptions;
+    use crate::analyser::stats;
+
+    #[test]
+    fn test_password_length() {
+        use crate::analyser::options::{Decoding, Engine};
+        use crate::analyser::filter::StreamFilter;
+
+        // `none` request of a 3-character username, then a 10-character password padded only to the block size
+        let model = CipherModel::reference();
+        assert_eq!(60, model.record_length(31 + 3));
+        let request = model.record_length(40 + 3 + 10);
+        assert!(length_by_size(request, 60, &model, 0).unwrap().contains(&10));
+        // OpenSSH pads the request past the next multiple of 64 bytes, which only bounds the length
+        let padded = model.padded_record_length(40 + 3 + 10, OPENSSH_PASSWORD_PADDING);
+        assert_eq!(4 + 128 + 16, padded);
+        let bound = length_by_size(padded, 60, &model, OPENSSH_PASSWORD_PADDING).unwrap();
+        assert!(bound.contains(&10) && bound.end() - bound.start() > 50);
+
+        // Pasted passwords cannot be timed; two seconds of typing at 200ms a key is about ten characters
+        let typing = stats::LatencyStats { samples: 10, p25_us: 200_000, p75_us: 200_000, ..Default::default() };
+        assert_eq!(None, length_by_timing(500_000, &typing));
+        assert_eq!(Some(3..=9), length_by_timing(2_000_000, &typing));
+
+        let capture = format!("{}/test_captures/known_pass_lsal_id_exit.pcapng", env!("CARGO_MANIFEST_DIR"));
+        let (streams, tcp) = utils::load_capture_reporting(&capture, -1, None, &StreamFilter::default(), Engine::Native, &Decoding::default(), |_, _| {}).unwrap();
+        let session = analyse(0, &streams[&0], &tcp[&0], &AnalysisOptions::default()).unwrap();
+        let correct = session.results.iter().find(|finding| finding.kind == containers::Event::CorrectPassword).unwrap();
+        assert_eq!(Some("password length 21-38 (size 8-78, timing 21-38)"), correct.details.as_deref());
+    }
+}
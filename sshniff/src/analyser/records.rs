@@ -0,0 +1,212 @@
+//! SSH record reassembly.
+//!
+//! Scans classify packets by their size, so they need the size of the SSH record rather than the
+//! size of the TCP segment(s) that happened to carry it. tshark already reassembles records whose
+//! length it can read (see [get_record_length](super::utils::get_record_length)); this pass covers
+//! records it could not reassemble, e.g. when the packet length is encrypted.
+use super::containers::PacketInfo;
+use super::options::Thresholds;
+use std::ops::Range;
+
+/// Smallest segment size that is considered to fill the MSS. 536 is the default MSS (RFC 879), so
+/// sessions that only ever send small packets don't have every packet treated as "full".
+pub const MIN_FULL_SEGMENT: i32 = 536;
+
+/// Largest number of keystroke records expected to be coalesced into a single TCP segment.
+pub const MAX_COALESCED_KEYSTROKES: i32 = 4;
+
+/// How many packets to look ahead for the continuation of a split record.
+const CONTINUATION_LOOKAHEAD: usize = 8;
+
+/// Merges SSH records that were split across consecutive TCP segments into single [PacketInfo]s.
+///
+/// A record is considered incomplete if
+/// 1. tshark exposes `ssh.packet_length` and the record is shorter than the length it announces, or
+/// 2. otherwise, its last segment fills the largest segment size observed in that direction.
+///
+/// It is then extended with the next packet of the same direction, as long as that packet
+/// continues the TCP byte stream (contiguous tcp.seq). Merged records keep the tcp.seq of their
+/// first segment, but take the timing and frame of the last segment, as that is when the record
+/// was complete. Indexes are renumbered to match the new positions.
+///
+/// Expects packets in capture order. Returns the records and the number of merged records.
+pub fn reassemble_records(packet_infos: Vec<PacketInfo>, mac_length: i32, thresholds: &Thresholds) -> (Vec<PacketInfo>, usize) {
+    log::info!("Reassembling SSH records.");
+
+    // Largest observed (client, server) segment size, standing in for the MSS.
+    let mut max_segment = (0, 0);
+    for packet_info in &packet_infos {
+        let segment = packet_info.segment_length;
+        if packet_info.is_server() {
+            max_segment.1 = max_segment.1.max(segment);
+        } else {
+            max_segment.0 = max_segment.0.max(segment);
+        }
+    }
+
+    let mut records: Vec<PacketInfo> = Vec::with_capacity(packet_infos.len());
+    let mut consumed = vec![false; packet_infos.len()];
+    let mut merged = 0;
+
+    for index in 0..packet_infos.len() {
+        if consumed[index] {
+            continue;
+        }
+
+        let mut record = packet_infos[index].clone();
+        let full_segment = if record.is_server() { max_segment.1 } else { max_segment.0 };
+        let expected = record.record_length(mac_length);
+        let mut last = index;
+
+        loop {
+            let incomplete = match expected {
+                Some(expected) => record.length < expected,
+                None => full_segment >= thresholds.min_full_segment && packet_infos[last].segment_length == full_segment,
+            };
+
+            if !incomplete {
+                break;
+            }
+
+            let next = (last + 1..packet_infos.len())
+                .take(CONTINUATION_LOOKAHEAD)
+                .find(|&i| !consumed[i] && packet_infos[i].direction == record.direction);
+
+            // The continuation has to pick up exactly where the record left off.
+            let next = match next {
+                Some(next) if packet_infos[next].seq == record.seq + record.length as i64 => next,
+                _ => break,
+            };
+
+            log::debug!("Merging segment {} into record {}", packet_infos[next].seq, record.seq);
+            consumed[next] = true;
+            record.length += packet_infos[next].length;
+            record.timestamp = packet_infos[next].timestamp;
+            record.frame = packet_infos[next].frame;
+            record.segment_length = packet_infos[next].segment_length;
+            record.ack_rtt = packet_infos[next].ack_rtt;
+            record.segments += 1;
+            last = next;
+        }
+
+        if record.segments > 1 {
+            merged += 1;
+        }
+
+        records.push(record);
+    }
+
+    for (position, record) in records.iter_mut().enumerate() {
+        record.index = position;
+    }
+
+    log::debug!("{} records reassembled from {} packets.", records.len(), packet_infos.len());
+    (records, merged)
+}
+
+/// Splits TCP segments carrying several SSH records into one [PacketInfo] per record.
+///
+/// Nagle's algorithm and delayed ACKs can put consecutive records into the same segment, which
+/// makes e.g. keystrokes invisible to per-packet length matching. Segments after `from` (New Keys)
+/// are split
+/// 1. by their `ssh.packet_length` fields, if tshark exposes one per record, or
+/// 2. for client segments whose length is a small multiple of `keystroke_size`, into that many
+///    keystrokes. A `keystroke_size` of 0 disables this heuristic.
+///
+/// Split records share their packet's metadata, with tcp.seq advanced by the preceding
+/// records' lengths. Indexes are renumbered. Returns the records and the number of split segments.
+pub fn split_coalesced_records(packet_infos: Vec<PacketInfo>, from: usize, keystroke_size: i32, mac_length: i32, thresholds: &Thresholds) -> (Vec<PacketInfo>, usize) {
+    log::info!("Splitting coalesced SSH records.");
+
+    let mut records: Vec<PacketInfo> = Vec::with_capacity(packet_infos.len());
+    let mut split = 0;
+
+    for (index, packet_info) in packet_infos.into_iter().enumerate() {
+        // Reassembled records span segments and can't contain other records' starts.
+        if index <= from || packet_info.segments > 1 {
+            records.push(packet_info);
+            continue;
+        }
+
+        let length = packet_info.length;
+        let mut lengths = packet_info.record_lengths(mac_length);
+
+        if lengths.len() < 2 || lengths.iter().sum::<i32>() != length {
+            lengths.clear();
+
+            if keystroke_size > 0 && packet_info.is_client() && length > keystroke_size && length % keystroke_size == 0 && length / keystroke_size <= thresholds.max_coalesced_keystrokes {
+                lengths = vec![keystroke_size; (length / keystroke_size) as usize];
+            }
+        }
+
+        if lengths.is_empty() {
+            records.push(packet_info);
+            continue;
+        }
+
+        log::debug!("Splitting segment {} into {} records.", packet_info.seq, lengths.len());
+        split += 1;
+
+        let mut seq = packet_info.seq;
+        for record_length in lengths {
+            let mut record = packet_info.clone();
+            record.seq = seq;
+            record.length = record_length;
+            records.push(record);
+            seq += record_length as i64;
+        }
+    }
+
+    for (position, record) in records.iter_mut().enumerate() {
+        record.index = position;
+    }
+
+    (records, split)
+}
+
+/// Removes the records at the given positions, e.g. the rekeys found by
+/// [scan_for_rekeys](super::scan::scan_for_rekeys). The ranges must be ascending and disjoint.
+/// Indexes are renumbered.
+pub fn drop_records(packet_infos: &mut Vec<PacketInfo>, ranges: Vec<Range<usize>>) {
+    for range in ranges.into_iter().rev() {
+        packet_infos.drain(range);
+    }
+
+    for (position, record) in packet_infos.iter_mut().enumerate() {
+        record.index = position;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::containers::tests::records;
+    use crate::analyser::containers::Direction::{ClientToServer as C, ServerToClient as S};
+
+    #[test]
+    fn test_split_coalesced_records() {
+        let mut packet_infos = records(&[(C, 72), (S, 72), (C, 72), (S, 72), (C, 180), (C, 100), (S, 100), (C, 72), (S, 80)]);
+        for packet_info in packet_infos.iter_mut() {
+            packet_info.seq = packet_info.index as i64 * 1000;
+        }
+        // Two records whose lengths tshark read, one that spans two segments, and lengths that
+        // don't add up to the segment
+        packet_infos[6].packet_lengths = vec![12, 48];
+        packet_infos[7].segments = 2;
+        packet_infos[8].packet_lengths = vec![12, 12];
+
+        let (split, count) = split_coalesced_records(packet_infos.clone(), 1, 36, 16, &Thresholds::default());
+        assert_eq!(2, count);
+        // Up to New Keys, server segments, too many keystrokes, and sizes off the keystroke size stay whole
+        assert_eq!(vec![72, 72, 36, 36, 72, 180, 100, 32, 68, 72, 80], split.iter().map(|record| record.length).collect::<Vec<_>>());
+        assert_eq!(vec![2000, 2036, 6000, 6032], [2, 3, 7, 8].map(|position| split[position].seq).to_vec());
+        assert_eq!((0..11).collect::<Vec<_>>(), split.iter().map(|record| record.index).collect::<Vec<_>>());
+        assert!(split[2].is_client() && split[3].is_client() && split[7].is_server());
+
+        // Without a keystroke size only the records tshark read are split
+        let (split, count) = split_coalesced_records(packet_infos, 1, 0, 16, &Thresholds::default());
+        assert_eq!(1, count);
+        assert_eq!(72, split[2].length);
+        assert_eq!(10, split.len());
+    }
+}
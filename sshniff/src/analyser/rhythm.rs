@@ -0,0 +1,151 @@
+//! Typing rhythm: how long an operator takes between two particular kinds of keystroke.
+//!
+//! The latency between consecutive keystrokes of a sequence is a digraph latency. Which keys were
+//! pressed stays hidden, but their [types](KeystrokeType) do not, and each pair of types has a
+//! rhythm of its own: the gap before Enter or before a correction differs from the gap between
+//! two characters, and the shape of each distribution is more personal than the overall median.
+//! A [TypingRhythm] keeps one latency distribution per [DIGRAPHS] class; two rhythms are compared
+//! by the overlap of their distributions (Bhattacharyya coefficient).
+use super::containers::{KeystrokeSequence, KeystrokeType};
+use serde::Serialize;
+
+/// Digraph classes, by the types of the first and second keystroke. Characters include multibyte
+/// ones; the other types are too unreliable to classify pairs of.
+pub const DIGRAPHS: [&str; 4] = ["char-char", "char-enter", "char-delete", "delete-delete"];
+
+/// Upper bounds (μs, inclusive) of the distribution bins, spaced evenly on a log scale; latencies
+/// up to [RHYTHM_MAX_US] go into a final bin.
+pub const RHYTHM_BOUNDS: [i64; 9] = [60_000, 90_000, 120_000, 160_000, 210_000, 280_000, 380_000, 520_000, 750_000];
+
+/// Latency above which the operator paused rather than typed on.
+pub const RHYTHM_MAX_US: i64 = 1_500_000;
+
+/// Digraphs a class needs in both rhythms to be compared.
+pub const RHYTHM_MIN_SAMPLES: usize = 8;
+
+/// Latency distribution of one digraph class.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct DigraphLatencies {
+    pub digraph: &'static str,
+    pub samples: usize,
+    /// Share of the samples per [RHYTHM_BOUNDS] bin, summing to 1 if there are any
+    pub distribution: Vec<f64>,
+}
+
+/// Digraph latency distributions of a session, one per [DIGRAPHS] class.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TypingRhythm {
+    pub digraphs: Vec<DigraphLatencies>,
+}
+
+impl Default for TypingRhythm {
+    fn default() -> Self {
+        Self {
+            digraphs: DIGRAPHS.iter()
+                .map(|digraph| DigraphLatencies { digraph, samples: 0, distribution: vec![0.0; RHYTHM_BOUNDS.len() + 1] })
+                .collect(),
+        }
+    }
+}
+
+impl TypingRhythm {
+    /// Rhythm of the keystroke sequences of a session.
+    pub fn new(sequences: &[KeystrokeSequence]) -> Self {
+        Self::from_digraphs(sequences.iter().flat_map(|sequence| {
+            sequence.keystrokes.windows(2).map(|pair| (&pair[0].k_type, &pair[1].k_type, pair[1].latency_us))
+        }))
+    }
+
+    /// Rhythm of (first type, second type, latency μs) digraphs.
+    pub fn from_digraphs<'a>(digraphs: impl IntoIterator<Item = (&'a KeystrokeType, &'a KeystrokeType, i64)>) -> Self {
+        let mut rhythm = Self::default();
+
+        for (first, second, latency) in digraphs {
+            if latency <= 0 || latency > RHYTHM_MAX_US {
+                continue;
+            }
+            if let Some(class) = digraph_class(first, second) {
+                let bin = RHYTHM_BOUNDS.iter().position(|bound| latency <= *bound).unwrap_or(RHYTHM_BOUNDS.len());
+                rhythm.digraphs[class].samples += 1;
+                rhythm.digraphs[class].distribution[bin] += 1.0;
+            }
+        }
+
+        for digraph in rhythm.digraphs.iter_mut().filter(|digraph| digraph.samples > 0) {
+            let samples = digraph.samples as f64;
+            digraph.distribution.iter_mut().for_each(|share| *share /= samples);
+        }
+        rhythm
+    }
+
+    /// Number of digraphs over all classes.
+    pub fn samples(&self) -> usize {
+        self.digraphs.iter().map(|digraph| digraph.samples).sum()
+    }
+
+    /// Overlap of two rhythms, from 0 to 1, over the classes with at least [RHYTHM_MIN_SAMPLES]
+    /// digraphs in both; weighted by the smaller sample count. `None` if no class has enough.
+    pub fn similarity(&self, other: &TypingRhythm) -> Option<f64> {
+        let (weighted, weight) = self.digraphs.iter().zip(&other.digraphs)
+            .filter(|(a, b)| a.samples.min(b.samples) >= RHYTHM_MIN_SAMPLES)
+            .map(|(a, b)| {
+                let overlap: f64 = a.distribution.iter().zip(&b.distribution).map(|(p, q)| (p * q).sqrt()).sum();
+                let weight = a.samples.min(b.samples) as f64;
+                (overlap * weight, weight)
+            })
+            .fold((0.0, 0.0), |(sum, total), (overlap, weight)| (sum + overlap, total + weight));
+
+        (weight > 0.0).then(|| (weighted / weight).min(1.0))
+    }
+}
+
+/// Position of a pair of keystroke types in [DIGRAPHS].
+fn digraph_class(first: &KeystrokeType, second: &KeystrokeType) -> Option<usize> {
+    use KeystrokeType::*;
+    match (first, second) {
+        (Keystroke | Multibyte, Keystroke | Multibyte) => Some(0),
+        (Keystroke | Multibyte, Enter) => Some(1),
+        (Keystroke | Multibyte, Delete) => Some(2),
+        (Delete, Delete) => Some(3),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::containers;
+    use crate::analyser::core::analyse;
+    use crate::analyser::options::AnalysisOptions;
+    use crate::analyser::utils;
+
+    #[test]
+    fn test_typing_rhythm() {
+        use crate::analyser::options::{Decoding, Engine};
+        use crate::analyser::filter::StreamFilter;
+        use containers::KeystrokeType::*;
+
+        // Two quick typists with the same median but a different spread, and a slow one
+        let typist = |latencies: &[i64], enter: i64| {
+            let mut digraphs: Vec<(&containers::KeystrokeType, &containers::KeystrokeType, i64)> = latencies.iter().cycle().take(40).map(|latency| (&Keystroke, &Keystroke, *latency)).collect();
+            digraphs.extend((0..10).map(|_| (&Keystroke, &Enter, enter)));
+            TypingRhythm::from_digraphs(digraphs)
+        };
+        let steady = typist(&[95_000, 105_000, 110_000, 115_000], 300_000);
+        let steady_again = typist(&[100_000, 105_000, 110_000, 118_000], 290_000);
+        let erratic = typist(&[50_000, 110_000, 200_000, 400_000], 600_000);
+        assert_eq!(50, steady.samples());
+        assert!((steady.digraphs[0].distribution.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(steady.similarity(&steady_again).unwrap() > 0.9);
+        assert!(steady.similarity(&erratic).unwrap() < 0.5);
+        // Too few digraphs to compare
+        let short = TypingRhythm::from_digraphs((0..RHYTHM_MIN_SAMPLES - 1).map(|_| (&Keystroke, &Keystroke, 100_000)));
+        assert_eq!(None, steady.similarity(&short));
+
+        // Typing statistics of an analysed session
+        let capture = format!("{}/test_captures/known_pass_lsal_id_exit.pcapng", env!("CARGO_MANIFEST_DIR"));
+        let (streams, tcp) = utils::load_capture_reporting(&capture, -1, None, &StreamFilter::default(), Engine::Native, &Decoding::default(), |_, _| {}).unwrap();
+        let session = analyse(0, &streams[&0], &tcp[&0], &AnalysisOptions::default()).unwrap();
+        assert_eq!(session.rhythm, TypingRhythm::new(&session.keystroke_data));
+    }
+}
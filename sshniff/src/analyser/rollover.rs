@@ -0,0 +1,244 @@
+//! Stitching of sessions that span rotated capture files.
+//!
+//! Captures rotated by size or time (`tcpdump -C`/`-G`, dumpcap ring buffers) cut long sessions
+//! into parts: tshark numbers the streams of each file anew, and a part without the handshake and
+//! login is of little use on its own. [stitch] joins the streams of consecutive captures back
+//! together when a stream of a later capture has the client and server of an earlier one and its
+//! absolute sequence numbers pick up where the earlier one stopped.
+//!
+//! tshark makes tcp.seq relative to the first packet of each file, so the sequence numbers of
+//! later parts are recorded as offsets in the stream's [TcpMeta] and applied to its
+//! [PacketInfo](super::containers::PacketInfo)s. Frame numbers still refer to the capture each
+//! packet came from.
+use super::containers::TcpMeta;
+use super::utils::{self, TcpMetaMap};
+use super::packet::Packet;
+use std::collections::HashMap;
+
+/// Largest number of bytes between the end of a stream in one capture and the start of its
+/// continuation in the next, to allow for segments lost at the rotation.
+pub const MAX_ROLLOVER_GAP: u32 = 1 << 20;
+
+/// Client and server address and port of a stream.
+type Endpoints = (String, String);
+
+/// Joins the streams of consecutive captures, given in order, into one stream map.
+///
+/// Streams of the first capture keep their IDs. Streams of later captures that continue an earlier
+/// stream are appended to it, and all others are numbered on from the highest ID so far.
+pub fn stitch(parts: Vec<(HashMap<u32, Vec<Packet>>, TcpMetaMap)>) -> (HashMap<u32, Vec<Packet>>, TcpMetaMap) {
+    let mut streams: HashMap<u32, Vec<Packet>> = HashMap::new();
+    let mut metas: TcpMetaMap = HashMap::new();
+    // Latest stream between each client and server
+    let mut latest: HashMap<Endpoints, u32> = HashMap::new();
+    let mut next_id = 0;
+    let mut stitched = 0;
+
+    for (part, (mut part_streams, mut part_metas)) in parts.into_iter().enumerate() {
+        let mut ids: Vec<u32> = part_streams.keys().copied().collect();
+        ids.sort_unstable();
+        let offset = next_id;
+
+        for part_id in ids {
+            let packets = part_streams.remove(&part_id).expect("ID taken from the map");
+            let meta = part_metas.remove(&part_id).unwrap_or_default();
+            let endpoints = match packets.first() {
+                Some(first) => endpoints(first),
+                None => continue,
+            };
+
+            let continued = latest.get(&endpoints).copied().filter(|_| part > 0).and_then(|id| {
+                let offsets = seq_offsets(&streams[&id], &metas[&id], &packets)?;
+                Some((id, offsets))
+            });
+            let id = match continued {
+                Some((id, offsets)) => {
+                    log::debug!("Stream {part_id} of capture {} continues stream {id}", part + 1);
+                    let earlier = streams.get_mut(&id).expect("latest streams are kept");
+                    let earlier_meta = metas.get_mut(&id).expect("latest streams are kept");
+                    earlier_meta.rollovers.push((earlier.len(), offsets));
+                    earlier.extend(packets);
+                    append_meta(earlier_meta, meta);
+                    stitched += 1;
+                    id
+                },
+                None => {
+                    let id = part_id + offset;
+                    streams.insert(id, packets);
+                    metas.insert(id, meta);
+                    id
+                },
+            };
+            latest.insert(endpoints, id);
+            next_id = next_id.max(id + 1);
+        }
+    }
+
+    if stitched > 0 {
+        log::info!("Stitched {stitched} streams across captures.");
+    }
+    (streams, metas)
+}
+
+/// Client and server of the stream `packet` belongs to.
+fn endpoints(packet: &Packet) -> Endpoints {
+    let (source_ip, destination_ip) = utils::find_ip_addresses(packet).unwrap_or_default();
+    let port = |field| utils::find_metadata(packet, field).unwrap_or_default();
+    let source = utils::format_endpoint(&source_ip, port("tcp.srcport"));
+    let destination = utils::format_endpoint(&destination_ip, port("tcp.dstport"));
+
+    if utils::is_server_packet(packet) {
+        (destination, source)
+    } else {
+        (source, destination)
+    }
+}
+
+/// The (client, server) offsets that continue the tcp.seq of `earlier` into `later`, if `later`
+/// starts where `earlier` ends in every direction both have packets in.
+fn seq_offsets(earlier: &[Packet], meta: &TcpMeta, later: &[Packet]) -> Option<(i64, i64)> {
+    if later.first()?.timestamp_micros() < earlier.last()?.timestamp_micros() {
+        return None;
+    }
+
+    let mut offsets = (0, 0);
+    let mut matched = false;
+    for is_server in [false, true] {
+        let last = earlier.iter().rposition(|packet| utils::is_server_packet(packet) == is_server);
+        let first = later.iter().find(|packet| utils::is_server_packet(packet) == is_server);
+        let (last, first) = match (last, first) {
+            (Some(last), Some(first)) => (last, first),
+            _ => continue,
+        };
+
+        let (last_seq, last_raw, last_len) = sequence(&earlier[last])?;
+        let (first_seq, first_raw, _) = sequence(first)?;
+        let gap = first_raw.wrapping_sub(last_raw.wrapping_add(last_len));
+        if gap > MAX_ROLLOVER_GAP {
+            return None;
+        }
+
+        // tcp.seq of the earlier packet as it ends up after its own part was rebased
+        let rebased = last_seq + meta.rollovers.iter().rev()
+            .find(|(index, _)| last >= *index)
+            .map_or(0, |(_, offsets)| if is_server { offsets.1 } else { offsets.0 });
+        let offset = rebased + i64::from(last_len) + i64::from(gap) - first_seq;
+        if is_server {
+            offsets.1 = offset;
+        } else {
+            offsets.0 = offset;
+        }
+        matched = true;
+    }
+
+    matched.then_some(offsets)
+}
+
+/// Relative and absolute tcp.seq and tcp.len of a packet; `None` if tshark did not expose them.
+fn sequence(packet: &Packet) -> Option<(i64, u32, u32)> {
+    let field = |name| -> Option<i64> { utils::find_metadata(packet, name).and_then(|value| value.parse().ok()) };
+    Some((field("tcp.seq")?, field("tcp.seq_raw")? as u32, field("tcp.len")? as u32))
+}
+
+/// Adds the TCP metadata of a later part to that of the stream it continues.
+fn append_meta(earlier: &mut TcpMeta, later: TcpMeta) {
+    earlier.acks.extend(later.acks);
+    earlier.tail = match (earlier.tail.take(), later.tail) {
+        (Some(mut tail), Some(later)) => {
+            tail.packets = (tail.packets.0 + later.packets.0, tail.packets.1 + later.packets.1);
+            tail.bytes = (tail.bytes.0 + later.bytes.0, tail.bytes.1 + later.bytes.1);
+            tail.last = tail.last.max(later.last);
+            tail.samples.extend(later.samples);
+            Some(tail)
+        },
+        (tail, later) => tail.or(later),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::packet::Layer;
+
+    /// Packet between client port `port` and the server at 10.0.0.2:22, at `time` (μs), with
+    /// relative and absolute tcp.seq and tcp.len.
+    fn packet(time: i64, port: u16, from_server: bool, seq: i64, seq_raw: u32, len: u32) -> Packet {
+        let mut packet = Packet::new(Some(time));
+        packet.set_from_server(from_server);
+        let (client, server) = (("10.0.0.1", port), ("10.0.0.2", 22));
+        let (source, destination) = if from_server { (server, client) } else { (client, server) };
+        let mut ip = Layer::new("ip");
+        ip.add("ip.src", source.0);
+        ip.add("ip.dst", destination.0);
+        packet.push(ip);
+        let mut tcp = Layer::new("tcp");
+        tcp.add("tcp.srcport", source.1);
+        tcp.add("tcp.dstport", destination.1);
+        tcp.add("tcp.seq", seq);
+        tcp.add("tcp.seq_raw", seq_raw);
+        tcp.add("tcp.len", len);
+        packet.push(tcp);
+        packet
+    }
+
+    /// A capture of the given streams, without TCP metadata.
+    fn part(streams: Vec<(u32, Vec<Packet>)>) -> (HashMap<u32, Vec<Packet>>, TcpMetaMap) {
+        let metas = streams.iter().map(|(id, _)| (*id, TcpMeta::default())).collect();
+        (streams.into_iter().collect(), metas)
+    }
+
+    /// Keystroke, echo and another keystroke of the client on `port`.
+    fn typing(port: u16, seq_raw: u32) -> Vec<Packet> {
+        vec![
+            packet(0, port, false, 1, seq_raw, 36),
+            packet(1000, port, true, 1, 5001, 36),
+            packet(2000, port, false, 37, seq_raw + 36, 36),
+        ]
+    }
+
+    #[test]
+    fn test_stitch() {
+        let first = part(vec![(0, typing(50000, 1001)), (1, typing(50001, 9001))]);
+        let second = part(vec![
+            // Continues stream 0 in both directions
+            (0, vec![packet(10_000, 50000, false, 1, 1073, 36), packet(11_000, 50000, true, 1, 5037, 36)]),
+            // A new client
+            (1, typing(50002, 1001)),
+            // Stream 1's client and server, but too far ahead to be its continuation
+            (2, vec![packet(10_000, 50001, false, 1, 9073 + MAX_ROLLOVER_GAP + 1, 36)]),
+        ]);
+
+        let (streams, metas) = stitch(vec![first, second]);
+        let mut ids: Vec<u32> = streams.keys().copied().collect();
+        ids.sort_unstable();
+        // Streams that do not continue are numbered on from the first capture's
+        assert_eq!(vec![0, 1, 3, 4], ids);
+        assert_eq!(5, streams[&0].len());
+        assert_eq!(vec![(3, (72, 36))], metas[&0].rollovers);
+        assert_eq!(3, streams[&1].len());
+        assert_eq!(Some("50002"), utils::find_metadata(&streams[&3][0], "tcp.srcport"));
+        assert_eq!(Some("50001"), utils::find_metadata(&streams[&4][0], "tcp.srcport"));
+        assert!(metas[&4].rollovers.is_empty());
+    }
+
+    #[test]
+    fn test_seq_offsets() {
+        let earlier = typing(50000, 1001);
+        let meta = TcpMeta::default();
+
+        // Segments lost at the rotation are skipped over
+        let later = [packet(10_000, 50000, false, 1, 1073 + 50, 36)];
+        assert_eq!(Some((122, 0)), seq_offsets(&earlier, &meta, &later));
+
+        // The gap is too large, or the later part starts before the earlier one ends
+        let later = [packet(10_000, 50000, false, 1, 1073 + MAX_ROLLOVER_GAP + 1, 36)];
+        assert_eq!(None, seq_offsets(&earlier, &meta, &later));
+        let later = [packet(1500, 50000, false, 1, 1073, 36)];
+        assert_eq!(None, seq_offsets(&earlier, &meta, &later));
+
+        // A third part continues from the second as it was rebased
+        let meta = TcpMeta { rollovers: vec![(2, (100, 0))], ..Default::default() };
+        let later = [packet(10_000, 50000, false, 1, 1073, 36)];
+        assert_eq!(Some((172, 0)), seq_offsets(&earlier, &meta, &later));
+    }
+}
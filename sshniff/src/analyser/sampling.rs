@@ -0,0 +1,113 @@
+//! Approximate analysis of very large streams.
+//!
+//! A forwarded port can carry tens of millions of packets, none of which say anything about
+//! keystrokes. With [Sampling] enabled, only the first packets of each stream are kept for the
+//! exact analysis (KEX, login, and the start of the session). Past that point every packet is
+//! reduced to counters while loading, and only one in `rate` keeps its timestamp, so memory stays
+//! bounded however long the stream runs. The resulting [SampledStats] state what is exact and
+//! what is estimated.
+use super::containers::SampledTail;
+use serde::Serialize;
+
+/// Default number of SSH packets per stream that are analysed exactly.
+pub const SAMPLE_AFTER: usize = 100_000;
+
+/// Default sampling rate past [SAMPLE_AFTER]: one in this many packets keeps its timestamp.
+pub const SAMPLE_RATE: usize = 100;
+
+/// Where sampling starts, and how densely.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sampling {
+    /// SSH packets per stream analysed exactly
+    pub after: usize,
+    /// One in `rate` packets past that point is sampled
+    pub rate: usize,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Self { after: SAMPLE_AFTER, rate: SAMPLE_RATE }
+    }
+}
+
+/// Volumetric and timing statistics of the sampled part of a stream.
+#[derive(Clone, Debug, Serialize)]
+pub struct SampledStats {
+    /// SSH packets analysed exactly, before sampling started
+    pub exact_packets: usize,
+    /// Packets sent by the (client, server) past that point (exact)
+    pub packets: (u64, u64),
+    /// TCP payload bytes sent by the (client, server) past that point (exact)
+    pub bytes: (u64, u64),
+    /// Time (μs) from the first to the last sampled-over packet (exact)
+    pub duration_us: i64,
+    /// Mean throughput (bytes/s) of the (client, server) (exact)
+    pub throughput: (f64, f64),
+    /// Highest packet rate (packets/s) in any second, both directions (estimated)
+    pub peak_packet_rate: f64,
+    /// Longest gap (μs) between sampled packets; an upper bound on the longest idle period
+    pub longest_gap_us: i64,
+    /// What the numbers can and cannot be used for
+    pub accuracy: String,
+}
+
+/// Summarises the sampled tail of a stream whose first `exact_packets` were analysed exactly.
+pub fn summarise(tail: &SampledTail, exact_packets: usize) -> SampledStats {
+    let duration_us = tail.last - tail.first;
+    let seconds = duration_us as f64 / 1_000_000.0;
+    let throughput = |bytes: u64| if seconds > 0.0 { bytes as f64 / seconds } else { 0.0 };
+
+    // Packets per one-second bucket, scaled up by the sampling rate
+    let mut peak = 0;
+    let mut bucket = (i64::MIN, 0);
+    for (timestamp, _) in &tail.samples {
+        let second = timestamp / 1_000_000;
+        bucket = if second == bucket.0 { (second, bucket.1 + 1) } else { (second, 1) };
+        peak = peak.max(bucket.1);
+    }
+
+    let longest_gap_us = tail.samples.windows(2)
+        .map(|pair| pair[1].0 - pair[0].0)
+        .max()
+        .unwrap_or(duration_us);
+
+    SampledStats {
+        exact_packets,
+        packets: tail.packets,
+        bytes: tail.bytes,
+        duration_us,
+        throughput: (throughput(tail.bytes.0), throughput(tail.bytes.1)),
+        peak_packet_rate: (peak * tail.rate) as f64,
+        longest_gap_us,
+        accuracy: format!(
+            "Findings, keystrokes, and record statistics cover the first {exact_packets} SSH packets only. \
+            Packet and byte counts, duration, and throughput past that point are exact (bytes are TCP payload, \
+            including SSH framing); the peak packet rate and longest gap are estimated from 1 in {} packets.",
+            tail.rate,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::containers;
+
+    #[test]
+    fn test_sampling() {
+        // 10 s of tunnel traffic at 1000 packets/s, alternating directions, with a 2 s gap
+        let mut tail = containers::SampledTail::new(10);
+        for i in 0..10_000i64 {
+            let timestamp = 1_000_000 + i * 1000 + if i >= 5000 { 2_000_000 } else { 0 };
+            tail.add(timestamp, i % 2 == 1, 1400);
+        }
+        assert_eq!(1000, tail.samples.len());
+
+        let stats = summarise(&tail, 500);
+        assert_eq!((5000, 5000), stats.packets);
+        assert_eq!((7_000_000, 7_000_000), stats.bytes);
+        assert_eq!(11_999_000, stats.duration_us);
+        assert_eq!(1000.0, stats.peak_packet_rate);
+        assert_eq!(2_010_000, stats.longest_gap_us);
+    }
+}
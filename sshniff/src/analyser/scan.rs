@@ -1,20 +1,18 @@
 //! Contains scanning/finding functions that iterate packet streams. 
 use std::{u128, usize};
-use crate::analyser::utils::{self, get_message_code};
-use super::containers::{PacketInfo, Event, KeystrokeType, Keystroke};
+use crate::analyser::utils;
+use super::containers::{PacketInfo, Event, KeystrokeType, Keystroke, JitterStats, Direction, Finding};
+use super::options::Thresholds;
 
 /// Returns timestamp of -R initiation (or None)
 /// This function's logic is adapted directly from Packet Strider.
-pub fn scan_for_reverse_session_r_option(ordered_packets: &Vec<PacketInfo>, prompt_size: i32) -> Option<i64> {
+pub fn scan_for_reverse_session_r_option(ordered_packets: &[PacketInfo], prompt_size: i32) -> Option<i64> {
     let size = ordered_packets.len();
-    let first_timestamp = ordered_packets[0].packet.timestamp_micros().unwrap();
+    let first_timestamp = ordered_packets[0].timestamp;
 
     for (index, packet_info) in ordered_packets.iter().take(40).enumerate() {
-        let packet = packet_info.packet;
-        let ssh_layer = packet.layer_name("ssh").unwrap();
-        // TODO: What if we store message codes in PacketInfo as Options?
-        let message_code = match ssh_layer.metadata("ssh.message_code") {
-            Some(message_code) => message_code.value().parse::<u32>().unwrap(),
+        let message_code = match packet_info.message_code {
+            Some(message_code) => message_code,
             None => continue,
         };
         // TODO: Even better, what if we keep track of index of essential New Keys and other such
@@ -29,14 +27,14 @@ pub fn scan_for_reverse_session_r_option(ordered_packets: &Vec<PacketInfo>, prom
 
         while (index + offset + 7) < size && offset < 20 {
             // We're looking for a successful login sequence.
-            if ordered_packets[index+offset].length != prompt_size {
+            if !ordered_packets[index+offset].is_server_len(prompt_size) {
                 //log::debug!("Expected login prompt size at New Keys + 4 but found length: {}", ordered_packets[index+offset].length);
                 offset += 1;
                 continue;
             }
             // If the second-to-next packet is a login prompt, too, it means login failed and we can skip
             // to the next login
-            if ordered_packets[index+offset+2].length == prompt_size {
+            if ordered_packets[index+offset+2].is_server_len(prompt_size) {
                 offset += 2;
                 continue;
             }
@@ -48,16 +46,16 @@ pub fn scan_for_reverse_session_r_option(ordered_packets: &Vec<PacketInfo>, prom
             // why these signatures exist.
 
             // This signature is "often but not always exhibited by mac clients when -R is used".
-            if ordered_packets[index + offset + 3].length > 0 &&
-            ordered_packets[index + offset + 4].length < 0 && 
-            ordered_packets[index + offset + 4].length != prompt_size && 
-            ordered_packets[index + offset + 5].length > 0 && 
-            ordered_packets[index + offset + 6].length < 0 && 
-            ordered_packets[index + offset + 6].length != prompt_size &&
-            (ordered_packets[index + offset + 6].length.abs() < ordered_packets[index + offset + 5].length.abs())
+            if ordered_packets[index + offset + 3].is_client() &&
+            ordered_packets[index + offset + 4].is_server() && 
+            !ordered_packets[index + offset + 4].is_server_len(prompt_size) && 
+            ordered_packets[index + offset + 5].is_client() && 
+            ordered_packets[index + offset + 6].is_server() && 
+            !ordered_packets[index + offset + 6].is_server_len(prompt_size) &&
+            (ordered_packets[index + offset + 6].length < ordered_packets[index + offset + 5].length)
             {
                 // TODO: Why +10?
-                let relative_timestamp = ordered_packets[index + 10].packet.timestamp_micros().unwrap() - first_timestamp;
+                let relative_timestamp = ordered_packets[index + 10].timestamp - first_timestamp;
                 return Some(relative_timestamp);
             }
 
@@ -67,16 +65,16 @@ pub fn scan_for_reverse_session_r_option(ordered_packets: &Vec<PacketInfo>, prom
             // 5 -> STC
             // 5 != prompt_size
             // 7 -> CTS
-            if ordered_packets[index + offset + 3].length > 0 &&
-            ordered_packets[index + offset + 4].length > 0 && 
-            ordered_packets[index + offset + 5].length != prompt_size && 
-            ordered_packets[index + offset + 5].length < 0 && 
-            ordered_packets[index + offset + 6].length < 0 && 
-            ordered_packets[index + offset + 6].length != prompt_size &&
-            (ordered_packets[index + offset + 6].length.abs() < ordered_packets[index + offset + 5].length.abs()) &&
-            ordered_packets[index + offset + 7].length > 0
+            if ordered_packets[index + offset + 3].is_client() &&
+            ordered_packets[index + offset + 4].is_client() && 
+            !ordered_packets[index + offset + 5].is_server_len(prompt_size) && 
+            ordered_packets[index + offset + 5].is_server() && 
+            ordered_packets[index + offset + 6].is_server() && 
+            !ordered_packets[index + offset + 6].is_server_len(prompt_size) &&
+            (ordered_packets[index + offset + 6].length < ordered_packets[index + offset + 5].length) &&
+            ordered_packets[index + offset + 7].is_client()
             {
-                let relative_timestamp = ordered_packets[index + 10].packet.timestamp_micros().unwrap() - first_timestamp;
+                let relative_timestamp = ordered_packets[index + 10].timestamp - first_timestamp;
                 return Some(relative_timestamp);
             }
 
@@ -88,32 +86,38 @@ pub fn scan_for_reverse_session_r_option(ordered_packets: &Vec<PacketInfo>, prom
 
 /// Finds and classifies keystrokes from a given session. 
 ///
-/// Determines keystroke type based on size and context.
-pub fn scan_for_keystrokes<'a>(packet_infos: &'a[PacketInfo<'a>], keystroke_size: i32, logged_in_at: usize) -> Vec<Keystroke> {
+/// Determines keystroke type based on size and context. Passwords typed without echo (see
+/// [find_unechoed_runs]) are skipped together with the response to their Return.
+pub fn scan_for_keystrokes(packet_infos: &[PacketInfo], keystroke_size: i32, logged_in_at: usize, thresholds: &Thresholds) -> Vec<Keystroke> {
     // Start after logged_in_at
     let mut index = logged_in_at;
     let mut keystrokes: Vec<Keystroke> = Vec::new();
+    let unechoed = find_unechoed_runs(packet_infos, keystroke_size, logged_in_at);
 
     // We look ahead two packets at most
     while index < packet_infos.len() - 2 {
+        if let Some(run) = unechoed.iter().find(|run| run.start == index) {
+            log::debug!("Skipping unechoed input: {}", packet_infos[index].seq);
+            index = run.end;
+            while index < packet_infos.len() && packet_infos[index].is_server() {
+                index += 1;
+            }
+            continue;
+        }
+
         // TODO: looks like if there are previous commands and arrow=up, the echo size can give
         // away information about how long the command is. Longer command = larger size. 
         
         // Arrow keys seem to be keystroke_size + 8 from client, echo may be keystroke_size;
         // depends on what arrow key and if there are previous commands. 
         // Upper bound is set to be paired with the ordering function in `utils`.
-        if packet_infos[index].length > keystroke_size && packet_infos[index].length <= (keystroke_size + utils::KEYSTROKE_UPPER_BOUND) { 
+        if packet_infos[index].is_client() && packet_infos[index].length > keystroke_size && packet_infos[index].length <= (keystroke_size + thresholds.keystroke_upper_bound) { 
             let next_packet = &packet_infos[index+1];
             // Left arrow seems to echo keystroke_size, Right arrow (if before end of command)
             // seems to echo same size (> keystroke_size)
-            if next_packet.length == -keystroke_size || next_packet.length == packet_infos[index].length {
+            if next_packet.is_server_len(keystroke_size) || next_packet.is_client_len(packet_infos[index].length) {
                 log::debug!("Horizontal Arrow: {}", packet_infos[index].seq);
-                keystrokes.push(Keystroke {
-                    k_type: KeystrokeType::ArrowHorizontal,
-                    timestamp: packet_infos[index].packet.timestamp_micros().unwrap(),
-                    response_size: None,
-                    seq: packet_infos[index].seq,
-                });
+                keystrokes.push(Keystroke::new(KeystrokeType::ArrowHorizontal, &packet_infos[index], None));
 
                 // We use the observed arrow key size as guidance for nested arrow-presses
                 let arrow_length = packet_infos[index].length;
@@ -124,65 +128,52 @@ pub fn scan_for_keystrokes<'a>(packet_infos: &'a[PacketInfo<'a>], keystroke_size
                 index += 2;
 
                 loop {
-                    if utils::is_server_packet(packet_infos[index+2].packet) {
+                    if packet_infos[index+2].is_server() {
                         break;
                     }
                     // Deletion echoes have the same size, but we can't reliably distinguish between
                     // keystrokes and deletions after moving into the command with arrows.
                     // Therefore we push the `Unknown` `KeyType`.
-                    if packet_infos[index].length == keystroke_size && packet_infos[index+1].length == -arrow_length {
+                    if packet_infos[index].is_client_len(keystroke_size) && packet_infos[index+1].is_server_len(arrow_length) {
                         log::debug!("Delete OR Keystroke: {}", packet_infos[index].seq);
-                        keystrokes.push(Keystroke {
-                            k_type: KeystrokeType::Unknown,
-                            timestamp: packet_infos[index].packet.timestamp_micros().unwrap(),
-                            response_size: None,
-                            seq: packet_infos[index].seq,
-                        });
+                        keystrokes.push(Keystroke::new(KeystrokeType::Unknown, &packet_infos[index], None));
                     }
                     // Interestingly, it looks like keystroke echoes can be larger if in the middle
                     // of the command. But not always.
-                    else if packet_infos[index].length == keystroke_size && packet_infos[index+1].length < -arrow_length {
+                    else if packet_infos[index].is_client_len(keystroke_size) && (packet_infos[index+1].is_server() && packet_infos[index+1].length > arrow_length) {
                         log::debug!("Delete OR Keystroke: {}", packet_infos[index].seq);
-                        keystrokes.push(Keystroke {
-                            k_type: KeystrokeType::Unknown,
-                            timestamp: packet_infos[index].packet.timestamp_micros().unwrap(),
-                            response_size: None,
-                            seq: packet_infos[index].seq,
-                        });
+                        keystrokes.push(Keystroke::new(KeystrokeType::Unknown, &packet_infos[index], None));
                     }
                     // Check for further arrow keys
-                    else if packet_infos[index].length == arrow_length { //&& packet_infos[index+1].length == -keystroke_size 
+                    else if packet_infos[index].is_client_len(arrow_length) { //&& packet_infos[index+1].is_server_len(keystroke_size) 
                         log::debug!("Horizontal Arrow: {}", packet_infos[index].seq);
-                        keystrokes.push(Keystroke {
-                            k_type: KeystrokeType::ArrowHorizontal,
-                            timestamp: packet_infos[index].packet.timestamp_micros().unwrap(),
-                            response_size: None,
-                            seq: packet_infos[index].seq,
-                        });
+                        keystrokes.push(Keystroke::new(KeystrokeType::ArrowHorizontal, &packet_infos[index], None));
                     }
                     // If we are back to Client/Server echos of keystroke_size, we must be at the
                     // end of the command and can exit this loop.
                     // I am not sure if we can reach this point, though, since we have a check for
                     // consecutive server packets indicating a RETURN.
-                    else if packet_infos[index].length == keystroke_size && packet_infos[index+1].length == -keystroke_size {
+                    else if packet_infos[index].is_client_len(keystroke_size) && packet_infos[index+1].is_server_len(keystroke_size) {
                         todo!("Reachable?")
                     } 
 
                     index += 2;
                 }
                 continue;
+            }
+            // A multibyte UTF-8 character is echoed as it was sent, so the echo matches the
+            // client record. Right arrows echo alike, but only after a left arrow, which the loop
+            // above handles.
+            else if next_packet.is_server_len(packet_infos[index].length) {
+                log::debug!("Multibyte: {}", packet_infos[index].seq);
+                keystrokes.push(Keystroke::new(KeystrokeType::Multibyte, &packet_infos[index], None));
             } else {
                 log::debug!("Vertical Arrow: {}", packet_infos[index].seq);
-                keystrokes.push(Keystroke {
-                    k_type: KeystrokeType::ArrowVertical,
-                    timestamp: packet_infos[index].packet.timestamp_micros().unwrap(),
-                    response_size: None,
-                            seq: packet_infos[index].seq,
-                });
+                keystrokes.push(Keystroke::new(KeystrokeType::ArrowVertical, &packet_infos[index], None));
             }
             index += 2;
             continue;
-        } else if packet_infos[index].length != keystroke_size {
+        } else if !packet_infos[index].is_client_len(keystroke_size) {
             index += 1;
             continue;
         }
@@ -193,41 +184,26 @@ pub fn scan_for_keystrokes<'a>(packet_infos: &'a[PacketInfo<'a>], keystroke_size
         // Check for keystroke -> response (echo) -> keystroke 
         // Edge case in OR statement: normal keystroke followed by arrow key (larger size)
         // This logic is broadly adapted from Packet Strider, but the keystroke sizings are fine tuned.
-        if next_packet.length == -keystroke_size && next_next_packet.length == keystroke_size || next_next_packet.length == keystroke_size + 8 {
+        if next_packet.is_server_len(keystroke_size) && next_next_packet.is_client_len(keystroke_size) || next_next_packet.is_client_len(keystroke_size + thresholds.echo_offset) {
             log::debug!("Keystroke: {}", packet_infos[index].seq);
-            keystrokes.push(Keystroke {
-                k_type: KeystrokeType::Keystroke,
-                timestamp: packet_infos[index].packet.timestamp_micros().unwrap(),
-                response_size: None,
-                            seq: packet_infos[index].seq,
-            });
+            keystrokes.push(Keystroke::new(KeystrokeType::Keystroke, &packet_infos[index], None));
         } 
-        // Backspace/Delete results in an echo that is keystroke_size + 8
+        // Backspace/Delete results in an echo that is keystroke_size + echo offset (8 by default)
         // Problem: (TODO) Ctrl+a (jump to start) and Ctrl+e also fulfill this condition.
-        else if next_packet.length == -(keystroke_size + 8) && next_next_packet.length == keystroke_size {
+        else if next_packet.is_server_len(keystroke_size + thresholds.echo_offset) && next_next_packet.is_client_len(keystroke_size) {
             log::debug!("Delete: {}", packet_infos[index].seq);
-            keystrokes.push(Keystroke {
-                k_type: KeystrokeType::Delete,
-                timestamp: packet_infos[index].packet.timestamp_micros().unwrap(),
-                response_size: None,
-                            seq: packet_infos[index].seq,
-            });
+            keystrokes.push(Keystroke::new(KeystrokeType::Delete, &packet_infos[index], None));
         } 
         // Tab, TBD if feasible, temporarily adapted from Packet Strider but does not seem reliable any longer.
-        else if next_packet.length < -(keystroke_size + 8) && next_next_packet.length == keystroke_size {
+        else if (next_packet.is_server() && next_packet.length > keystroke_size + thresholds.echo_offset) && next_next_packet.is_client_len(keystroke_size) {
             log::debug!("Tab: {} - Next: {}, len: {}", packet_infos[index].seq, next_packet.seq, next_packet.length);
 
             // TODO: refer to observation in notes -> I suspect this is far from fine-tuned.
-            keystrokes.push(Keystroke {
-                k_type: KeystrokeType::Tab,
-                timestamp: packet_infos[index].packet.timestamp_micros().unwrap(),
-                response_size: None,
-                            seq: packet_infos[index].seq,
-            });
+            keystrokes.push(Keystroke::new(KeystrokeType::Tab, &packet_infos[index], None));
         } 
         // Returns are also keystroke_size, but we can distinguish them from the additional data
         // packets returned. 
-        else if next_packet.length <= -keystroke_size && next_next_packet.length <= -keystroke_size && !keystrokes.is_empty() {
+        else if (next_packet.is_server() && next_packet.length >= keystroke_size) && (next_next_packet.is_server() && next_next_packet.length >= keystroke_size) && !keystrokes.is_empty() {
             log::debug!("Return: {}", packet_infos[index].seq);
             // After running a command (by sending enter/return), the return is echoed (but not always -keystroke_size length, interestingly)
             // We then iterate through the next packets until a Client packet, which indicates the end of the response (at least for typical commands).
@@ -236,23 +212,18 @@ pub fn scan_for_keystrokes<'a>(packet_infos: &'a[PacketInfo<'a>], keystroke_size
 
             while end < packet_infos.len() {
                 // Client packet indicates end of server block
-                if packet_infos[end].length > 0 {
+                if packet_infos[end].is_client() {
                     index = end;
                     break;
                 }
                 
                 // TODO: In ciphers with known payload length, this can be optimised.
                 // Currently this is just the length of the padded TCP packet(s)
-                response_size += packet_infos[end].length.abs() as u128;
+                response_size += packet_infos[end].length as u128;
                 end += 1;
             }
             
-            keystrokes.push(Keystroke {
-                k_type: KeystrokeType::Enter,
-                timestamp: packet_infos[index].packet.timestamp_micros().unwrap(),
-                response_size: Some(response_size),
-                            seq: packet_infos[index].seq,
-            });
+            keystrokes.push(Keystroke::new(KeystrokeType::Enter, &packet_infos[index], Some(response_size)));
 
             // We already set index = end in the loop, so no increment needed.
             continue;
@@ -264,21 +235,68 @@ pub fn scan_for_keystrokes<'a>(packet_infos: &'a[PacketInfo<'a>], keystroke_size
     keystrokes
 }
 
+/// Finds runs of keystroke-sized client records that the server does not echo, i.e. input at a
+/// password prompt (sudo, su, ssh within the session).
+///
+/// A run follows a server record (the prompt) and ends with the Return, which the server answers.
+/// Keys typed faster than the round trip also go out before their echoes, but the echoes follow
+/// the run one by one, whereas a password prompt answers with a newline and the command's output.
+/// Returns the positions of each run, Return included.
+fn find_unechoed_runs(packet_infos: &[PacketInfo], keystroke_size: i32, logged_in_at: usize) -> Vec<std::ops::Range<usize>> {
+    let mut runs = Vec::new();
+    let mut index = logged_in_at.max(1);
+
+    while index < packet_infos.len() {
+        if !packet_infos[index].is_client_len(keystroke_size) || !packet_infos[index - 1].is_server() {
+            index += 1;
+            continue;
+        }
+
+        let end = index + packet_infos[index..].iter().take_while(|packet_info| packet_info.is_client_len(keystroke_size)).count();
+        let length = end - index - 1;
+        let echoes = packet_infos[end..].iter().take_while(|packet_info| packet_info.is_server_len(keystroke_size)).count();
+        if length >= utils::PASSWORD_ENTRY_MIN_LENGTH && end < packet_infos.len() && packet_infos[end].is_server() && echoes < length {
+            runs.push(index..end);
+        }
+        index = end;
+    }
+
+    runs
+}
+
+/// Finds passwords typed without echo after login, with their length.
+///
+/// The last keystroke of a run is the Return, so a run of n keystroke records is a password of
+/// n - 1 characters, give or take characters deleted with Backspace, which is not echoed either.
+pub fn scan_for_unechoed_input(packet_infos: &[PacketInfo], keystroke_size: i32, logged_in_at: usize) -> Vec<Finding> {
+    log::info!("Looking for passwords typed without echo.");
+
+    find_unechoed_runs(packet_infos, keystroke_size, logged_in_at).into_iter()
+        .map(|run| {
+            let length = run.len() - 1;
+            log::debug!("Password entry at {}, {length} characters", packet_infos[run.start].seq);
+            Finding::new(Event::PasswordEntry, &packet_infos[run.start])
+                .with_details(format!("{length} characters"))
+                .with_confidence(0.6)
+        })
+        .collect()
+}
+
 /// Finds the index of packets suspected to be RET's.
-fn find_returns<'a>(packet_infos: &'a[PacketInfo<'a>], keystroke_size: i32, logged_in_at: usize) -> Vec<usize> {
+fn find_returns(packet_infos: &[PacketInfo], keystroke_size: i32, logged_in_at: usize) -> Vec<usize> {
     log::debug!("Finding return keystroke indexes.");
 
     let mut index = logged_in_at;
     let mut indexes = Vec::new();
 
     while index < packet_infos.len() - 2 {
-        if packet_infos[index].length != keystroke_size {
+        if !packet_infos[index].is_client_len(keystroke_size) {
             index += 2;
             continue;
         }
 
         // Basically looking for consecutive server packets that do not fall into the keystroke/chaff range
-        if packet_infos[index+1].length <= -keystroke_size / 2 && packet_infos[index+2].length <= -keystroke_size/2 {
+        if (packet_infos[index+1].is_server() && packet_infos[index+1].length >= keystroke_size / 2) && (packet_infos[index+2].is_server() && packet_infos[index+2].length >= keystroke_size/2) {
             log::debug!("RET seq - index: {} - {}", packet_infos[index].seq, index);
             indexes.push(index);
 
@@ -288,7 +306,7 @@ fn find_returns<'a>(packet_infos: &'a[PacketInfo<'a>], keystroke_size: i32, logg
 
             while end < packet_infos.len() {
                 // Client packet indicates end of server block
-                if packet_infos[end].length > 0 {
+                if packet_infos[end].is_client() {
                     index = end;
                     break;
                 } 
@@ -305,35 +323,34 @@ fn find_returns<'a>(packet_infos: &'a[PacketInfo<'a>], keystroke_size: i32, logg
     indexes
 }
 
-/// Find a gap between chaff that is greater than 35ms, indicating a pause in chaff/typing
+/// Find a gap between chaff that is greater than `chaff_gap` (35ms by default), indicating a pause in chaff/typing
 /// 
 /// Returns indexes of the first slim packet after a gap, initiating new chaff.
-fn find_chaff_gap<'a>(packet_infos: &'a [PacketInfo<'a>], returns: &[usize], keystroke_size: i32) -> Vec<usize> {
+fn find_chaff_gap(packet_infos: &[PacketInfo], returns: &[usize], keystroke_size: i32, chaff_gap: i64) -> Vec<usize> {
     log::info!("Finding chaff gaps.");
 
     let mut real_slims = Vec::new();
 
-    // Start looking after RET for chaff gap of >35ms
+    // Start looking after RET for a chaff gap
     for &ret_index in returns {
         let mut itr = ret_index;
 
         // Skip to chaff from first RET
-        while packet_infos[itr].length != keystroke_size / 2 {
+        while !packet_infos[itr].is_client_len(keystroke_size / 2) {
             itr += 1;
         }
 
-        let mut last_timestamp = packet_infos[itr].packet.timestamp_micros().unwrap();
+        let mut last_timestamp = packet_infos[itr].timestamp;
         itr += 2;
 
-        // Set the bound at 35ms
-        while itr < packet_infos.len() - 2 && packet_infos[itr].packet.timestamp_micros().unwrap() - last_timestamp < 35000 {
-            last_timestamp = packet_infos[itr].packet.timestamp_micros().unwrap();
+        while itr < packet_infos.len() - 2 && packet_infos[itr].timestamp - last_timestamp < chaff_gap {
+            last_timestamp = packet_infos[itr].timestamp;
             itr += 2;
         }
 
         // Only add it to real_slims if it was indeed a time gap and we didn't run out of iterations.
         if itr < packet_infos.len() - 2 {
-            log::debug!("Found gap at index {}, {}μs", itr, packet_infos[itr].packet.timestamp_micros().unwrap() - last_timestamp);
+            log::debug!("Found gap at index {}, {}μs", itr, packet_infos[itr].timestamp - last_timestamp);
             log::debug!("Real keystroke slim packet after gap: {}", packet_infos[itr].seq);
             real_slims.push(itr);
         }
@@ -343,7 +360,7 @@ fn find_chaff_gap<'a>(packet_infos: &'a [PacketInfo<'a>], returns: &[usize], key
 }
 
 /// Drops all chaff packets, leaving only real keystrokes behind.
-fn drop_chaff<'a>(packet_infos: &'a[PacketInfo<'a>], returns: &[usize], gaps: &[usize], keystroke_size: i32, logged_in_at: usize) -> Vec<&'a PacketInfo<'a>> {
+fn drop_chaff<'a>(packet_infos: &'a [PacketInfo], returns: &[usize], gaps: &[usize], keystroke_size: i32, logged_in_at: usize) -> Vec<&'a PacketInfo> {
     log::info!("Dropping Chaff");
 
     let mut real_keystrokes: Vec<&PacketInfo> = Vec::new();
@@ -351,12 +368,12 @@ fn drop_chaff<'a>(packet_infos: &'a[PacketInfo<'a>], returns: &[usize], gaps: &[
     let mut ret_itr = 0;
 
     // Skip to first real, small packet
-    while packet_infos[index].length != keystroke_size / 2 {
+    while !packet_infos[index].is_client_len(keystroke_size / 2) {
         index += 1;
     }
 
     // Add initial keystroke that initialises the chaff
-    if packet_infos[index+1].length == -keystroke_size / 2 && packet_infos[index+2].length == keystroke_size / 2 {
+    if packet_infos[index+1].is_server_len(keystroke_size / 2) && packet_infos[index+2].is_client_len(keystroke_size / 2) {
         log::debug!("Keystroke: {}", packet_infos[index].seq);
         real_keystrokes.push(&packet_infos[index]);
         real_keystrokes.push(&packet_infos[index+1]);
@@ -372,7 +389,7 @@ fn drop_chaff<'a>(packet_infos: &'a[PacketInfo<'a>], returns: &[usize], gaps: &[
             real_keystrokes.push(&packet_infos[index]);
             index += 1;
             // Push all server-side response packets
-            while packet_infos[index].length < 0 {
+            while packet_infos[index].is_server() {
                 log::debug!("Ret Response: {}", packet_infos[index].seq);
                 real_keystrokes.push(&packet_infos[index]);
                 index += 1;
@@ -387,7 +404,7 @@ fn drop_chaff<'a>(packet_infos: &'a[PacketInfo<'a>], returns: &[usize], gaps: &[
                 // If there is a gap between the previous ret and next ret, we skip chaff/add fats until the gap, then reset
                 if ret_itr + 1 < returns.len() && gap < returns[ret_itr+1] {
                     while index <= gap-2 {
-                        if packet_infos[index].length != keystroke_size  {
+                        if !packet_infos[index].is_client_len(keystroke_size)  {
                             index += 2;
                         } else {
                             // Possible for some keystrokes to be sent after a RET and before a Gap.
@@ -401,7 +418,7 @@ fn drop_chaff<'a>(packet_infos: &'a[PacketInfo<'a>], returns: &[usize], gaps: &[
                     // Here we hit the gap, so we add the slim packet and can restart the loop
                     log::debug!("Hit the gap.");
                     assert_eq!(index, gap);
-                    if packet_infos[index+1].length == -keystroke_size / 2 && packet_infos[index+2].length == keystroke_size / 2 {
+                    if packet_infos[index+1].is_server_len(keystroke_size / 2) && packet_infos[index+2].is_client_len(keystroke_size / 2) {
                         log::debug!("Keystroke: {}", packet_infos[index].seq);
                         real_keystrokes.push(&packet_infos[index]);
                         real_keystrokes.push(&packet_infos[index+1]);
@@ -416,7 +433,7 @@ fn drop_chaff<'a>(packet_infos: &'a[PacketInfo<'a>], returns: &[usize], gaps: &[
             ret_itr += 1;
         }
         // Push fat packet and next packet. TODO, maybe we need to check which subsequent packet is larger and push that instead
-        if packet_infos[index].length == keystroke_size {
+        if packet_infos[index].is_client_len(keystroke_size) {
             log::debug!("Keystroke: {}", packet_infos[index].seq);
             real_keystrokes.push(&packet_infos[index]);
             real_keystrokes.push(&packet_infos[index+1]);
@@ -427,11 +444,11 @@ fn drop_chaff<'a>(packet_infos: &'a[PacketInfo<'a>], returns: &[usize], gaps: &[
     real_keystrokes
 }
 
-pub fn scan_for_obfuscated_keystrokes<'a>(packet_infos: &'a[PacketInfo<'a>], keystroke_size: i32, logged_in_at: usize) -> Vec<Keystroke> {
+pub fn scan_for_obfuscated_keystrokes(packet_infos: &[PacketInfo], keystroke_size: i32, logged_in_at: usize, thresholds: &Thresholds) -> Vec<Keystroke> {
     let mut keystrokes: Vec<Keystroke> = Vec::new();
     let returns = find_returns(&packet_infos, keystroke_size, logged_in_at); 
 
-    let gaps = find_chaff_gap(packet_infos, &returns, keystroke_size);
+    let gaps = find_chaff_gap(packet_infos, &returns, keystroke_size, thresholds.chaff_gap);
     log::debug!("Chaff gap: {:?}", gaps);
 
     let real_keystrokes = drop_chaff(packet_infos, &returns,  &gaps, keystroke_size, logged_in_at);
@@ -448,20 +465,15 @@ pub fn scan_for_obfuscated_keystrokes<'a>(packet_infos: &'a[PacketInfo<'a>], key
         // Arrow keys seem to be keystroke_size + 8 from client, echo may be keystroke_size;
         // depends on what arrow key and if there are previous commands. 
         // Upper bound is set to be paired with the ordering function in `utils`.
-        if real_keystrokes[index].length > keystroke_size && real_keystrokes[index].length <= (keystroke_size + utils::KEYSTROKE_UPPER_BOUND)
-        //|| real_keystrokes[index].length > keystroke_size/2 && real_keystrokes[index].length/2 <= (keystroke_size + utils::KEYSTROKE_UPPER_BOUND)
+        if real_keystrokes[index].is_client() && real_keystrokes[index].length > keystroke_size && real_keystrokes[index].length <= (keystroke_size + thresholds.keystroke_upper_bound)
+        //|| real_keystrokes[index].length > keystroke_size/2 && real_keystrokes[index].length/2 <= (keystroke_size + thresholds.keystroke_upper_bound)
         { 
             let next_packet = &packet_infos[index+1];
             // Left arrow seems to echo keystroke_size, Right arrow (if before end of command)
             // seems to echo same size (> keystroke_size)
-            if next_packet.length == -keystroke_size/2 || next_packet.length == packet_infos[index].length {
+            if next_packet.is_server_len(keystroke_size/2) || next_packet.is_client_len(packet_infos[index].length) {
                 log::debug!("Horizontal Arrow: {}", packet_infos[index].seq);
-                keystrokes.push(Keystroke {
-                    k_type: KeystrokeType::ArrowHorizontal,
-                    timestamp: packet_infos[index].packet.timestamp_micros().unwrap(),
-                    response_size: None,
-                    seq: packet_infos[index].seq,
-                });
+                keystrokes.push(Keystroke::new(KeystrokeType::ArrowHorizontal, &packet_infos[index], None));
 
                 // We use the observed arrow key size as guidance for nested arrow-presses
                 let arrow_length = packet_infos[index].length;
@@ -472,47 +484,32 @@ pub fn scan_for_obfuscated_keystrokes<'a>(packet_infos: &'a[PacketInfo<'a>], key
                 index += 2;
 
                 loop {
-                    if utils::is_server_packet(packet_infos[index+2].packet) {
+                    if packet_infos[index+2].is_server() {
                         break;
                     }
                     // Deletion echoes have the same size, but we can't reliably distinguish between
                     // keystrokes and deletions after moving into the command with arrows.
                     // Therefore we push the `Unknown` `KeyType`.
-                    if packet_infos[index].length == keystroke_size && packet_infos[index+1].length == -arrow_length {
+                    if packet_infos[index].is_client_len(keystroke_size) && packet_infos[index+1].is_server_len(arrow_length) {
                         log::debug!("Delete OR Keystroke: {}", packet_infos[index].seq);
-                        keystrokes.push(Keystroke {
-                            k_type: KeystrokeType::Unknown,
-                            timestamp: packet_infos[index].packet.timestamp_micros().unwrap(),
-                            response_size: None,
-                            seq: packet_infos[index].seq,
-                        });
+                        keystrokes.push(Keystroke::new(KeystrokeType::Unknown, &packet_infos[index], None));
                     }
                     // Interestingly, it looks like keystroke echoes can be larger if in the middle
                     // of the command. But not always.
-                    else if packet_infos[index].length == keystroke_size && packet_infos[index+1].length < -arrow_length {
+                    else if packet_infos[index].is_client_len(keystroke_size) && (packet_infos[index+1].is_server() && packet_infos[index+1].length > arrow_length) {
                         log::debug!("Delete OR Keystroke: {}", packet_infos[index].seq);
-                        keystrokes.push(Keystroke {
-                            k_type: KeystrokeType::Unknown,
-                            timestamp: packet_infos[index].packet.timestamp_micros().unwrap(),
-                            response_size: None,
-                            seq: packet_infos[index].seq,
-                        });
+                        keystrokes.push(Keystroke::new(KeystrokeType::Unknown, &packet_infos[index], None));
                     }
                     // Check for further arrow keys
-                    else if packet_infos[index].length == arrow_length { //&& packet_infos[index+1].length == -keystroke_size 
+                    else if packet_infos[index].is_client_len(arrow_length) { //&& packet_infos[index+1].is_server_len(keystroke_size) 
                         log::debug!("Horizontal Arrow: {}", packet_infos[index].seq);
-                        keystrokes.push(Keystroke {
-                            k_type: KeystrokeType::ArrowHorizontal,
-                            timestamp: packet_infos[index].packet.timestamp_micros().unwrap(),
-                            response_size: None,
-                            seq: packet_infos[index].seq,
-                        });
+                        keystrokes.push(Keystroke::new(KeystrokeType::ArrowHorizontal, &packet_infos[index], None));
                     }
                     // If we are back to Client/Server echos of keystroke_size, we must be at the
                     // end of the command and can exit this loop.
                     // I am not sure if we can reach this point, though, since we have a check for
                     // consecutive server packets indicating a RETURN.
-                    else if packet_infos[index].length == keystroke_size && packet_infos[index+1].length == -keystroke_size {
+                    else if packet_infos[index].is_client_len(keystroke_size) && packet_infos[index+1].is_server_len(keystroke_size) {
                         todo!("Reachable?")
                     } 
 
@@ -521,12 +518,7 @@ pub fn scan_for_obfuscated_keystrokes<'a>(packet_infos: &'a[PacketInfo<'a>], key
                 continue;
             } else {
                 log::debug!("Vertical Arrow: {}", packet_infos[index].seq);
-                keystrokes.push(Keystroke {
-                    k_type: KeystrokeType::ArrowVertical,
-                    timestamp: packet_infos[index].packet.timestamp_micros().unwrap(),
-                    response_size: None,
-                            seq: packet_infos[index].seq,
-                });
+                keystrokes.push(Keystroke::new(KeystrokeType::ArrowVertical, &packet_infos[index], None));
             }
             index += 2;
             continue;
@@ -540,41 +532,26 @@ pub fn scan_for_obfuscated_keystrokes<'a>(packet_infos: &'a[PacketInfo<'a>], key
         // Check for keystroke -> response (echo) -> keystroke 
         // Edge case in OR statement: normal keystroke followed by arrow key (larger size)
         // This logic is broadly adapted from Packet Strider, but the keystroke sizings are fine tuned.
-        if next_packet.length == -keystroke_size / 2 && next_next_packet.length == keystroke_size || next_next_packet.length == keystroke_size + 8 {
+        if next_packet.is_server_len(keystroke_size / 2) && next_next_packet.is_client_len(keystroke_size) || next_next_packet.is_client_len(keystroke_size + thresholds.echo_offset) {
             log::debug!("Keystroke: {}", real_keystrokes[index].seq);
-            keystrokes.push(Keystroke {
-                k_type: KeystrokeType::Keystroke,
-                timestamp: real_keystrokes[index].packet.timestamp_micros().unwrap(),
-                response_size: None,
-                            seq: real_keystrokes[index].seq,
-            });
+            keystrokes.push(Keystroke::new(KeystrokeType::Keystroke, real_keystrokes[index], None));
         } 
-        // Backspace/Delete results in an echo that is keystroke_size + 8
+        // Backspace/Delete results in an echo that is keystroke_size + echo offset (8 by default)
         // Problem: (TODO) Ctrl+a (jump to start) and Ctrl+e also fulfill this condition.
-        else if next_packet.length == -((keystroke_size/2) + 8) && next_next_packet.length == keystroke_size {
+        else if next_packet.is_server_len((keystroke_size/2) + thresholds.echo_offset) && next_next_packet.is_client_len(keystroke_size) {
             log::debug!("Delete: {}", real_keystrokes[index].seq);
-            keystrokes.push(Keystroke {
-                k_type: KeystrokeType::Delete,
-                timestamp: real_keystrokes[index].packet.timestamp_micros().unwrap(),
-                response_size: None,
-                            seq: real_keystrokes[index].seq,
-            });
+            keystrokes.push(Keystroke::new(KeystrokeType::Delete, real_keystrokes[index], None));
         } 
         // Tab, TBD if feasible, temporarily adapted from Packet Strider but does not seem reliable any longer.
-        else if next_packet.length < -((keystroke_size/2) + 8) && next_next_packet.length == keystroke_size {
+        else if (next_packet.is_server() && next_packet.length > (keystroke_size/2) + thresholds.echo_offset) && next_next_packet.is_client_len(keystroke_size) {
             log::debug!("Tab: {} - Next: {}, len: {}", real_keystrokes[index].seq, next_packet.seq, next_packet.length);
 
             // TODO: refer to observation in notes -> I suspect this is far from fine-tuned.
-            keystrokes.push(Keystroke {
-                k_type: KeystrokeType::Tab,
-                timestamp: real_keystrokes[index].packet.timestamp_micros().unwrap(),
-                response_size: None,
-                            seq: real_keystrokes[index].seq,
-            });
+            keystrokes.push(Keystroke::new(KeystrokeType::Tab, real_keystrokes[index], None));
         } 
         // Returns are also keystroke_size, but we can distinguish them from the additional data
         // packets returned. 
-        else if next_packet.length <= -(keystroke_size/2) && next_next_packet.length <= -(keystroke_size/2) && !keystrokes.is_empty() {
+        else if (next_packet.is_server() && next_packet.length >= keystroke_size/2) && (next_next_packet.is_server() && next_next_packet.length >= keystroke_size/2) && !keystrokes.is_empty() {
             log::debug!("Return: {}", real_keystrokes[index].seq);
             // After running a command (by sending enter/return), the return is echoed (but not always -keystroke_size length, interestingly)
             // We then iterate through the next packets until a Client packet, which indicates the end of the response (at least for typical commands).
@@ -583,14 +560,14 @@ pub fn scan_for_obfuscated_keystrokes<'a>(packet_infos: &'a[PacketInfo<'a>], key
 
             while end < real_keystrokes.len() {
                 // Client packet indicates end of server block
-                if real_keystrokes[end].length > 0 {
+                if real_keystrokes[end].is_client() {
                     index = end;
                     break;
                 }
                 
                 // TODO: In ciphers with known payload length, this can be optimised.
                 // Currently this is just the length of the padded TCP packet(s)
-                response_size += real_keystrokes[end].length.abs() as u128;
+                response_size += real_keystrokes[end].length as u128;
                 end += 1;
             } 
             
@@ -599,12 +576,7 @@ pub fn scan_for_obfuscated_keystrokes<'a>(packet_infos: &'a[PacketInfo<'a>], key
                 index = end-1;
             }
             
-            keystrokes.push(Keystroke {
-                k_type: KeystrokeType::Enter,
-                timestamp: real_keystrokes[index].packet.timestamp_micros().unwrap(),
-                response_size: Some(response_size),
-                            seq: real_keystrokes[index].seq,
-            });
+            keystrokes.push(Keystroke::new(KeystrokeType::Enter, real_keystrokes[index], Some(response_size)));
 
             // We already set index = end in the loop, so no increment needed.
             continue;
@@ -629,7 +601,7 @@ pub fn _scan_for_agent_forwarding(packet_infos: &[PacketInfo]) {
     for (index, packet_info) in packet_infos.iter().take(40).enumerate() {
         // Once again, only look after New Keys. Further argument to keep track of New Keys index.
         // TODO ^ 
-        match get_message_code(&packet_info.packet) {
+        match packet_info.message_code {
             Some(code) => {
                 if code != 21 {
                     continue;
@@ -638,11 +610,9 @@ pub fn _scan_for_agent_forwarding(packet_infos: &[PacketInfo]) {
             None => continue,
         };
         // The New Keys (21) packet is *not* followed by message_code
-        let next_packet = packet_infos[index+1].packet;
-        match get_message_code(&next_packet) {
-            Some(_) => continue,
-            None => {}
-        };
+        if packet_infos[index+1].message_code.is_some() {
+            continue;
+        }
 
         // Tell-tale packet "is always surrounded by 2 Server packets before and 2 Server packets after"
         todo!("See comment above function definition.")        
@@ -653,17 +623,15 @@ pub fn _scan_for_agent_forwarding(packet_infos: &[PacketInfo]) {
 ///
 /// Happens when pubkey is in known_hosts.
 /// This logic is adapted from Packet Strider.
-pub fn scan_for_host_key_accepts<'a>(packet_infos: &[PacketInfo<'a>], logged_in_at: usize) -> Option<PacketInfo<'a>> {
+pub fn scan_for_host_key_accepts(packet_infos: &[PacketInfo], logged_in_at: usize) -> Option<Finding> {
     log::info!("Looking for host key acceptance by Client.");
-    let mut result: PacketInfo;
 
     for (index, packet_info) in packet_infos.iter().take(100).enumerate() {
         if index == logged_in_at {
             break;
         }
 
-        let packet = packet_info.packet;
-        let message_code = match get_message_code(&packet) {
+        let message_code = match packet_info.message_code {
             Some(code) => code,
             None => continue,
         };
@@ -672,21 +640,15 @@ pub fn scan_for_host_key_accepts<'a>(packet_infos: &[PacketInfo<'a>], logged_in_
             continue;
         }
 
-        let next_packet = packet_infos[index+1].packet;
-
         // The New Keys (21) packet is *not* followed by message_code
-        match get_message_code(&next_packet) {
-            Some(_) => continue,
-            None => {}
-        };
+        if packet_infos[index+1].message_code.is_some() {
+            continue;
+        }
 
         // This is the packet containing the server's key fingerprint.
         // TODO: In packet strider this is simply logged, but I think it's worth keeping track of
         // this packet and actually outputting the fingerprint; maybe make it optional.
-        result = packet_infos[index-1].clone();
-        result.description = Some("Server hostkey accepted".to_string());
-        
-        return Some(result); 
+        return Some(Finding::new(Event::HostKeyAccepted, &packet_infos[index-1]));
     }
 
     None
@@ -694,9 +656,14 @@ pub fn scan_for_host_key_accepts<'a>(packet_infos: &[PacketInfo<'a>], logged_in_
 
 /// Scans for login-related findings, such as key offers, key accepts/rejects, password attempts.
 ///
-/// Uses research findings of packet length ranges to classify key types (RSA, ED25519, ECDSA).
-pub fn scan_login_data<'a>(packet_infos: &[PacketInfo<'a>], prompt_size: i32, new_keys_index: usize, logged_in_at: usize) -> Vec<PacketInfo<'a>> {
+/// Uses research findings of packet length ranges to classify key types (see [key_offer]), and
+/// follows keyboard-interactive exchanges with [scan_keyboard_interactive].
+/// Prompts may differ from `prompt_size` by up to the prompt tolerance of `thresholds`.
+pub fn scan_login_data(packet_infos: &[PacketInfo], prompt_size: i32, new_keys_index: usize, logged_in_at: usize, thresholds: &Thresholds) -> Vec<Finding> {
     let _offset = new_keys_index;
+    let is_prompt = |packet_info: &PacketInfo| packet_info.is_server() && (packet_info.length - prompt_size).abs() <= thresholds.prompt_tolerance;
+    // Prompts only matched within the tolerance make the findings around them less certain
+    let prompt_confidence = |packet_info: &PacketInfo| if packet_info.length == prompt_size { 1.0 } else { utils::TOLERATED_PROMPT_CONFIDENCE };
     // We only care about the slice of packets between the first login prompt and up to the
     // successful logon.
 
@@ -707,21 +674,20 @@ pub fn scan_login_data<'a>(packet_infos: &[PacketInfo<'a>], prompt_size: i32, ne
 //                            .iter()
 //                            .skip(offset)
 //                            .take(logged_in_at - offset)
-//                            .find(|packet_info| packet_info.length == prompt_size)
+//                            .find(|packet_info| packet_info.is_server_len(prompt_size))
 //                            .unwrap_or_else(|| {
 //                                log::error!("Failed to find initial login prompt.");
 //                                panic!("Initial login prompt not found.");
 //                            }).index;
     let initial_prompt = packet_infos[new_keys_index+4].index;
 
-    let mut event_packets: Vec<PacketInfo> = Vec::new();
+    let mut event_packets: Vec<Finding> = Vec::new();
 
     let mut ptr: usize = initial_prompt;
 
     let mut curr_packet: &PacketInfo = &packet_infos[ptr];
     let mut next_packet: &PacketInfo;
     let mut next_next_packet: &PacketInfo;
-    let mut event_packet: PacketInfo;
     while (ptr + 2)  < packet_infos.len() && curr_packet.index != logged_in_at {
         curr_packet = &packet_infos[ptr];
         next_packet = &packet_infos[ptr+1];
@@ -730,7 +696,7 @@ pub fn scan_login_data<'a>(packet_infos: &[PacketInfo<'a>], prompt_size: i32, ne
         // A client packet sandwiched between prompt_size'd packets means either of two things:
         // 1. A wrong password attempt 
         // 2. A key was offered and rejected
-        if next_next_packet.length == prompt_size {
+        if is_prompt(next_next_packet) {
             // To distinguish between these two options, we must compare the client packet's size
             // to known pubkey offerings' sizes
             
@@ -740,48 +706,38 @@ pub fn scan_login_data<'a>(packet_infos: &[PacketInfo<'a>], prompt_size: i32, ne
             // TODO: Sometimes a wrong password can be padded to either of these sizes. Should be
             // easy to spot though if we start looking at time deltas, since key offers are sent
             // automatically and in rapid succession, as opposed to passwords.
-            let event = match next_packet.length {
-                492..=500 => {
-                    log::debug!("RSA key offered and rejected.");
-                    event_packet = next_packet.clone();
-                    event_packet.description = Some(Event::OfferRSAKey.to_string());
-                    event_packets.push(event_packet);
+            let confidence = prompt_confidence(next_next_packet);
+            let event = match key_offer(next_packet, thresholds) {
+                Some(offer) => {
+                    log::debug!("{offer:?} offered and rejected.");
+                    event_packets.push(Finding::new(offer, next_packet).with_confidence(confidence));
                     Event::RejectedKey
                 },
-                140..=148 => {
-                    log::debug!("ED25519 key offered and rejected.");
-                    event_packet = next_packet.clone();
-                    event_packet.description = Some(Event::OfferED25519Key.to_string());
-                    event_packets.push(event_packet);
-                    Event::RejectedKey
-                },
-                188..=212 => {
-                    log::debug!("ECDSA key offered and rejected.");
-                    event_packet = next_packet.clone();
-                    event_packet.description = Some(Event::OfferECDSAKey.to_string());
-                    event_packets.push(event_packet);
-                    Event:: RejectedKey
-                },
-                _ => {
+                None => {
                     log::debug!("Wrong password attempt detected.");
                     Event::WrongPassword
                 },
             };
 
-            event_packet = next_next_packet.clone();
-            event_packet.description = Some(event.to_string());
-            event_packets.push(event_packet);
+            event_packets.push(Finding::new(event, next_next_packet).with_confidence(confidence));
         } 
         // This MUST be a successful login. 
         // if ptr=prompt_size, then it must have been via a valid password:
         // prompt_size -> <password> -> SSH2_MSG_USERAUTH_SUCCESS
         else if next_next_packet.index == logged_in_at {
-            if curr_packet.length == prompt_size {
-                event_packet = next_next_packet.clone();
-                event_packet.description = Some(Event::CorrectPassword.to_string());
-                event_packets.push(event_packet);
+            if is_prompt(curr_packet) {
+                event_packets.push(Finding::new(Event::CorrectPassword, next_next_packet).with_confidence(prompt_confidence(curr_packet)));
+                break;
+            }
+        }
+        // Answered by INFO_REQUESTs rather than a prompt or PK_OK: keyboard-interactive
+        else if let Some((end, findings)) = scan_keyboard_interactive(packet_infos, ptr + 1, logged_in_at, thresholds, &is_prompt, &prompt_confidence) {
+            event_packets.extend(findings);
+            if packet_infos[end].index == logged_in_at {
                 break;
             }
+            ptr = end;
+            continue;
         }
         // If the packet-after-next is not prompt-sized, it means a key was offered and accepted 
         else {
@@ -794,37 +750,19 @@ pub fn scan_login_data<'a>(packet_infos: &[PacketInfo<'a>], prompt_size: i32, ne
             // packet size is much larger than on password-based authentication.
             // Otherwise, of course, latencies can be used to infer key-based vs password-based,
             // especially with unencrypted private keys.
-            let event = match next_packet.length {
-                492..=500 => {
-                    log::debug!("RSA key offered and accepted.");
-                    event_packet = next_packet.clone();
-                    event_packet.description = Some(Event::OfferRSAKey.to_string());
-                    event_packets.push(event_packet);
-                    Event::AcceptedKey
-                },
-                140..=148 => {
-                    log::debug!("ED25519 key offered and accepted.");
-                    event_packet = next_packet.clone();
-                    event_packet.description = Some(Event::OfferED25519Key.to_string());
-                    event_packets.push(event_packet);
+            let event = match key_offer(next_packet, thresholds) {
+                Some(offer) => {
+                    log::debug!("{offer:?} offered and accepted.");
+                    event_packets.push(Finding::new(offer, next_packet));
                     Event::AcceptedKey
                 },
-                188..=212 => {
-                    log::debug!("ECDSA key offered and accepted.");
-                    event_packet = next_packet.clone();
-                    event_packet.description = Some(Event::OfferECDSAKey.to_string());
-                    event_packets.push(event_packet);
-                    Event::AcceptedKey 
-                },
-                _ => {
+                None => {
                     log::debug!("Correct password detected.");
                     Event::CorrectPassword
                 },
             };
 
-            event_packet = next_next_packet.clone();
-            event_packet.description = Some(event.to_string());
-            event_packets.push(event_packet);
+            event_packets.push(Finding::new(event, next_next_packet));
 
             // The next packet after the accept key offer may be a password, or another key offer.
             // (at curr_packet + 4)
@@ -840,18 +778,237 @@ pub fn scan_login_data<'a>(packet_infos: &[PacketInfo<'a>], prompt_size: i32, ne
     event_packets
 }
 
+/// Follows a keyboard-interactive exchange from the client's request at `start`: each
+/// INFO_REQUEST of the server is answered by an INFO_RESPONSE, until the server accepts the login
+/// or prompts again. `None` if the request is not of the size of a keyboard-interactive one in
+/// `thresholds`, or is not answered that way.
+///
+/// Returns the position of the server's final answer and the findings: a
+/// [KeyboardInteractive](Event::KeyboardInteractive) login, a [SecondFactor](Event::SecondFactor)
+/// at the second round of prompts, if any, and the [CorrectPassword](Event::CorrectPassword) or
+/// [WrongPassword](Event::WrongPassword) outcome. The empty INFO_REQUEST that OpenSSH concludes a
+/// PAM conversation with is not counted as a round.
+pub fn scan_keyboard_interactive(packet_infos: &[PacketInfo], start: usize, logged_in_at: usize, thresholds: &Thresholds, is_prompt: &dyn Fn(&PacketInfo) -> bool, prompt_confidence: &dyn Fn(&PacketInfo) -> f32) -> Option<(usize, Vec<Finding>)> {
+    let request = packet_infos.get(start)?;
+    if !request.is_client() || !thresholds.keyboard_interactive_request.contains(&request.length) {
+        return None;
+    }
+
+    let mut rounds: Vec<&PacketInfo> = vec![];
+    let mut position = start + 1;
+    let end = loop {
+        let answer = packet_infos.get(position)?;
+        if !answer.is_server() {
+            return None;
+        }
+        if answer.index == logged_in_at || is_prompt(answer) {
+            break position;
+        }
+        match packet_infos.get(position + 1) {
+            Some(response) if response.is_client() => position += 2,
+            _ => return None,
+        }
+        if !thresholds.empty_info_request.contains(&answer.length) {
+            rounds.push(answer);
+        }
+    };
+    if position == start + 1 {
+        return None;
+    }
+
+    log::debug!("Keyboard-interactive login with {} rounds of prompts.", rounds.len());
+    let mut findings = vec![Finding::new(Event::KeyboardInteractive, request)
+        .with_confidence(0.8)
+        .with_details(format!("{} round{} of prompts", rounds.len(), if rounds.len() == 1 { "" } else { "s" }))];
+    if let Some(second) = rounds.get(1) {
+        findings.push(Finding::new(Event::SecondFactor, second).with_confidence(0.8));
+    }
+    let answer = &packet_infos[end];
+    findings.push(match answer.index == logged_in_at {
+        true => Finding::new(Event::CorrectPassword, answer),
+        false => Finding::new(Event::WrongPassword, answer).with_confidence(prompt_confidence(answer)),
+    });
+
+    Some((end, findings))
+}
+
+/// The kind of public key a client record offers, by its size; `None` if it is not a key offer
+/// of any of the sizes in `thresholds`. Under ciphers with larger blocks the ranges touch; the
+/// RSA, ED25519, and ECDSA ones take precedence over the FIDO and certificate ones.
+pub fn key_offer(packet_info: &PacketInfo, thresholds: &Thresholds) -> Option<Event> {
+    if !packet_info.is_client() {
+        return None;
+    }
+
+    let offers = [
+        (&thresholds.rsa_offer, Event::OfferRSAKey),
+        (&thresholds.ed25519_offer, Event::OfferED25519Key),
+        (&thresholds.ecdsa_offer, Event::OfferECDSAKey),
+        (&thresholds.sk_ed25519_offer, Event::OfferSkED25519Key),
+        (&thresholds.sk_ecdsa_offer, Event::OfferSkECDSAKey),
+        (&thresholds.certificate_offer, Event::OfferCertificate),
+    ];
+    offers.into_iter()
+        .find(|(sizes, _)| sizes.contains(&packet_info.length))
+        .map(|(_, offer)| offer)
+}
+
+/// Checks if the login succeeded on the client's first userauth request, so that the record taken
+/// for the [first prompt](Event::FirstLoginPrompt), at `first_prompt`, was already the success.
+/// Nothing was asked for: the server accepts `none` authentication, e.g. for an account without a
+/// password, or is a honeypot letting everyone in.
+pub fn scan_none_auth(packet_infos: &[PacketInfo], first_prompt: usize, logged_in_at: usize) -> Option<Finding> {
+    let success = packet_infos.get(logged_in_at)?;
+    if success.index > first_prompt || !packet_infos.get(logged_in_at.checked_sub(1)?)?.is_client() {
+        return None;
+    }
+
+    log::warn!("Login accepted without any credentials.");
+    Some(Finding::new(Event::NoneAuth, success)
+        .with_details("accepted on the first request, without credentials".to_string()))
+}
+
+/// Scans the login of a session that never logged in for its rejected passwords and keys.
+///
+/// Every client record answered by a prompt-sized server record, from the first prompt on, is
+/// taken as a rejected attempt: a key offer if its size is one of the key offers of `thresholds`,
+/// a wrong password otherwise, as in [scan_login_data].
+pub fn scan_failed_login(packet_infos: &[PacketInfo], prompt_size: i32, new_keys_index: usize, thresholds: &Thresholds) -> Vec<Finding> {
+    log::info!("Looking for rejected login attempts.");
+    let is_prompt = |packet_info: &PacketInfo| packet_info.is_server() && (packet_info.length - prompt_size).abs() <= thresholds.prompt_tolerance;
+
+    let mut findings = Vec::new();
+    for pair in packet_infos.iter().skip(new_keys_index + 4).collect::<Vec<_>>().windows(2) {
+        let (attempt, prompt) = (pair[0], pair[1]);
+        if !attempt.is_client() || !is_prompt(prompt) {
+            continue;
+        }
+        let confidence = if prompt.length == prompt_size { 1.0 } else { utils::TOLERATED_PROMPT_CONFIDENCE };
+        match key_offer(attempt, thresholds) {
+            Some(offer) => {
+                findings.push(Finding::new(offer, attempt).with_confidence(confidence));
+                findings.push(Finding::new(Event::RejectedKey, prompt).with_confidence(confidence));
+            },
+            None => findings.push(Finding::new(Event::WrongPassword, prompt).with_confidence(confidence)),
+        }
+    }
+
+    findings
+}
+
+/// Finds key re-exchanges after the initial one.
+///
+/// Rekey messages are encrypted, so they are matched by size: the client and server KEXINITs
+/// repeat the algorithm lists of the initial, plaintext ones and are sent within a few records
+/// of each other. No channel data is sent until the new keys are in use, so the
+/// [REKEY_BURST_LENGTH](utils::REKEY_BURST_LENGTH) records from the first KEXINIT on form the
+/// rekey and are returned as position ranges, for the caller to drop before scanning for
+/// keystrokes.
+pub fn scan_for_rekeys(packet_infos: &[PacketInfo], new_keys_index: usize, mac_length: i32) -> (Vec<std::ops::Range<usize>>, Vec<Finding>) {
+    log::info!("Looking for key re-exchanges.");
+
+    let mut bursts: Vec<std::ops::Range<usize>> = Vec::new();
+    let mut events: Vec<Finding> = Vec::new();
+
+    let kexinit_length = |direction: Direction| packet_infos.iter()
+        .take(new_keys_index)
+        .find(|packet_info| packet_info.direction == direction && packet_info.message_code == Some(20))
+        .map(|packet_info| packet_info.length + mac_length);
+    let (client_kexinit, server_kexinit) = match (kexinit_length(Direction::ClientToServer), kexinit_length(Direction::ServerToClient)) {
+        (Some(client), Some(server)) => (client, server),
+        _ => {
+            log::warn!("Initial KEXINITs not found; skipping rekey detection.");
+            return (bursts, events);
+        }
+    };
+
+    let is_kexinit = |packet_info: &PacketInfo| {
+        let initial = if packet_info.is_client() { client_kexinit } else { server_kexinit };
+        (packet_info.length - initial).abs() <= utils::REKEY_KEXINIT_TOLERANCE
+    };
+
+    let mut position = new_keys_index + 1;
+    while position < packet_infos.len() {
+        let first = &packet_infos[position];
+        let paired = is_kexinit(first) && packet_infos.iter()
+            .skip(position + 1)
+            .take(utils::REKEY_KEXINIT_WINDOW)
+            .any(|packet_info| packet_info.direction != first.direction && is_kexinit(packet_info));
+
+        if !paired {
+            position += 1;
+            continue;
+        }
+
+        let end = (position + utils::REKEY_BURST_LENGTH).min(packet_infos.len());
+        let duration = packet_infos[end - 1].timestamp - first.timestamp;
+        log::debug!("Rekey at {}, {duration}μs", first.seq);
+
+        events.push(Finding::new(Event::Rekey, first)
+            .with_details(format!("{} key exchange, by the {}, took {}ms", ordinal(bursts.len() + 2), if first.is_client() { "client" } else { "server" }, duration / 1000))
+            .with_confidence(0.8));
+        bursts.push(position..end);
+        position = end;
+    }
+
+    (bursts, events)
+}
+
+/// English ordinal of a small number, e.g. 2nd.
+fn ordinal(number: usize) -> String {
+    let suffix = match (number % 10, number % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{number}{suffix}")
+}
+
+/// Infers whether the successful password was pasted (or autofilled) or typed by hand.
+///
+/// Pre-auth, the password travels in a single record, so there are no per-keystroke packets to
+/// go by. Instead, the time between the server's prompt and the client's password is used: a
+/// password manager answers within a round trip, whereas typing takes seconds. The client's RTT
+/// (0 if unknown) is taken off the delay, since the prompt has to reach the client first. Delays
+/// in between the two bounds, e.g. from fetching the password out of a manager by hand, are
+/// left unclassified.
+pub fn scan_password_entry(packet_infos: &[PacketInfo], logged_in_at: usize, client_rtt: i64) -> Option<Finding> {
+    if logged_in_at < 2 {
+        return None;
+    }
+
+    let prompt = &packet_infos[logged_in_at - 2];
+    let password = &packet_infos[logged_in_at - 1];
+    if !prompt.is_server() || !password.is_client() {
+        log::debug!("Password not preceded by a prompt; skipping entry inference.");
+        return None;
+    }
+
+    let delay = password.timestamp - prompt.timestamp - client_rtt;
+    log::debug!("Password sent {delay}μs after the prompt.");
+
+    let event = if delay <= utils::PASTED_PASSWORD_MAX_DELAY {
+        Event::PastedPassword
+    } else if delay >= utils::TYPED_PASSWORD_MIN_DELAY {
+        Event::TypedPassword
+    } else {
+        return None;
+    };
+
+    Some(Finding::new(event, password)
+        .with_confidence(0.6)
+        .with_details(format!("{:.2}s after prompt", delay as f64 / 1_000_000.0)))
+}
+
 /// Looks for signature SSH2_MSG_USERAUTH_SUCCESS server response packet.
 /// 
-/// Research showed that this packet has a length of either 28 or 36 bytes;
-/// see `notes.md` for analysis.
-pub fn find_successful_login(packet_infos: &[PacketInfo]) -> Option<usize> {
-    // Maybe, if the SshSession struct comes to fruition, we can use the Cipher field to tailor
-    // this comparison to the current session, instead of comparing it to "all" possibilities (yes,
-    // currently only two, but there could be more- now, and in future.)
-    
+/// The packet only carries its message code, so its length is fixed by the cipher: `sizes` holds
+/// the lengths to expect, from the [cipher model](super::cipher_model); see `notes.md` for analysis.
+pub fn find_successful_login(packet_infos: &[PacketInfo], sizes: &[i32]) -> Option<usize> {
     for (index, packet_info) in packet_infos.iter().take(40).enumerate() {
-        // See `notes.md` for how we get to these two lengths for the current common ciphers.
-        if packet_info.length == -28 || packet_info.length == -36 {
+        if sizes.iter().any(|&size| packet_info.is_server_len(size)) {
             log::debug!("Successful login at packet {index}, sequence number {}", packet_info.seq);
             return Some(index);
         }
@@ -860,3 +1017,462 @@ pub fn find_successful_login(packet_infos: &[PacketInfo]) -> Option<usize> {
     None
 }
 
+
+/// Finds the client's pty-req, the env requests following it, and later window-change requests.
+///
+/// The terminal dimensions themselves are encrypted, so only the requests are reported: the pty-req
+/// with its size (which depends on the TERM name and terminal modes, and can fingerprint a
+/// client), and window-changes with their timestamps, which anchor interactive activity.
+/// The client sends pty-req, any env requests, and the shell request without waiting for replies,
+/// so the client records up to the next server record, minus the final shell request, are taken
+/// as env requests. Their count and total size depend on the client's SendEnv/SetEnv settings.
+/// Window-changes are matched by size alone and share it with e.g. short pastes, so they are
+/// reported with reduced confidence.
+pub fn scan_for_terminal_events(packet_infos: &[PacketInfo], keystroke_size: i32, logged_in_at: usize) -> Vec<Finding> {
+    log::info!("Looking for terminal requests.");
+
+    let mut events: Vec<Finding> = Vec::new();
+
+    let pty_request = packet_infos.iter()
+        .enumerate()
+        .skip(logged_in_at)
+        .filter(|(_, packet_info)| packet_info.is_client())
+        .take(utils::PTY_REQUEST_WINDOW)
+        .filter(|(_, packet_info)| packet_info.length >= utils::PTY_REQUEST_MIN_LENGTH)
+        .max_by_key(|(_, packet_info)| packet_info.length);
+
+    // Without a pty there is no terminal to resize.
+    let (position, pty_request) = match pty_request {
+        Some(pty_request) => pty_request,
+        None => return events,
+    };
+    log::debug!("pty-req at {}, {} bytes", pty_request.seq, pty_request.length);
+    events.push(Finding::new(Event::PtyRequest, pty_request).with_details(format!("{} bytes", pty_request.length)));
+
+    let burst: Vec<&PacketInfo> = packet_infos[position + 1..].iter()
+        .take_while(|packet_info| packet_info.is_client())
+        .collect();
+    if let [env_requests @ .., _shell] = burst.as_slice() {
+        if let Some(first) = env_requests.first() {
+            let bytes: i32 = env_requests.iter().map(|packet_info| packet_info.length).sum();
+            log::debug!("{} env requests at {}, {bytes} bytes", env_requests.len(), first.seq);
+            events.push(Finding::new(Event::EnvRequests, first)
+                .with_details(format!("{} requests, {bytes} bytes", env_requests.len()))
+                .with_confidence(0.5));
+        }
+    }
+
+    for packet_info in &packet_infos[position + 1..] {
+        if packet_info.is_client_len(keystroke_size + utils::WINDOW_CHANGE_OFFSET) {
+            log::debug!("Window change at {}", packet_info.seq);
+            events.push(Finding::new(Event::WindowChange, packet_info).with_confidence(0.5));
+        }
+    }
+
+    events
+}
+
+/// Finds text pasted into the shell in bracketed paste mode.
+///
+/// Shells with bracketed paste (bash 5.1+, zsh) make the terminal send a paste as one write,
+/// wrapped in `ESC[200~`...`ESC[201~`, and echo the text without the wrappers. A client record
+/// well above keystroke size, answered by an echo up to the wrappers smaller, is therefore a
+/// paste, and the echo gives its size. Window changes are sized like short pastes and skipped.
+/// The size is exact up to the cipher's padding.
+pub fn scan_for_pastes(packet_infos: &[PacketInfo], keystroke_size: i32, logged_in_at: usize) -> Vec<Finding> {
+    log::info!("Looking for bracketed pastes.");
+
+    let mut events: Vec<Finding> = Vec::new();
+    for pair in packet_infos.get(logged_in_at + 1..).unwrap_or_default().windows(2) {
+        let (paste, echo) = (&pair[0], &pair[1]);
+        if !paste.is_client()
+            || paste.length < keystroke_size + utils::PASTE_MIN_EXTRA
+            || paste.is_client_len(keystroke_size + utils::WINDOW_CHANGE_OFFSET)
+            || !echo.is_server() {
+            continue;
+        }
+
+        // The wrappers are gone from the echo; padding (up to a 16-byte block) blurs the difference.
+        let difference = paste.length - echo.length;
+        if !(0..=utils::BRACKETED_PASTE_WRAPPER + 16).contains(&difference) {
+            continue;
+        }
+
+        // A keystroke record carries one byte of text.
+        let payload = echo.length - keystroke_size + 1;
+        log::debug!("Bracketed paste at {}, ~{payload} bytes", paste.seq);
+        events.push(Finding::new(Event::BracketedPaste, paste)
+            .with_details(format!("~{payload} bytes"))
+            .with_confidence(0.6));
+    }
+
+    events
+}
+
+/// Looks for a terminal multiplexer's status line being refreshed on the server.
+///
+/// tmux and screen redraw their status line at a fixed interval (15s by default for tmux), also
+/// while the user is idle. Server records that follow a quiet client at a regular interval are
+/// therefore taken as refreshes; irregular unsolicited output, like `tail -f`, fails the
+/// regularity check.
+pub fn scan_for_status_refreshes(packet_infos: &[PacketInfo], logged_in_at: usize) -> Option<Finding> {
+    log::info!("Looking for status line refreshes.");
+
+    let mut last_client = i64::MIN;
+    let mut unsolicited: Vec<&PacketInfo> = Vec::new();
+    for packet_info in packet_infos.iter().skip(logged_in_at + 1) {
+        if packet_info.is_client() {
+            last_client = packet_info.timestamp;
+        } else if packet_info.timestamp.saturating_sub(last_client) >= utils::UNSOLICITED_QUIET {
+            unsolicited.push(packet_info);
+        }
+    }
+
+    // Refreshes come in bursts of a few records, so only the first of each burst counts.
+    unsolicited.dedup_by(|next, previous| next.timestamp - previous.timestamp < utils::UNSOLICITED_QUIET);
+    if unsolicited.len() < utils::STATUS_REFRESH_MIN_COUNT {
+        return None;
+    }
+
+    let intervals: Vec<i64> = unsolicited.windows(2).map(|pair| pair[1].timestamp - pair[0].timestamp).collect();
+    let median = utils::median(&intervals);
+    let regular = intervals.iter()
+        .filter(|&&interval| ((interval - median).abs() as f64) <= median as f64 * utils::STATUS_REFRESH_MAX_DEVIATION)
+        .count();
+
+    if (regular as f64) < intervals.len() as f64 * utils::STATUS_REFRESH_MIN_SHARE || regular + 1 < utils::STATUS_REFRESH_MIN_COUNT {
+        return None;
+    }
+    log::debug!("{regular} status refreshes every {median}μs");
+
+    Some(Finding::new(Event::StatusRefresh, unsolicited[0])
+        .with_details(format!("{} refreshes every {:.1}s", regular + 1, median as f64 / 1_000_000.0))
+        .with_confidence(0.6))
+}
+
+/// Finds channels opened for port forwarding.
+///
+/// A forwarding channel open is larger than the session's own channel requests, since it carries
+/// the target and originator addresses, and is promptly answered by a fixed-size confirmation.
+/// Client-opened channels (direct-tcpip) come from local (-L) or dynamic (-D) forwarding; a
+/// SOCKS proxy opens channels to varying targets, whose opens then differ in size. Server-opened
+/// channels (forwarded-tcpip) come from remote (-R) forwarding.
+///
+/// Reports one [PortForwarding](Event::PortForwarding) finding per kind, with the number of
+/// channels and the approximate tunneled volume: all records from the first open on that are not
+/// keystroke-shaped.
+pub fn scan_for_forwarding(packet_infos: &[PacketInfo], keystroke_size: i32, logged_in_at: usize, thresholds: &Thresholds) -> Vec<Finding> {
+    log::info!("Looking for port forwarding.");
+
+    let mut opens: Vec<(usize, &PacketInfo)> = Vec::new();
+    for (index, packet_info) in packet_infos.iter().enumerate().skip(logged_in_at) {
+        if !utils::FORWARD_OPEN_EXTRA.contains(&(packet_info.length - keystroke_size)) {
+            continue;
+        }
+
+        let confirmed = packet_infos.iter()
+            .skip(index + 1)
+            .take(3)
+            .find(|reply| reply.direction != packet_info.direction)
+            .is_some_and(|reply| utils::FORWARD_CONFIRM_EXTRA.contains(&(reply.length - keystroke_size)));
+
+        if confirmed {
+            log::debug!("Channel open at {}, {} bytes", packet_info.seq, packet_info.length);
+            opens.push((index, packet_info));
+        }
+    }
+
+    let mut events: Vec<Finding> = Vec::new();
+    for direction in [Direction::ClientToServer, Direction::ServerToClient] {
+        let channels: Vec<&(usize, &PacketInfo)> = opens.iter().filter(|(_, open)| open.direction == direction).collect();
+        let (first_index, first) = match channels.first() {
+            Some(first) => **first,
+            None => continue,
+        };
+
+        let mut sizes: Vec<i32> = channels.iter().map(|(_, open)| open.length).collect();
+        sizes.sort_unstable();
+        sizes.dedup();
+        let kind = match direction {
+            Direction::ServerToClient => "remote",
+            _ if channels.len() >= utils::DYNAMIC_FORWARD_MIN_CHANNELS && sizes.len() > 1 => "dynamic",
+            _ => "local",
+        };
+
+        let volume: i64 = packet_infos[first_index..].iter()
+            .filter(|packet_info| packet_info.length > keystroke_size + thresholds.keystroke_upper_bound)
+            .map(|packet_info| packet_info.length as i64)
+            .sum();
+
+        events.push(Finding::new(Event::PortForwarding, first)
+            .with_details(format!("{kind}, {} channels, ~{volume} bytes", channels.len()))
+            .with_confidence(0.5));
+    }
+
+    events
+}
+
+/// Measures per-direction RTT jitter and flags abrupt, sustained latency shifts.
+///
+/// RTT samples are taken from tshark's `tcp.analysis.ack_rtt`. Client packets acknowledging server
+/// data measure the client-side leg (capture point -> client -> capture point), server packets the
+/// server-side leg. A shift on either leg hints at a route change, VPN toggle or a proxy inserted
+/// mid-session, and also means latencies before and after it should not be compared directly.
+///
+/// Returns the (client, server) [JitterStats] and a [PathChange](Event::PathChange) finding for
+/// every detected shift. Expects packets in capture order, i.e. before keystroke ordering.
+pub fn scan_for_path_changes(packet_infos: &[PacketInfo], thresholds: &Thresholds) -> ((JitterStats, JitterStats), Vec<Finding>) {
+    log::info!("Analysing RTT jitter.");
+
+    let mut events: Vec<Finding> = Vec::new();
+    let mut stats: Vec<JitterStats> = Vec::new();
+
+    for (leg, direction) in [("client", Direction::ClientToServer), ("server", Direction::ServerToClient)] {
+        // Keep the packet next to its sample so shifts can be placed on the timeline.
+        let (samples, sources): (Vec<i64>, Vec<&PacketInfo>) = packet_infos
+            .iter()
+            .filter(|packet_info| packet_info.direction == direction)
+            .filter_map(|packet_info| packet_info.ack_rtt.map(|rtt| (rtt, packet_info)))
+            .unzip();
+
+        for shift in utils::find_latency_shifts(&samples, thresholds) {
+            let before = utils::median(&samples[shift - thresholds.path_change_window..shift]);
+            let after = utils::median(&samples[shift..shift + thresholds.path_change_window]);
+            log::debug!("RTT shift on {leg} side at {}: {before}μs -> {after}μs", sources[shift].seq);
+
+            events.push(Finding::new(Event::PathChange, sources[shift])
+                .with_details(format!("{leg} side RTT {before}μs -> {after}μs")));
+        }
+
+        stats.push(utils::jitter_stats(&samples));
+    }
+
+    let server = stats.pop().unwrap();
+    let client = stats.pop().unwrap();
+
+    ((client, server), events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::cipher_model::CipherModel;
+    use crate::analyser::containers::tests::records;
+    use crate::analyser::containers;
+    use crate::analyser::core::analyse;
+    use crate::analyser::options::AnalysisOptions;
+    use crate::analyser::options::Thresholds;
+    use crate::analyser::records;
+    use crate::analyser::tags;
+
+    #[test]
+    fn test_unechoed_input() {
+        use containers::Direction::*;
+
+        // sudo prompt, 5 characters and Return without echo, output, then `ls` typed as usual
+        let ordered = records(&[
+            (ServerToClient, 60),
+            (ClientToServer, 36), (ClientToServer, 36), (ClientToServer, 36), (ClientToServer, 36), (ClientToServer, 36), (ClientToServer, 36),
+            (ServerToClient, 40), (ServerToClient, 200),
+            (ClientToServer, 36), (ServerToClient, 36), (ClientToServer, 36), (ServerToClient, 36), (ClientToServer, 36), (ServerToClient, 40), (ServerToClient, 100),
+            (ClientToServer, 36), (ServerToClient, 36), (ClientToServer, 36), (ServerToClient, 36),
+        ]);
+        let findings = scan_for_unechoed_input(&ordered, 36, 0);
+        assert_eq!(1, findings.len());
+        assert_eq!(containers::Event::PasswordEntry, findings[0].kind);
+        assert_eq!(Some("5 characters".to_string()), findings[0].details);
+        let keystrokes = scan_for_keystrokes(&ordered, 36, 0, &Thresholds::default());
+        assert!(keystrokes.iter().all(|keystroke| keystroke.index.is_some_and(|index| index >= 9)));
+        assert_eq!(1, keystrokes.iter().filter(|keystroke| keystroke.k_type == containers::KeystrokeType::Enter).count());
+
+        // Typed ahead of the echoes, which then follow one by one
+        let ordered = records(&[
+            (ServerToClient, 60),
+            (ClientToServer, 36), (ClientToServer, 36), (ClientToServer, 36), (ClientToServer, 36), (ClientToServer, 36),
+            (ServerToClient, 36), (ServerToClient, 36), (ServerToClient, 36), (ServerToClient, 36), (ServerToClient, 36),
+        ]);
+        assert!(scan_for_unechoed_input(&ordered, 36, 0).is_empty());
+    }
+
+    #[test]
+    fn test_confidence() {
+        use containers::{Confidence, Direction::*, Event};
+        assert_eq!(Confidence::High, Confidence::of(0.9));
+        assert_eq!(Confidence::Medium, Confidence::of(0.7));
+        assert_eq!(Confidence::Low, Confidence::of(0.3));
+        assert_eq!("medium", Confidence::Medium.to_string());
+
+        // Login prompt, a wrong password answered by a prompt off by a padding step, another
+        // wrong password, and the correct one
+        let ordered = records(&[(ServerToClient, 52), (ClientToServer, 36), (ServerToClient, 36), (ClientToServer, 36),
+            (ServerToClient, 52), (ClientToServer, 100), (ServerToClient, 60), (ClientToServer, 100), (ServerToClient, 52), (ClientToServer, 100), (ServerToClient, 28)]);
+        let thresholds = Thresholds { prompt_tolerance: 8, ..Thresholds::default() };
+        let findings = scan_login_data(&ordered, 52, 0, 10, &thresholds);
+        let levels: Vec<(Event, Confidence)> = findings.iter().map(|finding| (finding.kind, finding.level())).collect();
+        assert_eq!(vec![(Event::WrongPassword, Confidence::Medium), (Event::WrongPassword, Confidence::High), (Event::CorrectPassword, Confidence::High)], levels);
+    }
+
+    #[test]
+    fn test_rekeys() {
+        use containers::{Direction::{ClientToServer as C, ServerToClient as S}, Event};
+
+        // Plaintext KEXINITs and New Keys, a keystroke, a rekey by the client, another keystroke
+        let mut packet_infos = records(&[
+            (C, 1000), (S, 1100), (C, 44), (S, 500), (C, 16),
+            (C, 36), (S, 36),
+            (C, 1012), (S, 1120), (C, 60), (S, 60), (C, 16), (S, 16),
+            (C, 36), (S, 36),
+        ]);
+        packet_infos[0].message_code = Some(20);
+        packet_infos[1].message_code = Some(20);
+
+        let (bursts, findings) = scan_for_rekeys(&packet_infos, 4, 16);
+        assert_eq!(vec![7..13], bursts);
+        assert_eq!(1, findings.len());
+        assert_eq!(Event::Rekey, findings[0].kind);
+        assert_eq!(7, findings[0].packet_ref.index);
+        assert_eq!(Some("2nd key exchange, by the client, took 1000ms"), findings[0].details.as_deref());
+
+        let mut remaining = packet_infos.clone();
+        records::drop_records(&mut remaining, bursts);
+        assert_eq!(vec![0, 1, 2, 3, 4, 5, 6, 7, 8], remaining.iter().map(|packet_info| packet_info.index).collect::<Vec<_>>());
+        assert_eq!(vec![13, 14], remaining[7..].iter().map(|packet_info| packet_info.seq).collect::<Vec<_>>());
+
+        // A KEXINIT-sized record without one from the other side is not a rekey
+        let mut lone = packet_infos.clone();
+        lone[8].length = 36;
+        let (bursts, findings) = scan_for_rekeys(&lone, 4, 16);
+        assert!(bursts.is_empty() && findings.is_empty());
+
+        // Without the initial KEXINITs there is nothing to compare against
+        let mut unknown = packet_infos.clone();
+        unknown[0].message_code = None;
+        let (bursts, findings) = scan_for_rekeys(&unknown, 4, 16);
+        assert!(bursts.is_empty() && findings.is_empty());
+    }
+
+    #[test]
+    fn test_status_refreshes() {
+        use containers::Direction::{ClientToServer as C, ServerToClient as S};
+        // Records at the given times (ms), after the login record at 0
+        let timeline = |times: &[(containers::Direction, i64)]| {
+            let mut packet_infos = records(&[&[(S, 28)], &times.iter().map(|&(direction, _)| (direction, 100)).collect::<Vec<_>>()[..]].concat());
+            for (packet_info, (_, ms)) in packet_infos.iter_mut().skip(1).zip(times) {
+                packet_info.timestamp = ms * 1000;
+            }
+            packet_infos
+        };
+        let refreshes = |count: i64| (1..=count).flat_map(|n| [(S, n * 15_000), (S, n * 15_000 + 10)]).collect::<Vec<_>>();
+
+        // tmux redraws its status line every 15s in two records while the user types now and then
+        let mut times = vec![(C, 500), (S, 520)];
+        times.extend(refreshes(6));
+        times.extend([(C, 50_000), (S, 50_020)]);
+        times.sort_by_key(|&(_, ms)| ms);
+        let finding = scan_for_status_refreshes(&timeline(&times), 0).unwrap();
+        assert_eq!(Event::StatusRefresh, finding.kind);
+        assert_eq!(15_000_000, finding.timestamp);
+        assert_eq!(Some("6 refreshes every 15.0s"), finding.details.as_deref());
+
+        // Too few refreshes, irregular output like `tail -f`, and output answering the client
+        assert!(scan_for_status_refreshes(&timeline(&refreshes(4)), 0).is_none());
+        let irregular = [3_000, 14_000, 21_000, 41_000, 43_000, 60_000].map(|ms| (S, ms));
+        assert!(scan_for_status_refreshes(&timeline(&irregular), 0).is_none());
+        let answers: Vec<_> = (1..=6).flat_map(|n| [(C, n * 15_000 - 100), (S, n * 15_000)]).collect();
+        assert!(scan_for_status_refreshes(&timeline(&answers), 0).is_none());
+    }
+
+    #[test]
+    fn test_forwarding() {
+        use containers::{Direction::{ClientToServer as C, ServerToClient as S}, Event};
+        let thresholds = Thresholds::default();
+        let kinds = |ordered: &[containers::PacketInfo]| scan_for_forwarding(ordered, 36, 0, &thresholds).into_iter()
+            .map(|finding| {
+                assert_eq!(Event::PortForwarding, finding.kind);
+                finding.details.unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        // Channel opens confirmed by the other side; the volume counts all large records from the first
+        let local = records(&[(C, 116), (S, 44), (C, 1036), (C, 116), (S, 52), (C, 1036), (C, 116), (S, 44)]);
+        assert_eq!(vec!["local, 3 channels, ~2420 bytes"], kinds(&local));
+
+        // Opens of differing sizes are SOCKS connections, also when the same size recurs apart
+        let dynamic = records(&[(C, 116), (S, 44), (C, 132), (S, 44), (C, 116), (S, 44)]);
+        assert_eq!(vec!["dynamic, 3 channels, ~364 bytes"], kinds(&dynamic));
+        let two = records(&[(C, 116), (S, 44), (C, 132), (S, 44)]);
+        assert_eq!(vec!["local, 2 channels, ~248 bytes"], kinds(&two));
+
+        let remote = records(&[(S, 116), (C, 44), (S, 1036)]);
+        assert_eq!(vec!["remote, 1 channels, ~1152 bytes"], kinds(&remote));
+
+        // Unconfirmed opens, and opens only answered by the same side, are not channels
+        let unconfirmed = records(&[(C, 116), (S, 36), (C, 116), (C, 44)]);
+        assert!(kinds(&unconfirmed).is_empty());
+    }
+
+    #[test]
+    fn test_key_offer_kinds() {
+        use containers::{Direction::{ClientToServer as C, ServerToClient as S}, Event};
+
+
+        // New keys, service request, `none`, then FIDO keys and a certificate offered before a password
+        let ordered = records(&[(C, 16), (C, 44), (S, 44), (C, 60), (S, 52), (C, 176), (S, 52), (C, 244), (S, 52), (C, 560), (S, 52), (C, 84), (S, 52)]);
+        let events: Vec<Event> = scan_failed_login(&ordered, 52, 0, &Thresholds::default()).iter().map(|finding| finding.kind).collect();
+        assert_eq!(events, vec![Event::OfferSkED25519Key, Event::RejectedKey, Event::OfferSkECDSAKey, Event::RejectedKey, Event::OfferCertificate, Event::RejectedKey, Event::WrongPassword]);
+
+        // The kinds of offer do not overlap under the reference cipher
+        let thresholds = Thresholds::default();
+        let mut ranges = [&thresholds.ed25519_offer, &thresholds.sk_ed25519_offer, &thresholds.ecdsa_offer, &thresholds.sk_ecdsa_offer, &thresholds.rsa_offer, &thresholds.certificate_offer];
+        ranges.sort_by_key(|range| range.start());
+        assert!(ranges.windows(2).all(|pair| pair[0].end() < pair[1].start()));
+
+        // Under aes128-ctr the FIDO ED25519 range touches the ECDSA one, which keeps its offers
+        let thresholds = CipherModel::negotiated("aes128-ctr", "hmac-sha2-256").unwrap().thresholds(&thresholds);
+        let shared = records(&[(C, *thresholds.ecdsa_offer.start())]);
+        assert_eq!(Some(Event::OfferECDSAKey), crate::analyser::scan::key_offer(&shared[0], &thresholds));
+    }
+
+    #[test]
+    fn test_keyboard_interactive() {
+        use containers::{Direction::{ClientToServer as C, ServerToClient as S}, Event};
+
+        let login = [(C, 16), (C, 44), (S, 44), (C, 60), (S, 52), (C, 88), (S, 76), (C, 148), (S, 92), (C, 84), (S, 44), (C, 36)];
+
+        // Password and verification code prompts, then the empty round PAM ends with
+        let ordered = records(&[&login[..], &[(S, 28)]].concat());
+        let findings = scan_login_data(&ordered, 52, 0, 12, &Thresholds::default());
+        let events: Vec<Event> = findings.iter().map(|finding| finding.kind).collect();
+        assert_eq!(events, vec![Event::KeyboardInteractive, Event::SecondFactor, Event::CorrectPassword]);
+        assert_eq!(Some("2 rounds of prompts"), findings[0].details.as_deref());
+        assert_eq!((5, 8, 12), (findings[0].packet_ref.index, findings[1].packet_ref.index, findings[2].packet_ref.index));
+
+        // A wrong code, then a plain password
+        let ordered = records(&[&login[..], &[(S, 52), (C, 148), (S, 28)]].concat());
+        let events: Vec<Event> = scan_login_data(&ordered, 52, 0, 14, &Thresholds::default()).iter().map(|finding| finding.kind).collect();
+        assert_eq!(events, vec![Event::KeyboardInteractive, Event::SecondFactor, Event::WrongPassword, Event::CorrectPassword]);
+    }
+
+    #[test]
+    fn test_none_auth() {
+        use crate::analyser::options::{Decoding, Engine};
+        use crate::analyser::filter::StreamFilter;
+        use containers::Direction::{ClientToServer as C, ServerToClient as S};
+
+
+        // The `none` request is answered by the login success, which was taken for the first prompt
+        let ordered = records(&[(C, 16), (C, 44), (S, 44), (C, 60), (S, 28), (C, 112)]);
+        let finding = scan_none_auth(&ordered, 4, 4).unwrap();
+        assert_eq!((containers::Event::NoneAuth, 4), (finding.kind, finding.packet_ref.index));
+        // A prompt came first
+        assert!(scan_none_auth(&ordered, 2, 4).is_none());
+
+        let capture = format!("{}/test_captures/known_pass_lsal_id_exit.pcapng", env!("CARGO_MANIFEST_DIR"));
+        let (streams, tcp) = utils::load_capture_reporting(&capture, -1, None, &StreamFilter::default(), Engine::Native, &Decoding::default(), |_, _| {}).unwrap();
+        let mut session = analyse(0, &streams[&0], &tcp[&0], &AnalysisOptions::default()).unwrap();
+        assert!(session.results.iter().all(|finding| finding.kind != containers::Event::NoneAuth));
+        assert!(!session.tags.contains(&tags::Tag::NoAuth));
+        session.results.push(finding);
+        assert!(tags::infer_tags(&session).contains(&tags::Tag::NoAuth));
+    }
+}
@@ -0,0 +1,145 @@
+//! Identification of client and server implementations.
+//!
+//! The HASSH of each side is looked up in a [HasshDb] first: it is derived from the offered
+//! algorithms and harder to fake than the version banner. Unknown hashes fall back to parsing the
+//! banner, which covers the common implementations but reports whatever the peer claims to be.
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+
+/// The bundled database; see `data/hassh.csv`.
+const BUNDLED: &str = include_str!("../../data/hassh.csv");
+
+/// Banner prefixes (after `SSH-2.0-`) of known implementations, and their names.
+const BANNERS: [(&str, &str); 12] = [
+    ("OpenSSH_", "OpenSSH"),
+    ("PuTTY_Release_", "PuTTY"),
+    ("PuTTY", "PuTTY"),
+    ("libssh2_", "libssh2"),
+    ("libssh_", "libssh"),
+    ("libssh-", "libssh"),
+    ("paramiko_", "Paramiko"),
+    ("Go", "Go crypto/ssh"),
+    ("dropbear_", "Dropbear"),
+    ("AsyncSSH_", "AsyncSSH"),
+    ("JSCH", "JSch"),
+    ("WinSCP_release_", "WinSCP"),
+];
+
+/// How an implementation was identified.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Source {
+    Hassh,
+    Banner,
+}
+
+/// Likely implementation of one side of a session.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Software {
+    pub name: String,
+    pub source: Source,
+}
+
+/// HASSH → software mapping, separately for clients and servers.
+#[derive(Clone, Debug, Default)]
+pub struct HasshDb {
+    client: HashMap<String, String>,
+    server: HashMap<String, String>,
+}
+
+impl HasshDb {
+    /// The database bundled with SSHniff.
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED).expect("bundled HASSH database is valid")
+    }
+
+    /// The bundled database, extended by (and overridden with) the entries of the file at `path`.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| format!("Failed to read {path}: {err}"))?;
+        let mut db = Self::bundled();
+        let user = Self::parse(&contents).map_err(|err| format!("{path}: {err}"))?;
+        db.client.extend(user.client);
+        db.server.extend(user.server);
+        Ok(db)
+    }
+
+    /// Parses `side,hash,software` lines. Empty lines and lines starting with `#` are ignored.
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut db = Self::default();
+
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.splitn(3, ',').map(str::trim);
+            let (side, hash, software) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(side), Some(hash), Some(software)) if !software.is_empty() => (side, hash, software),
+                _ => return Err(format!("line {}: expected side,hash,software", number + 1)),
+            };
+            if hash.len() != 32 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("line {}: '{hash}' is not an MD5 hash", number + 1));
+            }
+
+            let entries = match side {
+                "client" => &mut db.client,
+                "server" => &mut db.server,
+                _ => return Err(format!("line {}: side must be client or server", number + 1)),
+            };
+            entries.insert(hash.to_ascii_lowercase(), software.to_string());
+        }
+
+        Ok(db)
+    }
+
+    /// Identifies the (client, server) implementations from their HASSH and banners.
+    pub fn identify(&self, hassh: (&str, &str), banners: (&str, &str)) -> (Option<Software>, Option<Software>) {
+        let identify = |entries: &HashMap<String, String>, hassh: &str, banner: &str| {
+            match entries.get(hassh) {
+                Some(name) => Some(Software { name: name.clone(), source: Source::Hassh }),
+                None => from_banner(banner).map(|name| Software { name, source: Source::Banner }),
+            }
+        };
+
+        (identify(&self.client, hassh.0, banners.0), identify(&self.server, hassh.1, banners.1))
+    }
+}
+
+/// Implementation and version claimed by a version banner, e.g. `OpenSSH 9.6p1` for
+/// `SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13`.
+pub fn from_banner(banner: &str) -> Option<String> {
+    let software = banner.strip_prefix("SSH-2.0-").or_else(|| banner.strip_prefix("SSH-1.99-"))?;
+    let software = software.split_whitespace().next()?;
+
+    BANNERS.iter()
+        .find(|(prefix, _)| software.starts_with(prefix))
+        .map(|(prefix, name)| {
+            let version = software[prefix.len()..].trim_start_matches(['_', '-']);
+            if version.is_empty() {
+                name.to_string()
+            } else {
+                format!("{name} {version}")
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_software() {
+        assert_eq!(Some("OpenSSH 8.4p1".to_string()), from_banner("SSH-2.0-OpenSSH_8.4p1 Raspbian-5+deb11u3"));
+        assert_eq!(Some("PuTTY 0.78".to_string()), from_banner("SSH-2.0-PuTTY_Release_0.78"));
+        assert_eq!(Some("Go crypto/ssh".to_string()), from_banner("SSH-2.0-Go"));
+        assert_eq!(None, from_banner("SSH-2.0-Custom"));
+
+        let db = HasshDb::bundled();
+        let (client, server) = db.identify(("aae6b9604f6f3356543709a376d7f657", "0123"), ("SSH-2.0-OpenSSH_9.6", "SSH-2.0-dropbear_2022.83"));
+        assert_eq!(Source::Hassh, client.unwrap().source);
+        assert_eq!("Dropbear 2022.83", server.unwrap().name);
+
+        assert!(HasshDb::parse("client,not-a-hash,OpenSSH").is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! Capture sources: where the packets of an analysis come from.
+//!
+//! A [CaptureSource] hands out dissected [Packet]s one at a time. The loaders in
+//! [utils](super::utils) collect them into streams, from which [analyse](super::core::analyse)
+//! builds the [PacketInfo](super::containers::PacketInfo)s the scans work on, so nothing past the
+//! source depends on how the packets were captured or dissected. [open] picks the source for an
+//! [Engine]; live captures, remote sensors, or replays only have to implement the trait.
+use super::compression::CaptureFile;
+use super::native::{NativeError, NativeReader};
+use super::options::{Decoding, Engine};
+use super::packet::Packet;
+use rtshark::RTShark;
+use std::fmt;
+
+/// Display filter of the analysis (adapted from Packet Strider): SSH packets, SYNs for their MSS
+/// option, and bare ACKs for [JA4SSH](super::ja4ssh), without retransmissions.
+pub const ANALYSIS_FILTER: &str = "\
+    (ssh || tcp.flags.syn == 1 || (tcp.flags == 0x010 && tcp.len == 0)) &&\
+    !tcp.analysis.spurious_retransmission &&\
+    !tcp.analysis.retransmission &&\
+    !tcp.analysis.fast_retransmission\
+";
+
+/// A source of dissected packets, carrying the layers and fields tshark would; see
+/// [packet](super::packet).
+pub trait CaptureSource {
+    /// Next packet, in capture order; `None` once the source is exhausted or failed.
+    fn next_packet(&mut self) -> Option<Packet>;
+
+    /// Stops reading, returning the error that ended the packets early, if any.
+    fn finish(self: Box<Self>) -> Result<(), SourceError>;
+}
+
+/// Why a source could not be opened or read to the end.
+#[derive(Debug)]
+pub enum SourceError {
+    /// The source cannot dissect this capture, but another engine might
+    Unsupported(String),
+    /// Reading failed
+    Failed(String),
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceError::Unsupported(err) | SourceError::Failed(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl From<NativeError> for SourceError {
+    fn from(err: NativeError) -> Self {
+        match err {
+            NativeError::Unsupported(reason) => SourceError::Unsupported(reason),
+            NativeError::Io(err) => SourceError::Failed(err.to_string()),
+        }
+    }
+}
+
+/// Opens the source of `engine` on a capture, with tshark's [ANALYSIS_FILTER], dissecting SSH
+/// wherever `decoding` says besides port 22.
+pub fn open(capture: &CaptureFile, engine: Engine, decoding: &Decoding) -> Result<Box<dyn CaptureSource>, SourceError> {
+    match engine {
+        Engine::Tshark => Ok(Box::new(TsharkSource::spawn(capture.path(), ANALYSIS_FILTER, decoding)?)),
+        Engine::Native => Ok(Box::new(NativeReader::open(capture.path(), decoding.clone())?)),
+    }
+}
+
+/// Packets of a source as an iterator.
+pub fn packets(source: &mut dyn CaptureSource) -> impl Iterator<Item = Packet> + '_ {
+    std::iter::from_fn(move || source.next_packet())
+}
+
+/// Packets dissected by tshark, as far as they pass a display filter.
+pub struct TsharkSource {
+    rtshark: RTShark,
+    error: Option<String>,
+}
+
+impl TsharkSource {
+    /// Runs tshark on a capture file, with the decode-as rules of `decoding`.
+    pub fn spawn(path: &str, display_filter: &str, decoding: &Decoding) -> Result<Self, SourceError> {
+        let rules = decoding.tshark_rules();
+        let mut builder = rtshark::RTSharkBuilder::builder()
+            .input_path(path)
+            .display_filter(display_filter);
+        for rule in &rules {
+            builder = builder.decode_as(rule);
+        }
+
+        match builder.spawn() {
+            Ok(rtshark) => Ok(Self::from(rtshark)),
+            Err(err) => Err(SourceError::Failed(format!("Error spawning tshark: {err}"))),
+        }
+    }
+}
+
+impl From<RTShark> for TsharkSource {
+    fn from(rtshark: RTShark) -> Self {
+        Self { rtshark, error: None }
+    }
+}
+
+impl CaptureSource for TsharkSource {
+    fn next_packet(&mut self) -> Option<Packet> {
+        if self.error.is_some() {
+            return None;
+        }
+        match self.rtshark.read() {
+            Ok(packet) => packet.map(Packet::from),
+            Err(err) => {
+                log::error!("Error parsing TShark output: {err}");
+                self.error = Some(err.to_string());
+                None
+            },
+        }
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), SourceError> {
+        self.rtshark.kill();
+        match self.error {
+            Some(err) => Err(SourceError::Failed(err)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl CaptureSource for NativeReader {
+    fn next_packet(&mut self) -> Option<Packet> {
+        self.next()
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), SourceError> {
+        NativeReader::finish(*self).map_err(SourceError::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::packet::Packet;
+    use crate::analyser::utils;
+
+    #[test]
+    fn test_capture_source() {
+        use crate::analyser::compression::CaptureFile;
+        use crate::analyser::options::{Decoding, Engine};
+
+        /// Replays packets read earlier
+        struct Replay(std::vec::IntoIter<Packet>);
+
+        impl CaptureSource for Replay {
+            fn next_packet(&mut self) -> Option<Packet> {
+                self.0.next()
+            }
+
+            fn finish(self: Box<Self>) -> Result<(), SourceError> {
+                Ok(())
+            }
+        }
+
+        let capture = CaptureFile::open(&format!("{}/test_captures/four_offers.pcapng", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        let mut native = open(&capture, Engine::Native, &Decoding::default()).unwrap();
+        let packets: Vec<Packet> = packets(native.as_mut()).collect();
+        native.finish().unwrap();
+
+        let mut replay = Replay(packets.clone().into_iter());
+        let (streams, tcp) = utils::get_streams(&mut replay, -1, None);
+        assert_eq!(vec![&3], streams.keys().collect::<Vec<_>>());
+        assert_eq!(packets.iter().filter(|packet| packet.layer_name("ssh").is_some()).count(), streams[&3].len());
+        assert_eq!((Some(1460), Some(1460)), tcp[&3].mss);
+    }
+}
@@ -0,0 +1,145 @@
+//! Password spraying across servers.
+//!
+//! Brute force tries many passwords on one server; a spray tries a few passwords on each of many
+//! servers, staying below lockout thresholds and the [BruteForced](super::tags::Tag::BruteForced)
+//! tag alike. Only the capture as a whole shows it: the sessions are grouped by client IP and
+//! HASSH, and a group that made password attempts on at least [SPRAY_MIN_SERVERS] servers, with
+//! no more than [SPRAY_MAX_ATTEMPTS] on any of them, is reported as a [Spray].
+use super::containers::Event;
+use super::core::SshSession;
+use super::tags::{self, Tag};
+use super::utils;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Number of servers from which password attempts by one client are considered a spray.
+pub const SPRAY_MIN_SERVERS: usize = 5;
+
+/// Most password attempts a spray makes on one server; any more is brute force.
+pub const SPRAY_MAX_ATTEMPTS: usize = tags::BRUTE_FORCE_MIN_FAILURES - 1;
+
+/// Password attempts of one client on many servers.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Spray {
+    pub client_ip: String,
+    pub hassh_c: String,
+    /// Server IPs attempted, with the password attempts on each
+    pub servers: BTreeMap<String, usize>,
+    /// Streams of the sessions making the attempts
+    pub streams: Vec<u32>,
+}
+
+impl Spray {
+    /// Password attempts over all servers.
+    pub fn attempts(&self) -> usize {
+        self.servers.values().sum()
+    }
+}
+
+/// Finds the password sprays among the sessions of a capture.
+pub fn find_sprays(sessions: &BTreeMap<u32, SshSession>) -> Vec<Spray> {
+    let mut by_client: BTreeMap<(&str, &str), Vec<&SshSession>> = BTreeMap::new();
+    for session in sessions.values() {
+        by_client.entry((utils::ip_of(&session.src), &session.hassh_c)).or_default().push(session);
+    }
+
+    by_client.into_iter()
+        .filter_map(|((client_ip, hassh_c), sessions)| {
+            let mut servers: BTreeMap<String, usize> = BTreeMap::new();
+            let mut streams = vec![];
+            for session in sessions {
+                let attempts = session.results.iter()
+                    .filter(|finding| matches!(finding.kind, Event::WrongPassword | Event::CorrectPassword))
+                    .count();
+                if attempts > 0 {
+                    *servers.entry(utils::ip_of(&session.dst).to_string()).or_default() += attempts;
+                    streams.push(session.stream);
+                }
+            }
+
+            let spray = servers.len() >= SPRAY_MIN_SERVERS && servers.values().all(|&attempts| attempts <= SPRAY_MAX_ATTEMPTS);
+            spray.then(|| Spray { client_ip: client_ip.to_string(), hassh_c: hassh_c.to_string(), servers, streams })
+        })
+        .collect()
+}
+
+/// Adds a [PasswordSpray](Event::PasswordSpray) finding and tag to every session of a spray,
+/// naming the other streams involved, and returns the sprays.
+///
+/// The finding is placed with the session's first password attempt.
+pub fn mark_sprays(sessions: &mut BTreeMap<u32, SshSession>) -> Vec<Spray> {
+    let sprays = find_sprays(sessions);
+
+    for spray in &sprays {
+        let streams = spray.streams.iter().map(|stream| stream.to_string()).collect::<Vec<_>>().join(", ");
+        let details = format!("{} tried {} passwords on {} servers (streams {streams})", spray.client_ip, spray.attempts(), spray.servers.len());
+        log::warn!("Password spray: {details}");
+
+        for stream in &spray.streams {
+            let session = sessions.get_mut(stream).expect("stream taken from the sessions");
+            let attempt = session.results.iter().find(|finding| matches!(finding.kind, Event::WrongPassword | Event::CorrectPassword));
+            if let Some(attempt) = attempt {
+                let mut finding = attempt.clone().with_details(details.clone());
+                finding.kind = Event::PasswordSpray;
+                session.results.push(finding);
+            }
+            session.tags.push(Tag::PasswordSpray);
+        }
+    }
+
+    sprays
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::containers;
+    use crate::analyser::core::analyse;
+    use crate::analyser::options::AnalysisOptions;
+    use crate::analyser::tags;
+
+    #[test]
+    fn test_password_spray() {
+        use crate::analyser::options::{Decoding, Engine};
+        use crate::analyser::filter::StreamFilter;
+        use std::collections::BTreeMap;
+
+        // known_pass_lsal_id_exit cut off after the rejected key, before the password is sent
+        let capture = format!("{}/test_captures/rejected_key_no_login.pcap", env!("CARGO_MANIFEST_DIR"));
+        let (streams, tcp) = utils::load_capture_reporting(&capture, -1, None, &StreamFilter::default(), Engine::Native, &Decoding::default(), |_, _| {}).unwrap();
+        let failed = analyse(0, &streams[&0], &tcp[&0], &AnalysisOptions::default()).unwrap();
+        assert!(failed.tags.contains(&tags::Tag::FailedLogin));
+        assert!(failed.results.iter().any(|finding| matches!(finding.kind, containers::Event::RejectedKey)));
+        assert!(failed.results.iter().all(|finding| !matches!(finding.kind, containers::Event::CorrectPassword)));
+
+        // One password from the same client on each of four, then five servers
+        let full = format!("{}/test_captures/known_pass_lsal_id_exit.pcapng", env!("CARGO_MANIFEST_DIR"));
+        let (streams, tcp) = utils::load_capture_reporting(&full, -1, None, &StreamFilter::default(), Engine::Native, &Decoding::default(), |_, _| {}).unwrap();
+        let mut sessions = BTreeMap::new();
+        for stream in 0..5 {
+            let mut session = analyse(stream, &streams[&0], &tcp[&0], &AnalysisOptions::default()).unwrap();
+            session.dst = format!("10.0.0.{}:22", stream + 1);
+            sessions.insert(stream, session);
+            let sprays = find_sprays(&sessions);
+            assert_eq!(stream == 4, sprays.len() == 1);
+        }
+
+        let sprays = mark_sprays(&mut sessions);
+        assert_eq!(1, sprays.len());
+        assert_eq!("192.168.0.212", sprays[0].client_ip);
+        assert_eq!(5, sprays[0].attempts());
+        assert_eq!(vec![0, 1, 2, 3, 4], sprays[0].streams);
+        assert!(sessions.values().all(|session| session.tags.contains(&tags::Tag::PasswordSpray)
+            && session.results.iter().any(|finding| matches!(finding.kind, containers::Event::PasswordSpray))));
+
+        // Enough attempts on one of the servers make it brute force rather than a spray
+        let attempt = sessions[&0].results.iter().find(|finding| matches!(finding.kind, containers::Event::CorrectPassword)).unwrap().clone();
+        let session = sessions.get_mut(&0).unwrap();
+        for _ in 0..SPRAY_MAX_ATTEMPTS {
+            let mut wrong = attempt.clone();
+            wrong.kind = containers::Event::WrongPassword;
+            session.results.push(wrong);
+        }
+        assert!(find_sprays(&sessions).is_empty());
+    }
+}
@@ -0,0 +1,145 @@
+//! Typing statistics over classified keystrokes.
+//!
+//! Classifications are not equally reliable: a deletion may as well have been Ctrl+e, and an
+//! Unknown keystroke may not have been typed at all. Every latency is therefore weighted by the
+//! [confidence](super::containers::Keystroke::confidence) of its keystroke, and keystrokes below
+//! a minimum confidence are left out entirely.
+use super::containers::{Keystroke, KeystrokeType};
+use serde::Serialize;
+
+/// Confidence below which keystrokes do not count towards typing statistics by default.
+pub const MIN_LATENCY_CONFIDENCE: f32 = 0.5;
+
+/// Upper bounds (μs, inclusive) of the latency histogram buckets. Latencies above the last bound
+/// go into a final, open-ended bucket; those are mostly pauses rather than typing.
+pub const HISTOGRAM_BOUNDS: [i64; 8] = [50_000, 100_000, 150_000, 200_000, 300_000, 500_000, 1_000_000, 2_000_000];
+
+/// Latency range of a histogram and the keystrokes that fall into it.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Bucket {
+    /// Upper bound (μs, inclusive); None for the open-ended last bucket
+    pub upper_us: Option<i64>,
+    /// Number of keystrokes
+    pub samples: usize,
+    /// Sum of their confidences
+    pub weight: f64,
+}
+
+/// Confidence-weighted keystroke latencies.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct LatencyStats {
+    /// Number of keystrokes counted
+    pub samples: usize,
+    /// Sum of their confidences
+    pub weight: f64,
+    /// Weighted mean latency
+    pub mean_us: i64,
+    /// Weighted median latency
+    pub median_us: i64,
+    /// Weighted standard deviation of the latencies
+    pub stddev_us: i64,
+    /// Weighted 10th percentile latency
+    pub p10_us: i64,
+    /// Weighted 25th percentile latency
+    pub p25_us: i64,
+    /// Weighted 75th percentile latency
+    pub p75_us: i64,
+    /// Weighted 90th percentile latency
+    pub p90_us: i64,
+    /// Weighted 99th percentile latency
+    pub p99_us: i64,
+    /// Latencies bucketed by [HISTOGRAM_BOUNDS]; empty if there are no samples
+    pub histogram: Vec<Bucket>,
+}
+
+/// Weighted latency statistics of all keystrokes with a confidence of at least `min_confidence`.
+///
+/// Keystrokes without a latency (the first of each sequence) and chaff are never counted.
+pub fn latency_stats<'a>(keystrokes: impl IntoIterator<Item = &'a Keystroke>, min_confidence: f32) -> LatencyStats {
+    let mut samples: Vec<(i64, f64)> = keystrokes.into_iter()
+        .filter(|keystroke| keystroke.latency_us > 0 && keystroke.k_type != KeystrokeType::Chaff)
+        .filter(|keystroke| keystroke.confidence > 0.0 && keystroke.confidence >= min_confidence)
+        .map(|keystroke| (keystroke.latency_us, keystroke.confidence as f64))
+        .collect();
+
+    let weight: f64 = samples.iter().map(|(_, weight)| weight).sum();
+    if samples.is_empty() {
+        return LatencyStats::default();
+    }
+
+    let mean = samples.iter().map(|(latency, weight)| *latency as f64 * weight).sum::<f64>() / weight;
+    let variance = samples.iter().map(|(latency, weight)| weight * (*latency as f64 - mean).powi(2)).sum::<f64>() / weight;
+
+    samples.sort_by_key(|(latency, _)| *latency);
+
+    let mut histogram: Vec<Bucket> = HISTOGRAM_BOUNDS.iter()
+        .map(|bound| Some(*bound))
+        .chain([None])
+        .map(|upper_us| Bucket { upper_us, ..Default::default() })
+        .collect();
+    for (latency, sample_weight) in &samples {
+        let bucket = HISTOGRAM_BOUNDS.iter().position(|bound| latency <= bound).unwrap_or(HISTOGRAM_BOUNDS.len());
+        histogram[bucket].samples += 1;
+        histogram[bucket].weight += sample_weight;
+    }
+
+    LatencyStats {
+        samples: samples.len(),
+        weight,
+        mean_us: mean.round() as i64,
+        median_us: weighted_percentile(&samples, weight, 50.0),
+        stddev_us: variance.sqrt().round() as i64,
+        p10_us: weighted_percentile(&samples, weight, 10.0),
+        p25_us: weighted_percentile(&samples, weight, 25.0),
+        p75_us: weighted_percentile(&samples, weight, 75.0),
+        p90_us: weighted_percentile(&samples, weight, 90.0),
+        p99_us: weighted_percentile(&samples, weight, 99.0),
+        histogram,
+    }
+}
+
+/// Lowest latency at which the cumulative weight reaches `percentile` percent of the total.
+/// `samples` must be sorted by latency.
+fn weighted_percentile(samples: &[(i64, f64)], weight: f64, percentile: f64) -> i64 {
+    let mut cumulative = 0.0;
+    for (latency, sample_weight) in samples {
+        cumulative += sample_weight;
+        if cumulative >= weight * percentile / 100.0 {
+            return *latency;
+        }
+    }
+    samples.last().map_or(0, |(latency, _)| *latency)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::containers;
+    use crate::analyser::containers::tests::keystroke;
+
+    #[test]
+    fn test_latency_stats() {
+        let keystrokes = vec![
+            keystroke(containers::KeystrokeType::Keystroke, 0),
+            keystroke(containers::KeystrokeType::Keystroke, 100_000),
+            keystroke(containers::KeystrokeType::Keystroke, 120_000),
+            keystroke(containers::KeystrokeType::Delete, 200_000),
+            keystroke(containers::KeystrokeType::Unknown, 2_000_000),
+            keystroke(containers::KeystrokeType::Chaff, 5_000),
+        ];
+
+        let typing = latency_stats(&keystrokes, MIN_LATENCY_CONFIDENCE);
+        assert_eq!(3, typing.samples);
+        assert_eq!(120_000, typing.median_us);
+        assert_eq!(135_714, typing.mean_us);
+        assert_eq!(100_000, typing.p10_us);
+        assert_eq!(200_000, typing.p90_us);
+        assert_eq!(vec![0, 1, 1, 1], typing.histogram[..4].iter().map(|bucket| bucket.samples).collect::<Vec<_>>());
+        assert_eq!(HISTOGRAM_BOUNDS.len() + 1, typing.histogram.len());
+
+        // Without a minimum, the Unknown keystroke counts, but only with its low weight.
+        let typing = latency_stats(&keystrokes, 0.0);
+        assert_eq!(4, typing.samples);
+        assert_eq!(120_000, typing.median_us);
+    }
+}
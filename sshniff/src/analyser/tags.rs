@@ -0,0 +1,129 @@
+//! Automatic session tagging.
+//!
+//! Tags are short labels summarising what kind of session an [SshSession] is, so that large
+//! numbers of sessions can be filtered without reading every result.
+use super::containers::{Event, InputSource};
+use super::core::SshSession;
+use super::transfers::TransferProtocol;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fmt;
+
+/// Number of wrong passwords from which a session is considered brute-forced.
+pub const BRUTE_FORCE_MIN_FAILURES: usize = 3;
+
+/// Bytes sent in both directions after login from which a non-interactive session is considered
+/// a tunnel.
+pub const TUNNEL_MIN_BYTES: u64 = 64 << 10;
+
+/// Labels attached to a session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Tag {
+    /// Keystrokes were found
+    Interactive,
+    /// Client logged in with a key
+    KeyAuth,
+    /// Several wrong passwords were entered
+    BruteForced,
+    /// Port forwarding, or bulk traffic in both directions without keystrokes
+    Tunneled,
+    /// Client uses keystroke obfuscation
+    Obfuscated,
+    /// An SCP/SFTP transfer was found
+    FileTransfer,
+    /// TCP handshake, or even the KEX, was not captured
+    PartialCapture,
+    /// A terminal multiplexer (tmux, screen) refreshes its status line
+    Multiplexer,
+    /// A command was sent by a script (expect, Ansible) rather than typed
+    Scripted,
+    /// An SSH session was opened onward from the server
+    Nested,
+    /// The client gave up or was disconnected without logging in
+    FailedLogin,
+    /// The client tried a few passwords on each of many servers; see [spray](super::spray)
+    PasswordSpray,
+    /// A second factor, e.g. a verification code, was prompted for at login
+    TwoFactor,
+    /// The login succeeded without any credentials (`none` authentication)
+    NoAuth,
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.to_possible_value().expect("no skipped tags");
+        write!(f, "{}", name.get_name())
+    }
+}
+
+/// Infers the tags of an analysed session.
+pub fn infer_tags(session: &SshSession) -> Vec<Tag> {
+    let mut tags = vec![];
+    let count = |kind: Event| session.results.iter().filter(|finding| finding.kind == kind).count();
+    let (client_bytes, server_bytes) = session.bytes;
+
+    let interactive = !session.keystroke_data.is_empty();
+    if interactive {
+        tags.push(Tag::Interactive);
+    }
+    if count(Event::AcceptedKey) > 0 {
+        tags.push(Tag::KeyAuth);
+    }
+    if count(Event::NoneAuth) > 0 {
+        tags.push(Tag::NoAuth);
+    }
+    if count(Event::SecondFactor) > 0 {
+        tags.push(Tag::TwoFactor);
+    }
+    if count(Event::WrongPassword) >= BRUTE_FORCE_MIN_FAILURES {
+        tags.push(Tag::BruteForced);
+    }
+    let bulk = client_bytes >= TUNNEL_MIN_BYTES && server_bytes >= TUNNEL_MIN_BYTES;
+    if count(Event::PortForwarding) > 0 || (!interactive && bulk) {
+        tags.push(Tag::Tunneled);
+    }
+    if session.obfuscated {
+        tags.push(Tag::Obfuscated);
+    }
+    if session.transfers.iter().any(|transfer| transfer.protocol != TransferProtocol::Shell) {
+        tags.push(Tag::FileTransfer);
+    }
+    if count(Event::StatusRefresh) > 0 {
+        tags.push(Tag::Multiplexer);
+    }
+    if session.keystroke_data.iter().any(|sequence| sequence.input == InputSource::Scripted) {
+        tags.push(Tag::Scripted);
+    }
+    if count(Event::NestedSession) > 0 {
+        tags.push(Tag::Nested);
+    }
+    if session.partial || !session.segmentation.handshake {
+        tags.push(Tag::PartialCapture);
+    }
+
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::core::tests::session;
+
+    #[test]
+    fn test_partial_capture() {
+        let partial = |handshake, mss, mid_session| {
+            let mut session = session();
+            session.segmentation.handshake = handshake;
+            session.segmentation.mss = mss;
+            session.partial = mid_session;
+            infer_tags(&session).contains(&Tag::PartialCapture)
+        };
+
+        assert!(!partial(true, (Some(1460), Some(1460)), false));
+        // A handshake without the MSS option is still complete
+        assert!(!partial(true, (None, None), false));
+        assert!(partial(false, (None, None), false));
+        assert!(partial(true, (Some(1460), Some(1460)), true));
+    }
+}
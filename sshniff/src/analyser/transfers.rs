@@ -0,0 +1,189 @@
+//! File transfer detection.
+//!
+//! Bulk transfers show up as bursts of large records in one direction. What the opposite direction
+//! does during the burst tells the protocols apart: SFTP acknowledges every chunk with a request or
+//! status message, while SCP streams the file and only sees the occasional window adjust.
+use super::containers::{Direction, Event, Finding, PacketInfo};
+use super::utils;
+use serde::Serialize;
+
+/// Smallest record that counts as transfer data.
+pub const TRANSFER_MIN_RECORD: i32 = 1024;
+
+/// Longest pause (μs) within a single transfer.
+pub const TRANSFER_MAX_GAP: i64 = 1_000_000;
+
+/// Smallest number of bytes in the dominant direction for a burst to count as a transfer.
+pub const TRANSFER_MIN_BYTES: u64 = 100 << 10;
+
+/// Smallest share of the burst's bytes the dominant direction has to carry.
+pub const TRANSFER_MIN_SHARE: f64 = 0.9;
+
+/// Opposite-direction records per MiB transferred from which a transfer is considered SFTP.
+/// SFTP clients read and write in 32 KiB chunks, i.e. ~32 requests or replies per MiB.
+pub const SFTP_MIN_REPLIES_PER_MIB: f64 = 16.0;
+
+/// Protocol a transfer was most likely made with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum TransferProtocol {
+    Scp,
+    Sftp,
+    /// Bulk output inside an interactive shell, e.g. `cat` of a large file
+    Shell,
+}
+
+/// Direction of a transfer, from the client's point of view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum TransferDirection {
+    Upload,
+    Download,
+}
+
+/// A detected bulk transfer.
+#[derive(Clone, Debug, Serialize)]
+pub struct Transfer {
+    pub protocol: TransferProtocol,
+    pub direction: TransferDirection,
+    /// Bytes (SSH record lengths) sent in the transfer direction
+    pub bytes: u64,
+    /// UNIX timestamp (μs) of the first transfer record
+    pub start: i64,
+    /// UNIX timestamp (μs) of the last transfer record
+    pub end: i64,
+    pub start_utc: String,
+    pub end_utc: String,
+}
+
+/// Finds bulk transfers after login.
+///
+/// `interactive` marks sessions with a pty, where bulk data is shell output rather than a file
+/// transfer protocol. `logged_in_at` has to index `packet_infos`. Returns the transfers and a
+/// [FileTransfer](Event::FileTransfer) finding for each.
+pub fn scan_for_transfers(packet_infos: &[PacketInfo], logged_in_at: usize, interactive: bool) -> (Vec<Transfer>, Vec<Finding>) {
+    log::info!("Looking for file transfers.");
+
+    let mut transfers: Vec<Transfer> = Vec::new();
+    let mut events: Vec<Finding> = Vec::new();
+    let packet_infos = &packet_infos[logged_in_at.min(packet_infos.len())..];
+
+    let mut start = 0;
+    while start < packet_infos.len() {
+        // A burst runs until the first pause longer than TRANSFER_MAX_GAP between data records.
+        let mut end = start;
+        let mut last_data = packet_infos[start].timestamp;
+        for (index, packet_info) in packet_infos.iter().enumerate().skip(start + 1) {
+            if packet_info.timestamp - last_data > TRANSFER_MAX_GAP {
+                break;
+            }
+            if packet_info.length >= TRANSFER_MIN_RECORD {
+                last_data = packet_info.timestamp;
+            }
+            end = index;
+        }
+
+        if let Some((transfer, first)) = classify_burst(&packet_infos[start..=end], interactive) {
+            log::debug!("{:?} {:?} of {} bytes at {}", transfer.protocol, transfer.direction, transfer.bytes, first.seq);
+            events.push(Finding::new(Event::FileTransfer, first)
+                .with_details(format!("{:?} {:?}, {} bytes", transfer.protocol, transfer.direction, transfer.bytes))
+                .with_confidence(0.5));
+            transfers.push(transfer);
+        }
+
+        start = end + 1;
+    }
+
+    (transfers, events)
+}
+
+/// Checks if a burst is a transfer, and if so, returns it along with its first data record.
+fn classify_burst(burst: &[PacketInfo], interactive: bool) -> Option<(Transfer, &PacketInfo)> {
+    let (client_bytes, server_bytes) = utils::count_bytes(burst);
+    let (dominant, bytes) = if client_bytes >= server_bytes {
+        (Direction::ClientToServer, client_bytes)
+    } else {
+        (Direction::ServerToClient, server_bytes)
+    };
+
+    if bytes < TRANSFER_MIN_BYTES || (bytes as f64) < TRANSFER_MIN_SHARE * (client_bytes + server_bytes) as f64 {
+        return None;
+    }
+
+    let data: Vec<&PacketInfo> = burst.iter()
+        .filter(|packet_info| packet_info.direction == dominant && packet_info.length >= TRANSFER_MIN_RECORD)
+        .collect();
+    let (first, last) = (data.first()?, data.last()?);
+
+    let replies = burst.iter().filter(|packet_info| packet_info.direction != dominant).count();
+    let replies_per_mib = replies as f64 / (bytes as f64 / (1 << 20) as f64);
+
+    let protocol = if interactive {
+        TransferProtocol::Shell
+    } else if replies_per_mib >= SFTP_MIN_REPLIES_PER_MIB {
+        TransferProtocol::Sftp
+    } else {
+        TransferProtocol::Scp
+    };
+
+    let direction = match dominant {
+        Direction::ClientToServer => TransferDirection::Upload,
+        Direction::ServerToClient => TransferDirection::Download,
+    };
+
+    Some((Transfer {
+        protocol,
+        direction,
+        bytes,
+        start: first.timestamp,
+        end: last.timestamp,
+        start_utc: utils::format_timestamp(first.timestamp),
+        end_utc: utils::format_timestamp(last.timestamp),
+    }, first))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::containers::tests::timed_records;
+    use crate::analyser::containers::Direction::{ClientToServer as C, ServerToClient as S};
+
+    /// `chunks` records of 2 KiB in `direction`, each `every`th answered by a small record, 10ms
+    /// apart from `start`.
+    fn transfer(direction: Direction, chunks: usize, every: usize, start: i64) -> Vec<(Direction, i32, i64)> {
+        let reply = if direction == C { S } else { C };
+        (1..=chunks).flat_map(|chunk| {
+            let answer = (chunk % every == 0).then_some((reply, 36));
+            [Some((direction, 2048)), answer].into_iter().flatten()
+        }).enumerate().map(|(index, (direction, length))| (direction, length, start + index as i64 * 10_000)).collect()
+    }
+
+    #[test]
+    fn test_transfers() {
+        // 400 KiB streamed with the odd window adjust, and fetched in acknowledged chunks
+        let (found, findings) = scan_for_transfers(&timed_records(&transfer(C, 200, 50, 0)), 0, false);
+        assert_eq!(1, found.len());
+        assert_eq!((TransferProtocol::Scp, TransferDirection::Upload, 200 * 2048), (found[0].protocol, found[0].direction, found[0].bytes));
+        // The last chunk is preceded by three window adjusts
+        assert_eq!((0, 202 * 10_000), (found[0].start, found[0].end));
+        assert_eq!(Event::FileTransfer, findings[0].kind);
+        assert_eq!(Some("Scp Upload, 409600 bytes"), findings[0].details.as_deref());
+
+        let (found, _) = scan_for_transfers(&timed_records(&transfer(S, 200, 4, 0)), 0, false);
+        assert_eq!((TransferProtocol::Sftp, TransferDirection::Download), (found[0].protocol, found[0].direction));
+        // With a pty, the same is shell output
+        let (found, _) = scan_for_transfers(&timed_records(&transfer(S, 200, 4, 0)), 0, true);
+        assert_eq!(TransferProtocol::Shell, found[0].protocol);
+
+        // Pauses split transfers; data before the login is not looked at
+        let mut layout = transfer(C, 100, 50, 0);
+        let pause = layout.len();
+        layout.extend(transfer(C, 100, 50, layout[pause - 1].2 + 5_010_000));
+        let packet_infos = timed_records(&layout);
+        assert_eq!(2, scan_for_transfers(&packet_infos, 0, false).0.len());
+        assert_eq!(1, scan_for_transfers(&packet_infos, pause, false).0.len());
+
+        // Too little data, or as much going back as forth
+        assert!(scan_for_transfers(&timed_records(&transfer(C, 20, 50, 0)), 0, false).0.is_empty());
+        let balanced: Vec<(Direction, i32, i64)> = transfer(C, 100, 200, 0).into_iter().chain(transfer(S, 100, 200, 1_000_000)).collect();
+        assert!(scan_for_transfers(&timed_records(&balanced), 0, false).0.is_empty());
+    }
+}
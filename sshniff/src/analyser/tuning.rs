@@ -0,0 +1,102 @@
+//! Per-session tuning of the size thresholds.
+//!
+//! The fixed [Thresholds] were measured on OpenSSH with chacha20-poly1305, where records grow in
+//! steps of 8 bytes. Other ciphers pad to 16-byte blocks, and other clients size their arrow keys
+//! and prompts differently. Instead of relying on the defaults, this pre-pass reads the steps off
+//! the session's own size distributions, and only keeps a tuned value if enough records agree.
+use super::containers::PacketInfo;
+use super::options::Thresholds;
+use std::collections::BTreeMap;
+
+/// Minimum number of records that have to agree on a value before it replaces the default.
+pub const TUNING_MIN_SAMPLES: usize = 3;
+
+/// Largest size difference to a keystroke considered for the echo offset and upper bound.
+pub const TUNING_MAX_DIFFERENCE: i32 = 48;
+
+/// Returns `thresholds` with the echo offset, keystroke upper bound, and prompt tolerance derived
+/// from the records after New Keys.
+///
+/// Runs before the records are ordered, so only pairs the capture happens to keep adjacent
+/// count; that is most of them in interactive sessions.
+pub fn tune(packet_infos: &[PacketInfo], keystroke_size: i32, prompt_size: i32, new_keys_index: usize, thresholds: &Thresholds) -> Thresholds {
+    let mut tuned = thresholds.clone();
+    let records = packet_infos.get(new_keys_index..).unwrap_or_default();
+
+    // Echoes larger than the keystroke they answer: deletions, and completions in the same step.
+    let echo_differences = records.windows(2)
+        .filter(|pair| pair[0].is_client_len(keystroke_size) && pair[1].is_server())
+        .map(|pair| pair[1].length - keystroke_size);
+    if let Some(offset) = frequent(echo_differences).into_iter().next() {
+        tuned.echo_offset = offset;
+    }
+
+    // Client records slightly larger than a keystroke: arrow keys and other escape sequences.
+    let client_differences = records.iter()
+        .filter(|record| record.is_client())
+        .map(|record| record.length - keystroke_size);
+    if let Some(bound) = frequent(client_differences).into_iter().last() {
+        tuned.keystroke_upper_bound = bound.max(tuned.echo_offset);
+    }
+
+    // Server records within a padding step of the prompt, before the session settles into
+    // keystrokes. Repeated prompts that are not exactly prompt-sized widen the tolerance.
+    let prompt_deviations = records.iter()
+        .take_while(|record| !record.is_client_len(keystroke_size))
+        .filter(|record| record.is_server() && record.length != prompt_size && (record.length - prompt_size).abs() < tuned.echo_offset)
+        .map(|record| (record.length - prompt_size).abs());
+    if let Some(tolerance) = frequent(prompt_deviations).into_iter().last() {
+        tuned.prompt_tolerance = tolerance;
+    }
+
+    log::info!("Tuned thresholds: echo offset {}, keystroke upper bound {}, prompt tolerance {}.", tuned.echo_offset, tuned.keystroke_upper_bound, tuned.prompt_tolerance);
+    tuned
+}
+
+/// Positive differences up to [TUNING_MAX_DIFFERENCE] seen at least [TUNING_MIN_SAMPLES] times,
+/// in ascending order.
+fn frequent(differences: impl Iterator<Item = i32>) -> Vec<i32> {
+    let mut counts: BTreeMap<i32, usize> = BTreeMap::new();
+    for difference in differences.filter(|difference| (1..=TUNING_MAX_DIFFERENCE).contains(difference)) {
+        *counts.entry(difference).or_default() += 1;
+    }
+
+    counts.into_iter()
+        .filter(|(_, count)| *count >= TUNING_MIN_SAMPLES)
+        .map(|(difference, _)| difference)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::containers::tests::records;
+    use crate::analyser::containers::Direction::{ClientToServer as C, ServerToClient as S};
+
+    #[test]
+    fn test_tune() {
+        // A 16-byte block cipher: prompts off by a padding step, deletion echoes 16 bytes larger
+        // than keystrokes, and arrow keys 24 bytes larger
+        let layout = [
+            (S, 60), (C, 100), (S, 60), (C, 100), (S, 60), (C, 100),
+            (C, 36), (S, 52), (C, 36), (S, 52), (C, 36), (S, 52), (C, 36), (S, 36),
+            (C, 60), (S, 36), (C, 60), (S, 36), (C, 60), (S, 36),
+        ];
+        let tuned = tune(&records(&layout), 36, 52, 0, &Thresholds::default());
+        assert_eq!((16, 24, 8), (tuned.echo_offset, tuned.keystroke_upper_bound, tuned.prompt_tolerance));
+
+        // The upper bound covers the echo offset even if escape sequences are smaller
+        let mut short = layout.to_vec();
+        short.splice(14.., [(C, 44), (S, 36), (C, 44), (S, 36), (C, 44), (S, 36)]);
+        let tuned = tune(&records(&short), 36, 52, 0, &Thresholds { keystroke_upper_bound: 4, ..Thresholds::default() });
+        assert_eq!((16, 16), (tuned.echo_offset, tuned.keystroke_upper_bound));
+
+        // Below TUNING_MIN_SAMPLES records per value, or before New Keys, the defaults stay
+        let defaults = Thresholds::default();
+        let few = [(S, 60), (C, 100), (S, 60), (C, 36), (S, 52), (C, 36), (S, 52), (C, 60), (C, 60)];
+        for (layout, new_keys_index) in [(&few[..], 0), (&layout[..], layout.len())] {
+            let tuned = tune(&records(layout), 36, 52, new_keys_index, &defaults);
+            assert_eq!((defaults.echo_offset, defaults.keystroke_upper_bound, defaults.prompt_tolerance), (tuned.echo_offset, tuned.keystroke_upper_bound, tuned.prompt_tolerance));
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Encapsulation of mirrored traffic.
+//!
+//! Captures from SPAN ports and TAP aggregators often carry the sessions inside VLAN tags, or
+//! wrapped in GRE, ERSPAN, or VXLAN by a remote mirror. Streams are keyed and directed on the
+//! innermost IP and TCP headers (see [find_ip_addresses](super::utils::find_ip_addresses)), so
+//! the encapsulation does not change the analysis; [find_tunnel] keeps it for the output, to tell
+//! which mirror session or segment a session was seen on.
+use super::packet::Packet;
+use serde::Serialize;
+use std::fmt;
+use std::net::IpAddr;
+
+/// Encapsulation layers, as named by tshark.
+const ENCAPSULATIONS: [&str; 4] = ["vlan", "gre", "erspan", "vxlan"];
+
+/// How the packets of a session were encapsulated, as seen on its first packet.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Tunnel {
+    /// Encapsulations, outermost first, e.g. `["vlan", "gre", "erspan"]`
+    pub encapsulations: Vec<String>,
+    /// (source, destination) of the outermost IP header, if the session was tunnelled over IP
+    pub outer: Option<(String, String)>,
+    /// VLAN IDs, outermost first
+    pub vlan_ids: Vec<u16>,
+    pub gre_key: Option<u32>,
+    /// ERSPAN session ID
+    pub erspan_id: Option<u16>,
+    /// VXLAN network identifier
+    pub vxlan_vni: Option<u32>,
+}
+
+impl fmt::Display for Tunnel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut vlan_ids = self.vlan_ids.iter();
+        let layers: Vec<String> = self.encapsulations.iter().map(|name| {
+            let id = match name.as_str() {
+                "vlan" => vlan_ids.next().map(|id| id.to_string()),
+                "gre" => self.gre_key.map(|key| format!("key {key}")),
+                "erspan" => self.erspan_id.map(|id| id.to_string()),
+                "vxlan" => self.vxlan_vni.map(|vni| vni.to_string()),
+                _ => None,
+            };
+            match id {
+                Some(id) => format!("{} {id}", name.to_uppercase()),
+                None => name.to_uppercase(),
+            }
+        }).collect();
+        write!(f, "{}", layers.join(" / "))?;
+        if let Some((source, destination)) = &self.outer {
+            write!(f, " via {source} -> {destination}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Finds the encapsulation of a stream on its first packet; `None` if it was captured bare.
+pub fn find_tunnel(packets: &[Packet]) -> Option<Tunnel> {
+    let packet = packets.first()?;
+    let mut tunnel = Tunnel::default();
+    let mut ip_layers = 0;
+
+    for layer in packet.iter() {
+        match layer.name() {
+            "ip" | "ipv6" => ip_layers += 1,
+            name if ENCAPSULATIONS.contains(&name) => tunnel.encapsulations.push(name.to_string()),
+            _ => continue,
+        }
+        match layer.name() {
+            "vlan" => tunnel.vlan_ids.extend(layer.metadata("vlan.id").and_then(|id| parse_number::<u16>(id.value()))),
+            "gre" => tunnel.gre_key = tunnel.gre_key.or(layer.metadata("gre.key").and_then(|key| parse_number(key.value()))),
+            "erspan" => tunnel.erspan_id = tunnel.erspan_id.or(layer.metadata("erspan.spanid").and_then(|id| parse_number(id.value()))),
+            "vxlan" => tunnel.vxlan_vni = tunnel.vxlan_vni.or(layer.metadata("vxlan.vni").and_then(|vni| parse_number(vni.value()))),
+            // The first of several IP layers is the outermost
+            _ if tunnel.outer.is_none() => {
+                let source = layer.metadata("ip.src").or(layer.metadata("ipv6.src"));
+                let destination = layer.metadata("ip.dst").or(layer.metadata("ipv6.dst"));
+                if let (Some(source), Some(destination)) = (source, destination) {
+                    tunnel.outer = Some((canonical(source.value()), canonical(destination.value())));
+                }
+            },
+            _ => {},
+        }
+    }
+
+    if ip_layers < 2 {
+        tunnel.outer = None;
+    }
+    if tunnel.encapsulations.is_empty() && tunnel.outer.is_none() {
+        return None;
+    }
+    Some(tunnel)
+}
+
+/// An address in canonical form, like those of [find_ip_addresses](super::utils::find_ip_addresses).
+fn canonical(ip: &str) -> String {
+    ip.parse::<IpAddr>().map_or_else(|_| ip.to_string(), |ip| ip.to_string())
+}
+
+/// Parses a field tshark shows in decimal or hex.
+fn parse_number<T: TryFrom<u64>>(value: &str) -> Option<T> {
+    let number = match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => value.parse().ok()?,
+    };
+    T::try_from(number).ok()
+}
@@ -1,28 +1,206 @@
 //! Contains utilities and helper functions that aid in Packet processing.
-use rtshark::{Packet, RTShark};
-use core::panic;
-use std::{collections::HashMap, usize};
+use super::packet::Packet;
+use std::{collections::{HashMap, VecDeque}, usize};
+use std::net::IpAddr;
 use md5::{Digest, Md5};
-use super::containers::PacketInfo;
+use super::containers::{PacketInfo, JitterStats, CommandLength, Direction, InputSource, Keystroke, KeystrokeType, SampledTail, TcpMeta};
+use super::options::{Decoding, Engine, Thresholds};
+use super::sampling::Sampling;
+use super::filter::StreamFilter;
+use super::compression::{self, CaptureFile};
+use super::source::{self, CaptureSource, SourceError};
+use crate::error::Error;
 use hex;
+use chrono::{TimeZone, Utc};
 
 /// Constant upper boundary for what might be considered a keystroke.
 /// Important to keep track of this because it pops up in comparison operations 
 /// and needs to be uniform, especially when sorting the initial stream.
 pub const KEYSTROKE_UPPER_BOUND: i32 = 16;
 
-/// Iterates through rtshark packets, checking for streams and adding them to a hashmap.
+/// Size difference between a keystroke and the echo of a deletion, i.e. one padding step.
+pub const ECHO_OFFSET: i32 = 8;
+
+/// Largest size difference between a login prompt and the expected prompt size.
+pub const PROMPT_TOLERANCE: i32 = 0;
+
+/// Number of RTT samples on either side of a candidate path change.
+pub const PATH_CHANGE_WINDOW: usize = 8;
+
+/// Factor by which the median RTT has to change to be considered a path change.
+pub const PATH_CHANGE_RATIO: f64 = 2.0;
+
+/// Minimum absolute median RTT change (μs), so that sub-millisecond LAN noise is not flagged.
+pub const PATH_CHANGE_MIN_DELTA: i64 = 5000;
+
+/// Confidence of login findings next to a prompt that only matched within the prompt tolerance.
+pub const TOLERATED_PROMPT_CONFIDENCE: f32 = 0.7;
+
+/// Smallest pause (μs) between chaff records that ends a run of chaff, i.e. a pause in typing.
+pub const CHAFF_GAP: i64 = 35_000;
+
+/// Client record lengths of an RSA public key offer.
+pub const RSA_OFFER_SIZE: std::ops::RangeInclusive<i32> = 492..=500;
+
+/// Client record lengths of an ED25519 public key offer.
+pub const ED25519_OFFER_SIZE: std::ops::RangeInclusive<i32> = 140..=148;
+
+/// Client record lengths of an ECDSA public key offer, across curves and ciphers.
+pub const ECDSA_OFFER_SIZE: std::ops::RangeInclusive<i32> = 188..=212;
+
+/// Client record lengths of a keyboard-interactive request, with usernames of up to 16 characters.
+pub const KEYBOARD_INTERACTIVE_REQUEST_SIZE: std::ops::RangeInclusive<i32> = 84..=100;
+
+/// Server record lengths of a keyboard-interactive INFO_REQUEST without any prompts.
+pub const EMPTY_INFO_REQUEST_SIZE: std::ops::RangeInclusive<i32> = 44..=44;
+
+/// Client record lengths of a FIDO ED25519 (sk-ssh-ed25519) key offer, for usernames of up to 8
+/// characters; longer ones overlap the ECDSA offers.
+pub const SK_ED25519_OFFER_SIZE: std::ops::RangeInclusive<i32> = 172..=180;
+
+/// Client record lengths of a FIDO ECDSA (sk-ecdsa-sha2-nistp256) key offer.
+pub const SK_ECDSA_OFFER_SIZE: std::ops::RangeInclusive<i32> = 236..=252;
+
+/// Client record lengths of an OpenSSH certificate offer, from an ED25519 certificate signed by
+/// an ED25519 CA up to RSA ones. Offers of plain RSA keys above 3072 bits fall in this range, too.
+pub const CERTIFICATE_OFFER_SIZE: std::ops::RangeInclusive<i32> = 520..=2048;
+
+/// Smallest client record considered a pty-req. The encoded terminal modes alone take up around
+/// 250 bytes with OpenSSH, so channel opens, env and shell requests stay below this.
+pub const PTY_REQUEST_MIN_LENGTH: i32 = 200;
+
+/// Number of client records after login in which the pty-req is looked for.
+pub const PTY_REQUEST_WINDOW: usize = 10;
+
+/// Size difference between a window-change request and a keystroke. The request carries 29 more
+/// payload bytes (request name and four dimensions), which pads out to 32 more bytes with AEAD and
+/// ETM ciphers.
+pub const WINDOW_CHANGE_OFFSET: i32 = 32;
+
+/// Size range, relative to a keystroke, of a direct-tcpip/forwarded-tcpip channel open. The open
+/// carries the channel type, target host and port, and originator address and port, i.e. 60 to
+/// 100 more payload bytes than a keystroke; smaller channel requests (session open, env, shell)
+/// stay below the range.
+pub const FORWARD_OPEN_EXTRA: std::ops::RangeInclusive<i32> = 48..=160;
+
+/// Sizes, relative to a keystroke, of a channel open confirmation, depending on the cipher's
+/// block size.
+pub const FORWARD_CONFIRM_EXTRA: [i32; 2] = [8, 16];
+
+/// Number of client channel opens from which forwarding with varying targets is considered
+/// dynamic (SOCKS) rather than local.
+pub const DYNAMIC_FORWARD_MIN_CHANNELS: usize = 3;
+
+/// Time (μs) without client records before a server record for it to count as unsolicited output,
+/// e.g. a multiplexer's status line refresh.
+pub const UNSOLICITED_QUIET: i64 = 1_000_000;
+
+/// Minimum number of regular unsolicited server records to report a status line.
+pub const STATUS_REFRESH_MIN_COUNT: usize = 5;
+
+/// Largest deviation of a refresh interval from the median interval, relative to the median.
+pub const STATUS_REFRESH_MAX_DEVIATION: f64 = 0.1;
+
+/// Share of intervals between unsolicited records that have to be regular.
+pub const STATUS_REFRESH_MIN_SHARE: f64 = 0.6;
+
+/// Largest difference between a rekey KEXINIT and the initial one plus MAC. Rekey KEXINITs
+/// drop the ext-info and strict-kex pseudo-algorithms, and gain encryption padding.
+pub const REKEY_KEXINIT_TOLERANCE: i32 = 96;
+
+/// Number of records within which the client and server KEXINITs of a rekey are sent.
+pub const REKEY_KEXINIT_WINDOW: usize = 4;
+
+/// Number of records in a rekey: both KEXINITs, the key exchange itself, and both NEWKEYS.
+pub const REKEY_BURST_LENGTH: usize = 6;
+
+/// Longest delay (μs) between the login prompt and the password, net of the client's RTT, that
+/// is attributed to pasting or autofill.
+pub const PASTED_PASSWORD_MAX_DELAY: i64 = 800_000;
+
+/// Shortest delay (μs) between the login prompt and the password, net of the client's RTT, that
+/// is attributed to typing. Even short passwords take longer than this to type by hand.
+pub const TYPED_PASSWORD_MIN_DELAY: i64 = 1_500_000;
+
+/// Fewest characters of a run of unechoed keystrokes to be taken as a password. Shorter runs also
+/// occur when keys are typed faster than the echoes come back.
+pub const PASSWORD_ENTRY_MIN_LENGTH: usize = 4;
+
+/// Bytes bracketed paste mode wraps around pasted text (`ESC[200~` and `ESC[201~`).
+pub const BRACKETED_PASTE_WRAPPER: i32 = 12;
+
+/// Minimum size of a client record over a keystroke to be taken as a paste. Larger than arrow
+/// keys and other escape sequences, and than the paste wrappers themselves.
+pub const PASTE_MIN_EXTRA: i32 = 24;
+
+/// Longest gap (μs) between inputs of a burst generated by mouse reporting or a TUI application
+/// rather than typing. Key autorepeat also stays below it.
+pub const INPUT_BURST_MAX_LATENCY: i64 = 35_000;
+
+/// Minimum number of inputs in a burst; typists can hit a few keys this fast, but not many.
+pub const INPUT_BURST_MIN_LENGTH: usize = 8;
+
+/// Range, relative to a sequence's median latency, of a digraph slowed down by a modifier key
+/// (Shift for capitals and most punctuation). Slower digraphs are pauses rather than keystrokes.
+pub const SHIFTED_DIGRAPH_FACTOR: std::ops::RangeInclusive<f64> = 1.5..=3.0;
+
+/// Share of shifted digraphs from which a sequence is labelled symbol-heavy.
+pub const SYMBOL_HEAVY_MIN_SHARE: f32 = 0.3;
+
+/// Minimum number of typed keystrokes for a sequence to be labelled symbol-heavy.
+pub const SYMBOL_HEAVY_MIN_KEYSTROKES: usize = 6;
+
+/// Longest latency (μs) of a pasted key. Terminals split a paste without bracketed paste mode into
+/// writes that arrive back to back; no one types this fast.
+pub const PASTED_MAX_LATENCY: i64 = 10_000;
+
+/// Share of a sequence's latencies below [PASTED_MAX_LATENCY] from which it counts as pasted.
+pub const PASTED_MIN_SHARE: f64 = 0.8;
+
+/// Coefficient of variation (standard deviation over mean) of latencies below which a sequence
+/// counts as scripted. Human typing rarely gets below 0.3, even for practised words.
+pub const SCRIPTED_MAX_VARIATION: f64 = 0.1;
+
+/// Minimum number of latencies for a sequence to be classified at all.
+pub const INPUT_CLASSIFY_MIN_LATENCIES: usize = 4;
+
+/// Default number of records encoded in a session's size signature. Covers the KEX, login, and
+/// the start of the interactive session.
+pub const SIGNATURE_LENGTH: usize = 32;
+
+/// Non-SSH TCP metadata per stream: <stream_id> -> [TcpMeta].
+pub type TcpMetaMap = HashMap<u32, TcpMeta>;
+
+/// Iterates through the packets of a [CaptureSource], checking for streams and adding them to a hashmap.
 ///
 /// Packets are added per-stream into the map. If the nstreams argument is set, only add that
 /// stream to the map for further processing.
-pub fn get_streams(rtshark: &mut RTShark, stream: i32) -> HashMap<u32, Vec<Packet>> {
+/// Handshake (SYN) packets and bare ACKs carry no SSH layer and are not added; instead, the MSS
+/// the former announce and the timestamps of the latter are collected into a second [TcpMetaMap].
+pub fn get_streams(source: &mut dyn CaptureSource, stream: i32, sampling: Option<Sampling>) -> (HashMap<u32, Vec<Packet>>, TcpMetaMap) {
+    get_streams_reporting(source, stream, sampling, &StreamFilter::default(), |_, _| {})
+}
+
+/// Collects streams like [get_streams], keeping only the streams `filter` matches on their first
+/// packet, and calling `on_packet` with the number of packets read and SSH streams found after
+/// each packet.
+pub fn get_streams_reporting(source: &mut dyn CaptureSource, stream: i32, sampling: Option<Sampling>, filter: &StreamFilter, on_packet: impl FnMut(usize, usize)) -> (HashMap<u32, Vec<Packet>>, TcpMetaMap) {
+    collect_streams(source::packets(source), stream, sampling, filter, on_packet)
+}
+
+/// Collects packets into streams; see [get_streams_reporting].
+pub fn collect_streams(packets: impl Iterator<Item = Packet>, stream: i32, sampling: Option<Sampling>, filter: &StreamFilter, mut on_packet: impl FnMut(usize, usize)) -> (HashMap<u32, Vec<Packet>>, TcpMetaMap) {
     log::info!("Collecting streams.");
     let mut stream_map: HashMap<u32, Vec<Packet>> = HashMap::new();
+    let mut meta_map: TcpMetaMap = HashMap::new();
+    // Whether each stream seen so far matched the filter
+    let mut selected: HashMap<u32, bool> = HashMap::new();
+    let mut servers: ServerMap = HashMap::new();
+    let mut read = 0;
 
-    while let Some(packet) = rtshark.read().unwrap_or_else(|e| {
-        log::error!("Error parsing TShark output when collecting streams: {e}");
-        None 
-    }) {
+    for mut packet in packets {
+        read += 1;
+        on_packet(read, stream_map.len());
         if let Some(tcp) = packet.layer_name("tcp") {
             let stream_id = tcp.metadata("tcp.stream").expect("tcp.stream expected in TCP packet").value();
 
@@ -31,82 +209,493 @@ pub fn get_streams(rtshark: &mut RTShark, stream: i32) -> HashMap<u32, Vec<Packe
                     if stream != -1 && stream_id != u32::try_from(stream).expect("Stream ID conversion error") {
                         continue;
                     }
+                    mark_direction(&mut servers, stream_id, &mut packet);
+                    if !filter.is_empty() && !*selected.entry(stream_id).or_insert_with(|| filter.matches(&packet)) {
+                        continue;
+                    }
+
+                    if packet.layer_name("ssh").is_none() {
+                        let entry = meta_map.entry(stream_id).or_default();
+                        let is_server = is_server_packet(&packet);
+                        if is_syn_packet(&packet) {
+                            let mss = find_metadata(&packet, "tcp.options.mss_val").and_then(|mss| mss.parse::<u32>().ok());
+                            entry.handshake = true;
+                            if is_server {
+                                entry.mss.1 = mss;
+                            } else {
+                                entry.mss.0 = mss;
+                            }
+                        } else if let (Some(timestamp), None) = (packet.timestamp_micros(), &entry.tail) {
+                            entry.acks.push((timestamp, is_server));
+                        }
+                        continue;
+                    }
 
-                    stream_map.entry(stream_id).or_insert_with(Vec::new).push(packet);
+                    let packets = stream_map.entry(stream_id).or_default();
+                    match sampling {
+                        Some(sampling) if packets.len() >= sampling.after => {
+                            let tail = meta_map.entry(stream_id).or_default().tail.get_or_insert_with(|| {
+                                log::info!("Stream {stream_id} reached {} packets; sampling 1 in {} from here.", sampling.after, sampling.rate);
+                                SampledTail::new(sampling.rate)
+                            });
+                            tail.add(packet.timestamp_micros().unwrap_or_default(), is_server_packet(&packet), get_segment_length(&packet) as u64);
+                        },
+                        _ => packets.push(packet),
+                    }
                 },
                 Err(_) => log::warn!("Failed to parse tcp.stream metadata as u32"),
             }
         }
     }
 
-    stream_map
+    (stream_map, meta_map)
 }
 
-/// Loads PCAP/PCAPNG file via rtshark.
-///
-/// Display filters used (adapted from Packet Strider):
-/// `ssh && !tcp.analysis.spurious_retransmission && !tcp.analysis.retransmission &&
+/// Whether the SYN flag of a [Packet] is set.
+pub fn is_syn_packet(packet: &Packet) -> bool {
+    find_metadata(packet, "tcp.flags.syn").is_some_and(|syn| syn == "1" || syn == "True")
+}
+
+/// Loads PCAP/PCAPNG file via tshark, with the [display filter](super::source::ANALYSIS_FILTER):
+/// `(ssh || tcp.flags.syn == 1 || (tcp.flags == 0x010 && tcp.len == 0)) &&
+/// !tcp.analysis.spurious_retransmission && !tcp.analysis.retransmission &&
 /// !tcp.analysis.fast_retransmission`
+/// SYN packets are only let through for their MSS option, bare ACKs for [JA4SSH](super::ja4ssh).
+/// gzip, zstd, and xz-compressed captures are [decompressed](super::compression) first.
 /// Calls get_streams() after loading packets.
-pub fn load_file(filepath: String, stream: i32) -> HashMap<u32, Vec<Packet>> {
+pub fn load_capture(filepath: &str, stream: i32) -> Result<(HashMap<u32, Vec<Packet>>, TcpMetaMap), Error> {
+    load_capture_sampled(filepath, stream, None)
+}
+
+/// Loads a capture like [load_capture], keeping only the first packets of long streams in full;
+/// see [sampling](super::sampling).
+pub fn load_capture_sampled(filepath: &str, stream: i32, sampling: Option<Sampling>) -> Result<(HashMap<u32, Vec<Packet>>, TcpMetaMap), Error> {
+    load_capture_reporting(filepath, stream, sampling, &StreamFilter::default(), Engine::Tshark, &Decoding::default(), |_, _| {})
+}
+
+/// Loads a capture like [load_capture_sampled], keeping only the streams matching `filter` and
+/// reporting the packets read and SSH streams found to `on_packet`; see [get_streams_reporting].
+///
+/// With the [native](super::native) engine, captures it cannot dissect are read with tshark instead,
+/// unless they come from standard input, which cannot be read twice. Errors that end reading
+/// early, e.g. a capture cut off in the middle of a record, are logged and the packets read up to
+/// them are analysed, as with tshark.
+pub fn load_capture_reporting(filepath: &str, stream: i32, sampling: Option<Sampling>, filter: &StreamFilter, engine: Engine, decoding: &Decoding, mut on_packet: impl FnMut(usize, usize)) -> Result<(HashMap<u32, Vec<Packet>>, TcpMetaMap), Error> {
     log::info!("Loading capture file.");
 
-    let filter = String::from("\
-        ssh &&\
-        !tcp.analysis.spurious_retransmission &&\
-        !tcp.analysis.retransmission &&\
-        !tcp.analysis.fast_retransmission\
-    ");
+    let capture = CaptureFile::open(filepath).map_err(Error::Capture)?;
+    if engine == Engine::Native {
+        match source::open(&capture, Engine::Native, decoding) {
+            Ok(mut native) => {
+                log::info!("Reading from {} with the native engine", filepath);
+                let streams = get_streams_reporting(native.as_mut(), stream, sampling, filter, &mut on_packet);
+                match native.finish() {
+                    Ok(()) => return Ok(streams),
+                    Err(SourceError::Unsupported(reason)) if filepath != compression::STDIN => log::warn!("{filepath}: {reason}; reading it with tshark."),
+                    Err(err) => {
+                        log::error!("{filepath}: {err}");
+                        return Ok(streams);
+                    },
+                }
+            },
+            Err(SourceError::Unsupported(reason)) if filepath != compression::STDIN => log::warn!("{filepath}: {reason}; reading it with tshark."),
+            Err(err) => return Err(Error::Capture(format!("{filepath}: {err}"))),
+        }
+    }
+
+    let mut tshark = source::open(&capture, Engine::Tshark, decoding).map_err(|err| Error::Capture(err.to_string()))?;
+    log::info!("Reading from {}", filepath);
+    let streams = get_streams_reporting(tshark.as_mut(), stream, sampling, filter, on_packet);
+    // Parse errors are logged as they happen; the packets read up to them are analysed
+    let _ = tshark.finish();
 
-    let builder = rtshark::RTSharkBuilder::builder()
-        .input_path(&filepath)
-        .display_filter(&filter);
-    
-    let mut rtshark = match builder.spawn() {
-        Err(err) => {
-            log::error!("Error spawning tshark: {err}"); 
-            panic!();
+    Ok(streams)
+}
+
+/// Returns the length of the SSH record a [Packet] completes.
+///
+/// When an SSH record is split across TCP segments, tshark only attaches the SSH layer to the last
+/// segment and records the full size in `tcp.reassembled.length`. Using the plain `tcp.len` of
+/// that segment would yield a wrong-sized packet, so the reassembled length takes precedence.
+pub fn get_record_length(packet: &Packet) -> i32 {
+    match find_metadata(packet, "tcp.reassembled.length") {
+        Some(length) => length.parse().unwrap(),
+        None => get_segment_length(packet),
+    }
+}
+
+/// Returns the plain tcp.len of a [Packet], i.e. the size of the TCP segment itself.
+pub fn get_segment_length(packet: &Packet) -> i32 {
+    packet.layer_name("tcp").unwrap().metadata("tcp.len").unwrap().value().parse().unwrap()
+}
+
+/// Returns the `ssh.packet_length` of all SSH records in a [Packet], as far as tshark exposes them.
+pub fn get_ssh_packet_lengths(packet: &Packet) -> Vec<i32> {
+    let ssh_layer = match packet.layer_name("ssh") {
+        Some(ssh_layer) => ssh_layer,
+        None => return Vec::new(),
+    };
+
+    ssh_layer.iter()
+        .filter(|metadata| metadata.name() == "ssh.packet_length")
+        .filter_map(|metadata| metadata.value().parse::<i32>().ok())
+        .collect()
+}
+
+/// Returns the length (bytes) of the MAC appended to each SSH record for the negotiated
+/// encryption and MAC algorithms.
+///
+/// AEAD ciphers carry their own 16-byte tag and ignore the MAC algorithm.
+pub fn get_mac_length(encryption: &str, mac: &str) -> i32 {
+    if encryption.contains("chacha20-poly1305") || encryption.contains("-gcm") {
+        return 16;
+    }
+
+    match mac.trim_end_matches("-etm@openssh.com") {
+        "hmac-sha2-512" => 64,
+        "hmac-sha2-256" => 32,
+        "hmac-sha1" | "hmac-ripemd160" => 20,
+        "hmac-md5" | "umac-128" => 16,
+        "hmac-sha1-96" | "hmac-md5-96" => 12,
+        "umac-64" => 8,
+        _ => 0,
+    }
+}
+
+/// Returns the number of TCP segments an SSH record was reassembled from (1 if it was not split).
+pub fn get_segment_count(packet: &Packet) -> u32 {
+    find_metadata(packet, "tcp.segment.count")
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(1)
+}
+
+/// Formats a UNIX timestamp (μs) as UTC date and time, with microseconds.
+pub fn format_timestamp(timestamp: i64) -> String {
+    format_timestamp_as(timestamp, "%Y-%m-%d %H:%M:%S%.6f")
+}
+
+/// Formats a UNIX timestamp (μs) as UTC with a strftime-style `format`; empty if out of range.
+pub fn format_timestamp_as(timestamp: i64, format: &str) -> String {
+    Utc.timestamp_micros(timestamp).single()
+        .map_or(String::new(), |datetime| datetime.format(format).to_string())
+}
+
+/// Parses a session's `start_utc`/`end_utc` back into a UNIX timestamp (μs).
+pub fn parse_session_time(time: &str) -> Option<i64> {
+    chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").ok()
+        .map(|datetime| datetime.and_utc().timestamp_micros())
+}
+
+/// Encodes the sizes and directions of the first `count` records as a compact string, e.g.
+/// `c41.s41.c1512.s1080`. Identical tooling produces identical prefixes, which can be grepped for.
+pub fn size_signature(packet_infos: &[PacketInfo], count: usize) -> String {
+    packet_infos.iter()
+        .take(count)
+        .map(|packet_info| format!("{}{}", if packet_info.is_client() { 'c' } else { 's' }, packet_info.length))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Returns the share of [corrective](KeystrokeType::is_corrective) keystrokes, ignoring chaff.
+pub fn error_rate<'a>(keystrokes: impl IntoIterator<Item = &'a Keystroke>) -> f32 {
+    let (corrective, total) = keystrokes.into_iter()
+        .filter(|keystroke| keystroke.k_type != KeystrokeType::Chaff)
+        .fold((0, 0), |(corrective, total), keystroke| (corrective + keystroke.k_type.is_corrective() as u32, total + 1));
+
+    if total == 0 {
+        return 0.0;
+    }
+    corrective as f32 / total as f32
+}
+
+/// Separates bursts of inputs too fast to be typed, like terminal mouse reporting or navigating
+/// htop or mc, from the keystrokes.
+///
+/// Returns the remaining keystrokes and the bursts, each in capture order.
+pub fn separate_input_bursts(keystrokes: Vec<Keystroke>) -> (Vec<Keystroke>, Vec<Vec<Keystroke>>) {
+    let mut typed: Vec<Keystroke> = Vec::new();
+    let mut bursts: Vec<Vec<Keystroke>> = Vec::new();
+    let mut run: Vec<Keystroke> = Vec::new();
+
+    let mut flush = |run: &mut Vec<Keystroke>, typed: &mut Vec<Keystroke>| {
+        if run.len() >= INPUT_BURST_MIN_LENGTH {
+            bursts.push(std::mem::take(run));
+        } else {
+            typed.append(run);
         }
-        Ok(rtshark) => {
-            log::info!("Reading from {}", filepath);
-            rtshark
+    };
+
+    for keystroke in keystrokes {
+        let continues = run.last().is_some_and(|last| keystroke.timestamp - last.timestamp <= INPUT_BURST_MAX_LATENCY);
+        if !continues {
+            flush(&mut run, &mut typed);
         }
+        run.push(keystroke);
+    }
+    flush(&mut run, &mut typed);
+
+    (typed, bursts)
+}
+
+/// Returns whether a sequence is dominated by shifted characters and punctuation, e.g. URLs or
+/// one-liners with pipes and redirects.
+///
+/// Pressing Shift delays the following key, so such input shows up as digraph latencies a
+/// fixed factor above the typist's median, without reaching the length of a pause.
+pub fn is_symbol_heavy(keystrokes: &[Keystroke]) -> bool {
+    // The first keystroke carries no latency, and Enter is usually preceded by a pause.
+    let mut latencies: Vec<i64> = keystrokes.iter()
+        .skip(1)
+        .filter(|keystroke| keystroke.k_type == KeystrokeType::Keystroke)
+        .map(|keystroke| keystroke.latency_us)
+        .collect();
+
+    if latencies.len() < SYMBOL_HEAVY_MIN_KEYSTROKES {
+        return false;
+    }
+
+    latencies.sort_unstable();
+    let median = latencies[latencies.len() / 2] as f64;
+    if median <= 0.0 {
+        return false;
+    }
+
+    let shifted = latencies.iter()
+        .filter(|&&latency| SHIFTED_DIGRAPH_FACTOR.contains(&(latency as f64 / median)))
+        .count();
+
+    shifted as f32 / latencies.len() as f32 >= SYMBOL_HEAVY_MIN_SHARE
+}
+
+/// Labels a sequence as typed by a person, sent by a script, or pasted.
+///
+/// Pastes arrive in bursts of a few milliseconds per key. Tools such as expect or Ansible send keys
+/// one at a time with a fixed delay, which leaves latencies far more uniform than any typist's,
+/// even though they are slow enough to look typed. Enter is left out, as it usually follows a
+/// pause, and so is chaff.
+pub fn classify_input(keystrokes: &[Keystroke]) -> InputSource {
+    let latencies: Vec<f64> = keystrokes.iter()
+        .skip(1)
+        .filter(|keystroke| !matches!(keystroke.k_type, KeystrokeType::Enter | KeystrokeType::Chaff))
+        .map(|keystroke| keystroke.latency_us as f64)
+        .collect();
+
+    if latencies.len() < INPUT_CLASSIFY_MIN_LATENCIES {
+        return InputSource::Unknown;
+    }
+
+    let pasted = latencies.iter().filter(|&&latency| latency <= PASTED_MAX_LATENCY as f64).count();
+    if pasted as f64 / latencies.len() as f64 >= PASTED_MIN_SHARE {
+        return InputSource::Pasted;
+    }
+
+    let mean = latencies.iter().sum::<f64>() / latencies.len() as f64;
+    let stddev = (latencies.iter().map(|latency| (latency - mean).powi(2)).sum::<f64>() / latencies.len() as f64).sqrt();
+    if mean > 0.0 && stddev / mean < SCRIPTED_MAX_VARIATION {
+        InputSource::Scripted
+    } else {
+        InputSource::Human
+    }
+}
+
+/// Estimates the length of the command a sequence submits by replaying it on an empty line.
+///
+/// Characters add one and deletions remove one, down to an empty line; horizontal arrows only move
+/// the cursor. [Unknown](KeystrokeType::Unknown) keystrokes are mostly deletions, so they count as
+/// such for the estimate and the minimum, and as characters for the maximum. Tab completion and
+/// history recall (vertical arrows) insert text of unknown length, which leaves the maximum open;
+/// a recalled line replaces what was typed before, so the count restarts from it.
+pub fn command_length(keystrokes: &[Keystroke]) -> CommandLength {
+    let mut length = CommandLength { max: Some(0), ..Default::default() };
+
+    for keystroke in keystrokes {
+        match keystroke.k_type {
+            KeystrokeType::Keystroke | KeystrokeType::Multibyte => {
+                length.estimate += 1;
+                length.min += 1;
+                length.max = length.max.map(|max| max + 1);
+            },
+            KeystrokeType::Delete | KeystrokeType::Unknown => {
+                if length.estimate > 0 {
+                    length.estimate -= 1;
+                    length.edit_distance += 1;
+                }
+                length.min = length.min.saturating_sub(1);
+                length.max = match keystroke.k_type {
+                    KeystrokeType::Delete => length.max.map(|max| max.saturating_sub(1)),
+                    _ => length.max.map(|max| max + 1),
+                };
+            },
+            KeystrokeType::Tab => length.max = None,
+            KeystrokeType::ArrowVertical => {
+                length.estimate = 0;
+                length.min = 0;
+                length.max = None;
+            },
+            KeystrokeType::ArrowHorizontal | KeystrokeType::Enter | KeystrokeType::Chaff => {},
+        }
+    }
+
+    length
+}
+
+/// Strips the port from an `ip:port` address.
+pub fn ip_of(address: &str) -> &str {
+    split_endpoint(address).0
+}
+
+/// Splits an endpoint formatted by [format_endpoint] into its address, without the brackets of an
+/// IPv6 address, and its port, if any.
+pub fn split_endpoint(address: &str) -> (&str, Option<&str>) {
+    if let Some(bracketed) = address.strip_prefix('[') {
+        return match bracketed.split_once(']') {
+            Some((ip, port)) => (ip, port.strip_prefix(':')),
+            None => (bracketed, None),
+        };
+    }
+    match address.split_once(':') {
+        // An IPv6 address without brackets has no port
+        Some((_, rest)) if rest.contains(':') => (address, None),
+        Some((ip, port)) => (ip, Some(port)),
+        None => (address, None),
+    }
+}
+
+/// Formats an endpoint as `ip:port`, with IPv6 addresses in brackets (`[2001:db8::1]:22`) so that
+/// the port stays separable.
+pub fn format_endpoint(ip: &str, port: &str) -> String {
+    if ip.contains(':') {
+        format!("[{ip}]:{port}")
+    } else {
+        format!("{ip}:{port}")
+    }
+}
+
+/// (source, destination) address of a [Packet], from its innermost IPv4 or IPv6 layer, in canonical form
+/// (RFC 5952 for IPv6).
+pub fn find_ip_addresses(packet: &Packet) -> Option<(String, String)> {
+    // The innermost IP layer is the connection's; outer ones belong to tunnels
+    let layer = packet.iter().filter(|layer| layer.name() == "ip" || layer.name() == "ipv6").last()?;
+    let address = |fields: [&str; 2]| -> Option<String> {
+        let ip = fields.iter().find_map(|field| layer.metadata(field))?.value();
+        Some(ip.parse::<IpAddr>().map_or_else(|_| ip.to_string(), |ip| ip.to_string()))
     };
-    
-    let streams = get_streams(&mut rtshark, stream);
-    rtshark.kill();
+    Some((address(["ip.src", "ipv6.src"])?, address(["ip.dst", "ipv6.dst"])?))
+}
 
-    streams
+/// Sums the lengths of the (client, server) SSH records.
+pub fn count_bytes<'a>(packet_infos: impl IntoIterator<Item = &'a PacketInfo>) -> (u64, u64) {
+    packet_infos.into_iter().fold((0, 0), |(client, server), packet_info| {
+        if packet_info.is_client() {
+            (client + packet_info.length as u64, server)
+        } else {
+            (client, server + packet_info.length as u64)
+        }
+    })
+}
+
+/// Returns the capture frame number (frame.number) of a [Packet], as shown by Wireshark.
+pub fn get_frame_number(packet: &Packet) -> Option<u32> {
+    find_metadata(packet, "frame.number").and_then(|number| number.parse().ok())
+}
+
+/// Looks up a metadata field in any layer of a [Packet].
+///
+/// Some fields (e.g. reassembly information) are not nested under the layer their name suggests.
+pub fn find_metadata<'a>(packet: &'a Packet, name: &str) -> Option<&'a str> {
+    packet.iter().find_map(|layer| layer.metadata(name)).map(|metadata| metadata.value())
 }
 
 /// Checks is a [Packet] is a server packet.
-/// Helper function that does some onion peeling on [Packet]s.
+///
+/// Packets [marked](mark_direction) while loading know their direction; for others the server is
+/// assumed to be on the lower port.
 pub fn is_server_packet(packet: &Packet) -> bool {
-        let tcp_layer = packet.layer_name("tcp").unwrap();
-        tcp_layer.metadata("tcp.dstport").unwrap().value().parse::<u32>().unwrap() > tcp_layer.metadata("tcp.srcport").unwrap().value().parse::<u32>().unwrap()
+    if let Some(from_server) = packet.from_server() {
+        return from_server;
+    }
+    let tcp_layer = packet.layer_name("tcp").unwrap();
+    tcp_layer.metadata("tcp.dstport").unwrap().value().parse::<u32>().unwrap() > tcp_layer.metadata("tcp.srcport").unwrap().value().parse::<u32>().unwrap()
+}
+
+/// Server endpoint (`ip:port`) of each stream: <stream_id> -> endpoint; see [mark_direction].
+pub type ServerMap = HashMap<u32, String>;
+
+/// Marks `packet` with the direction it was sent in, learning the server of its stream from the
+/// handshake or, without one, from the first banner.
+///
+/// The server is the destination of the SYN and the source of the SYN/ACK. Clients send their
+/// banner first, so the first banner goes to the server. Streams with neither, e.g. when the
+/// capture started mid-session, stay unmarked and fall back to the port comparison of
+/// [is_server_packet].
+pub fn mark_direction(servers: &mut ServerMap, stream: u32, packet: &mut Packet) {
+    let (source, destination) = match (find_ip_addresses(packet), find_metadata(packet, "tcp.srcport"), find_metadata(packet, "tcp.dstport")) {
+        (Some((source_ip, destination_ip)), Some(source_port), Some(destination_port)) => {
+            (format_endpoint(&source_ip, source_port), format_endpoint(&destination_ip, destination_port))
+        },
+        _ => return,
+    };
+
+    if let std::collections::hash_map::Entry::Vacant(entry) = servers.entry(stream) {
+        let ack = find_metadata(packet, "tcp.flags.ack").is_some_and(|ack| ack == "1" || ack == "True");
+        let server = if is_syn_packet(packet) {
+            Some(if ack { &source } else { &destination })
+        } else if find_metadata(packet, "ssh.protocol").is_some() {
+            Some(&destination)
+        } else {
+            None
+        };
+        if let Some(server) = server {
+            entry.insert(server.clone());
+        }
+    }
+
+    if let Some(server) = servers.get(&stream) {
+        packet.set_from_server(source == *server);
+    }
+}
+
+/// Returns the [Direction] a [Packet] was sent in.
+pub fn get_direction(packet: &Packet) -> Direction {
+    if is_server_packet(packet) {
+        Direction::ServerToClient
+    } else {
+        Direction::ClientToServer
+    }
 }
 
 /// Transform an rtshark packet slice into a vector of PacketInfo objects.
 ///
 /// Saves us the constant unwrapping of tcp and ssh layers / metadata to access the info we want.
-/// STC packets' lengths are negative, indicating the Server -> Client direction.
 pub fn create_size_matrix(packets: &[Packet]) -> Vec<PacketInfo> {
     log::info!("Creating PacketInfo matrix.");
-    packets.iter().enumerate().map(|(index, packet)| { 
-        let tcp_layer = packet.layer_name("tcp").unwrap();
-        let length: i32 = tcp_layer.metadata("tcp.len").unwrap().value().parse().unwrap();
-        let is_server_packet = is_server_packet(&packet);
-        let adjusted_length = if is_server_packet { -length } else { length };
-
-        let seq = tcp_layer.metadata("tcp.seq").unwrap().value().parse().unwrap();
-        PacketInfo {
-            index,
-            seq,
-            length: adjusted_length,
-            packet,
-            description: None,
+    packets.iter().enumerate().map(|(index, packet)| PacketInfo::new(packet, index)).collect()
+}
+
+/// Moves timestamps that run backwards forward to their predecessor's, so that no latency
+/// computed later comes out negative. Such timestamps come from merged captures or taps with
+/// unsynchronised clocks.
+///
+/// Returns the positions of the corrected [PacketInfo]s and the number of timestamps equal to
+/// their predecessor's, which are kept but make latencies of 0 likely.
+pub fn correct_timestamps(packet_infos: &mut [PacketInfo]) -> (Vec<usize>, usize) {
+    let mut corrected = Vec::new();
+    let mut duplicated = 0;
+
+    for position in 1..packet_infos.len() {
+        let previous = packet_infos[position - 1].timestamp;
+        let packet_info = &mut packet_infos[position];
+
+        if packet_info.timestamp < previous {
+            log::debug!("Timestamp of {} runs back {}μs", packet_info.seq, previous - packet_info.timestamp);
+            packet_info.timestamp = previous;
+            corrected.push(position);
+        } else if packet_info.timestamp == previous {
+            duplicated += 1;
         }
-    }).collect()
+    }
+
+    (corrected, duplicated)
 }
 
 /// Orders [PacketInfo]s into their inferred order of being sent. 
@@ -116,10 +705,16 @@ pub fn create_size_matrix(packets: &[Packet]) -> Vec<PacketInfo> {
 /// Rinse and repeat until all packets are ordered.
 /// There's some nuance to this as server echoes sometimes differ in size. 
 /// We account for that by checking up to keystroke_size + [KEYSTROKE_UPPER_BOUND] as possible responses.
-pub fn order_keystrokes<'a>(packet_infos: &mut Vec<PacketInfo<'a>>, keystroke_size: u32) -> Vec<PacketInfo<'a>> {
+///
+/// Packets are only ever taken from the front or from a few positions after it, so `packet_infos`
+/// is drained into a [VecDeque], where that is constant time, keeping the ordering linear in the
+/// number of packets. `packet_infos` is left empty. A keystroke that ends the stream is kept as
+/// the last packet.
+pub fn order_keystrokes(packet_infos: &mut Vec<PacketInfo>, keystroke_size: u32, thresholds: &Thresholds) -> Vec<PacketInfo> {
     log::info!("Ordering keystrokes.");
-    let mut ordered_packets: Vec<PacketInfo<'a>> = Vec::new();
     let size = packet_infos.len();
+    let mut ordered_packets: Vec<PacketInfo> = Vec::with_capacity(size);
+    let mut packet_infos: VecDeque<PacketInfo> = packet_infos.drain(..).collect();
 
     let curr: usize = 0;
     let mut found_match;
@@ -128,40 +723,43 @@ pub fn order_keystrokes<'a>(packet_infos: &mut Vec<PacketInfo<'a>>, keystroke_si
         found_match = false;
 
         if is_keystroke(&packet_infos[curr], keystroke_size) {
-            ordered_packets.push(packet_infos.remove(curr));
+            ordered_packets.extend(packet_infos.remove(curr));
             let mut itr: usize = 0;
             while !found_match && itr < packet_infos.len() && itr < 10 {
                 // Found server echo of keystroke
-                if packet_infos[curr+itr].length == -(keystroke_size as i32) {
+                if packet_infos[curr+itr].is_server_len(keystroke_size as i32) {
                     // We remove it from the original vec and add it to ordered.
                     // This is done so we don't match the same response to multiple forward packets
                     // that might have been sent successively before the first resposne is
                     // intercepted.
-                    ordered_packets.push(packet_infos.remove(curr+itr));
+                    ordered_packets.extend(packet_infos.remove(curr+itr));
                     found_match = true;
                 } 
                 // Echoes are sometimes slightly larger (see scan.rs), so we need to account for that.
-                else if packet_infos[curr+itr].length == -(keystroke_size as i32 + 8) || packet_infos[curr+itr].length == -(keystroke_size as i32 + KEYSTROKE_UPPER_BOUND) {
-                    ordered_packets.push(packet_infos.remove(curr+itr));
+                else if packet_infos[curr+itr].is_server_len(keystroke_size as i32 + thresholds.echo_offset) || packet_infos[curr+itr].is_server_len(keystroke_size as i32 + thresholds.keystroke_upper_bound) {
+                    ordered_packets.extend(packet_infos.remove(curr+itr));
                     found_match = true;
                 }
                 itr += 1;
             }
             if !found_match {
-                ordered_packets.push(packet_infos.remove(curr));
+                ordered_packets.extend(packet_infos.remove(curr));
             }
         } else {
             // If non-keystroke, just add to the ordered vector
-            ordered_packets.push(packet_infos.remove(curr));
+            ordered_packets.extend(packet_infos.remove(curr));
         }
     }
 
     ordered_packets
 }
 
-pub fn order_obfuscated_keystrokes<'a>(packet_infos: &mut Vec<PacketInfo<'a>>, keystroke_size: u32) -> Vec<PacketInfo<'a>> {
+/// Orders the [PacketInfo]s of an obfuscated session like [order_keystrokes], pairing keystrokes
+/// and chaff of half the keystroke size with their echoes, and dropping the second echo of chaff.
+pub fn order_obfuscated_keystrokes(packet_infos: &mut Vec<PacketInfo>, keystroke_size: u32, thresholds: &Thresholds) -> Vec<PacketInfo> {
     log::info!("Ordering obfuscated keystrokes.");
-    let mut ordered_packets: Vec<PacketInfo<'a>> = Vec::new();
+    let mut ordered_packets: Vec<PacketInfo> = Vec::with_capacity(packet_infos.len());
+    let mut packet_infos: VecDeque<PacketInfo> = packet_infos.drain(..).collect();
     //let size = packet_infos.len();
 
     let curr: usize = 0;
@@ -176,46 +774,46 @@ pub fn order_obfuscated_keystrokes<'a>(packet_infos: &mut Vec<PacketInfo<'a>>, k
 
         // Will catch initial keystroke and all the chaff
         if is_keystroke(&packet_infos[curr], keystroke_size / 2) {
-            ordered_packets.push(packet_infos.remove(curr));
+            ordered_packets.extend(packet_infos.remove(curr));
             let mut itr: usize = 0;
             while !found_match && itr < packet_infos.len() && itr < 10 {
                 // Found server echo of keystroke
-                if packet_infos[curr+itr].length == -(keystroke_size as i32  / 2) {
+                if packet_infos[curr+itr].is_server_len(keystroke_size as i32  / 2) {
                     // We remove it from the original vec and add it to ordered.
                     // This is done so we don't match the same response to multiple forward packets
                     // that might have been sent successively before the first resposne is
                     // intercepted.
-                    ordered_packets.push(packet_infos.remove(curr+itr));
+                    ordered_packets.extend(packet_infos.remove(curr+itr));
                     found_match = true;
                 } 
                 // Echoes are sometimes slightly larger (see scan.rs), so we need to account for that.
-                else if packet_infos[curr+itr].length == -((keystroke_size as i32 / 2) + 8) || packet_infos[curr+itr].length == -((keystroke_size as i32 / 2) + KEYSTROKE_UPPER_BOUND) {
-                    ordered_packets.push(packet_infos.remove(curr+itr));
+                else if packet_infos[curr+itr].is_server_len((keystroke_size as i32 / 2) + thresholds.echo_offset) || packet_infos[curr+itr].is_server_len((keystroke_size as i32 / 2) + thresholds.keystroke_upper_bound) {
+                    ordered_packets.extend(packet_infos.remove(curr+itr));
                     found_match = true;
                 }
                 itr += 1;
             }
             if !found_match {
-                ordered_packets.push(packet_infos.remove(curr));
+                ordered_packets.extend(packet_infos.remove(curr));
             }
         } else if is_keystroke(&packet_infos[curr], keystroke_size) {
             // Will catch fat packets
-            ordered_packets.push(packet_infos.remove(curr));
+            ordered_packets.extend(packet_infos.remove(curr));
             fat_packets.push(ordered_packets.len());
 
             // This is zero because we removed the curr so we are looking at the first packet after curr at itr=0
             // Itr basically only increments when we are dealing with consecutive client packets.
             let mut itr: usize = 0;
             while !found_match && itr < packet_infos.len() && itr < 10 {
-                if packet_infos[curr+itr].length == -(keystroke_size as i32 / 2) {
+                if packet_infos[curr+itr].is_server_len(keystroke_size as i32 / 2) {
 
                     // PROBABLY a RET because 3 consecutive stc packets, so don't delete consecutive server-side packets
-                    if packet_infos[curr+1].length < 0 && packet_infos[curr+2].length < 0 {
+                    if packet_infos[curr+1].is_server() && packet_infos[curr+2].is_server() {
                         log::debug!("Probably a RET"); 
-                        while packet_infos[curr+itr].length < 0 {
+                        while packet_infos[curr+itr].is_server() {
                             log::debug!("Adding ret response: {}", packet_infos[curr+itr].seq);
                             // shifts remaining elements left so we don't increment itr
-                            ordered_packets.push(packet_infos.remove(curr+itr));
+                            ordered_packets.extend(packet_infos.remove(curr+itr));
                             //packet_infos.remove(curr+itr);
                         }
                         found_match = true; 
@@ -224,8 +822,8 @@ pub fn order_obfuscated_keystrokes<'a>(packet_infos: &mut Vec<PacketInfo<'a>>, k
                     // look ahead 4 packets to ensure we aren't skipping the actual echo in lieu of chaff
                     // this should actually always just be a +1 lookahead maximum, but better safe, I guess
                     for j in 1..=4 {
-                        if packet_infos[curr+itr+j].length == -((keystroke_size as i32 / 2) + 8) || packet_infos[curr+itr+j].length == -((keystroke_size as i32 / 2) + KEYSTROKE_UPPER_BOUND) {
-                            ordered_packets.push(packet_infos.remove(curr+itr+j));
+                        if packet_infos[curr+itr+j].is_server_len((keystroke_size as i32 / 2) + thresholds.echo_offset) || packet_infos[curr+itr+j].is_server_len((keystroke_size as i32 / 2) + thresholds.keystroke_upper_bound) {
+                            ordered_packets.extend(packet_infos.remove(curr+itr+j));
                             packet_infos.remove(curr+itr);
                             found_match = true;
                             break;
@@ -233,27 +831,27 @@ pub fn order_obfuscated_keystrokes<'a>(packet_infos: &mut Vec<PacketInfo<'a>>, k
                     } 
                     if !found_match {
                         // We remove the echo from the original array, but also the second chaff echo, without adding it to the ordered array.
-                        ordered_packets.push(packet_infos.remove(curr+itr));
+                        ordered_packets.extend(packet_infos.remove(curr+itr));
                         packet_infos.remove(curr+itr);
                         found_match = true;
                     }
                 } 
                 // Echoes are sometimes slightly larger (see scan.rs), so we need to account for that.
-                else if packet_infos[curr+itr].length == -((keystroke_size as i32 / 2) + 8) || packet_infos[curr+itr].length == -((keystroke_size as i32 / 2) + KEYSTROKE_UPPER_BOUND) {
-                    ordered_packets.push(packet_infos.remove(curr+itr));
+                else if packet_infos[curr+itr].is_server_len((keystroke_size as i32 / 2) + thresholds.echo_offset) || packet_infos[curr+itr].is_server_len((keystroke_size as i32 / 2) + thresholds.keystroke_upper_bound) {
+                    ordered_packets.extend(packet_infos.remove(curr+itr));
                     packet_infos.remove(curr+itr);
                     found_match = true;
                 }
                 itr += 1;
             }
             if !found_match {
-                ordered_packets.push(packet_infos.remove(curr));
+                ordered_packets.extend(packet_infos.remove(curr));
             }
         }
         
         else {
             // If non-keystroke, just add to the ordered vector
-            ordered_packets.push(packet_infos.remove(curr));
+            ordered_packets.extend(packet_infos.remove(curr));
         }
     }
     log::debug!("{} ordered packets.", ordered_packets.len());
@@ -275,10 +873,86 @@ pub fn get_message_code(packet: &Packet) -> Option<u32> {
     message_code
 }
 
+/// Unpacks an rtshark Packet to return tshark's `tcp.analysis.ack_rtt` in μs, if it exists.
+///
+/// Only present on packets acknowledging a previously seen segment.
+pub fn get_ack_rtt(packet: &Packet) -> Option<i64> {
+    let tcp_layer = packet.layer_name("tcp")?;
+    let rtt = tcp_layer.metadata("tcp.analysis.ack_rtt")?.value().parse::<f64>().ok()?;
+
+    Some((rtt * 1_000_000.0) as i64)
+}
+
+/// Summarises a series of RTT samples into [JitterStats].
+pub fn jitter_stats(samples: &[i64]) -> JitterStats {
+    if samples.is_empty() {
+        return JitterStats::default();
+    }
+
+    let jitter = if samples.len() > 1 {
+        samples.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum::<i64>() / (samples.len() as i64 - 1)
+    } else {
+        0
+    };
+
+    JitterStats {
+        samples: samples.len(),
+        mean_rtt: samples.iter().sum::<i64>() / samples.len() as i64,
+        min_rtt: *samples.iter().min().unwrap(),
+        max_rtt: *samples.iter().max().unwrap(),
+        jitter,
+    }
+}
+
+/// Finds the sample indexes at which the RTT shifts abruptly and stays shifted.
+///
+/// Compares the median of the `path_change_window` samples before an index to the median of the
+/// ones from it onward. Single spikes do not move the median, so only sustained shifts (route
+/// change, VPN toggle, proxy insertion) are reported.
+pub fn find_latency_shifts(samples: &[i64], thresholds: &Thresholds) -> Vec<usize> {
+    let window = thresholds.path_change_window;
+    let mut shifts = Vec::new();
+    let mut index = window;
+
+    while index + window <= samples.len() {
+        let before = median(&samples[index - window..index]).max(1);
+        let after = median(&samples[index..index + window]).max(1);
+        let ratio = before.max(after) as f64 / before.min(after) as f64;
+
+        if ratio >= thresholds.path_change_ratio && (after - before).abs() >= thresholds.path_change_min_delta {
+            // The medians flip once half of the window is shifted, so the first shifted sample
+            // lies somewhere within the window after `index`.
+            let shift = (index..index + window)
+                .find(|&i| (samples[i] - after).abs() < (samples[i] - before).abs())
+                .unwrap_or(index);
+            shifts.push(shift);
+            // Don't report the same shift again for the following samples.
+            index = shift + window;
+            continue;
+        }
+
+        index += 1;
+    }
+
+    shifts
+}
+
+/// Median of a slice of values. Returns 0 for an empty slice.
+pub fn median(values: &[i64]) -> i64 {
+    if values.is_empty() {
+        return 0;
+    }
+
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+
+    sorted[sorted.len() / 2]
+}
+
 /// Checks if a [PacketInfo] is a keystroke.
 /// Probably a stupid method now that I look at it... only used once.
 fn is_keystroke(packet: &PacketInfo, keystroke_size: u32) -> bool {
-    packet.length == keystroke_size as i32
+    packet.is_client_len(keystroke_size as i32)
 }
 
 /// MD5 Hash for HASSSH calculations. 
@@ -324,4 +998,190 @@ pub fn is_obfuscated(client: &str, server: &str) -> bool {
         }
     }
     return clientv && serverv;
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::containers::tests::{keystroke, records};
+    use crate::analyser::containers;
+    use crate::analyser::options::Thresholds;
+    use crate::analyser::packet::Packet;
+
+    #[test]
+    fn test_error_rate() {
+        let keystrokes = vec![
+            keystroke(containers::KeystrokeType::Keystroke, 0),
+            keystroke(containers::KeystrokeType::Delete, 0),
+            keystroke(containers::KeystrokeType::Chaff, 0),
+            keystroke(containers::KeystrokeType::Keystroke, 0),
+            keystroke(containers::KeystrokeType::Enter, 0),
+        ];
+
+        assert_eq!(error_rate(&keystrokes), 0.25);
+        assert_eq!(error_rate(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_input_bursts() {
+        // Keystrokes at the given times (ms), numbered by their seq
+        let keystrokes = |times: &[i64]| times.iter().enumerate().map(|(seq, ms)| containers::Keystroke {
+            timestamp: ms * 1000,
+            seq: seq as i64,
+            ..keystroke(containers::KeystrokeType::Keystroke, 0)
+        }).collect::<Vec<_>>();
+        let seqs = |keystrokes: &[containers::Keystroke]| keystrokes.iter().map(|keystroke| keystroke.seq).collect::<Vec<_>>();
+
+        // Typing, mouse reports every 20ms, a fast but short run, and arrow keys held at exactly
+        // the latency bound, which ends the stream
+        let mut times = vec![0, 150, 300];
+        times.extend((0..10).map(|n| 1000 + n * 20));
+        times.extend((0..INPUT_BURST_MIN_LENGTH as i64 - 1).map(|n| 3000 + n * 20));
+        times.extend((0..INPUT_BURST_MIN_LENGTH as i64).map(|n| 5000 + n * INPUT_BURST_MAX_LATENCY / 1000));
+
+        let (typed, bursts) = separate_input_bursts(keystrokes(&times));
+        assert_eq!(2, bursts.len());
+        assert_eq!((3..13).collect::<Vec<_>>(), seqs(&bursts[0]));
+        assert_eq!((20..28).collect::<Vec<_>>(), seqs(&bursts[1]));
+        assert_eq!([0, 1, 2].into_iter().chain(13..20).collect::<Vec<_>>(), seqs(&typed));
+
+        let (typed, bursts) = separate_input_bursts(keystrokes(&[0, 200, 400]));
+        assert_eq!((3, 0), (typed.len(), bursts.len()));
+        assert_eq!((0, 0), (separate_input_bursts(vec![]).0.len(), separate_input_bursts(vec![]).1.len()));
+    }
+
+    #[test]
+    fn test_symbol_heavy() {
+        let typed = |latency_us| keystroke(containers::KeystrokeType::Keystroke, latency_us);
+
+        // Steady typing with a single thinking pause.
+        let plain: Vec<_> = [0, 100_000, 110_000, 95_000, 105_000, 100_000, 900_000, 98_000].into_iter().map(typed).collect();
+        // Every third digraph slowed down by Shift, e.g. `|` and `>`.
+        let symbols: Vec<_> = [0, 100_000, 210_000, 95_000, 105_000, 190_000, 100_000, 220_000].into_iter().map(typed).collect();
+
+        assert!(!is_symbol_heavy(&plain));
+        assert!(is_symbol_heavy(&symbols));
+        assert!(!is_symbol_heavy(&symbols[..4]));
+    }
+
+    #[test]
+    fn test_classify_input() {
+        use containers::InputSource;
+        let sequence = |latencies: &[i64]| latencies.iter().map(|&latency| keystroke(containers::KeystrokeType::Keystroke, latency)).collect::<Vec<_>>();
+
+        assert_eq!(InputSource::Human, classify_input(&sequence(&[0, 140_000, 95_000, 210_000, 120_000, 180_000])));
+        assert_eq!(InputSource::Scripted, classify_input(&sequence(&[0, 100_000, 101_000, 99_000, 100_500, 100_000])));
+        assert_eq!(InputSource::Pasted, classify_input(&sequence(&[0, 2_000, 1_500, 3_000, 2_500, 250_000])));
+        assert_eq!(InputSource::Unknown, classify_input(&sequence(&[0, 100_000, 100_000])));
+    }
+
+    #[test]
+    fn test_command_length() {
+        use containers::KeystrokeType::*;
+        let sequence = |types: Vec<containers::KeystrokeType>| types.into_iter().map(|k_type| keystroke(k_type, 0)).collect::<Vec<_>>();
+
+        // `lss`, Backspace, ` -l`: "ls -l"
+        let length = command_length(&sequence(vec![Keystroke, Keystroke, Keystroke, Delete, Keystroke, Keystroke, Keystroke, Enter]));
+        assert_eq!(containers::CommandLength { estimate: 5, min: 5, max: Some(5), edit_distance: 1 }, length);
+        assert_eq!("5", length.to_string());
+
+        // Deleting more than was typed, then an Unknown keystroke and a completion
+        let length = command_length(&sequence(vec![Keystroke, Delete, Delete, Keystroke, Keystroke, Unknown, Tab, Enter]));
+        assert_eq!(containers::CommandLength { estimate: 1, min: 1, max: None, edit_distance: 2 }, length);
+        assert_eq!("~1 (1+)", length.to_string());
+    }
+
+    #[test]
+    fn test_correct_timestamps() {
+        use containers::Direction::{ClientToServer as C, ServerToClient as S};
+
+        // A record from a clock that runs behind, and one as late as the record before it
+        let mut packet_infos = records(&[(C, 36), (S, 36), (C, 36), (S, 36), (C, 36), (S, 36), (C, 36)]);
+        for (packet_info, timestamp) in packet_infos.iter_mut().zip([100, 50, 60, 200, 200, 150, 300]) {
+            packet_info.timestamp = timestamp;
+        }
+        assert_eq!((vec![1, 2, 5], 1), correct_timestamps(&mut packet_infos));
+        assert_eq!(vec![100, 100, 100, 200, 200, 200, 300], packet_infos.iter().map(|packet_info| packet_info.timestamp).collect::<Vec<_>>());
+
+        // Corrected timestamps stay put, as duplicates of their predecessor's
+        assert_eq!((vec![], 4), correct_timestamps(&mut packet_infos));
+        assert_eq!((vec![], 0), correct_timestamps(&mut []));
+    }
+
+    #[test]
+    fn test_latency_shifts() {
+        // Stable ~10ms path that jumps to ~40ms (e.g. VPN enabled), with a single spike before.
+        let mut samples = vec![10_000, 10_200, 9_900, 10_100, 60_000, 10_000, 9_800, 10_300, 10_100, 10_000];
+        samples.extend(vec![40_000, 41_000, 39_500, 40_200, 40_100, 39_900, 40_300, 40_000, 40_500]);
+
+        let shifts = find_latency_shifts(&samples, &Thresholds::default());
+        assert_eq!(vec![10], shifts);
+
+        let stats = jitter_stats(&samples[..10]);
+        assert_eq!(10, stats.samples);
+        assert_eq!(60_000, stats.max_rtt);
+    }
+
+    #[test]
+    fn test_direction_marking() {
+        use crate::analyser::packet::Layer;
+
+        // Client on a low port, server on a high one, as with `ssh -p 2222` from a privileged port
+        let packet = |source: u16, destination: u16, flags: Option<(u8, u8)>, banner: bool| {
+            let mut packet = Packet::new(Some(0));
+            let mut ip = Layer::new("ip");
+            ip.add("ip.src", if source == 1022 { "10.0.0.2" } else { "10.0.0.1" });
+            ip.add("ip.dst", if destination == 1022 { "10.0.0.2" } else { "10.0.0.1" });
+            packet.push(ip);
+            let mut tcp = Layer::new("tcp");
+            tcp.add("tcp.srcport", source);
+            tcp.add("tcp.dstport", destination);
+            if let Some((syn, ack)) = flags {
+                tcp.add("tcp.flags.syn", syn);
+                tcp.add("tcp.flags.ack", ack);
+            }
+            packet.push(tcp);
+            if banner {
+                let mut ssh = Layer::new("ssh");
+                ssh.add("ssh.protocol", "SSH-2.0-OpenSSH_9.6");
+                packet.push(ssh);
+            }
+            packet
+        };
+
+        let mut servers = ServerMap::new();
+        // Handshake: the SYN/ACK comes from the server
+        let mut syn_ack = packet(2222, 1022, Some((1, 1)), false);
+        mark_direction(&mut servers, 0, &mut syn_ack);
+        let mut reply = packet(2222, 1022, None, false);
+        mark_direction(&mut servers, 0, &mut reply);
+        assert!(is_server_packet(&syn_ack));
+        assert!(is_server_packet(&reply));
+
+        // No handshake: the client's banner comes first
+        let mut banner = packet(1022, 2222, None, true);
+        mark_direction(&mut servers, 1, &mut banner);
+        let mut reply = packet(2222, 1022, None, false);
+        mark_direction(&mut servers, 1, &mut reply);
+        assert!(!is_server_packet(&banner));
+        assert!(is_server_packet(&reply));
+
+        // Neither: the lower port is taken for the server
+        let mut unknown = packet(2222, 1022, None, false);
+        mark_direction(&mut servers, 2, &mut unknown);
+        assert_eq!(None, unknown.from_server());
+        assert!(!is_server_packet(&unknown));
+    }
+
+    #[test]
+    fn test_order_keystrokes_interleaved() {
+        use containers::Direction::{ClientToServer as C, ServerToClient as S};
+
+
+        // The second echo arrives after the third keystroke; the last keystroke ends the stream
+        let mut packet_infos = records(&[(S, 100), (C, 36), (S, 36), (C, 36), (C, 36), (S, 36), (S, 36), (C, 36)]);
+        let ordered = order_keystrokes(&mut packet_infos, 36, &Thresholds::default());
+        assert!(packet_infos.is_empty());
+        assert_eq!(vec![0, 1, 2, 3, 5, 4, 6, 7], ordered.iter().map(|packet_info| packet_info.index).collect::<Vec<_>>());
+    }
+}
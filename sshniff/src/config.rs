@@ -0,0 +1,204 @@
+//! Configuration file, for tuning the heuristics and setting defaults without recompiling.
+//!
+//! Read from `--config`, or else from `$XDG_CONFIG_HOME/sshniff/config.toml` (by default
+//! `~/.config/sshniff/config.toml`) if it exists. Every key is optional and command-line flags
+//! take precedence over the file:
+//!
+//! ```toml
+//! [thresholds]
+//! keystroke_upper_bound = 16
+//! chaff_gap_ms = 35
+//! rsa_offer = [492, 500]
+//!
+//! [analysis]
+//! self_tune = true
+//! skip = ["transfers"]
+//!
+//! [output]
+//! output_dir = "results"
+//! format = "json"
+//! ```
+use crate::analyser::options::{Engine, Scan, Thresholds};
+use serde::Deserialize;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+/// Contents of a configuration file.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub thresholds: ThresholdConfig,
+    pub analysis: AnalysisConfig,
+    pub output: OutputConfig,
+}
+
+/// `[thresholds]`: replacements for the heuristic [Thresholds].
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThresholdConfig {
+    pub keystroke_upper_bound: Option<i32>,
+    pub echo_offset: Option<i32>,
+    pub prompt_tolerance: Option<i32>,
+    pub path_change_window: Option<usize>,
+    pub path_change_ratio: Option<f64>,
+    /// Minimum median RTT change (μs) of a path change
+    pub path_change_min_delta: Option<i64>,
+    pub min_full_segment: Option<i32>,
+    pub max_coalesced_keystrokes: Option<i32>,
+    /// Smallest pause between chaff records that ends a run of chaff, in milliseconds
+    pub chaff_gap_ms: Option<f64>,
+    /// Smallest and largest client record length of an RSA key offer, with chacha20-poly1305
+    pub rsa_offer: Option<[i32; 2]>,
+    pub ed25519_offer: Option<[i32; 2]>,
+    pub ecdsa_offer: Option<[i32; 2]>,
+    pub sk_ed25519_offer: Option<[i32; 2]>,
+    pub sk_ecdsa_offer: Option<[i32; 2]>,
+    pub certificate_offer: Option<[i32; 2]>,
+    /// Smallest and largest client record length of a keyboard-interactive request
+    pub keyboard_interactive_request: Option<[i32; 2]>,
+    /// Smallest and largest server record length of an INFO_REQUEST without prompts
+    pub empty_info_request: Option<[i32; 2]>,
+}
+
+/// `[analysis]`: defaults for the analysis flags of the same names.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalysisConfig {
+    pub keystroke_size: Option<u32>,
+    pub prompt_size: Option<i32>,
+    pub mac_length: Option<i32>,
+    pub obfuscated: Option<bool>,
+    pub signature_length: Option<usize>,
+    /// Idle gap (seconds) to split sessions into activity periods at
+    pub split_idle: Option<f64>,
+    pub self_tune: Option<bool>,
+    pub verbose_kex: Option<bool>,
+    /// Optional scans to skip, added to those given with `--skip`
+    pub skip: Vec<Scan>,
+    pub sample_after: Option<usize>,
+    pub sample_rate: Option<usize>,
+    pub hassh_db: Option<String>,
+    pub advisory_db: Option<String>,
+    pub command_db: Option<String>,
+    pub indicators: Option<String>,
+    pub known_hosts: Option<String>,
+    pub engine: Option<Engine>,
+    /// Ports to dissect SSH on, added to those given with `--ssh-port`
+    pub ssh_ports: Vec<u16>,
+    /// tshark decode-as rules, added to those given with `--decode-as`
+    pub decode_as: Vec<String>,
+}
+
+/// `[output]`: defaults for the output flags of the same names. Formats are given as on the
+/// command line, e.g. `format = "zeek"`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub output_dir: Option<String>,
+    pub format: Option<String>,
+    pub report: Option<String>,
+    pub locale: Option<String>,
+    pub remediation: Option<String>,
+    pub show_remediation: Option<bool>,
+}
+
+impl Config {
+    /// Default location of the configuration file, if a home or config directory is set.
+    pub fn default_path() -> Option<PathBuf> {
+        match std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => Some(PathBuf::from(dir).join("sshniff/config.toml")),
+            None => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/sshniff/config.toml")),
+        }
+    }
+
+    /// Reads the configuration from `path`, or from the [default path](Config::default_path);
+    /// a missing default file yields the empty configuration.
+    pub fn load(path: Option<&str>) -> Result<Self, String> {
+        let path = match (path, Self::default_path()) {
+            (Some(path), _) => PathBuf::from(path),
+            (None, Some(path)) if path.exists() => path,
+            (None, _) => return Ok(Config::default()),
+        };
+
+        let contents = fs::read_to_string(&path).map_err(|err| format!("Failed to read {}: {err}", path.display()))?;
+        Self::parse(&contents).map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    /// Parses the contents of a configuration file.
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let config: Config = toml::from_str(contents).map_err(|err| err.message().to_string())?;
+
+        let thresholds = &config.thresholds;
+        let offers = [
+            ("rsa_offer", thresholds.rsa_offer),
+            ("ed25519_offer", thresholds.ed25519_offer),
+            ("ecdsa_offer", thresholds.ecdsa_offer),
+            ("sk_ed25519_offer", thresholds.sk_ed25519_offer),
+            ("sk_ecdsa_offer", thresholds.sk_ecdsa_offer),
+            ("certificate_offer", thresholds.certificate_offer),
+            ("keyboard_interactive_request", thresholds.keyboard_interactive_request),
+            ("empty_info_request", thresholds.empty_info_request),
+        ];
+        for (name, range) in offers {
+            if let Some([low, high]) = range {
+                if low > high {
+                    return Err(format!("{name}: {low} is larger than {high}"));
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// The default thresholds with the configured ones replaced.
+    pub fn thresholds(&self) -> Thresholds {
+        let config = &self.thresholds;
+        let defaults = Thresholds::default();
+        let range = |range: Option<[i32; 2]>, default: RangeInclusive<i32>| range.map_or(default, |[low, high]| low..=high);
+
+        Thresholds {
+            keystroke_upper_bound: config.keystroke_upper_bound.unwrap_or(defaults.keystroke_upper_bound),
+            echo_offset: config.echo_offset.unwrap_or(defaults.echo_offset),
+            prompt_tolerance: config.prompt_tolerance.unwrap_or(defaults.prompt_tolerance),
+            path_change_window: config.path_change_window.unwrap_or(defaults.path_change_window),
+            path_change_ratio: config.path_change_ratio.unwrap_or(defaults.path_change_ratio),
+            path_change_min_delta: config.path_change_min_delta.unwrap_or(defaults.path_change_min_delta),
+            min_full_segment: config.min_full_segment.unwrap_or(defaults.min_full_segment),
+            max_coalesced_keystrokes: config.max_coalesced_keystrokes.unwrap_or(defaults.max_coalesced_keystrokes),
+            chaff_gap: config.chaff_gap_ms.map_or(defaults.chaff_gap, |gap| (gap * 1000.0) as i64),
+            rsa_offer: range(config.rsa_offer, defaults.rsa_offer),
+            ed25519_offer: range(config.ed25519_offer, defaults.ed25519_offer),
+            ecdsa_offer: range(config.ecdsa_offer, defaults.ecdsa_offer),
+            sk_ed25519_offer: range(config.sk_ed25519_offer, defaults.sk_ed25519_offer),
+            sk_ecdsa_offer: range(config.sk_ecdsa_offer, defaults.sk_ecdsa_offer),
+            certificate_offer: range(config.certificate_offer, defaults.certificate_offer),
+            keyboard_interactive_request: range(config.keyboard_interactive_request, defaults.keyboard_interactive_request),
+            empty_info_request: range(config.empty_info_request, defaults.empty_info_request),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::options::Scan;
+    use crate::analyser::options::Thresholds;
+
+    #[test]
+    fn test_config() {
+        let config = Config::parse("[thresholds]\nchaff_gap_ms = 20.5\nrsa_offer = [480, 510]\nempty_info_request = [40, 48]\n\n[analysis]\nskip = [\"transfers\"]\n\n[output]\nformat = \"zeek\"").unwrap();
+        let thresholds = config.thresholds();
+        assert_eq!(20_500, thresholds.chaff_gap);
+        assert_eq!(480..=510, thresholds.rsa_offer);
+        assert_eq!(Thresholds::default().ed25519_offer, thresholds.ed25519_offer);
+        assert_eq!(40..=48, thresholds.empty_info_request);
+        assert_eq!(Thresholds::default().keyboard_interactive_request, thresholds.keyboard_interactive_request);
+        assert_eq!(vec![Scan::Transfers], config.analysis.skip);
+        assert_eq!(Some("zeek".to_string()), config.output.format);
+
+        assert!(Config::parse("[thresholds]\nrsa_offer = [500, 492]").is_err());
+        assert!(Config::parse("[thresholds]\nkeyboard_interactive_request = [120, 100]").is_err());
+        assert!(Config::parse("[thresholds]\nchaff_gap = 35").is_err());
+    }
+}
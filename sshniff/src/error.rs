@@ -0,0 +1,25 @@
+//! Errors returned by the library.
+use std::fmt;
+
+/// Reasons a capture or session could not be analysed.
+#[derive(Debug)]
+pub enum Error {
+    /// tshark could not be spawned or could not read the capture
+    Capture(String),
+    /// Metadata the analysis depends on is missing from the stream, e.g. the KEX
+    Metadata(&'static str),
+    /// No successful login was found, so the session cannot be split into login and interaction
+    LoginNotFound,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Capture(err) => write!(f, "Failed to read capture: {err}"),
+            Error::Metadata(err) => write!(f, "{err}"),
+            Error::LoginNotFound => write!(f, "Failed to find login packet."),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,30 @@
+//! SSHniff library.
+//!
+//! Extracts metadata and keystroke information from SSH traffic, so other tools can embed the
+//! analysis instead of shelling out to the CLI.
+//!
+//! ```no_run
+//! use sshniff::{load_capture, AnalysisOptions, SshSession};
+//!
+//! let (streams, tcp) = load_capture("capture.pcapng", -1)?;
+//! let options = AnalysisOptions::default();
+//! for (stream_id, packets) in &streams {
+//!     let session = SshSession::analyse(*stream_id, packets, &tcp.get(stream_id).cloned().unwrap_or_default(), &options)?;
+//!     println!("{session}");
+//! }
+//! # Ok::<(), sshniff::Error>(())
+//! ```
+//!
+//! The [prelude] wraps these steps behind an API that is kept stable across releases; prefer it
+//! over the modules below unless you need their internals.
+pub mod analyser;
+pub mod config;
+pub mod error;
+pub mod store;
+pub mod ui;
+pub mod prelude;
+
+pub use analyser::core::{analyse, SshSession};
+pub use analyser::options::{AnalysisOptions, AnalysisOptionsBuilder, Scan, Thresholds, Overrides};
+pub use analyser::utils::load_capture;
+pub use error::Error;
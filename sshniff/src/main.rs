@@ -1,16 +1,52 @@
 //! Entrypoint to SSHniff. 
 //! 
 //! Contains [clap] argument parsing and [simple_logger] setup. 
-//! Calls [analyse] and feeds output to [ui] for handling. 
-mod analyser;
-mod ui;
-
-use analyser::core::{analyse, SshSession};
-use clap::{Parser, ArgAction};
+//! Calls [analyse] and feeds output to [output] for handling. 
+use sshniff::analyser;
+use sshniff::analyser::containers::TcpMeta;
+use sshniff::analyser::software::HasshDb;
+use sshniff::analyser::advisories::AdvisoryDb;
+use sshniff::analyser::commands::CommandDb;
+use sshniff::analyser::intel::IndicatorSet;
+use sshniff::analyser::knownhosts::KnownHosts;
+use sshniff::analyser::sampling;
+use sshniff::analyser::filter::{self, StreamFilter};
+use sshniff::analyser::rollover;
+use sshniff::analyser::options::{Decoding, Engine};
+use sshniff::analyser::hostkey;
+use sshniff::{AnalysisOptions, Scan, SshSession};
+use sshniff::analyser::tags::Tag;
+use sshniff::analyser::compare::{Posture, compare_postures, compare_sessions};
+use sshniff::analyser::nat::nat_reports;
+use sshniff::analyser::audit::audit_endpoints;
+use sshniff::analyser::spray;
+use sshniff::analyser::operators::{link_operators, OperatorFingerprint, LINK_MIN_SCORE};
+use sshniff::analyser::health::check_capture;
+use sshniff::analyser::discovery::list_streams;
+use sshniff::store::query::Query;
+#[cfg(feature = "sqlite")]
+use sshniff::store::sqlite;
+#[cfg(feature = "elastic")]
+use sshniff::store::elastic::ElasticSink;
+use sshniff::store::history;
+use sshniff::store::batch::{self, Checkpoint, CHECKPOINT_FILE};
+#[cfg(feature = "inference")]
+use sshniff::analyser::inference;
+#[cfg(feature = "tui")]
+use sshniff::ui::tui;
+#[cfg(feature = "s3")]
+use sshniff::store::s3::{S3Client, S3Location};
+use sshniff::analyser::compression::{self, CaptureFile};
+use sshniff::ui::{eve, formats::{self, SiemFormat}, html, output, pcapng, pdf, report::Provenance, zeek, catalog::Catalog, remediation::RemediationSet};
+use sshniff::ui::syslog::{SyslogSender, Transport};
+use sshniff::ui::progress::Progress;
+use clap::{Parser, Subcommand, ValueEnum, ArgAction};
 use log::LevelFilter;
 use simple_logger::SimpleLogger;
-use ui::output;
-use std::{collections::HashMap, fs};
+use rayon::prelude::*;
+use sshniff::config::{Config, OutputConfig};
+use sshniff::error::Error;
+use std::{collections::{BTreeMap, HashSet}, fs, path::Path, sync::Mutex, time::Duration};
 
 /// SSHniff is a packet forensics tool for SSH
 #[derive(Parser, Debug)]
@@ -21,12 +57,47 @@ use std::{collections::HashMap, fs};
     about = "Analyses SSH traffic metadata", 
     long_about = "SSHniff is a packet forensics tool for SSH.\n\nIt uses traffic analysis to extract metadata from a PCAP/NG intercept. The extracted data currently includes general session information such as KEX and encryption algorithms used, HASSH values, and server/client protocol versions; it also includes observed keystroke-related information, such as the individual keystroke timings and their latencies.\n\nKeystrokes are also classified into different types, such as Backspace, Enter (Return), and Arrow keys, all done using packet size analysis. Keystrokes are ordered into sequences, which are delimited by a Return keystroke, which also stores the server's response size which indicates the magnitude of the returned data.\n\nFor more information on methods and how to analyse the data, refer to this tool's repository.",
     before_help = "GNU General Public License v3.0",
+    subcommand_negates_reqs = true,
 )]
-struct Args {
-    /// pcap/pcapng file to analyze
-    #[arg(short = 'f', long, value_parser)]
-    file: String,
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    global: GlobalArgs,
+
+    /// Without a subcommand, the arguments are those of `analyse`
+    #[command(flatten)]
+    analyse: AnalyseArgs,
+}
+
+/// Arguments accepted by every subcommand.
+#[derive(clap::Args, Debug)]
+struct GlobalArgs {
+    /// Display output as JSON (prints to STDOUT)
+    #[arg(short = 'j', long, global = true, action = ArgAction::SetTrue)]
+    json: bool,
+
+    /// Set the debug level (Off, Error, Warn, Info, Debug, Trace)
+    #[arg(short = 'd', long, global = true, default_value_t = LevelFilter::Info, value_parser = parse_level_filter)]
+    debug: LevelFilter, 
+
+    /// Number of streams to analyse in parallel (0 = one per CPU)
+    #[arg(long, global = true, default_value_t = 0, value_parser)]
+    jobs: usize,
+
+    /// Do not report loading and analysis progress on STDERR (always off with machine-readable output)
+    #[arg(long, global = true)]
+    no_progress: bool,
 
+    /// Configuration file with thresholds and defaults (TOML); defaults to ~/.config/sshniff/config.toml if it exists. Flags take precedence
+    #[arg(long, global = true, value_parser)]
+    config: Option<String>,
+}
+
+/// How streams are analysed, for every subcommand that analyses captures.
+#[derive(clap::Args, Debug)]
+struct AnalysisArgs {
     /// Perform analysis only on stream n
     #[arg(short, long, default_value_t = -1, value_parser)]
     nstream: i32,
@@ -35,6 +106,113 @@ struct Args {
     #[arg(short = 'm', long, action = ArgAction::SetTrue)]
     metaonly: bool,
 
+    /// Hardcode the keystroke length (tcp.len)
+    #[arg(short = 's', long, value_parser)]
+    keystroke_size: Option<u32>,
+
+    /// Hardcode the login prompt length (tcp.len)
+    #[arg(long, value_parser)]
+    prompt_size: Option<i32>,
+
+    /// Hardcode the MAC length appended to each SSH record
+    #[arg(long, value_parser)]
+    mac_length: Option<i32>,
+
+    /// Force keystroke obfuscation handling on or off (true, false)
+    #[arg(long, value_parser)]
+    obfuscated: Option<bool>,
+
+    /// Number of records encoded in the size/direction signature [default: 32]
+    #[arg(long, value_parser)]
+    signature_length: Option<usize>,
+
+    /// Split sessions into activity periods at idle gaps of at least this many seconds
+    #[arg(long, value_parser)]
+    split_idle: Option<f64>,
+
+    /// Skip optional scans (comma-separated)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    skip: Vec<Scan>,
+
+    /// Derive size thresholds (echo offset, keystroke upper bound, prompt tolerance) from each session instead of using the fixed ones
+    #[arg(long, action = ArgAction::SetTrue)]
+    self_tune: bool,
+
+    /// Keep and output the complete client and server KEXINIT algorithm lists (KEX, host key, ciphers, MACs, compression), not just the negotiated ones
+    #[arg(long, action = ArgAction::SetTrue)]
+    verbose_kex: bool,
+
+    /// Approximate mode for bulk tunnels: analyse only the first N SSH packets of each stream exactly and sample the rest for volume and timing statistics
+    #[arg(long, value_parser)]
+    sample_after: Option<usize>,
+
+    /// With --sample-after, keep the timestamp of one in this many packets past the threshold [default: 100]
+    #[arg(long, value_parser)]
+    sample_rate: Option<usize>,
+
+    /// HASSH database (side,hash,software lines) to identify clients and servers with, on top of the bundled one
+    #[arg(long, value_parser)]
+    hassh_db: Option<String>,
+
+    /// Advisory table (product,side,introduced,fixed,id,cvss,summary lines) to match the banner versions against, on top of the bundled one
+    #[arg(long, value_parser)]
+    advisory_db: Option<String>,
+
+    /// Response-size fingerprints (command,min_response,max_response lines) to guess submitted commands with, on top of the bundled ones
+    #[arg(long, value_parser)]
+    command_db: Option<String>,
+
+    /// Threat-intel indicators (HASSH values, IPs, CIDR ranges) as CSV, or JSON with a .json extension; matching sessions are flagged
+    #[arg(long, value_parser)]
+    indicators: Option<String>,
+
+    /// OpenSSH known_hosts file to verify the server host keys against
+    #[arg(long, value_parser)]
+    known_hosts: Option<String>,
+
+    /// Only load sessions whose client is this IP or CIDR range; repeatable
+    #[arg(long, value_parser = filter::parse_network)]
+    src: Vec<(std::net::IpAddr, u8)>,
+
+    /// Only load sessions whose server is this IP or CIDR range; repeatable
+    #[arg(long, value_parser = filter::parse_network)]
+    dst: Vec<(std::net::IpAddr, u8)>,
+
+    /// Only load sessions using this port on either end; repeatable
+    #[arg(long, value_parser)]
+    port: Vec<u16>,
+
+    /// Only load sessions starting at or after this time (UTC `2024-01-31 12:00:00`, a date, RFC 3339, or UNIX seconds)
+    #[arg(long, value_parser = filter::parse_time)]
+    from: Option<i64>,
+
+    /// Only load sessions starting at or before this time; same formats as --from
+    #[arg(long, value_parser = filter::parse_time)]
+    to: Option<i64>,
+
+    /// Dissector to read captures with; `native` needs no tshark and falls back to it for link types it cannot read [default: tshark]
+    #[arg(long, value_enum)]
+    engine: Option<Engine>,
+
+    /// Dissect SSH on this TCP port too, e.g. 2222 or 443 (comma-separated, repeatable)
+    #[arg(long, value_parser, value_delimiter = ',')]
+    ssh_port: Vec<u16>,
+
+    /// tshark decode-as rule, e.g. `tcp.port==8443,ssh`; repeatable
+    #[arg(long, value_parser)]
+    decode_as: Vec<String>,
+}
+
+/// Arguments of `analyse`, `export`, and `watch`: the capture, and where the results go.
+#[derive(clap::Args, Debug)]
+struct AnalyseArgs {
+    /// pcap/pcapng file to analyze, optionally gzip, zstd, or xz-compressed, or `-` for standard input; `s3://bucket/key` with the `s3` feature. A directory, glob (`host1.pcap*`), or `s3://bucket/prefix/` is analysed capture by capture; repeatable
+    #[arg(short = 'f', long, value_parser, required = true, num_args = 1..)]
+    file: Vec<String>,
+
+    #[command(flatten)]
+    analysis: AnalysisArgs,
+
     /// Only save keystroke-related data. No effect on STDOUT unless `--json` is used. 
     #[arg(short = 'k', long, action = ArgAction::SetTrue)]
     keystrokes: bool,
@@ -43,17 +221,241 @@ struct Args {
     #[arg(short = 'o', long, value_parser)]
     output_dir: Option<String>,
 
-    /// Display output as JSON (prints to STDOUT)
-    #[arg(short = 'j', long, action = ArgAction::SetTrue)]
+    /// Only output sessions with all of the given tags (comma-separated)
+    #[arg(short = 't', long, value_enum, value_delimiter = ',')]
+    tag: Vec<Tag>,
+
+    /// SQLite corpus to append the analysed sessions to (created if missing)
+    #[arg(long, value_parser)]
+    sqlite: Option<String>,
+
+    /// Checkpoint of a batch run, to resume it from if interrupted; defaults to sshniff_checkpoint.tsv in the output directory
+    #[arg(long, value_parser)]
+    checkpoint: Option<String>,
+
+    /// S3 location (`s3://bucket/prefix/`) to upload the session JSON to; needs the `s3` feature, credentials are read from AWS_* variables
+    #[arg(long, value_parser)]
+    upload: Option<String>,
+
+    /// Infer likely typed character classes and dictionary words from keystroke latencies, written to <capture>_inference.json in the output directory; needs the `inference` feature
+    #[arg(long)]
+    infer: bool,
+
+    /// Elasticsearch/OpenSearch cluster (e.g. http://localhost:9200) to index sessions and keystrokes in
+    #[arg(long, value_parser)]
+    elastic_url: Option<String>,
+
+    /// Index for `--elastic-url`
+    #[arg(long, value_parser, default_value = "sshniff")]
+    index: String,
+
+    /// Syslog server (host:port) to send each session summary and timeline event to as CEF
+    #[arg(long, value_parser)]
+    syslog: Option<String>,
+
+    /// Transport for `--syslog`
+    #[arg(long, value_enum, default_value_t = Transport::Udp)]
+    syslog_transport: Transport,
+
+    /// Output format; `json` is the same as `-j`, `csv` outputs one row per keystroke, `zeek` outputs ssh.log and sshniff.log, `eve` outputs Suricata EVE JSON lines, `cef`/`leef` output SIEM messages
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+
+    /// Print each session to STDOUT as a line of JSON as soon as it is analysed, rather than all sessions at the end (only keystrokes with `-k`); host key changes between sessions are left out
+    #[arg(long, conflicts_with_all = ["format", "tui"])]
+    jsonl: bool,
+
+    /// With `--jsonl`, follow each session with a line per finding
+    #[arg(long, requires = "jsonl")]
+    jsonl_events: bool,
+
+    /// Write a report per session to the output directory
+    #[arg(long, value_enum)]
+    report: Option<Report>,
+
+    /// Write the capture to the output directory as <capture>_annotated.pcapng, with each classified packet commented for Wireshark
+    #[arg(long)]
+    annotate: bool,
+
+    /// Treat the captures as consecutive rotated parts of one capture, and stitch sessions spanning them back together
+    #[arg(long, conflicts_with = "annotate")]
+    rollover: bool,
+
+    /// Language of finding descriptions on the console and in reports: `en`, or a message catalog file (ID = description lines; see data/messages/en.txt) [default: en]
+    #[arg(long, value_parser)]
+    locale: Option<String>,
+
+    /// Remediation guidance replacing or adding to the bundled guidance, by finding ID (JSON; see data/remediation/en.json)
+    #[arg(long, value_parser)]
+    remediation: Option<String>,
+
+    /// Print remediation guidance for each session's findings to the console
+    #[arg(long)]
+    show_remediation: bool,
+
+    /// Browse the sessions in an interactive terminal viewer instead of printing them; needs the `tui` feature
+    #[arg(long)]
+    tui: bool,
+}
+
+/// Machine-readable output formats, printed to STDOUT and written to the output directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// All session data, or only keystrokes with `-k`
+    Json,
+    /// Keystrokes only, one row each
+    Csv,
+    /// Zeek ssh.log, plus sshniff.log with findings and commands
+    Zeek,
+    /// Suricata EVE JSON, one event per line
+    Eve,
+    /// ArcSight CEF, one message per session and per finding
+    Cef,
+    /// QRadar LEEF, one message per session and per finding
+    Leef,
+}
+
+/// Texts findings are described with.
+struct Texts {
+    catalog: Catalog,
+    remediation: RemediationSet,
+}
+
+/// Output formats selected on the command line.
+struct Outputs {
     json: bool,
+    csv: bool,
+    zeek: bool,
+    eve: bool,
+    siem: Option<SiemFormat>,
+    /// Nothing but the selected format is printed to STDOUT
+    machine_readable: bool,
+    /// JSON or CSV of all captures is printed at once, keyed by capture and stream
+    aggregate: bool,
+    /// Status of the capture being analysed, on STDERR
+    progress: Progress,
+    /// Sink of `--elastic-url`, shared by all captures of the run
+    #[cfg(feature = "elastic")]
+    elastic: Option<ElasticSink>,
+}
 
-    /// Set the debug level (Off, Error, Warn, Info, Debug, Trace)
-    #[arg(short = 'd', long, default_value_t = LevelFilter::Info, value_parser = parse_level_filter)]
-    debug: LevelFilter, 
+/// Per-session report formats.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Report {
+    /// Standalone HTML page
+    Html,
+    /// Paginated PDF, stamped with the capture and report hashes, with a provenance appendix
+    Pdf,
+}
 
-    /// Hardcode the keystroke length (tcp.len)
-    #[arg(short = 's', long, default_value_t = 0, value_parser)]
-    keystroke_size: u32,
+/// Seconds between looks for new captures in `watch`.
+const WATCH_INTERVAL: u64 = 30;
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Analyse a capture, or a directory of captures; the same as leaving out the subcommand
+    Analyse(AnalyseArgs),
+    /// List the SSH streams of a capture (endpoints, packets, start, banners) without analysing them, to pick one for `-n`
+    List {
+        /// pcap/pcapng file to list
+        #[arg(short = 'f', long)]
+        file: String,
+
+        /// Dissect SSH on this TCP port too, e.g. 2222 or 443 (comma-separated, repeatable)
+        #[arg(long, value_parser, value_delimiter = ',')]
+        ssh_port: Vec<u16>,
+    },
+    /// Analyse a capture and output it only in a machine-readable format, to STDOUT and the output directory
+    Export {
+        /// Format to export to; takes precedence over `--format`
+        #[arg(value_enum)]
+        to: Format,
+        #[command(flatten)]
+        args: AnalyseArgs,
+    },
+    /// Analyse the captures in a directory or under an `s3://` prefix, then keep analysing new ones as they appear
+    Watch {
+        /// Seconds between looks for new captures; local captures modified more recently are left for the next look
+        #[arg(long, default_value_t = WATCH_INTERVAL)]
+        interval: u64,
+        #[command(flatten)]
+        args: AnalyseArgs,
+    },
+    /// Compare two sessions (metadata, timeline, keystroke statistics) from captures or session JSON, or with `--server` a server's SSH posture between two captures
+    #[command(alias = "compare-captures")]
+    Compare {
+        /// Capture or session JSON (`-o`/`--json` output) with the first session; the capture from before the change with `--server`
+        old: String,
+        /// Capture or session JSON with the second session, if not in the first; the capture from after the change with `--server`
+        new: Option<String>,
+        /// IP of the server to compare the posture of
+        #[arg(long, requires = "new")]
+        server: Option<String>,
+        /// Streams of the first and second session (comma-separated), needed where a file holds several
+        #[arg(long, value_delimiter = ',', num_args = 1..=2)]
+        streams: Vec<u32>,
+        #[command(flatten)]
+        analysis: AnalysisArgs,
+    },
+    /// Check that a capture is usable before analysing it (SSH present, both directions, no truncation)
+    Check {
+        /// pcap/pcapng file to check
+        file: String,
+
+        /// Dissector to read the capture with; `native` needs no tshark and falls back to it for link types it cannot read [default: tshark]
+        #[arg(long, value_enum)]
+        engine: Option<Engine>,
+
+        /// Dissect SSH on this TCP port too, e.g. 2222 or 443 (comma-separated, repeatable)
+        #[arg(long, value_parser, value_delimiter = ',')]
+        ssh_port: Vec<u16>,
+
+        /// tshark decode-as rule, e.g. `tcp.port==8443,ssh`; repeatable
+        #[arg(long, value_parser)]
+        decode_as: Vec<String>,
+    },
+    /// Estimate the distinct clients behind client IPs shared by several sessions (e.g. NAT)
+    NatReport {
+        /// pcap/pcapng file to analyze
+        file: String,
+        /// Only report client IPs with at least this many sessions
+        #[arg(long, default_value_t = 2)]
+        min_sessions: usize,
+        #[command(flatten)]
+        analysis: AnalysisArgs,
+    },
+    /// List the deprecated algorithms (SHA-1, CBC, group1, MD5, ...) each client and server offers, over all its sessions
+    Audit {
+        /// pcap/pcapng file to audit
+        file: String,
+        #[command(flatten)]
+        analysis: AnalysisArgs,
+    },
+    /// Score whether sessions across captures and client IPs belong to the same operator, by client software and typing rhythm
+    LinkOperators {
+        /// pcap/pcapng files to analyze
+        #[arg(required_unless_present = "db")]
+        files: Vec<String>,
+        /// Only report pairs scoring at least this (0 to 1)
+        #[arg(long, default_value_t = LINK_MIN_SCORE)]
+        min_score: f64,
+        /// Also score the sessions stored in this SQLite corpus (`--sqlite`)
+        #[arg(long)]
+        db: Option<String>,
+        /// Also pair sessions of the same client IP within a capture, e.g. several operators behind a jump host
+        #[arg(long, action = ArgAction::SetTrue)]
+        same_client: bool,
+        #[command(flatten)]
+        analysis: AnalysisArgs,
+    },
+    /// Run a canned query over a SQLite corpus
+    Query {
+        /// SQLite corpus file
+        #[arg(long)]
+        db: String,
+        #[command(subcommand)]
+        query: Query,
+    },
 }
 
 fn parse_level_filter(s: &str) -> Result<LevelFilter, String> {
@@ -61,17 +463,378 @@ fn parse_level_filter(s: &str) -> Result<LevelFilter, String> {
 }
 
 fn main() {
-    let args = Args::parse();
-    let debug_level: LevelFilter;
+    let cli = Cli::parse();
+    // Errors are reported once logging is set up
+    let config = Config::load(cli.global.config.as_deref());
+
+    // `export` is `analyse` with a format, `watch` is `analyse` that keeps looking for captures
+    let (args, watch) = match cli.command {
+        None => (cli.analyse, None),
+        Some(Command::Analyse(args)) => (args, None),
+        Some(Command::Export { to, mut args }) => {
+            args.format = Some(to);
+            (args, None)
+        },
+        Some(Command::Watch { interval, args }) => (args, Some(Duration::from_secs(interval))),
+        Some(command) => {
+            init(&cli.global, cli.global.json);
+            run_command(&cli.global, command, &checked(config));
+            return;
+        },
+    };
 
+    analyse(&cli.global, args, watch, config);
+}
+
+/// The configuration file's contents; exits if it cannot be read.
+fn checked(config: Result<Config, String>) -> Config {
+    match config {
+        Ok(config) => config,
+        Err(err) => {
+            log::error!("{err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Sets up logging, off if `quiet`, and the thread pool.
+fn init(global: &GlobalArgs, quiet: bool) {
     // `--json` needs to disable any logging, else it breaks intended piping behaviour (i.e. | jq)
-    if args.json {
-        debug_level = LevelFilter::Off;
+    let debug_level = if quiet { LevelFilter::Off } else { global.debug };
+    SimpleLogger::new().with_level(debug_level).init().unwrap();
+
+    rayon::ThreadPoolBuilder::new().num_threads(global.jobs).build_global().unwrap();
+}
+
+impl AnalysisArgs {
+    /// Builds the analysis options from the flags, falling back to the configuration file, and
+    /// exits if a database or feed cannot be loaded.
+    fn options(&self, config: &Config) -> AnalysisOptions {
+        let defaults = &config.analysis;
+        let mut builder = AnalysisOptions::builder().thresholds(config.thresholds());
+        for scan in self.skip.iter().chain(&defaults.skip) {
+            builder = builder.skip(*scan);
+        }
+        if self.metaonly {
+            builder = builder.skip(Scan::Keystrokes);
+        }
+        if let Some(keystroke_size) = self.keystroke_size.or(defaults.keystroke_size).filter(|&size| size > 0) {
+            builder = builder.keystroke_size(keystroke_size);
+        }
+        if let Some(prompt_size) = self.prompt_size.or(defaults.prompt_size) {
+            builder = builder.prompt_size(prompt_size);
+        }
+        if let Some(mac_length) = self.mac_length.or(defaults.mac_length) {
+            builder = builder.mac_length(mac_length);
+        }
+        if let Some(obfuscated) = self.obfuscated.or(defaults.obfuscated) {
+            builder = builder.obfuscated(obfuscated);
+        }
+        if let Some(seconds) = self.split_idle.or(defaults.split_idle) {
+            builder = builder.idle_split((seconds * 1_000_000.0) as i64);
+        }
+        if self.self_tune || defaults.self_tune == Some(true) {
+            builder = builder.self_tune();
+        }
+        if self.verbose_kex || defaults.verbose_kex == Some(true) {
+            builder = builder.verbose_kex();
+        }
+        if let Some(after) = self.sample_after.or(defaults.sample_after) {
+            builder = builder.sample(after, self.sample_rate.or(defaults.sample_rate).unwrap_or(sampling::SAMPLE_RATE));
+        }
+        builder = builder.filter(StreamFilter {
+            src: self.src.clone(),
+            dst: self.dst.clone(),
+            ports: self.port.clone(),
+            from: self.from,
+            to: self.to,
+        });
+        if let Some(engine) = self.engine.or(defaults.engine) {
+            builder = builder.engine(engine);
+        }
+        for port in self.ssh_port.iter().chain(&defaults.ssh_ports) {
+            builder = builder.ssh_port(*port);
+        }
+        for rule in self.decode_as.iter().chain(&defaults.decode_as) {
+            builder = builder.decode_as(rule);
+        }
+        if let Some(path) = self.hassh_db.as_deref().or(defaults.hassh_db.as_deref()) {
+            match HasshDb::load(path) {
+                Ok(hassh_db) => builder = builder.hassh_db(hassh_db),
+                Err(err) => {
+                    log::error!("{err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        if let Some(path) = self.advisory_db.as_deref().or(defaults.advisory_db.as_deref()) {
+            match AdvisoryDb::load(path) {
+                Ok(advisory_db) => builder = builder.advisory_db(advisory_db),
+                Err(err) => {
+                    log::error!("{err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        if let Some(path) = self.command_db.as_deref().or(defaults.command_db.as_deref()) {
+            match CommandDb::load(path) {
+                Ok(command_db) => builder = builder.command_db(command_db),
+                Err(err) => {
+                    log::error!("{err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        if let Some(path) = self.indicators.as_deref().or(defaults.indicators.as_deref()) {
+            match IndicatorSet::load(path) {
+                Ok(indicators) => {
+                    log::info!("Loaded {} threat indicators from {path}", indicators.len());
+                    builder = builder.indicators(indicators);
+                },
+                Err(err) => {
+                    log::error!("{err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        if let Some(path) = self.known_hosts.as_deref().or(defaults.known_hosts.as_deref()) {
+            match KnownHosts::load(path) {
+                Ok(known_hosts) => {
+                    log::info!("Loaded {} known host keys from {path}", known_hosts.len());
+                    builder = builder.known_hosts(known_hosts);
+                },
+                Err(err) => {
+                    log::error!("{err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        let signature_length = self.signature_length.or(defaults.signature_length).unwrap_or(analyser::utils::SIGNATURE_LENGTH);
+        builder.signature_length(signature_length).build()
+    }
+}
+
+impl AnalyseArgs {
+    /// Fills in the output flags not given from the `[output]` section of the configuration file.
+    fn apply(&mut self, output: &OutputConfig) -> Result<(), String> {
+        if self.output_dir.is_none() {
+            self.output_dir = output.output_dir.clone();
+        }
+        if let (None, Some(format)) = (self.format, output.format.as_deref()) {
+            self.format = Some(Format::from_str(format, true).map_err(|_| format!("Unknown output format in configuration: {format}"))?);
+        }
+        if let (None, Some(report)) = (self.report, output.report.as_deref()) {
+            self.report = Some(Report::from_str(report, true).map_err(|_| format!("Unknown report format in configuration: {report}"))?);
+        }
+        if self.locale.is_none() {
+            self.locale = output.locale.clone();
+        }
+        if self.remediation.is_none() {
+            self.remediation = output.remediation.clone();
+        }
+        self.show_remediation |= output.show_remediation == Some(true);
+        Ok(())
+    }
+}
+
+/// Runs the subcommands other than `analyse`, `export`, and `watch`.
+fn run_command(global: &GlobalArgs, command: Command, config: &Config) {
+    let progress = Progress::new(!global.json && !global.no_progress);
+
+    match &command {
+        Command::Compare { old, new: Some(new), server: Some(server), analysis, .. } => {
+            let options = analysis.options(config);
+            let old_posture = Posture::from_sessions(server, &analyse_file(old, analysis.nstream, &options, &progress, |_| {}));
+            let new_posture = Posture::from_sessions(server, &analyse_file(new, analysis.nstream, &options, &progress, |_| {}));
+            let changes = compare_postures(&old_posture, &new_posture);
+
+            if global.json {
+                println!("{}", output::posture_changes_as_json(&changes).unwrap());
+            } else {
+                output::print_posture_changes(server, &old_posture, &new_posture, &changes);
+            }
+        },
+        Command::Compare { old, new, streams, analysis, .. } => {
+            // Both sessions may come from the same file
+            let second = new.as_deref().unwrap_or(old);
+            let options = analysis.options(config);
+            let first_sessions = load_sessions(old, analysis.nstream, &options, &progress);
+            let second_sessions = if second == old { first_sessions.clone() } else { load_sessions(second, analysis.nstream, &options, &progress) };
+
+            let (first_stream, second_stream) = (streams.first().copied(), streams.get(1).copied());
+            if second == old && (first_stream.is_none() || first_stream == second_stream) {
+                log::error!("Comparing two sessions of {old} needs two different --streams.");
+                std::process::exit(1);
+            }
+            let (first_stream, first) = pick_session(&first_sessions, old, first_stream);
+            let (second_stream, second_session) = pick_session(&second_sessions, second, second_stream);
+            let comparisons = compare_sessions(&first, &second_session);
+
+            if global.json {
+                println!("{}", output::session_comparison_as_json(&comparisons).unwrap());
+            } else {
+                output::print_session_comparison((&format!("stream {first_stream} of {old}"), &format!("stream {second_stream} of {second}")), &comparisons);
+            }
+        },
+        Command::List { file, ssh_port } => {
+            let decoding = Decoding { ssh_ports: ssh_port.clone(), ..Default::default() };
+            let streams = match list_streams(file, &decoding) {
+                Ok(streams) => streams,
+                Err(err) => {
+                    log::error!("{err}");
+                    std::process::exit(1);
+                }
+            };
+
+            if global.json {
+                println!("{}", output::streams_as_json(&streams).unwrap());
+            } else {
+                output::print_streams(&streams);
+            }
+        },
+        Command::Check { file, engine, ssh_port, decode_as } => {
+            let decoding = Decoding { ssh_ports: ssh_port.clone(), decode_as: decode_as.clone() };
+            let report = match check_capture(file, engine.unwrap_or_default(), &decoding) {
+                Ok(report) => report,
+                Err(err) => {
+                    log::error!("{err}");
+                    std::process::exit(1);
+                }
+            };
+
+            if global.json {
+                println!("{}", output::health_report_as_json(&report).unwrap());
+            } else {
+                output::print_health_report(file, &report);
+            }
+            if !report.is_usable() {
+                std::process::exit(1);
+            }
+        },
+        Command::NatReport { file, min_sessions, analysis } => {
+            let reports = nat_reports(&analyse_file(file, analysis.nstream, &analysis.options(config), &progress, |_| {}), *min_sessions);
+
+            if global.json {
+                println!("{}", output::nat_reports_as_json(&reports).unwrap());
+            } else {
+                output::print_nat_reports(&reports);
+            }
+        },
+        Command::Audit { file, analysis } => {
+            let audits = audit_endpoints(&analyse_file(file, analysis.nstream, &analysis.options(config), &progress, |_| {}));
+
+            if global.json {
+                println!("{}", output::audits_as_json(&audits).unwrap());
+            } else {
+                output::print_audits(&audits);
+            }
+        },
+        Command::LinkOperators { files, min_score, db, same_client, analysis } => {
+            if db.is_some() && !cfg!(feature = "sqlite") {
+                log::error!("--db needs a build with the `sqlite` feature.");
+                std::process::exit(1);
+            }
+            let options = analysis.options(config);
+            let mut fingerprints: Vec<OperatorFingerprint> = files.iter()
+                .flat_map(|file| analyse_file(file, analysis.nstream, &options, &progress, |_| {}).values()
+                    .map(|session| OperatorFingerprint::new(file, session))
+                    .collect::<Vec<_>>())
+                .collect();
+            let stored = stored_fingerprints(db.as_deref(), &fingerprints);
+            fingerprints.extend(stored);
+            let links = link_operators(&fingerprints, *min_score, *same_client);
+
+            if global.json {
+                println!("{}", output::links_as_json(&links).unwrap());
+            } else {
+                output::print_links(&links);
+            }
+        },
+        #[cfg(feature = "sqlite")]
+        Command::Query { db, query } => {
+            let table = match sqlite::open(db).and_then(|connection| sqlite::run_query(&connection, query)) {
+                Ok(table) => table,
+                Err(err) => {
+                    log::error!("Query failed: {err}");
+                    std::process::exit(1);
+                }
+            };
+
+            if global.json {
+                println!("{}", serde_json::to_string(&table.to_json()).unwrap());
+            } else {
+                output::print_table(&table);
+            }
+        },
+        #[cfg(not(feature = "sqlite"))]
+        Command::Query { .. } => {
+            log::error!("query needs a build with the `sqlite` feature.");
+            std::process::exit(1);
+        },
+        Command::Analyse(_) | Command::Export { .. } | Command::Watch { .. } => unreachable!("analysed in main"),
+    }
+}
+
+/// Sessions of a session JSON file (`.json`, as written with `-o`) or of a capture, serialised,
+/// by stream. Exits if the file cannot be read.
+fn load_sessions(file: &str, nstream: i32, options: &AnalysisOptions, progress: &Progress) -> serde_json::Map<String, serde_json::Value> {
+    let sessions = if file.ends_with(".json") {
+        fs::read_to_string(file).map_err(|err| err.to_string())
+            .and_then(|json| serde_json::from_str(&json).map_err(|err| err.to_string()))
     } else {
-        debug_level = args.debug;
+        serde_json::to_value(analyse_file(file, nstream, options, progress, |_| {})).map_err(|err| err.to_string())
+    };
+
+    match sessions {
+        Ok(serde_json::Value::Object(sessions)) => sessions,
+        Ok(_) => {
+            log::error!("{file} holds no sessions by stream.");
+            std::process::exit(1);
+        },
+        Err(err) => {
+            log::error!("Failed to read {file}: {err}");
+            std::process::exit(1);
+        }
     }
+}
 
-    SimpleLogger::new().with_level(debug_level).init().unwrap();
+/// The session of `stream`, or the only session if no stream is given. Exits if there is none.
+fn pick_session(sessions: &serde_json::Map<String, serde_json::Value>, file: &str, stream: Option<u32>) -> (String, serde_json::Value) {
+    let picked = match stream {
+        Some(stream) => sessions.get_key_value(&stream.to_string()),
+        None if sessions.len() == 1 => sessions.iter().next(),
+        None => {
+            log::error!("{file} holds {} sessions; pick one with --streams.", sessions.len());
+            std::process::exit(1);
+        },
+    };
+
+    match picked {
+        Some((stream, session)) => (stream.clone(), session.clone()),
+        None => {
+            log::error!("{file} holds no session for stream {}.", stream.map_or("-".to_string(), |stream| stream.to_string()));
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Analyses a capture or a batch of them and writes all requested outputs. With `watch`, keeps
+/// looking for new captures in the batch at that interval.
+fn analyse(global: &GlobalArgs, mut args: AnalyseArgs, watch: Option<Duration>, config: Result<Config, String>) {
+    let config = config.and_then(|config| args.apply(&config.output).map(|()| config));
+    let json = global.json || args.format == Some(Format::Json);
+    let csv = args.format == Some(Format::Csv);
+    let zeek = args.format == Some(Format::Zeek);
+    let eve = args.format == Some(Format::Eve);
+    let siem = match args.format {
+        Some(Format::Cef) => Some(SiemFormat::Cef),
+        Some(Format::Leef) => Some(SiemFormat::Leef),
+        _ => None,
+    };
+
+    let machine_readable = json || csv || zeek || eve || siem.is_some() || args.jsonl;
+    init(global, machine_readable);
+    let config = checked(config);
 
     let out;
 
@@ -84,19 +847,277 @@ fn main() {
         out = None;
     }
 
-    // Load file into stream map: <stream_id> -> <packets>
-    let streams = analyser::utils::load_file(args.file.clone(), args.nstream);
+    let options = args.analysis.options(&config);
+
+    let catalog = match Catalog::load(args.locale.as_deref().unwrap_or("en")) {
+        Ok(catalog) => catalog,
+        Err(err) => {
+            log::error!("{err}");
+            std::process::exit(1);
+        }
+    };
+    let remediation = match args.remediation.as_deref().map(RemediationSet::load) {
+        Some(Ok(remediation)) => remediation,
+        Some(Err(err)) => {
+            log::error!("{err}");
+            std::process::exit(1);
+        },
+        None => RemediationSet::bundled().clone(),
+    };
+    let texts = Texts { catalog, remediation };
+    let progress = Progress::new(!machine_readable && !global.no_progress);
+
+    let files = args.file.clone();
+    // Standard input can only be read once, and not hashed or read again afterwards
+    if files.iter().any(|file| file == compression::STDIN) {
+        if files.len() > 1 || watch.is_some() {
+            log::error!("-f - reads a single capture from standard input; it cannot be combined with other captures or watch.");
+            std::process::exit(1);
+        }
+        if args.annotate || matches!(args.report, Some(Report::Pdf)) {
+            log::error!("--annotate and PDF reports read the capture again and need a capture file rather than -f -.");
+            std::process::exit(1);
+        }
+    }
+    if let Some(file) = files.iter().find(|file| file.starts_with("s3://") && !cfg!(feature = "s3")) {
+        log::error!("{file}: this build has no S3 support; rebuild with `--features s3`.");
+        std::process::exit(1);
+    }
+    if let Some(url) = args.upload.as_deref() {
+        if !cfg!(feature = "s3") || !url.starts_with("s3://") {
+            log::error!("--upload needs an s3:// location and a build with the `s3` feature.");
+            std::process::exit(1);
+        }
+    }
+    if args.elastic_url.is_some() && !cfg!(feature = "elastic") {
+        log::error!("--elastic-url needs a build with the `elastic` feature.");
+        std::process::exit(1);
+    }
+    if args.sqlite.is_some() && !cfg!(feature = "sqlite") {
+        log::error!("--sqlite needs a build with the `sqlite` feature.");
+        std::process::exit(1);
+    }
+    if args.infer && (!cfg!(feature = "inference") || args.output_dir.is_none()) {
+        log::error!("--infer needs an output directory (-o) and a build with the `inference` feature.");
+        std::process::exit(1);
+    }
+    if args.tui && (!cfg!(feature = "tui") || machine_readable || watch.is_some()) {
+        log::error!("--tui cannot be combined with machine-readable output or watch and needs a build with the `tui` feature.");
+        std::process::exit(1);
+    }
+
+    if args.syslog.is_some() && args.syslog_transport == Transport::Tls && !cfg!(feature = "tls") {
+        log::error!("--syslog-transport tls needs a build with the `tls` feature.");
+        std::process::exit(1);
+    }
+
+    let syslog = args.syslog.as_deref().map(|address| match SyslogSender::connect(address, args.syslog_transport) {
+        Ok(sender) => Mutex::new(sender),
+        Err(err) => {
+            log::error!("Failed to connect to syslog server {address}: {err}");
+            std::process::exit(1);
+        }
+    });
+    // Several captures, directories, globs, and object-store prefixes are analysed capture by
+    // capture, resuming from a checkpoint
+    let batch = !args.rollover && (files.len() > 1 || files.iter().any(|file| is_batch_source(file)));
+    if watch.is_some() && args.rollover {
+        log::error!("--rollover cannot be combined with watch.");
+        std::process::exit(1);
+    }
+    if let Some(file) = files.iter().find(|file| watch.is_some() && !is_batch_source(file)) {
+        log::error!("{file}: watch needs a directory, a glob, or an s3:// prefix.");
+        std::process::exit(1);
+    }
+    let aggregate = batch && watch.is_none() && !args.jsonl && (json || csv);
+    #[cfg(feature = "elastic")]
+    let elastic = args.elastic_url.as_deref().map(|url| ElasticSink::new(url, &args.index));
+    let outputs = Outputs {
+        json, csv, zeek, eve, siem, machine_readable, aggregate, progress,
+        #[cfg(feature = "elastic")]
+        elastic,
+    };
+
+    let checkpoint_path = match (&args.checkpoint, out) {
+        (Some(path), _) => Some(path.clone()),
+        (None, Some(out_dir)) if batch => Some(format!("{out_dir}/{CHECKPOINT_FILE}")),
+        _ => None,
+    };
+    let mut checkpoint = checkpoint_path.map(|path| match Checkpoint::open(Path::new(&path)) {
+        Ok(checkpoint) => {
+            if !checkpoint.is_empty() {
+                log::info!("Resuming from {path}, {} captures already completed", checkpoint.len());
+            }
+            checkpoint
+        },
+        Err(err) => {
+            log::error!("Failed to read checkpoint {path}: {err}");
+            std::process::exit(1);
+        }
+    });
+    if batch && checkpoint.is_none() {
+        log::warn!("No checkpoint is kept without an output directory or --checkpoint.");
+    }
+
+    // Captures already attempted in this run, for watch to only pick up new ones
+    let mut seen = HashSet::new();
+    // Sessions of every capture, when they are printed together
+    let mut aggregated = BTreeMap::new();
+    loop {
+        let captures = files.iter().flat_map(|file| list_captures(file));
+        let captures: Vec<String> = captures
+            .filter(|capture| watch.is_none_or(|interval| is_settled(capture, interval)))
+            .filter(|capture| seen.insert(capture.clone()))
+            .collect();
+        // Rotated parts are analysed together, under the name of the first
+        let groups = match args.rollover {
+            true if !captures.is_empty() => vec![captures],
+            true => vec![],
+            false => captures.into_iter().map(|capture| vec![capture]).collect(),
+        };
 
-    // Iterate through all sessions (or just session n)
-    let mut sessions: HashMap<u32, SshSession> = HashMap::new();
-    for stream_id in streams.keys() {
-        sessions.insert(*stream_id, analyse(*stream_id, streams.get(stream_id).unwrap(), args.metaonly));
+        for group in groups {
+            // Captures read from standard input are reported as `stdin`
+            let capture = match group[0].as_str() {
+                compression::STDIN => "stdin",
+                capture => capture,
+            };
+            // Captures in object storage are downloaded first; they are still reported under their URL
+            let downloads: Result<Vec<_>, String> = group.iter().map(|capture| fetch_capture(capture)).collect();
+            let analysed = downloads.and_then(|downloads| {
+                let paths: Vec<&str> = group.iter().zip(&downloads)
+                    .map(|(capture, download)| download.as_ref().map_or(capture.as_str(), |download| download.path()))
+                    .collect();
+                let path = paths[0];
+
+                let hash = match &checkpoint {
+                    Some(checkpoint) if path != compression::STDIN => {
+                        let hash = history::hash_file(path).map_err(|err| format!("Failed to hash {capture}: {err}"))?;
+                        if checkpoint.is_completed(&hash) {
+                            log::info!("Skipping {capture}, completed in an earlier run");
+                            return Ok(None);
+                        }
+                        Some(hash)
+                    },
+                    _ => None,
+                };
+
+                let sessions = process_capture(&args, &options, &texts, &outputs, syslog.as_ref(), capture, &paths).map_err(|err| err.to_string())?;
+                if outputs.aggregate {
+                    aggregated.insert(capture.to_string(), sessions);
+                }
+                Ok(hash)
+            });
+
+            match (analysed, &mut checkpoint) {
+                (Ok(Some(hash)), Some(checkpoint)) => {
+                    if let Err(err) = checkpoint.complete(&hash, capture) {
+                        log::error!("Failed to update checkpoint: {err}");
+                    }
+                },
+                (Ok(_), _) => {},
+                (Err(err), _) => {
+                    log::error!("{err}");
+                    // A batch carries on with the next capture; it is retried when the batch is resumed
+                    if !batch {
+                        std::process::exit(1);
+                    }
+                },
+            }
+        }
+
+        match watch {
+            Some(interval) => std::thread::sleep(interval),
+            None => break,
+        }
     }
 
+    if outputs.aggregate {
+        if outputs.csv {
+            print!("{}", output::captures_keystrokes_as_csv(&aggregated));
+        } else {
+            println!("{}", output::captures_as_json(&aggregated, args.keystrokes).unwrap());
+        }
+    }
+}
+
+/// Whether a capture argument stands for several captures: a directory, a glob, or an `s3://` prefix.
+fn is_batch_source(file: &str) -> bool {
+    Path::new(file).is_dir() || batch::is_glob(file) || (file.starts_with("s3://") && file.ends_with('/'))
+}
+
+/// Whether a capture has not been modified for `interval`, i.e. is no longer being written.
+/// Objects in object storage are always complete.
+fn is_settled(capture: &str, interval: Duration) -> bool {
+    match fs::metadata(capture).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified.elapsed().is_ok_and(|elapsed| elapsed >= interval),
+        Err(_) => capture.starts_with("s3://"),
+    }
+}
+
+/// Analyses a single capture and writes all requested outputs. `file` is the name the capture is
+/// reported under and `paths` where it is read from, which differ for downloads; with
+/// `--rollover`, the rotated parts in order, of which the first stands for the capture.
+fn process_capture(args: &AnalyseArgs, options: &AnalysisOptions, texts: &Texts, outputs: &Outputs, syslog: Option<&Mutex<SyslogSender>>, file: &str, paths: &[&str]) -> Result<BTreeMap<u32, SshSession>, Error> {
+    let out = args.output_dir.as_deref();
+    let path = paths[0];
+    let has_tags = |session: &SshSession| args.tag.iter().all(|tag| session.tags.contains(tag));
+
+    // Sessions are forwarded as soon as they are analysed, rather than after the whole capture.
+    let analysed = try_analyse_file(paths, args.analysis.nstream, options, &outputs.progress, |session| {
+        if args.jsonl && has_tags(session) {
+            match output::session_as_json_lines(session, file, args.keystrokes, args.jsonl_events) {
+                // A single print keeps the lines of concurrently finished sessions apart
+                Ok(lines) => print!("{lines}"),
+                Err(err) => log::error!("Failed to serialise stream {}: {err}", session.stream),
+            }
+        }
+        if let Some(syslog) = syslog {
+            if has_tags(session) {
+                if let Err(err) = syslog.lock().unwrap().send_session(session) {
+                    log::error!("Failed to send stream {} to syslog: {err}", session.stream);
+                }
+            }
+        }
+    });
+    let mut sessions = analysed?;
+
+    // Host keys are compared across sessions, including those of earlier captures in the corpus
+    let earlier = stored_host_keys(args.sqlite.as_deref());
+    hostkey::find_key_changes(&mut sessions, file, &earlier);
+    spray::mark_sprays(&mut sessions);
+
+    // The run is recorded before the tag filter, so that runs with different --tag stay comparable
+    let run = match out.filter(|_| path != compression::STDIN) {
+        Some(_) => match history::hash_file(path) {
+            Ok(hash) => Some(history::Run::new(file, &hash, &sessions)),
+            Err(err) => {
+                log::error!("Failed to hash {file}: {err}");
+                None
+            },
+        },
+        None => None,
+    };
+
+    // Drop sessions missing any of the requested tags
+    sessions.retain(|_, session| has_tags(session));
+
     // ---- Output ----
 
     // No pretty-printing to STDOUT, only print JSON data (feedable to `jq` is the idea).
-    if args.json {
+    if args.jsonl {
+        // Already printed as the sessions were analysed
+    } else if outputs.aggregate {
+        // Printed once all captures are analysed
+    } else if outputs.csv {
+        print!("{}", output::keystrokes_as_csv(&sessions));
+    } else if outputs.zeek {
+        print!("{}{}", zeek::ssh_log(&sessions, file), zeek::sshniff_log(&sessions, file));
+    } else if outputs.eve {
+        print!("{}", eve::sessions_as_eve(&sessions, file).unwrap());
+    } else if let Some(siem) = outputs.siem {
+        print!("{}", formats::sessions_as_messages(&sessions, siem));
+    } else if outputs.json {
         let json: String;
         // Only output keystroke data
         if args.keystrokes {
@@ -105,23 +1126,296 @@ fn main() {
             json = output::data_as_json(&sessions).unwrap();
         }
         println!("{}", json);
-    } 
+    }
+    // Browse interactively
+    else if args.tui {
+        #[cfg(feature = "tui")]
+        if let Err(err) = tui::run(&sessions, file, &texts.catalog, &texts.remediation) {
+            log::error!("Terminal viewer failed: {err}");
+        }
+    }
     // Pretty-print to STDOUT
     else {
-        output::print_results(&sessions);
+        output::print_results(&sessions, &texts.catalog, args.show_remediation.then_some(&texts.remediation));
+    }
+
+    #[cfg(feature = "sqlite")]
+    if let Some(db) = args.sqlite.as_deref() {
+        let stored = sqlite::open(db).and_then(|mut connection| sqlite::store_sessions(&mut connection, file, &sessions));
+        match stored {
+            Ok(()) => log::info!("Stored {} sessions in {db}", sessions.len()),
+            Err(err) => log::error!("Failed to store sessions in {db}: {err}"),
+        }
+    }
+
+    #[cfg(feature = "elastic")]
+    if let Some(sink) = &outputs.elastic {
+        match sink.send_sessions(&sessions, file) {
+            Ok(indexed) => log::info!("Indexed {indexed} documents in {} (run {})", args.index, sink.run()),
+            Err(err) => log::error!("Failed to index sessions in {}: {err}", args.elastic_url.as_deref().unwrap_or_default()),
+        }
+    }
+
+    if let Some(report) = args.report {
+        match out {
+            Some(out_dir) => {
+                let stem = std::path::Path::new(file).file_stem().unwrap().to_string_lossy();
+                match report {
+                    Report::Html => {
+                        for session in sessions.values() {
+                            let path = format!("{out_dir}/{stem}_stream{}.html", session.stream);
+                            if let Err(err) = output::data_to_file(html::session_as_html(session, file, &texts.catalog, &texts.remediation), std::path::Path::new(&path)) {
+                                log::error!("Failed to write report {path}: {err}");
+                            }
+                        }
+                    },
+                    Report::Pdf => match Provenance::new(path, options) {
+                        Ok(mut provenance) => {
+                            provenance.capture = file.to_string();
+                            for session in sessions.values() {
+                                let path = format!("{out_dir}/{stem}_stream{}.pdf", session.stream);
+                                if let Err(err) = fs::write(&path, pdf::session_as_pdf(session, file, &provenance, &texts.catalog, &texts.remediation)) {
+                                    log::error!("Failed to write report {path}: {err}");
+                                }
+                            }
+                        },
+                        Err(err) => log::error!("Failed to hash {file}: {err}"),
+                    },
+                }
+            },
+            None => log::error!("--report needs an output directory (-o)."),
+        }
+    }
+
+    if args.annotate {
+        match out {
+            Some(out_dir) => {
+                let stem = std::path::Path::new(file).file_stem().unwrap().to_string_lossy();
+                let annotated_path = format!("{out_dir}/{stem}_annotated.pcapng");
+                match pcapng::write_annotated(path, Path::new(&annotated_path), &pcapng::annotations(&sessions, &texts.catalog)) {
+                    Ok(annotated) => log::info!("Annotated {annotated} packets in {annotated_path}"),
+                    Err(err) => log::error!("Failed to write {annotated_path}: {err}"),
+                }
+            },
+            None => log::error!("--annotate needs an output directory (-o)."),
+        }
+    }
+
+    // Compare against the previous run of the same capture in the output directory
+    if let (Some(out_dir), Some(run)) = (out, &run) {
+        match history::previous_run(out_dir, &run.capture_hash) {
+            Ok(Some(previous)) if !outputs.machine_readable => output::print_run_diff(&run.diff(&previous)),
+            Ok(_) => {},
+            Err(err) => log::error!("Failed to read run history: {err}"),
+        }
+        if let Err(err) = history::append_run(out_dir, run) {
+            log::error!("Failed to append to run history: {err}");
+        }
     }
 
     // Write to output directory
-    if out.is_some() {
-        let stem = std::path::Path::new(&args.file).file_stem().unwrap();
+    if let Some(out_dir) = out {
+        let stem = std::path::Path::new(file).file_stem().unwrap();
+        if outputs.csv {
+            let _ = output::data_to_file(output::keystrokes_as_csv(&sessions), std::path::Path::new(&format!("{}/{}_keystrokes.csv", out_dir, stem.to_string_lossy())));
+        } else if outputs.zeek {
+            let _ = output::data_to_file(zeek::ssh_log(&sessions, file), std::path::Path::new(&format!("{}/{}_ssh.log", out_dir, stem.to_string_lossy())));
+            let _ = output::data_to_file(zeek::sshniff_log(&sessions, file), std::path::Path::new(&format!("{}/{}_sshniff.log", out_dir, stem.to_string_lossy())));
+        } else if outputs.eve {
+            let _ = output::data_to_file(eve::sessions_as_eve(&sessions, file).unwrap(), std::path::Path::new(&format!("{}/{}_eve.json", out_dir, stem.to_string_lossy())));
+        } else if let Some(siem) = outputs.siem {
+            let extension = if siem == SiemFormat::Cef { "cef" } else { "leef" };
+            let _ = output::data_to_file(formats::sessions_as_messages(&sessions, siem), std::path::Path::new(&format!("{}/{}.{extension}", out_dir, stem.to_string_lossy())));
+        }
         // Only write keystroke data
-        if args.keystrokes {
+        else if args.keystrokes {
             let json = output::keystrokes_as_json(&sessions);
-            let _ = output::data_to_file(json.unwrap(), std::path::Path::new(&format!("{}/{}_session_keystrokes.json", args.output_dir.unwrap(), stem.to_owned().into_string().unwrap()).to_string()));
+            let _ = output::data_to_file(json.unwrap(), std::path::Path::new(&format!("{}/{}_session_keystrokes.json", out_dir, stem.to_owned().into_string().unwrap()).to_string()));
         } else {
             let json = output::data_as_json(&sessions);
-            let _ = output::data_to_file(json.unwrap(), std::path::Path::new(&format!("{}/{}_sessions.json", args.output_dir.unwrap(), stem.to_owned().into_string().unwrap()).to_string()));
+            let _ = output::data_to_file(json.unwrap(), std::path::Path::new(&format!("{}/{}_sessions.json", out_dir, stem.to_owned().into_string().unwrap()).to_string()));
+        }
+    }
+
+    #[cfg(feature = "inference")]
+    if let (true, Some(out_dir)) = (args.infer, out) {
+        let model = inference::Model::bundled();
+        let inferences: Vec<_> = sessions.values().flat_map(|session| model.infer(session)).collect();
+        let stem = std::path::Path::new(file).file_stem().unwrap().to_string_lossy();
+        let path = format!("{out_dir}/{stem}_inference.json");
+        if let Err(err) = output::data_to_file(serde_json::to_string_pretty(&inferences).unwrap(), std::path::Path::new(&path)) {
+            log::error!("Failed to write {path}: {err}");
         }
     }
+
+    #[cfg(feature = "s3")]
+    if let Some(url) = args.upload.as_deref() {
+        let stem = std::path::Path::new(file).file_stem().unwrap().to_string_lossy();
+        let location = S3Location::parse(url).expect("checked before the analysis").join(&format!("{stem}_sessions.json"));
+        let uploaded = S3Client::from_env()
+            .and_then(|client| client.upload(&location, output::data_as_json(&sessions).unwrap().as_bytes(), "application/json"));
+        match uploaded {
+            Ok(()) => log::info!("Uploaded sessions to s3://{}/{}", location.bucket, location.key),
+            Err(err) => log::error!("{err}"),
+        }
+    }
+
+    Ok(sessions)
+}
+
+/// Host keys of the sessions stored in the corpus `db`, if one was given.
+#[cfg(feature = "sqlite")]
+fn stored_host_keys(db: Option<&str>) -> Vec<hostkey::KeySighting> {
+    match db {
+        Some(db) => sqlite::open(db).and_then(|connection| sqlite::host_keys(&connection)).unwrap_or_else(|err| {
+            log::error!("Failed to read host keys from {db}: {err}");
+            vec![]
+        }),
+        None => vec![],
+    }
 }
 
+/// Builds without the `sqlite` feature have no corpus.
+#[cfg(not(feature = "sqlite"))]
+fn stored_host_keys(_db: Option<&str>) -> Vec<hostkey::KeySighting> {
+    vec![]
+}
+
+/// Fingerprints of the sessions stored in the corpus `db`, if one was given, except for those
+/// already in `fingerprints`: captures given again would otherwise link to their stored copies.
+#[cfg(feature = "sqlite")]
+fn stored_fingerprints(db: Option<&str>, fingerprints: &[OperatorFingerprint]) -> Vec<OperatorFingerprint> {
+    let Some(db) = db else {
+        return vec![];
+    };
+    match sqlite::open(db).and_then(|connection| sqlite::operator_fingerprints(&connection)) {
+        Ok(stored) => {
+            log::info!("Loaded {} sessions from {db}", stored.len());
+            stored.into_iter()
+                .filter(|stored| !fingerprints.iter().any(|fingerprint| fingerprint.capture == stored.capture && fingerprint.stream == stored.stream))
+                .collect()
+        },
+        Err(err) => {
+            log::error!("Failed to read sessions from {db}: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Builds without the `sqlite` feature have no corpus.
+#[cfg(not(feature = "sqlite"))]
+fn stored_fingerprints(_db: Option<&str>, _fingerprints: &[OperatorFingerprint]) -> Vec<OperatorFingerprint> {
+    vec![]
+}
+
+/// Downloads a capture given as an `s3://` URL; None for local files.
+#[cfg(feature = "s3")]
+fn fetch_capture(file: &str) -> Result<Option<CaptureFile>, String> {
+    match S3Location::parse(file) {
+        Some(location) => S3Client::from_env().and_then(|client| client.download(&location)).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Builds without the `s3` feature only read local files.
+#[cfg(not(feature = "s3"))]
+fn fetch_capture(_file: &str) -> Result<Option<CaptureFile>, String> {
+    Ok(None)
+}
+
+/// Captures of a capture argument: the capture files in a directory, the files matching a glob, the
+/// objects under an `s3://` prefix, or else the capture itself.
+fn list_captures(source: &str) -> Vec<String> {
+    if !is_batch_source(source) {
+        return vec![source.to_string()];
+    }
+    if batch::is_glob(source) {
+        return match batch::expand_glob(source) {
+            Ok(captures) => {
+                log::info!("Found {} captures matching {source}", captures.len());
+                captures.into_iter().map(|capture| capture.to_string_lossy().into_owned()).collect()
+            },
+            Err(err) => {
+                log::error!("Failed to expand {source}: {err}");
+                std::process::exit(1);
+            }
+        };
+    }
+
+    #[cfg(feature = "s3")]
+    if let Some(prefix) = S3Location::parse(source) {
+        return match S3Client::from_env().and_then(|client| client.list(&prefix)) {
+            Ok(objects) => objects.into_iter()
+                .filter(|object| batch::is_capture(&object.key))
+                .map(|object| format!("s3://{}/{}", object.bucket, object.key))
+                .collect(),
+            Err(err) => {
+                log::error!("{err}");
+                std::process::exit(1);
+            }
+        };
+    }
+
+    match batch::list_captures(Path::new(source)) {
+        Ok(captures) => {
+            log::info!("Found {} captures in {source}", captures.len());
+            captures.into_iter().map(|capture| capture.to_string_lossy().into_owned()).collect()
+        },
+        Err(err) => {
+            log::error!("Failed to list {source}: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Analyses all sessions (or just session n) in a capture.
+///
+/// Streams are analysed in parallel on the global thread pool; see `--jobs`. `on_session` is
+/// called with each session as soon as it is analysed.
+fn analyse_file(file: &str, nstream: i32, options: &AnalysisOptions, progress: &Progress, on_session: impl Fn(&SshSession) + Sync) -> BTreeMap<u32, SshSession> {
+    match try_analyse_file(&[file], nstream, options, progress, on_session) {
+        Ok(sessions) => sessions,
+        Err(err) => {
+            log::error!("{err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Like [analyse_file], but returns an error if the capture cannot be read instead of exiting. Several
+/// `files` are rotated parts of one capture; see [rollover].
+fn try_analyse_file(files: &[&str], nstream: i32, options: &AnalysisOptions, progress: &Progress, on_session: impl Fn(&SshSession) + Sync) -> Result<BTreeMap<u32, SshSession>, Error> {
+    progress.reset();
+    // Load file into stream map: <stream_id> -> <packets>, and the handshake MSS and ACKs per stream
+    let mut parts = vec![];
+    for file in files {
+        parts.push(sshniff::analyser::utils::load_capture_reporting(file, nstream, options.sampling, &options.filter, options.engine, &options.decoding, |packets, streams| progress.read(packets, streams))?);
+    }
+    // Rotated parts of a capture are stitched into one
+    let (streams, tcp) = match parts.len() {
+        1 => parts.pop().expect("one part"),
+        _ => rollover::stitch(parts),
+    };
+    progress.collected(streams.len());
+
+    // Collecting into a BTreeMap orders sessions by stream, however the threads finished.
+    let sessions = streams.par_iter()
+        .filter_map(|(stream_id, packets)| {
+            let analysed = SshSession::analyse(*stream_id, packets, tcp.get(stream_id).unwrap_or(&TcpMeta::default()), options);
+            progress.analysed();
+            match analysed {
+                Ok(session) => {
+                    on_session(&session);
+                    Some((*stream_id, session))
+                },
+                Err(err) => {
+                    log::error!("Skipping stream {stream_id}: {err}");
+                    None
+                }
+            }
+        })
+        .collect();
+    progress.finish();
+
+    Ok(sessions)
+}
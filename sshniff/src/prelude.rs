@@ -0,0 +1,130 @@
+//! Stable entry point to the library.
+//!
+//! Everything needed for the common workflows is re-exported here, and the types defined here
+//! only change with a semver-incompatible release. The modules under [analyser](crate::analyser)
+//! are free to change between minor versions; code that only uses the prelude is insulated from
+//! that.
+//!
+//! Analyse a capture and iterate its sessions:
+//!
+//! ```no_run
+//! use sshniff::prelude::*;
+//!
+//! let analysis = analyse_file("capture.pcapng", &AnalysisOptions::default())?;
+//! for session in analysis.sessions() {
+//!     println!("{} -> {}: {} commands", session.src, session.dst, session.keystroke_data.len());
+//! }
+//! for (stream, err) in analysis.errors() {
+//!     eprintln!("stream {stream} skipped: {err}");
+//! }
+//! # Ok::<(), Error>(())
+//! ```
+//!
+//! Skip the keystroke scan and export the sessions as JSON:
+//!
+//! ```no_run
+//! use sshniff::prelude::*;
+//!
+//! let options = AnalysisOptions::builder().skip(Scan::Keystrokes).build();
+//! let analysis = analyse_file("capture.pcapng", &options)?;
+//! std::fs::write("sessions.json", analysis.to_json().unwrap()).unwrap();
+//! # Ok::<(), Error>(())
+//! ```
+//!
+//! Find sessions with a given tag:
+//!
+//! ```no_run
+//! use sshniff::prelude::*;
+//!
+//! let analysis = analyse_file("capture.pcapng", &AnalysisOptions::default())?;
+//! let tunneled: Vec<u32> = analysis.sessions()
+//!     .filter(|session| session.tags.contains(&Tag::Tunneled))
+//!     .map(|session| session.stream)
+//!     .collect();
+//! # Ok::<(), Error>(())
+//! ```
+use crate::analyser::utils;
+use std::collections::BTreeMap;
+
+pub use crate::analyser::containers::{Event, Finding, Keystroke, KeystrokeSequence, KeystrokeType};
+pub use crate::analyser::core::SshSession;
+pub use crate::analyser::options::{AnalysisOptions, AnalysisOptionsBuilder, Scan};
+pub use crate::analyser::tags::Tag;
+pub use crate::error::Error;
+
+/// Sessions of a capture, and the streams that could not be analysed.
+#[derive(Debug)]
+pub struct Analysis {
+    capture: String,
+    sessions: BTreeMap<u32, SshSession>,
+    errors: BTreeMap<u32, Error>,
+}
+
+impl Analysis {
+    /// Path of the analysed capture.
+    pub fn capture(&self) -> &str {
+        &self.capture
+    }
+
+    /// Sessions, ordered by stream.
+    pub fn sessions(&self) -> impl Iterator<Item = &SshSession> {
+        self.sessions.values()
+    }
+
+    /// Session of the given TCP stream, if it was analysed.
+    pub fn session(&self, stream: u32) -> Option<&SshSession> {
+        self.sessions.get(&stream)
+    }
+
+    /// Streams that carried SSH but could not be analysed, and why.
+    pub fn errors(&self) -> impl Iterator<Item = (u32, &Error)> {
+        self.errors.iter().map(|(stream, err)| (*stream, err))
+    }
+
+    /// Number of analysed sessions.
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Whether no session could be analysed.
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Sessions as JSON, keyed by stream; the same as `sshniff -j`.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&self.sessions)
+    }
+
+    /// Takes the sessions, keyed by stream.
+    pub fn into_sessions(self) -> BTreeMap<u32, SshSession> {
+        self.sessions
+    }
+}
+
+/// Loads a capture and analyses every SSH stream in it.
+///
+/// Fails only if the capture cannot be read; streams that fail to analyse are collected in
+/// [Analysis::errors].
+pub fn analyse_file(path: &str, options: &AnalysisOptions) -> Result<Analysis, Error> {
+    let (streams, tcp) = utils::load_capture_reporting(path, -1, options.sampling, &options.filter, options.engine, &options.decoding, |_, _| {})?;
+    let mut analysis = Analysis {
+        capture: path.to_string(),
+        sessions: BTreeMap::new(),
+        errors: BTreeMap::new(),
+    };
+
+    for (stream_id, packets) in &streams {
+        let meta = tcp.get(stream_id).cloned().unwrap_or_default();
+        match SshSession::analyse(*stream_id, packets, &meta, options) {
+            Ok(session) => {
+                analysis.sessions.insert(*stream_id, session);
+            },
+            Err(err) => {
+                analysis.errors.insert(*stream_id, err);
+            },
+        }
+    }
+
+    Ok(analysis)
+}
@@ -0,0 +1,186 @@
+//! Batch runs over directories of captures, glob patterns, and, with the `s3` feature, object-store
+//! prefixes.
+//!
+//! A batch run records every capture it has finished in a [Checkpoint] file, one line each, as soon
+//! as it is done. When an interrupted run is started again with the same checkpoint, captures
+//! already in it are skipped. Captures are matched by the MD5 of their contents, like the
+//! [run history](super::history), so renamed or moved captures are not analysed twice either.
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Name of the checkpoint kept in the output directory unless another path is given.
+pub const CHECKPOINT_FILE: &str = "sshniff_checkpoint.tsv";
+
+/// Extensions of the files a directory batch picks up, also when followed by `.gz`, `.zst`, or `.xz`.
+pub const CAPTURE_EXTENSIONS: [&str; 3] = ["pcap", "pcapng", "cap"];
+
+/// Captures completed by earlier and current batch runs.
+#[derive(Debug)]
+pub struct Checkpoint {
+    path: PathBuf,
+    completed: HashSet<String>,
+}
+
+impl Checkpoint {
+    /// Opens a checkpoint, which is created on the first completed capture if it does not exist.
+    ///
+    /// Each line is `MD5<TAB>capture<TAB>time`; lines without a hash are skipped.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut completed = HashSet::new();
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                let line = line?;
+                match line.split('\t').next() {
+                    Some(hash) if !hash.is_empty() => {
+                        completed.insert(hash.to_string());
+                    },
+                    _ => log::warn!("Skipping unreadable line of {}", path.display()),
+                }
+            }
+        }
+
+        Ok(Self { path: path.to_path_buf(), completed })
+    }
+
+    /// Number of captures completed so far.
+    pub fn len(&self) -> usize {
+        self.completed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.completed.is_empty()
+    }
+
+    /// Whether the capture with the given MD5 has been completed.
+    pub fn is_completed(&self, hash: &str) -> bool {
+        self.completed.contains(hash)
+    }
+
+    /// Records a capture as completed. The line is written out immediately, so that it survives
+    /// the run being killed.
+    pub fn complete(&mut self, hash: &str, capture: &str) -> io::Result<()> {
+        if let Some(parent) = self.path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{hash}\t{capture}\t{}", chrono::Utc::now().format("%Y-%m-%d %H:%M:%S"))?;
+        file.sync_data()?;
+
+        self.completed.insert(hash.to_string());
+        Ok(())
+    }
+}
+
+/// Whether a file name looks like a capture, possibly compressed.
+pub fn is_capture(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    let name = name.trim_end_matches(".gz").trim_end_matches(".zst").trim_end_matches(".xz");
+    CAPTURE_EXTENSIONS.iter().any(|extension| name.ends_with(&format!(".{extension}")))
+}
+
+/// Captures in a directory and its subdirectories, sorted by path so that runs are repeatable.
+pub fn list_captures(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut captures = vec![];
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.file_name().is_some_and(|name| is_capture(&name.to_string_lossy())) {
+                captures.push(path);
+            }
+        }
+    }
+
+    captures.sort();
+    Ok(captures)
+}
+
+/// Whether a capture argument is a glob pattern, e.g. for captures rotated by `tcpdump -C`.
+pub fn is_glob(source: &str) -> bool {
+    source.contains(['*', '?'])
+}
+
+/// Files matching a glob pattern, sorted by path. The wildcards `*` (any run of characters) and
+/// `?` (any one character) may only appear in the file name, e.g. `captures/host1.pcap*`.
+pub fn expand_glob(pattern: &str) -> io::Result<Vec<PathBuf>> {
+    let pattern = Path::new(pattern);
+    let name = match pattern.file_name() {
+        Some(name) => name.to_string_lossy(),
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "glob without a file name")),
+    };
+    let dir = match pattern.parent() {
+        Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+        Some(dir) if is_glob(&dir.to_string_lossy()) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "wildcards are only supported in the file name")),
+        Some(dir) => dir,
+        None => Path::new("."),
+    };
+
+    let name: Vec<char> = name.chars().collect();
+    let mut matches = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() && wildcard_match(&name, &entry.file_name().to_string_lossy().chars().collect::<Vec<_>>()) {
+            matches.push(pattern.with_file_name(entry.file_name()));
+        }
+    }
+
+    matches.sort();
+    Ok(matches)
+}
+
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => wildcard_match(&pattern[1..], name) || (!name.is_empty() && wildcard_match(pattern, &name[1..])),
+        (Some('?'), Some(_)) => wildcard_match(&pattern[1..], &name[1..]),
+        (Some(expected), Some(actual)) => expected == actual && wildcard_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint() {
+        assert!(is_capture("archive/2024-05-12.PCAPNG"));
+        assert!(is_capture("host1.pcap.zst"));
+        assert!(is_capture("host1.pcapng.xz"));
+        assert!(!is_capture("notes.txt.gz"));
+
+        let path = std::env::temp_dir().join(format!("sshniff-test-{}-checkpoint.tsv", std::process::id()));
+        let mut checkpoint = Checkpoint::open(&path).unwrap();
+        assert!(checkpoint.is_empty());
+        checkpoint.complete("d41d8cd98f00b204e9800998ecf8427e", "archive/a.pcap").unwrap();
+
+        // A resumed run sees what the interrupted one completed
+        let resumed = Checkpoint::open(&path).unwrap();
+        assert!(resumed.is_completed("d41d8cd98f00b204e9800998ecf8427e"));
+        assert!(!resumed.is_completed("0cc175b9c0f1b6a831c399e269772661"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_capture_glob() {
+        let dir = std::env::temp_dir().join(format!("sshniff-test-{}-glob", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["host1.pcap", "host1.pcap1", "host1.pcap2", "host2.pcap", "notes.txt"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+
+        assert!(is_glob("captures/host1.pcap*"));
+        assert!(!is_glob("captures/host1.pcap"));
+        let rotated = expand_glob(&format!("{}/host1.pcap*", dir.display())).unwrap();
+        assert_eq!(vec![dir.join("host1.pcap"), dir.join("host1.pcap1"), dir.join("host1.pcap2")], rotated);
+        let hosts = expand_glob(&format!("{}/host?.pcap", dir.display())).unwrap();
+        assert_eq!(vec![dir.join("host1.pcap"), dir.join("host2.pcap")], hosts);
+        assert!(expand_glob(&format!("{}/*/host1.pcap", dir.display())).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,253 @@
+//! Elasticsearch/OpenSearch bulk indexing of analysed sessions.
+//!
+//! Every session becomes one `sshniff.session` document and every keystroke one
+//! `sshniff.keystroke` document, in the same index. Field names follow ECS where it has one
+//! (`@timestamp`, `source.ip`, `event.dataset`) and live under `sshniff.*` otherwise, with a
+//! single type per field, so dynamic mapping produces something Kibana can aggregate on.
+//!
+//! All documents of a run carry the same `sshniff.run` id and are sent in batches of
+//! [BATCH_SIZE] through the `_bulk` API.
+use crate::analyser::core::SshSession;
+use crate::analyser::utils;
+use chrono::Utc;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// Documents per bulk request.
+pub const BATCH_SIZE: usize = 1000;
+
+/// RFC 3339 in UTC, which Elasticsearch maps as `date`.
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.6fZ";
+
+/// Bulk endpoint of a cluster, and the index all documents of a run go to.
+pub struct ElasticSink {
+    url: String,
+    index: String,
+    run: String,
+}
+
+impl ElasticSink {
+    /// Creates a sink for the cluster at `url` (e.g. `http://localhost:9200`).
+    pub fn new(url: &str, index: &str) -> Self {
+        Self {
+            url: format!("{}/_bulk", url.trim_end_matches('/')),
+            index: index.to_string(),
+            run: Utc::now().format("%Y%m%dT%H%M%S%.6fZ").to_string(),
+        }
+    }
+
+    /// Id shared by all documents sent through this sink.
+    pub fn run(&self) -> &str {
+        &self.run
+    }
+
+    /// Indexes the sessions of a capture and their keystrokes. Returns the number of documents
+    /// indexed.
+    ///
+    /// Stops at the first request that fails; documents rejected within a successful request are
+    /// logged and skipped.
+    pub fn send_sessions(&self, sessions: &BTreeMap<u32, SshSession>, capture: &str) -> Result<usize, String> {
+        let documents = documents(sessions, capture, &self.run);
+        let mut indexed = 0;
+
+        for batch in documents.chunks(BATCH_SIZE) {
+            let response = ureq::post(&self.url)
+                .set("Content-Type", "application/x-ndjson")
+                .send_string(&bulk_body(&self.index, batch))
+                .map_err(|err| err.to_string())?;
+            let response: Value = response.into_json().map_err(|err| err.to_string())?;
+
+            let failed = rejected(&response);
+            if failed > 0 {
+                log::warn!("{failed} of {} documents were rejected by {}", batch.len(), self.url);
+            }
+            indexed += batch.len() - failed;
+        }
+
+        Ok(indexed)
+    }
+}
+
+/// Session and keystroke documents of a capture.
+pub fn documents(sessions: &BTreeMap<u32, SshSession>, capture: &str, run: &str) -> Vec<Value> {
+    let mut documents = Vec::new();
+
+    for session in sessions.values() {
+        let document = |timestamp: i64, dataset: &str, body: Value| {
+            let (source_ip, source_port) = split(&session.src);
+            let (destination_ip, destination_port) = split(&session.dst);
+            json!({
+                "@timestamp": utils::format_timestamp_as(timestamp, TIMESTAMP_FORMAT),
+                "event": { "kind": "event", "module": "sshniff", "dataset": dataset, "severity": session.severity },
+                "source": { "ip": source_ip, "port": source_port },
+                "destination": { "ip": destination_ip, "port": destination_port },
+                "sshniff": {
+                    "run": run,
+                    "capture": capture,
+                    "stream": session.stream,
+                    dataset.trim_start_matches("sshniff."): body,
+                },
+            })
+        };
+
+        documents.push(document(utils::parse_session_time(&session.start_utc).unwrap_or_default(), "sshniff.session", json!({
+            "start": utils::format_timestamp_as(utils::parse_session_time(&session.start_utc).unwrap_or_default(), TIMESTAMP_FORMAT),
+            "end": utils::format_timestamp_as(utils::parse_session_time(&session.end_utc).unwrap_or_default(), TIMESTAMP_FORMAT),
+            "client": session.protocols.0,
+            "server": session.protocols.1,
+            "hassh_client": session.hassh_c,
+            "hassh_server": session.hassh_s,
+            "kex": session.algorithms.0,
+            "cipher": session.algorithms.1,
+            "mac": session.algorithms.2,
+            "compression": session.algorithms.3,
+            "host_key": session.host_key,
+            "obfuscated": session.obfuscated,
+            "bytes_client": session.bytes.0,
+            "bytes_server": session.bytes.1,
+            "rtt_client_us": session.jitter.0.mean_rtt,
+            "rtt_server_us": session.jitter.1.mean_rtt,
+            "commands": session.keystroke_data.len(),
+            "error_rate": session.error_rate,
+            "typing_median_us": session.typing.median_us,
+            "tags": session.tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>(),
+            "findings": session.results.iter().map(|finding| json!({
+                "kind": finding.kind.to_string(),
+                "timestamp": utils::format_timestamp_as(finding.timestamp, TIMESTAMP_FORMAT),
+                "tcp_seq": finding.packet_ref.seq,
+                "confidence": finding.confidence,
+                "details": finding.details,
+            })).collect::<Vec<_>>(),
+        })));
+
+        for sequence in &session.keystroke_data {
+            for keystroke in &sequence.keystrokes {
+                documents.push(document(keystroke.timestamp, "sshniff.keystroke", json!({
+                    "type": format!("{:?}", keystroke.k_type),
+                    "command": sequence.id,
+                    "tcp_seq": keystroke.seq,
+                    "frame": keystroke.frame,
+                    "latency_us": keystroke.latency_us,
+                    "confidence": keystroke.confidence,
+                    "response_size": keystroke.response_size.map(|size| size as u64),
+                })));
+            }
+        }
+    }
+
+    documents
+}
+
+/// Bulk request body indexing `documents` into `index`.
+fn bulk_body(index: &str, documents: &[Value]) -> String {
+    let action = json!({ "index": { "_index": index } }).to_string();
+    documents.iter()
+        .map(|document| format!("{action}\n{document}\n"))
+        .collect()
+}
+
+/// Number of items a bulk response reports as failed.
+fn rejected(response: &Value) -> usize {
+    if response["errors"].as_bool() != Some(true) {
+        return 0;
+    }
+    response["items"].as_array().map_or(0, |items| items.iter()
+        .filter(|item| item["index"]["error"].is_object())
+        .count())
+}
+
+/// Splits `ip:port`; the port is null if missing.
+fn split(address: &str) -> (&str, Option<u16>) {
+    let (ip, port) = utils::split_endpoint(address);
+    (ip, port.and_then(|port| port.parse().ok()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::core::tests::session;
+
+    const RUN: &str = "20240131T120000.000000Z";
+
+    #[test]
+    fn test_documents() {
+        let sessions = BTreeMap::from([(3, session())]);
+        let documents = documents(&sessions, "capture.pcapng", RUN);
+        // The session, then each of its three keystrokes
+        assert_eq!(4, documents.len());
+
+        let common = |timestamp: &str, dataset: &str| json!({
+            "@timestamp": timestamp,
+            "event": { "kind": "event", "module": "sshniff", "dataset": dataset, "severity": 0 },
+            "source": { "ip": "192.168.1.10", "port": 51514 },
+            "destination": { "ip": "10.0.0.1", "port": 22 },
+            "sshniff": { "run": RUN, "capture": "capture.pcapng", "stream": 3 },
+        });
+        let with = |mut document: Value, key: &str, body: Value| {
+            document["sshniff"][key] = body;
+            document
+        };
+
+        assert_eq!(with(common("2024-01-31T12:00:00.000000Z", "sshniff.session"), "session", json!({
+            "start": "2024-01-31T12:00:00.000000Z",
+            "end": "2024-01-31T12:00:10.000000Z",
+            "client": "SSH-2.0-OpenSSH_9.6",
+            "server": "SSH-2.0-OpenSSH_8.9p1 Ubuntu-3ubuntu0.6",
+            "hassh_client": "ec7378c1a92f5a8dde7e8b7a1ddf33d1",
+            "hassh_server": "b12d2871a1189eff20364cf5333619ee",
+            "kex": "curve25519-sha256",
+            "cipher": "chacha20-poly1305@openssh.com",
+            "mac": "hmac-sha2-256",
+            "compression": "none",
+            "host_key": null,
+            "obfuscated": false,
+            "bytes_client": 0,
+            "bytes_server": 0,
+            "rtt_client_us": 0,
+            "rtt_server_us": 0,
+            "commands": 1,
+            "error_rate": 0.0,
+            "typing_median_us": 0,
+            "tags": ["interactive", "tunneled"],
+            "findings": [
+                { "kind": "WrongPassword", "timestamp": "2024-01-31T12:00:02.500000Z", "tcp_seq": 1342, "confidence": 0.5, "details": null },
+                { "kind": "PortForwarding", "timestamp": "2024-01-31T12:00:09.000000Z", "tcp_seq": 3718, "confidence": 0.75, "details": "to \"db|main\"=5432\tvia C:\\tmp <x> & y\nnext" },
+            ],
+        })), documents[0]);
+
+        // Keystrokes are timestamped when typed, with their latency alongside
+        assert_eq!(with(common("2024-01-31T12:00:04.500000Z", "sshniff.keystroke"), "keystroke", json!({
+            "type": "Enter",
+            "command": 1,
+            "tcp_seq": 2072,
+            "frame": 34,
+            "latency_us": 300_000,
+            "confidence": 1.0,
+            "response_size": 512,
+        })), documents[3]);
+        let types: Vec<&str> = documents[1..].iter().map(|document| document["sshniff"]["keystroke"]["type"].as_str().unwrap()).collect();
+        assert_eq!(vec!["Keystroke", "Delete", "Enter"], types);
+    }
+
+    #[test]
+    fn test_bulk_body() {
+        let documents = [json!({ "a": 1 }), json!({ "b": "x\ny" })];
+        assert_eq!("\
+{\"index\":{\"_index\":\"sshniff\"}}\n{\"a\":1}\n\
+{\"index\":{\"_index\":\"sshniff\"}}\n{\"b\":\"x\\ny\"}\n", bulk_body("sshniff", &documents));
+        assert_eq!("", bulk_body("sshniff", &[]));
+    }
+
+    #[test]
+    fn test_rejected() {
+        let item = |error: Option<&str>| match error {
+            Some(reason) => json!({ "index": { "status": 400, "error": { "type": "mapper_parsing_exception", "reason": reason } } }),
+            None => json!({ "index": { "status": 201, "result": "created" } }),
+        };
+
+        assert_eq!(0, rejected(&json!({ "errors": false, "items": [item(None), item(None)] })));
+        assert_eq!(2, rejected(&json!({ "errors": true, "items": [item(Some("a")), item(None), item(Some("b"))] })));
+        // Without the errors flag, items are not looked at
+        assert_eq!(0, rejected(&json!({ "items": [item(Some("a"))] })));
+    }
+}
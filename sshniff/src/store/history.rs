@@ -0,0 +1,146 @@
+//! Run history of the captures analysed into an output directory.
+//!
+//! Every run appends a [Run] to [HISTORY_FILE] in the output directory. When the same capture
+//! (matched by the MD5 of its contents, not its name) is analysed again, its findings are compared
+//! against the last run, so the effect of upgraded heuristics on existing evidence shows up as
+//! added and removed findings.
+use crate::analyser::core::SshSession;
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+/// Name of the run history, one JSON [Run] per line.
+pub const HISTORY_FILE: &str = "sshniff_history.jsonl";
+
+/// Identifies a finding across runs.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct FindingKey {
+    pub stream: u32,
+    /// tcp.seq of the evidence packet
+    pub seq: i64,
+    /// Event name, kept as a string so that runs of older versions stay readable
+    pub kind: String,
+}
+
+/// A single analysis of a capture.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Run {
+    pub capture: String,
+    /// MD5 of the capture file
+    pub capture_hash: String,
+    /// UTC time the run finished
+    pub time: String,
+    /// SSHniff version of the run
+    pub version: String,
+    pub findings: Vec<FindingKey>,
+}
+
+/// Findings that differ between two runs of the same capture.
+#[derive(Debug, Serialize)]
+pub struct RunDiff {
+    /// Time and version of the earlier run
+    pub previous: (String, String),
+    /// Findings only in the new run
+    pub added: Vec<FindingKey>,
+    /// Findings only in the earlier run
+    pub removed: Vec<FindingKey>,
+}
+
+impl Run {
+    /// Creates the [Run] of the given sessions, finished now.
+    pub fn new(capture: &str, capture_hash: &str, sessions: &BTreeMap<u32, SshSession>) -> Self {
+        let findings = sessions.values()
+            .flat_map(|session| session.results.iter().map(|finding| FindingKey {
+                stream: session.stream,
+                seq: finding.packet_ref.seq,
+                kind: finding.kind.to_string(),
+            }))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        Self {
+            capture: capture.to_string(),
+            capture_hash: capture_hash.to_string(),
+            time: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            findings,
+        }
+    }
+
+    /// Compares this run against an earlier one.
+    pub fn diff(&self, previous: &Run) -> RunDiff {
+        let current: BTreeSet<&FindingKey> = self.findings.iter().collect();
+        let earlier: BTreeSet<&FindingKey> = previous.findings.iter().collect();
+
+        RunDiff {
+            previous: (previous.time.clone(), previous.version.clone()),
+            added: current.difference(&earlier).map(|key| (*key).clone()).collect(),
+            removed: earlier.difference(&current).map(|key| (*key).clone()).collect(),
+        }
+    }
+}
+
+/// MD5 of a file's contents.
+pub fn hash_file(path: &str) -> io::Result<String> {
+    let mut hasher = Md5::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Last run of the capture with the given hash in the output directory, if any.
+///
+/// Lines that cannot be parsed are skipped, so a damaged history only loses those runs.
+pub fn previous_run(out_dir: &str, capture_hash: &str) -> io::Result<Option<Run>> {
+    let path = Path::new(out_dir).join(HISTORY_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let mut last = None;
+    for line in BufReader::new(File::open(path)?).lines() {
+        match serde_json::from_str::<Run>(&line?) {
+            Ok(run) if run.capture_hash == capture_hash => last = Some(run),
+            Ok(_) => {},
+            Err(err) => log::warn!("Skipping unreadable line of {HISTORY_FILE}: {err}"),
+        }
+    }
+    Ok(last)
+}
+
+/// Appends a run to the history of the output directory.
+pub fn append_run(out_dir: &str, run: &Run) -> io::Result<()> {
+    fs::create_dir_all(out_dir)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(Path::new(out_dir).join(HISTORY_FILE))?;
+    writeln!(file, "{}", serde_json::to_string(run)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_diff() {
+        let key = |stream, seq, kind: &str| FindingKey { stream, seq, kind: kind.to_string() };
+        let run = |time: &str, findings| Run {
+            capture: "capture.pcapng".to_string(),
+            capture_hash: "d41d8cd98f00b204e9800998ecf8427e".to_string(),
+            time: time.to_string(),
+            version: "0.1.0".to_string(),
+            findings,
+        };
+        let previous = run("2026-01-01 10:00:00", vec![key(0, 100, "LoginSuccess"), key(0, 900, "PortForwarding"), key(1, 100, "LoginSuccess")]);
+        let current = run("2026-01-02 10:00:00", vec![key(0, 100, "LoginSuccess"), key(0, 500, "Paste"), key(1, 100, "LoginSuccess")]);
+
+        let diff = current.diff(&previous);
+        assert_eq!(("2026-01-01 10:00:00".to_string(), "0.1.0".to_string()), diff.previous);
+        assert_eq!(vec![key(0, 500, "Paste")], diff.added);
+        assert_eq!(vec![key(0, 900, "PortForwarding")], diff.removed);
+
+        let unchanged = current.diff(&current);
+        assert!(unchanged.added.is_empty() && unchanged.removed.is_empty());
+    }
+}
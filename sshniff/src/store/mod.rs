@@ -0,0 +1,15 @@
+//! Persistence-related module.
+//!
+//! Contains the SQLite corpus that results are accumulated in (`--sqlite`) and queried from
+//! (`sqlite` feature), the Elasticsearch/OpenSearch bulk sink (`--elastic-url`, `elastic`
+//! feature), the run history kept in output directories, checkpoints of batch runs, and S3 object
+//! storage for captures and results (`s3` feature).
+pub mod query;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "elastic")]
+pub mod elastic;
+pub mod history;
+pub mod batch;
+#[cfg(feature = "s3")]
+pub mod s3;
@@ -0,0 +1,45 @@
+//! Canned queries over a corpus, and their results.
+//!
+//! Kept apart from the SQLite backend, so the `query` subcommand and the tables of the console
+//! output exist in builds without the `sqlite` feature.
+use clap::Subcommand;
+use serde_json::{Map, Value};
+
+/// Canned queries over the corpus.
+#[derive(Subcommand, Clone, Debug)]
+pub enum Query {
+    /// Sessions of a client IP
+    ByClient {
+        ip: String,
+    },
+    /// Client IPs with the most wrong passwords
+    FailedLogins {
+        /// Number of clients to list
+        #[arg(short, long, default_value_t = 10)]
+        limit: u32,
+    },
+    /// Sessions with a given finding (e.g. AcceptedKey)
+    WithFinding {
+        kind: String,
+    },
+    /// Sessions whose mean keystroke latency is far from the corpus mean
+    KeystrokeOutliers,
+    /// Servers that presented more than one host key of the same type
+    HostKeyChanges,
+}
+
+/// Result of a [Query]; every value is kept as text for display.
+#[derive(Debug)]
+pub struct Table {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Rows as JSON objects keyed by column name.
+    pub fn to_json(&self) -> Vec<Map<String, Value>> {
+        self.rows.iter()
+            .map(|row| self.columns.iter().cloned().zip(row.iter().cloned().map(Value::String)).collect())
+            .collect()
+    }
+}
@@ -0,0 +1,351 @@
+//! SQLite corpus of analysed sessions.
+//!
+//! Sessions, their [results](crate::analyser::containers::Finding), and their keystrokes are kept
+//! in normalised tables, so a corpus can grow across many captures. The canned [queries](Query)
+//! give access to it without writing SQL.
+use crate::analyser::containers::{Keystroke, KeystrokeType};
+use crate::analyser::core::SshSession;
+use crate::analyser::hostkey::KeySighting;
+use crate::analyser::operators::OperatorFingerprint;
+use crate::analyser::rhythm::TypingRhythm;
+use crate::analyser::{stats, utils};
+use super::query::{Query, Table};
+use chrono::{TimeZone, Timelike, Utc};
+use rusqlite::{params, types::ValueRef, Connection};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Tables of the corpus. Created on open, so querying a fresh file returns empty results.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    capture TEXT NOT NULL,
+    stream INTEGER NOT NULL,
+    src TEXT NOT NULL,
+    dst TEXT NOT NULL,
+    client_ip TEXT NOT NULL,
+    protocol_client TEXT NOT NULL,
+    protocol_server TEXT NOT NULL,
+    hassh_c TEXT NOT NULL,
+    hassh_s TEXT NOT NULL,
+    kex TEXT NOT NULL,
+    enc TEXT NOT NULL,
+    mac TEXT NOT NULL,
+    cmp TEXT NOT NULL,
+    start_utc TEXT NOT NULL,
+    end_utc TEXT NOT NULL,
+    keystroke_size INTEGER NOT NULL,
+    tags TEXT NOT NULL,
+    host_key TEXT
+);
+CREATE TABLE IF NOT EXISTS results (
+    session_id INTEGER NOT NULL REFERENCES sessions(id),
+    kind TEXT NOT NULL,
+    seq INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    confidence REAL NOT NULL,
+    details TEXT
+);
+CREATE TABLE IF NOT EXISTS keystrokes (
+    session_id INTEGER NOT NULL REFERENCES sessions(id),
+    sequence INTEGER,
+    k_type TEXT NOT NULL,
+    seq INTEGER NOT NULL,
+    frame INTEGER,
+    timestamp INTEGER NOT NULL,
+    latency_us INTEGER NOT NULL,
+    response_size INTEGER
+);
+";
+
+/// Number of standard deviations from the corpus mean from which a keystroke rate is an outlier.
+pub const OUTLIER_STDDEVS: f64 = 2.0;
+
+/// Opens (or creates) a corpus and makes sure its tables exist.
+pub fn open(path: &str) -> rusqlite::Result<Connection> {
+    let connection = Connection::open(path)?;
+    connection.execute_batch(SCHEMA)?;
+    migrate(&connection)?;
+    Ok(connection)
+}
+
+/// Adds the columns introduced after a corpus was created.
+fn migrate(connection: &Connection) -> rusqlite::Result<()> {
+    let mut statement = connection.prepare("SELECT name FROM pragma_table_info('sessions')")?;
+    let columns = statement.query_map([], |row| row.get::<_, String>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+    if !columns.iter().any(|column| column == "host_key") {
+        connection.execute_batch("ALTER TABLE sessions ADD COLUMN host_key TEXT")?;
+    }
+    Ok(())
+}
+
+/// Host keys of all sessions in the corpus, oldest first.
+pub fn host_keys(connection: &Connection) -> rusqlite::Result<Vec<KeySighting>> {
+    let mut statement = connection.prepare(
+        "SELECT dst, host_key, capture, stream, start_utc FROM sessions WHERE host_key IS NOT NULL ORDER BY start_utc",
+    )?;
+    let rows = statement.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, u32>(3)?, row.get::<_, String>(4)?))
+    })?;
+
+    let mut sightings = vec![];
+    for row in rows {
+        let (server, host_key, capture, stream, start_utc) = row?;
+        if let Some((key_type, sha256)) = host_key.split_once(' ') {
+            sightings.push(KeySighting { server, key_type: key_type.to_string(), sha256: sha256.to_string(), capture, stream, start_utc });
+        }
+    }
+    Ok(sightings)
+}
+
+/// Appends the sessions of a capture, with their results and keystrokes, to the corpus.
+///
+/// Runs in a single transaction, so a failed run leaves no partial capture behind. Re-adding a
+/// capture adds its sessions again.
+pub fn store_sessions(connection: &mut Connection, capture: &str, sessions: &BTreeMap<u32, SshSession>) -> rusqlite::Result<()> {
+    let transaction = connection.transaction()?;
+
+    for session in sessions.values() {
+        transaction.execute(
+            "INSERT INTO sessions (capture, stream, src, dst, client_ip, protocol_client, protocol_server, hassh_c, hassh_s,
+                kex, enc, mac, cmp, start_utc, end_utc, keystroke_size, tags, host_key)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+            params![
+                capture,
+                session.stream,
+                session.src,
+                session.dst,
+                utils::ip_of(&session.src),
+                session.protocols.0,
+                session.protocols.1,
+                session.hassh_c,
+                session.hassh_s,
+                session.algorithms.0,
+                session.algorithms.1,
+                session.algorithms.2,
+                session.algorithms.3,
+                session.start_utc,
+                session.end_utc,
+                session.keystroke_size,
+                session.tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>().join(","),
+                session.host_key.as_ref().map(|host_key| format!("{} {}", host_key.key_type, host_key.sha256)),
+            ],
+        )?;
+        let session_id = transaction.last_insert_rowid();
+
+        let mut insert_result = transaction.prepare_cached(
+            "INSERT INTO results (session_id, kind, seq, timestamp, confidence, details) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        for finding in &session.results {
+            insert_result.execute(params![session_id, finding.kind.to_string(), finding.packet_ref.seq, finding.timestamp, finding.confidence, finding.details])?;
+        }
+
+        let mut insert_keystroke = transaction.prepare_cached(
+            "INSERT INTO keystrokes (session_id, sequence, k_type, seq, frame, timestamp, latency_us, response_size)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )?;
+        for keystroke in session.keystroke_data.iter().flat_map(|sequence| &sequence.keystrokes) {
+            insert_keystroke.execute(params![
+                session_id,
+                keystroke.sequence,
+                format!("{:?}", keystroke.k_type),
+                keystroke.seq,
+                keystroke.frame,
+                keystroke.timestamp,
+                keystroke.latency_us,
+                keystroke.response_size.map(|size| size as i64),
+            ])?;
+        }
+    }
+
+    transaction.commit()
+}
+
+/// Operator fingerprints of all sessions in the corpus that have keystrokes, for linking them to
+/// the sessions of new captures.
+///
+/// The corpus keeps the types and latencies of keystrokes, so typing speed, rhythm, command length
+/// and error rate can be rebuilt, but not the share of symbol-heavy commands.
+pub fn operator_fingerprints(connection: &Connection) -> rusqlite::Result<Vec<OperatorFingerprint>> {
+    let mut statement = connection.prepare(
+        "SELECT s.id, s.capture, s.stream, s.client_ip, s.hassh_c, s.protocol_client, s.start_utc, k.sequence, k.k_type, k.latency_us
+         FROM sessions s JOIN keystrokes k ON s.id = k.session_id ORDER BY s.id, k.rowid",
+    )?;
+    let mut rows = statement.query([])?;
+
+    let mut fingerprints: BTreeMap<i64, (OperatorFingerprint, Vec<Keystroke>)> = BTreeMap::new();
+    while let Some(row) = rows.next()? {
+        let k_type = match keystroke_type(&row.get::<_, String>(8)?) {
+            Some(k_type) => k_type,
+            None => continue,
+        };
+        let keystroke = Keystroke { sequence: row.get(7)?, ..Keystroke::with_latency(k_type, row.get(9)?) };
+
+        let entry = match fingerprints.entry(row.get(0)?) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let start_utc: String = row.get(6)?;
+                let fingerprint = OperatorFingerprint {
+                    capture: row.get(1)?,
+                    stream: row.get(2)?,
+                    client_ip: row.get(3)?,
+                    hassh_c: row.get(4)?,
+                    banner: row.get(5)?,
+                    typing: Default::default(),
+                    rhythm: Default::default(),
+                    start_hour: utils::parse_session_time(&start_utc)
+                        .and_then(|timestamp| Utc.timestamp_micros(timestamp).single())
+                        .map(|datetime| datetime.hour()),
+                    command_length: None,
+                    error_rate: 0.0,
+                    symbol_share: None,
+                };
+                entry.insert((fingerprint, vec![]))
+            },
+        };
+        entry.1.push(keystroke);
+    }
+
+    Ok(fingerprints.into_values()
+        .map(|(mut fingerprint, keystrokes)| {
+            // Keystrokes are stored in order, so consecutive ones of a sequence are its digraphs
+            fingerprint.rhythm = TypingRhythm::from_digraphs(keystrokes.windows(2)
+                .filter(|pair| pair[0].sequence == pair[1].sequence)
+                .map(|pair| (&pair[0].k_type, &pair[1].k_type, pair[1].latency_us)));
+            let sequences = keystrokes.iter().filter_map(|keystroke| keystroke.sequence).collect::<BTreeSet<_>>().len().max(1);
+            fingerprint.typing = stats::latency_stats(&keystrokes, stats::MIN_LATENCY_CONFIDENCE);
+            fingerprint.command_length = Some(keystrokes.len() as f64 / sequences as f64);
+            fingerprint.error_rate = utils::error_rate(&keystrokes);
+            fingerprint
+        })
+        .collect())
+}
+
+/// Keystroke type from its name as stored.
+fn keystroke_type(name: &str) -> Option<KeystrokeType> {
+    use KeystrokeType::*;
+    [Keystroke, Delete, Tab, Enter, ArrowHorizontal, ArrowVertical, Multibyte, Unknown, Chaff].into_iter()
+        .find(|k_type| format!("{k_type:?}") == name)
+}
+
+/// Runs a canned query.
+pub fn run_query(connection: &Connection, query: &Query) -> rusqlite::Result<Table> {
+    const SESSION_COLUMNS: &str = "s.id, s.capture, s.stream, s.src, s.dst, s.protocol_client, s.start_utc, s.tags";
+
+    match query {
+        Query::ByClient { ip } => select(
+            connection,
+            &format!("SELECT {SESSION_COLUMNS} FROM sessions s WHERE s.client_ip = ?1 ORDER BY s.start_utc"),
+            params![ip],
+        ),
+        Query::FailedLogins { limit } => select(
+            connection,
+            "SELECT s.client_ip, COUNT(*) AS wrong_passwords, COUNT(DISTINCT s.id) AS sessions
+             FROM sessions s JOIN results r ON s.id = r.session_id
+             WHERE r.kind = 'WrongPassword' GROUP BY s.client_ip ORDER BY wrong_passwords DESC LIMIT ?1",
+            params![limit],
+        ),
+        Query::WithFinding { kind } => select(
+            connection,
+            &format!("SELECT DISTINCT {SESSION_COLUMNS} FROM sessions s JOIN results r ON s.id = r.session_id WHERE r.kind = ?1 ORDER BY s.start_utc"),
+            params![kind],
+        ),
+        Query::KeystrokeOutliers => keystroke_outliers(connection),
+        Query::HostKeyChanges => select(
+            connection,
+            "SELECT s.dst, substr(s.host_key, 1, instr(s.host_key, ' ') - 1) AS key_type, s.host_key,
+                 MIN(s.start_utc) AS first_seen, MAX(s.start_utc) AS last_seen, COUNT(*) AS sessions
+             FROM sessions s
+             WHERE s.host_key IS NOT NULL AND (s.dst, substr(s.host_key, 1, instr(s.host_key, ' ') - 1)) IN (
+                 SELECT dst, substr(host_key, 1, instr(host_key, ' ') - 1) FROM sessions WHERE host_key IS NOT NULL
+                 GROUP BY 1, 2 HAVING COUNT(DISTINCT host_key) > 1
+             )
+             GROUP BY s.dst, s.host_key ORDER BY s.dst, first_seen",
+            [],
+        ),
+    }
+}
+
+/// Runs a statement and collects all of its rows as text.
+fn select(connection: &Connection, sql: &str, params: impl rusqlite::Params) -> rusqlite::Result<Table> {
+    let mut statement = connection.prepare(sql)?;
+    let columns: Vec<String> = statement.column_names().iter().map(|name| name.to_string()).collect();
+    let count = columns.len();
+
+    let rows = statement.query_map(params, |row| {
+        (0..count).map(|index| row.get_ref(index).map(as_text)).collect()
+    })?.collect::<rusqlite::Result<_>>()?;
+
+    Ok(Table { columns, rows })
+}
+
+/// Sessions whose mean keystroke latency is more than [OUTLIER_STDDEVS] away from the mean of all
+/// sessions.
+fn keystroke_outliers(connection: &Connection) -> rusqlite::Result<Table> {
+    // The first keystroke of a sequence has no latency, so it is left out.
+    let table = select(
+        connection,
+        "SELECT s.id, s.capture, s.stream, s.src, AVG(k.latency_us) AS mean_latency_us, COUNT(*) AS keystrokes
+         FROM sessions s JOIN keystrokes k ON s.id = k.session_id WHERE k.latency_us > 0 GROUP BY s.id",
+        [],
+    )?;
+
+    let means: Vec<f64> = table.rows.iter().map(|row| row[4].parse().unwrap_or_default()).collect();
+    if means.len() < 2 {
+        return Ok(Table { columns: table.columns, rows: vec![] });
+    }
+    let mean = means.iter().sum::<f64>() / means.len() as f64;
+    let stddev = (means.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / means.len() as f64).sqrt();
+
+    let rows = table.rows.into_iter().zip(means)
+        .filter(|(_, value)| (value - mean).abs() > OUTLIER_STDDEVS * stddev)
+        .map(|(row, _)| row)
+        .collect();
+
+    Ok(Table { columns: table.columns, rows })
+}
+
+fn as_text(value: ValueRef) -> String {
+    match value {
+        ValueRef::Null => String::new(),
+        ValueRef::Integer(value) => value.to_string(),
+        ValueRef::Real(value) => format!("{value:.1}"),
+        ValueRef::Text(value) | ValueRef::Blob(value) => String::from_utf8_lossy(value).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::core::analyse;
+    use crate::analyser::filter::StreamFilter;
+    use crate::analyser::operators;
+    use crate::analyser::options::{AnalysisOptions, Decoding, Engine};
+
+    #[test]
+    fn test_operator_fingerprints() {
+        let capture = format!("{}/test_captures/known_pass_lsal_id_exit.pcapng", env!("CARGO_MANIFEST_DIR"));
+        let (streams, tcp) = utils::load_capture_reporting(&capture, -1, None, &StreamFilter::default(), Engine::Native, &Decoding::default(), |_, _| {}).unwrap();
+        let session = analyse(0, &streams[&0], &tcp[&0], &AnalysisOptions::default()).unwrap();
+        let fingerprint = OperatorFingerprint::new("capture.pcapng", &session);
+
+        // Sessions stored in a corpus come back with the same typing statistics
+        let path = std::env::temp_dir().join(format!("sshniff-test-{}-rhythm.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut connection = open(path.to_str().unwrap()).unwrap();
+        store_sessions(&mut connection, "capture.pcapng", &BTreeMap::from([(0, session)])).unwrap();
+        let stored = operator_fingerprints(&connection).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(1, stored.len());
+        assert_eq!(fingerprint.rhythm, stored[0].rhythm);
+        assert_eq!(fingerprint.typing.median_us, stored[0].typing.median_us);
+        assert_eq!(fingerprint.command_length, stored[0].command_length);
+
+        // A capture and its stored copy are only paired when sessions of one client are
+        let pair = [fingerprint, stored[0].clone()];
+        assert!(operators::link_operators(&pair, 0.0, false).is_empty());
+        assert!(operators::link_operators(&pair, 0.0, true)[0].score > 0.99);
+    }
+}
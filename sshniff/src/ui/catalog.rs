@@ -0,0 +1,106 @@
+//! Message catalogs for finding descriptions.
+//!
+//! Descriptions are looked up by finding ID, i.e. the [Event] variant name that serialised output
+//! uses. The English catalog is bundled (`data/messages/en.txt`); translations are files in the
+//! same format and only replace the descriptions they list. Console, HTML, and PDF output are
+//! localised, while JSON and the SIEM formats keep the IDs so that they stay machine-readable.
+use crate::analyser::containers::{Event, Finding};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::OnceLock;
+
+/// The bundled English descriptions.
+const ENGLISH: &str = include_str!("../../data/messages/en.txt");
+
+/// Finding descriptions by finding ID.
+#[derive(Clone, Debug)]
+pub struct Catalog {
+    messages: HashMap<String, String>,
+}
+
+impl Catalog {
+    /// The bundled English catalog.
+    pub fn english() -> &'static Catalog {
+        static ENGLISH_CATALOG: OnceLock<Catalog> = OnceLock::new();
+        ENGLISH_CATALOG.get_or_init(|| Self::parse(ENGLISH).expect("bundled message catalog is valid"))
+    }
+
+    /// Catalog for `locale`: `en` for the bundled one, or the path of a translation, which falls
+    /// back to English for the IDs it leaves out.
+    pub fn load(locale: &str) -> Result<Self, String> {
+        let english = Self::english();
+        if locale == "en" {
+            return Ok(english.clone());
+        }
+
+        let contents = fs::read_to_string(locale).map_err(|err| format!("Failed to read message catalog {locale}: {err}"))?;
+        let translation = Self::parse(&contents).map_err(|err| format!("{locale}: {err}"))?;
+        for id in translation.messages.keys().filter(|id| !english.messages.contains_key(*id)) {
+            log::warn!("{locale}: unknown finding ID '{id}'");
+        }
+
+        let mut catalog = english.clone();
+        catalog.messages.extend(translation.messages);
+        Ok(catalog)
+    }
+
+    /// Parses `ID = description` lines. Empty lines and lines starting with `#` are ignored.
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut messages = HashMap::new();
+
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match line.split_once('=') {
+                Some((id, message)) if !id.trim().is_empty() && !message.trim().is_empty() => {
+                    messages.insert(id.trim().to_string(), message.trim().to_string());
+                },
+                _ => return Err(format!("line {}: expected ID = description", number + 1)),
+            }
+        }
+
+        Ok(Self { messages })
+    }
+
+    /// Whether the catalog describes a finding ID.
+    pub fn contains(&self, id: &str) -> bool {
+        self.messages.contains_key(id)
+    }
+
+    /// Description of a finding ID; the ID itself if the catalog has none.
+    pub fn event(&self, kind: Event) -> String {
+        let id = kind.to_string();
+        match self.messages.get(&id) {
+            Some(message) => message.clone(),
+            None => id,
+        }
+    }
+
+    /// Description of a finding, including its details if there are any.
+    pub fn describe(&self, finding: &Finding) -> String {
+        match &finding.details {
+            Some(details) => format!("{} ({details})", self.event(finding.kind)),
+            None => self.event(finding.kind),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::containers;
+
+    #[test]
+    fn test_catalog() {
+        assert_eq!("Server hostkey accepted", containers::Event::HostKeyAccepted.description());
+        assert_eq!("WrongPassword", Catalog::english().event(containers::Event::WrongPassword));
+
+        let german = Catalog::parse("# de\nWrongPassword = Falsches Passwort").unwrap();
+        assert_eq!("Falsches Passwort", german.event(containers::Event::WrongPassword));
+        assert_eq!("Rekey", german.event(containers::Event::Rekey));
+        assert!(Catalog::parse("WrongPassword").is_err());
+    }
+}
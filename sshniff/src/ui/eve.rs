@@ -0,0 +1,118 @@
+//! Suricata EVE-style JSON events.
+//!
+//! One JSON object per line: an `ssh` event per session, laid out like Suricata's own, and an
+//! `sshniff` event per finding and per command. All events share the standard `timestamp`,
+//! `flow_id`, `src_ip`, `src_port`, `dest_ip`, `dest_port`, `proto`, and `event_type` fields,
+//! so SIEM pipelines that ingest EVE can take them as they are.
+use crate::analyser::core::SshSession;
+use crate::analyser::utils;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// Timestamps as Suricata writes them: `2024-01-31T12:00:00.123456+0000`.
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.6f%z";
+
+/// Returns the sessions as EVE JSON lines.
+pub fn sessions_as_eve(sessions: &BTreeMap<u32, SshSession>, capture: &str) -> Result<String, serde_json::Error> {
+    let mut lines: Vec<String> = Vec::new();
+
+    for session in sessions.values() {
+        let event = |timestamp: i64, event_type: &str, body: Value| {
+            let mut event = base(session, capture, timestamp, event_type);
+            event.insert(event_type.to_string(), body);
+            serde_json::to_string(&event)
+        };
+
+        lines.push(event(utils::parse_session_time(&session.start_utc).unwrap_or_default(), "ssh", json!({
+            "client": {
+                "proto_version": "2.0",
+                "software_version": software(&session.protocols.0),
+                "hassh": { "hash": session.hassh_c },
+            },
+            "server": {
+                "proto_version": "2.0",
+                "software_version": software(&session.protocols.1),
+                "hassh": { "hash": session.hassh_s },
+                "host_key": session.host_key,
+            },
+        }))?);
+
+        for finding in &session.results {
+            lines.push(event(finding.timestamp, "sshniff", json!({
+                "kind": finding.kind,
+                "description": finding.kind.description(),
+                "details": finding.details,
+                "confidence": finding.confidence,
+                "tcp_seq": finding.packet_ref.seq,
+            }))?);
+        }
+
+        for sequence in &session.keystroke_data {
+            lines.push(event(sequence.start, "sshniff", json!({
+                "kind": "KeystrokeBurst",
+                "description": format!("Command #{}", sequence.id),
+                "keystrokes": sequence.keystrokes.len(),
+                "error_rate": sequence.error_rate,
+                "response_size": sequence.keystrokes.last().and_then(|keystroke| keystroke.response_size).map(|size| size as u64),
+                "confidence": 1.0,
+                "tcp_seq": sequence.keystrokes.first().map(|keystroke| keystroke.seq),
+            }))?);
+        }
+    }
+
+    Ok(lines.iter().map(|line| format!("{line}\n")).collect())
+}
+
+/// Fields shared by all events of a session.
+fn base(session: &SshSession, capture: &str, timestamp: i64, event_type: &str) -> Map<String, Value> {
+    let split = |address: &str| {
+        let (ip, port) = utils::split_endpoint(address);
+        (ip.to_string(), port.and_then(|port| port.parse::<u16>().ok()).map_or(Value::Null, Value::from))
+    };
+    let (src_ip, src_port) = split(&session.src);
+    let (dest_ip, dest_port) = split(&session.dst);
+
+    let mut event = Map::new();
+    event.insert("timestamp".to_string(), Value::from(utils::format_timestamp_as(timestamp, TIMESTAMP_FORMAT)));
+    event.insert("flow_id".to_string(), Value::from(flow_id(capture, session.stream)));
+    event.insert("src_ip".to_string(), Value::from(src_ip));
+    event.insert("src_port".to_string(), src_port);
+    event.insert("dest_ip".to_string(), Value::from(dest_ip));
+    event.insert("dest_port".to_string(), dest_port);
+    event.insert("proto".to_string(), Value::from("TCP"));
+    event.insert("event_type".to_string(), Value::from(event_type));
+    event
+}
+
+/// Flow id, stable for a capture and stream. Suricata's are opaque too, so only the tuple
+/// correlates with its own events.
+fn flow_id(capture: &str, stream: u32) -> u64 {
+    let hash = utils::get_md5_hash(format!("{capture}:{stream}"));
+    u64::from_str_radix(&hash[..13], 16).unwrap_or_default()
+}
+
+/// Software part of a version banner, e.g. `OpenSSH_9.6` of `SSH-2.0-OpenSSH_9.6`.
+fn software(banner: &str) -> &str {
+    banner.splitn(3, '-').nth(2).unwrap_or(banner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::core::tests::session;
+
+    #[test]
+    fn test_sessions_as_eve() {
+        let eve = sessions_as_eve(&BTreeMap::from([(3, session())]), "capture.pcapng").unwrap();
+        let dest = r#""dest_ip":"10.0.0.1","dest_port":22,"event_type":"#;
+        let flow = r#""flow_id":3118909779844240,"proto":"TCP","src_ip":"192.168.1.10","src_port":51514"#;
+
+        assert_eq!(vec![
+            format!(r#"{{{dest}"ssh",{flow},"ssh":{{"client":{{"hassh":{{"hash":"ec7378c1a92f5a8dde7e8b7a1ddf33d1"}},"proto_version":"2.0","software_version":"OpenSSH_9.6"}},"server":{{"hassh":{{"hash":"b12d2871a1189eff20364cf5333619ee"}},"host_key":null,"proto_version":"2.0","software_version":"OpenSSH_8.9p1 Ubuntu-3ubuntu0.6"}}}},"timestamp":"2024-01-31T12:00:00.000000+0000"}}"#),
+            format!(r#"{{{dest}"sshniff",{flow},"sshniff":{{"confidence":0.5,"description":"WrongPassword","details":null,"kind":"WrongPassword","tcp_seq":1342}},"timestamp":"2024-01-31T12:00:02.500000+0000"}}"#),
+            format!(r#"{{{dest}"sshniff",{flow},"sshniff":{{"confidence":0.75,"description":"Port forwarding","details":"to \"db|main\"=5432\tvia C:\\tmp <x> & y\nnext","kind":"PortForwarding","tcp_seq":3718}},"timestamp":"2024-01-31T12:00:09.000000+0000"}}"#),
+            format!(r#"{{{dest}"sshniff",{flow},"sshniff":{{"confidence":1.0,"description":"Command #1","error_rate":0.25,"keystrokes":3,"kind":"KeystrokeBurst","response_size":512,"tcp_seq":2000}},"timestamp":"2024-01-31T12:00:04.000000+0000"}}"#),
+        ], eve.lines().collect::<Vec<_>>());
+        assert!(eve.ends_with('\n'));
+    }
+}
@@ -0,0 +1,190 @@
+//! CEF and LEEF serialisers for SIEM ingestion (ArcSight, QRadar).
+//!
+//! Every session yields one summary message, followed by one message per [Finding] on its
+//! timeline. Messages are single lines without a syslog header, so they can be written to a file
+//! or handed to a syslog sender as they are.
+use crate::analyser::containers::{Event, Finding};
+use crate::analyser::core::SshSession;
+use crate::analyser::tags::Tag;
+use crate::analyser::utils;
+use std::collections::BTreeMap;
+
+const VENDOR: &str = "SSHniff";
+const PRODUCT: &str = "SSHniff";
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// SIEM message formats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SiemFormat {
+    /// ArcSight Common Event Format
+    Cef,
+    /// QRadar Log Event Extended Format (1.0)
+    Leef,
+}
+
+/// Returns one message per session summary and per finding, each on its own line.
+pub fn sessions_as_messages(sessions: &BTreeMap<u32, SshSession>, format: SiemFormat) -> String {
+    sessions.values()
+        .flat_map(|session| session_messages(session, format))
+        .map(|message| format!("{message}\n"))
+        .collect()
+}
+
+/// Returns the summary message of a session, followed by one message per finding.
+pub fn session_messages(session: &SshSession, format: SiemFormat) -> Vec<String> {
+    let mut messages = vec![session_message(session, format)];
+    messages.extend(session.results.iter().map(|finding| finding_message(session, finding, format)));
+    messages
+}
+
+/// Summary message of a session.
+pub fn session_message(session: &SshSession, format: SiemFormat) -> String {
+    let severity = if session.tags.contains(&Tag::BruteForced) { 6 } else { 3 }.max(session.severity);
+    let tags = session.tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>().join(",");
+
+    let mut fields = connection_fields(session, utils::parse_session_time(&session.start_utc).unwrap_or_default());
+    fields.extend([
+        ("client", session.protocols.0.clone()),
+        ("server", session.protocols.1.clone()),
+        ("hasshClient", session.hassh_c.clone()),
+        ("hasshServer", session.hassh_s.clone()),
+        ("cipher", session.algorithms.1.clone()),
+        ("tags", tags),
+        ("commands", session.keystroke_data.len().to_string()),
+    ]);
+
+    message(format, "Session", "SSH session", severity, fields)
+}
+
+/// Message for a single finding of a session.
+pub fn finding_message(session: &SshSession, finding: &Finding, format: SiemFormat) -> String {
+    let mut fields = connection_fields(session, finding.timestamp);
+    fields.extend([
+        ("tcpSeq", finding.packet_ref.seq.to_string()),
+        ("confidence", format!("{:.2}", finding.confidence)),
+    ]);
+    if let Some(details) = &finding.details {
+        fields.push(("msg", details.clone()));
+    }
+
+    // Threat matches carry the severity of the matched indicators
+    let severity = if finding.kind == Event::ThreatMatch { session.severity } else { severity(finding.kind) };
+    message(format, &finding.kind.to_string(), &finding.kind.description(), severity, fields)
+}
+
+/// Severity (0-10) of a finding.
+fn severity(kind: Event) -> u8 {
+    match kind {
+        Event::HostKeyMismatch | Event::HostKeyChanged => 9,
+        Event::NoneAuth => 8,
+        Event::Exfiltration | Event::PasswordSpray => 7,
+        Event::WrongPassword | Event::RejectedKey | Event::PasswordEntry => 5,
+        Event::PortForwarding | Event::FileTransfer | Event::NestedSession => 6,
+        Event::HostKeyAccepted | Event::PathChange | Event::TimestampCorrection | Event::WeakCrypto => 4,
+        _ => 3,
+    }
+}
+
+/// Time and connection tuple, under neutral names that [message] maps to each format's keys.
+fn connection_fields(session: &SshSession, timestamp: i64) -> Vec<(&'static str, String)> {
+    let split = |address: &str| {
+        let (ip, port) = utils::split_endpoint(address);
+        (ip.to_string(), port.unwrap_or_default().to_string())
+    };
+    let (src, src_port) = split(&session.src);
+    let (dst, dst_port) = split(&session.dst);
+
+    vec![
+        ("time", (timestamp / 1000).to_string()),
+        ("src", src),
+        ("srcPort", src_port),
+        ("dst", dst),
+        ("dstPort", dst_port),
+        ("stream", session.stream.to_string()),
+    ]
+}
+
+fn message(format: SiemFormat, id: &str, name: &str, severity: u8, fields: Vec<(&'static str, String)>) -> String {
+    match format {
+        SiemFormat::Cef => cef(id, name, severity, fields),
+        SiemFormat::Leef => leef(id, name, severity, fields),
+    }
+}
+
+/// `CEF:0|Vendor|Product|Version|SignatureID|Name|Severity|Extension`
+fn cef(id: &str, name: &str, severity: u8, fields: Vec<(&'static str, String)>) -> String {
+    let header = |value: &str| value.replace('\\', "\\\\").replace('|', "\\|");
+    let value = |value: &str| value.replace('\\', "\\\\").replace('=', "\\=").replace('\n', "\\n");
+
+    // Keys CEF defines; everything else goes into labelled custom fields, of which CEF has six
+    // strings, three numbers, and four floats.
+    let (mut strings, mut numbers, mut floats) = (0, 0, 0);
+    let extension: Vec<String> = fields.into_iter()
+        .filter(|(_, field)| !field.is_empty())
+        .map(|(key, field)| {
+            let custom = match key {
+                "time" => return format!("rt={}", value(&field)),
+                "src" => return format!("src={}", value(&field)),
+                "srcPort" => return format!("spt={}", value(&field)),
+                "dst" => return format!("dst={}", value(&field)),
+                "dstPort" => return format!("dpt={}", value(&field)),
+                "msg" => return format!("msg={}", value(&field)),
+                "stream" | "tcpSeq" | "commands" => { numbers += 1; format!("cn{numbers}") },
+                "confidence" => { floats += 1; format!("cfp{floats}") },
+                _ => { strings += 1; format!("cs{strings}") },
+            };
+            format!("{custom}Label={key} {custom}={}", value(&field))
+        })
+        .collect();
+
+    format!("CEF:0|{VENDOR}|{PRODUCT}|{VERSION}|{}|{}|{severity}|proto=TCP {}", header(id), header(name), extension.join(" "))
+}
+
+/// `LEEF:1.0|Vendor|Product|Version|EventID|` followed by tab-separated attributes.
+fn leef(id: &str, name: &str, severity: u8, fields: Vec<(&'static str, String)>) -> String {
+    let header = |value: &str| value.replace('|', " ");
+    let value = |value: &str| value.replace(['\t', '\n'], " ");
+
+    let mut attributes = vec![format!("cat={}", value(name)), format!("sev={severity}"), "proto=TCP".to_string()];
+    attributes.extend(fields.into_iter()
+        .filter(|(_, field)| !field.is_empty())
+        .map(|(key, field)| match key {
+            // Epoch milliseconds, which QRadar takes without a devTimeFormat.
+            "time" => format!("devTime={}", value(&field)),
+            other => format!("{other}={}", value(&field)),
+        }));
+
+    format!("LEEF:1.0|{VENDOR}|{PRODUCT}|{VERSION}|{}|{}", header(id), attributes.join("\t"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::core::tests::session;
+
+    #[test]
+    fn test_cef() {
+        let sessions = BTreeMap::from([(3, session())]);
+        let connection = |time: i64| format!("rt={time} src=192.168.1.10 spt=51514 dst=10.0.0.1 dpt=22 cn1Label=stream cn1=3");
+
+        // Pipes are only escaped in the header, equals signs and backslashes only in the extension
+        assert_eq!(format!("\
+CEF:0|SSHniff|SSHniff|{VERSION}|Session|SSH session|3|proto=TCP {} cs1Label=client cs1=SSH-2.0-OpenSSH_9.6 cs2Label=server cs2=SSH-2.0-OpenSSH_8.9p1 Ubuntu-3ubuntu0.6 cs3Label=hasshClient cs3=ec7378c1a92f5a8dde7e8b7a1ddf33d1 cs4Label=hasshServer cs4=b12d2871a1189eff20364cf5333619ee cs5Label=cipher cs5=chacha20-poly1305@openssh.com cs6Label=tags cs6=interactive,tunneled cn2Label=commands cn2=1
+CEF:0|SSHniff|SSHniff|{VERSION}|WrongPassword|WrongPassword|5|proto=TCP {} cn2Label=tcpSeq cn2=1342 cfp1Label=confidence cfp1=0.50
+CEF:0|SSHniff|SSHniff|{VERSION}|PortForwarding|Port forwarding|6|proto=TCP {} cn2Label=tcpSeq cn2=3718 cfp1Label=confidence cfp1=0.75 msg=to \"db|main\"\\=5432\tvia C:\\\\tmp <x> & y\\nnext
+", connection(1706702400000), connection(1706702402500), connection(1706702409000)), sessions_as_messages(&sessions, SiemFormat::Cef));
+    }
+
+    #[test]
+    fn test_leef() {
+        let sessions = BTreeMap::from([(3, session())]);
+        let connection = "src=192.168.1.10\tsrcPort=51514\tdst=10.0.0.1\tdstPort=22\tstream=3";
+
+        // Tabs and newlines in values would split attributes, so they become spaces
+        assert_eq!(format!("\
+LEEF:1.0|SSHniff|SSHniff|{VERSION}|Session|cat=SSH session\tsev=3\tproto=TCP\tdevTime=1706702400000\t{connection}\tclient=SSH-2.0-OpenSSH_9.6\tserver=SSH-2.0-OpenSSH_8.9p1 Ubuntu-3ubuntu0.6\thasshClient=ec7378c1a92f5a8dde7e8b7a1ddf33d1\thasshServer=b12d2871a1189eff20364cf5333619ee\tcipher=chacha20-poly1305@openssh.com\ttags=interactive,tunneled\tcommands=1
+LEEF:1.0|SSHniff|SSHniff|{VERSION}|WrongPassword|cat=WrongPassword\tsev=5\tproto=TCP\tdevTime=1706702402500\t{connection}\ttcpSeq=1342\tconfidence=0.50
+LEEF:1.0|SSHniff|SSHniff|{VERSION}|PortForwarding|cat=Port forwarding\tsev=6\tproto=TCP\tdevTime=1706702409000\t{connection}\ttcpSeq=3718\tconfidence=0.75\tmsg=to \"db|main\"=5432 via C:\\tmp <x> & y next
+"), sessions_as_messages(&sessions, SiemFormat::Leef));
+    }
+}
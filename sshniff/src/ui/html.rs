@@ -0,0 +1,139 @@
+//! Standalone HTML reports.
+//!
+//! Each session is rendered into a single file with inline styling and no external resources, so
+//! it can be attached to a ticket and opened anywhere. The layout comes from [report].
+use crate::analyser::core::SshSession;
+use super::catalog::Catalog;
+use super::remediation::RemediationSet;
+use super::report::{self, Block, Report};
+use std::fmt::Write;
+
+const STYLE: &str = "\
+body { font-family: monospace; margin: 2em; color: #222; }
+h1 { font-size: 1.4em; }
+h2 { font-size: 1.1em; margin-top: 2em; border-bottom: 1px solid #ccc; }
+table { border-collapse: collapse; margin: 0.5em 0; }
+td, th { padding: 2px 10px; text-align: left; vertical-align: top; }
+th { background: #eee; }
+tr:nth-child(even) td { background: #f8f8f8; }
+.enter { font-weight: bold; }
+.muted { color: #888; }
+";
+
+/// Renders a session (metadata, event timeline, remediation, keystroke sequences) as a standalone
+/// HTML page.
+pub fn session_as_html(session: &SshSession, capture: &str, catalog: &Catalog, remediation: &RemediationSet) -> String {
+    report_as_html(&report::session_report(session, capture, catalog, remediation))
+}
+
+/// Renders a [Report] as a standalone HTML page.
+pub fn report_as_html(report: &Report) -> String {
+    let mut html = String::new();
+
+    let _ = write!(html, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{STYLE}</style>\n</head>\n<body>\n", escape(&report.title));
+    let _ = writeln!(html, "<h1>{}</h1>", escape(&report.title));
+
+    for section in &report.sections {
+        let _ = writeln!(html, "<h2>{}</h2>", escape(&section.heading));
+        for block in &section.blocks {
+            write_block(&mut html, block);
+        }
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn write_block(html: &mut String, block: &Block) {
+    match block {
+        Block::Fields(fields) => {
+            html.push_str("<table>\n");
+            for (name, value) in fields {
+                let _ = writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", escape(name), escape(value));
+            }
+            html.push_str("</table>\n");
+        },
+        Block::Table { header, rows } => {
+            let _ = writeln!(html, "<table>\n<tr>{}</tr>", header.iter().map(|name| format!("<th>{}</th>", escape(name))).collect::<String>());
+            for row in rows {
+                let class = if row.emphasis { " class=\"enter\"" } else { "" };
+                let _ = writeln!(html, "<tr{class}>{}</tr>", row.cells.iter().map(|cell| format!("<td>{}</td>", escape(cell))).collect::<String>());
+            }
+            html.push_str("</table>\n");
+        },
+        Block::Note(text) => {
+            let _ = writeln!(html, "<p class=\"muted\">{}</p>", escape(text));
+        },
+        Block::Subheading { text, note } => {
+            let _ = writeln!(html, "<h3>{} <span class=\"muted\">{}</span></h3>", escape(text), escape(note));
+        },
+        Block::List(items) => {
+            html.push_str("<ul>\n");
+            for item in items {
+                let _ = writeln!(html, "<li>{}</li>", escape(item));
+            }
+            html.push_str("</ul>\n");
+        },
+    }
+}
+
+/// Escapes text for use in HTML content and attributes.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::core::tests::session;
+
+    #[test]
+    fn test_session_as_html() {
+        let html = session_as_html(&session(), "capture.pcapng", Catalog::english(), &RemediationSet::parse("[]").unwrap());
+
+        assert!(html.starts_with("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>SSHniff: capture.pcapng stream 3</title>\n<style>\n"));
+        assert_eq!(Some("\n</head>\n<body>\n\
+<h1>SSHniff: capture.pcapng stream 3</h1>
+<h2>Session</h2>
+<table>
+<tr><th>Duration (UTC)</th><td>2024-01-31 12:00:00 - 2024-01-31 12:00:10</td></tr>
+<tr><th>Client</th><td>192.168.1.10:51514 (SSH-2.0-OpenSSH_9.6)</td></tr>
+<tr><th>Server</th><td>10.0.0.1:22 (SSH-2.0-OpenSSH_8.9p1 Ubuntu-3ubuntu0.6)</td></tr>
+<tr><th>HASSH client</th><td>ec7378c1a92f5a8dde7e8b7a1ddf33d1</td></tr>
+<tr><th>HASSH server</th><td>b12d2871a1189eff20364cf5333619ee</td></tr>
+<tr><th>Tags</th><td>interactive, tunneled</td></tr>
+<tr><th>Severity</th><td>0/10</td></tr>
+<tr><th>KEX</th><td>curve25519-sha256</td></tr>
+<tr><th>Encryption</th><td>chacha20-poly1305@openssh.com</td></tr>
+<tr><th>MAC</th><td>hmac-sha2-256</td></tr>
+<tr><th>Compression</th><td>none</td></tr>
+<tr><th>Host key</th><td></td></tr>
+<tr><th>Bytes after login</th><td>0 client / 0 server</td></tr>
+<tr><th>RTT</th><td>0μs client / 0μs server</td></tr>
+<tr><th>Signature</th><td></td></tr>
+<tr><th>Size confidence</th><td>keystroke 0 (low), prompt 0 (low), login success (low)</td></tr>
+</table>
+<h2>Timeline of Events</h2>
+<table>
+<tr><th>tcp.seq</th><th>Time (UTC)</th><th>Event</th><th>Confidence</th></tr>
+<tr><td>1342</td><td>2024-01-31 12:00:02.500000</td><td>WrongPassword</td><td>50%</td></tr>
+<tr><td>3718</td><td>2024-01-31 12:00:09.000000</td><td>Port forwarding (to &quot;db|main&quot;=5432\tvia C:\\tmp &lt;x&gt; &amp; y
+next)</td><td>75%</td></tr>
+</table>
+<h2>Keystroke Sequences</h2>
+<p class=\"muted\">Error rate 0%</p>
+<h3>Command #1 <span class=\"muted\">(2024-01-31 12:00:04.000000, length 0, error rate 25%, human)</span></h3>
+<table>
+<tr><th>tcp.seq</th><th>Latency μs</th><th>Type</th><th>Confidence</th><th>Response</th><th>Likely command</th></tr>
+<tr><td>2000</td><td>0</td><td>Keystroke</td><td>100%</td><td></td><td></td></tr>
+<tr><td>2036</td><td>200000</td><td>Delete</td><td>80%</td><td></td><td></td></tr>
+<tr class=\"enter\"><td>2072</td><td>300000</td><td>Enter</td><td>100%</td><td>512</td><td></td></tr>
+</table>
+</body>
+</html>
+"), html.split_once("</style>").map(|(_, body)| body));
+    }
+}
@@ -2,3 +2,16 @@
 //! 
 //! Contains serialisation calls and CLI pretty-printing.
 pub mod output;
+pub mod catalog;
+pub mod remediation;
+pub mod report;
+pub mod html;
+pub mod pdf;
+pub mod zeek;
+pub mod eve;
+pub mod formats;
+pub mod syslog;
+pub mod progress;
+pub mod pcapng;
+#[cfg(feature = "tui")]
+pub mod tui;
@@ -2,19 +2,37 @@
 //! 
 //! (it was not fun doing this bit. unicode tables drove me mad).
 use crate::analyser::core::SshSession;
-use crate::analyser::containers::{self, Keystroke, KeystrokeType};
-use std::collections::HashMap;
+use crate::analyser::compare::{Posture, PostureChange, SessionComparison};
+use crate::analyser::nat::NatReport;
+use crate::analyser::audit::EndpointAudit;
+use crate::analyser::operators::Link;
+use crate::analyser::health::{HealthReport, Status};
+use crate::analyser::discovery::StreamSummary;
+use crate::analyser::software::{Software, Source};
+use crate::analyser::advisories::Advisory;
+use crate::analyser::stats;
+use crate::store::query::Table;
+use crate::store::history::RunDiff;
+use super::catalog::Catalog;
+use super::report;
+use super::remediation::RemediationSet;
+use crate::analyser::containers::{self, Confidence, InputSource, KeystrokeSequence, KeystrokeType};
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{self, Write};
 use std::path::Path;
 use ansi_term::Colour;
 
-/// Prints all the human-readable output to console.
-pub fn print_results(sessions: &HashMap<u32, SshSession>) {
+/// Prints all the human-readable output to console, with findings described from `catalog`, and
+/// the guidance for them if `remediation` is given.
+pub fn print_results(sessions: &BTreeMap<u32, SshSession>, catalog: &Catalog, remediation: Option<&RemediationSet>) {
     println!("\n\u{250F}\u{2501}\u{2501}\u{2501}\u{2501} Results");
     for session in sessions.values() {
         print_core(session);
-        print_result_sequence(session);
+        print_result_sequence(session, catalog);
+        if let Some(remediation) = remediation {
+            print_remediation(session, remediation);
+        }
         
         // Only print if keystrokes were analysed.
         if !&session.keystroke_data.is_empty() {
@@ -31,10 +49,49 @@ fn print_core(session: &SshSession) {
     let line = "\u{2500}";
     println!("\u{2503} Stream {}", Colour::Red.paint(session.stream.to_string()));
     println!("\u{2503} Duration (UTC): {} - {}", session.start_utc, session.end_utc);
+    println!("\u{2503} Tags        {}", Colour::Yellow.paint(session.tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>().join(", ")));
+    if session.severity > 0 {
+        println!("\u{2503} Severity    {}", Colour::Red.bold().paint(format!("{}/10 (threat indicator matched)", session.severity)));
+    }
     println!("\u{2503} KEX         {}", Colour::Yellow.paint(&session.algorithms.0));
     println!("\u{2503} Encryption  {}", Colour::Yellow.paint(&session.algorithms.1));
     println!("\u{2503} MAC         {}", Colour::Yellow.paint(&session.algorithms.2));
     println!("\u{2503} Compression {}", Colour::Yellow.paint(&session.algorithms.3));
+    for (label, kexinit) in [("Client", &session.kexinit.0), ("Server", &session.kexinit.1)] {
+        if let Some(kexinit) = kexinit {
+            println!("\u{2503} KEXINIT {label}");
+            for (list, names) in report::kexinit_lists(kexinit, true) {
+                println!("\u{2503}   {list:<16} {}", Colour::Yellow.paint(names));
+            }
+        }
+    }
+    if let Some(host_key) = &session.host_key {
+        println!("\u{2503} Host key    {}", Colour::Yellow.paint(format!("{} {} ({})", host_key.key_type, host_key.sha256, host_key.md5)));
+    }
+    for (label, advisories) in [("CVEs Client", &session.advisories.0), ("CVEs Server", &session.advisories.1)] {
+        if !advisories.is_empty() {
+            println!("\u{2503} {label} {}", Colour::Red.paint(format_advisories(advisories)));
+        }
+    }
+    if let Some(tunnel) = &session.tunnel {
+        println!("\u{2503} Tunnel      {}", Colour::Yellow.paint(tunnel.to_string()));
+    }
+    println!("\u{2503} RTT Client  {}", Colour::Yellow.paint(format!("{}μs (jitter {}μs, {} samples)", session.jitter.0.mean_rtt, session.jitter.0.jitter, session.jitter.0.samples)));
+    println!("\u{2503} MSS         {}", Colour::Yellow.paint(format!("{} / {} (max segment {} / {}, {} split records, {} coalesced segments)", format_mss(session.segmentation.mss.0), format_mss(session.segmentation.mss.1), session.segmentation.max_segment.0, session.segmentation.max_segment.1, session.segmentation.split_records, session.segmentation.coalesced_segments)));
+    println!("\u{2503} Signature   {}", Colour::Yellow.paint(&session.signature));
+    println!("\u{2503} Sizes       {}", Colour::Yellow.paint(report::format_size_confidence(session)));
+    if let Some(first) = session.ja4ssh.first() {
+        println!("\u{2503} JA4SSH      {}", Colour::Yellow.paint(format!("{first} ({} windows)", session.ja4ssh.len())));
+    }
+    println!("\u{2503} RTT Server  {}", Colour::Yellow.paint(format!("{}μs (jitter {}μs, {} samples)", session.jitter.1.mean_rtt, session.jitter.1.jitter, session.jitter.1.samples)));
+    println!("\u{2503} Volume      {}", Colour::Yellow.paint(format!("{} / {} bytes, {:.0}% / {:.0}% unexplained", session.volume.0.bytes, session.volume.1.bytes, session.volume.0.score * 100.0, session.volume.1.score * 100.0)));
+    if let Some(sampled) = &session.sampled {
+        println!("\u{2503} Sampled     {}", Colour::Yellow.paint(format!("after {} packets: {} / {} packets, {} / {} bytes, {:.0} / {:.0} B/s, peak ~{:.0} packets/s", sampled.exact_packets, sampled.packets.0, sampled.packets.1, sampled.bytes.0, sampled.bytes.1, sampled.throughput.0, sampled.throughput.1, sampled.peak_packet_rate)));
+        println!("\u{2503}             {}", sampled.accuracy);
+    }
+    for period in &session.activity {
+        println!("\u{2503} Activity #{} {}", period.id, Colour::Yellow.paint(format!("{} - {} ({} / {} bytes, {} commands, {} keystrokes, median latency {}ms, error rate {:.0}%, {} findings, idle {}s before)", period.start_utc, period.end_utc, period.bytes.0, period.bytes.1, period.sequences.len(), period.keystrokes, period.typing.median_us / 1000, period.error_rate * 100.0, period.findings.len(), period.idle_before / 1_000_000)));
+    }
 
    // === Row 1 ===
     print!("\u{2503}{}", Colour::Green.paint("\u{256D}"));
@@ -54,11 +111,16 @@ fn print_core(session: &SshSession) {
     println!("{}", Colour::Cyan.paint(format!("\u{2502}{:^40}\u{2502}", &session.hassh_s)));
 
     // === Row 4 ===
+    print!("\u{2503}{}", Colour::Green.paint(format!("\u{2502}{:^40}\u{2502}", format_software(&session.software.0))));
+    print!("      ");
+    println!("{}", Colour::Cyan.paint(format!("\u{2502}{:^40}\u{2502}", format_software(&session.software.1))));
+
+    // === Row 5 ===
     print!("\u{2503}{}", Colour::Green.paint(format!("\u{2502}{:^40}\u{2502}", &session.protocols.0)));
     print!("      ");
     println!("{}", Colour::Cyan.paint(format!("\u{2502}{:^40}\u{2502}", &session.protocols.1)));
 
-    // === Row 5 ===
+    // === Row 6 ===
     print!("\u{2503}{}", Colour::Green.paint(format!("\u{2570}{}\u{256F}", line.repeat(40))));
     print!("      ");
     println!("{}", Colour::Cyan.paint(format!("\u{2570}{}\u{256F}", line.repeat(40))));
@@ -66,38 +128,100 @@ fn print_core(session: &SshSession) {
     println!("\u{2503}");
 }
 
+/// Formats advisories as their IDs and CVSS scores.
+fn format_advisories(advisories: &[Advisory]) -> String {
+    advisories.iter().map(|advisory| format!("{} ({:.1})", advisory.id, advisory.cvss)).collect::<Vec<_>>().join(", ")
+}
+
+/// Formats an identified implementation along with how it was identified.
+fn format_software(software: &Option<Software>) -> String {
+    match software {
+        Some(software) if software.source == Source::Hassh => format!("{} (HASSH)", software.name),
+        Some(software) => format!("{} (banner)", software.name),
+        None => "unknown".to_string(),
+    }
+}
+
+/// Formats the input source of a sequence, highlighting input that was not typed by hand.
+fn format_input(input: InputSource) -> String {
+    match input {
+        InputSource::Human | InputSource::Unknown => String::new(),
+        InputSource::Scripted | InputSource::Pasted => format!(", {}", Colour::Yellow.paint(input.to_string())),
+    }
+}
+
+/// Flags a confidence below high, e.g. ` (low confidence)`; empty if high.
+fn format_level(level: Confidence) -> String {
+    match level {
+        Confidence::High => String::new(),
+        Confidence::Medium | Confidence::Low => format!(" {}", Colour::Purple.paint(format!("({level} confidence)"))),
+    }
+}
+
+/// Formats an MSS that may not have been captured.
+fn format_mss(mss: Option<u32>) -> String {
+    mss.map_or("?".to_string(), |mss| mss.to_string())
+}
+
 /// Prints a [session](SshSession)'s [results](SshSession::results).
 /// 
-/// [Results](SshSession::results) consist of [PacketInfos](containers::PacketInfo), whose descriptions are printed out sequentially.
-/// Assumes that all [PacketInfos](containers::PacketInfo) added contain [descriptions](containers::PacketInfo::description).
-fn print_result_sequence(session: &SshSession) {
+/// [Results](SshSession::results) consist of [Findings](containers::Finding), whose descriptions are printed out sequentially.
+fn print_result_sequence(session: &SshSession, catalog: &Catalog) {
     let results = &session.results;
 
     println!("\u{2523}\u{2501} Timeline of Events");
 
-    for pinfo in results {
-        println!("\u{2523} [{}] {}", pinfo.seq, pinfo.description.clone().expect("Result with no description"));
+    for finding in results {
+        println!("\u{2523} [{}] {}{}", finding.packet_ref.seq, catalog.describe(finding), format_level(finding.level()));
     }
 
     println!("\u{2503}");
 }
 
+/// Prints the remediation guidance for a session's findings, if there is any.
+fn print_remediation(session: &SshSession, remediation: &RemediationSet) {
+    let guidance = remediation.for_session(session);
+    if guidance.is_empty() {
+        return;
+    }
+
+    println!("\u{2523}\u{2501} Remediation");
+    for entry in guidance {
+        println!("\u{2523} {} ({}x {})", Colour::Yellow.paint(&entry.summary), entry.count, entry.finding);
+        for step in &entry.steps {
+            println!("\u{2503}   - {step}");
+        }
+        for reference in &entry.references {
+            println!("\u{2503}   {}", Colour::Blue.paint(reference));
+        }
+    }
+    println!("\u{2503}");
+}
+
 /// Prints keystroke sequences and their respective response sizes.
 ///
 /// Prints keystroke types and sequence IDs of each keystroke packet, should the user wish to
 /// investigate the capture themselves. 
 fn print_keystrokes(session: &SshSession) {
     let keystroke_sequences = &session.keystroke_data;
-    println!("\u{2523}\u{2501} Keystroke Sequences");
+    println!("\u{2523}\u{2501} Keystroke Sequences (error rate {:.0}%)", session.error_rate * 100.0);
+    if session.typing.samples > 0 {
+        println!("\u{2523}\u{2501} Typing: median {}μs, mean {}μs \u{00B1} {}μs over {} keystrokes", session.typing.median_us, session.typing.mean_us, session.typing.stddev_us, session.typing.samples);
+        println!("\u{2523}\u{2501} Percentiles: p10 {}μs, p25 {}μs, p75 {}μs, p90 {}μs, p99 {}μs",
+            session.typing.p10_us, session.typing.p25_us, session.typing.p75_us, session.typing.p90_us, session.typing.p99_us);
+        print_histogram(&session.typing.histogram);
+    }
     println!("\u{2523}\u{2501} {} \u{2500} {} \u{2500} {}", Colour::Red.paint("tcp.seq"), Colour::Red.paint("Latency μs"), Colour::Red.paint("Type"));
 
     for sequence in keystroke_sequences {
-        for keystroke in sequence {
+        println!("\u{2523}\u{2501} Command #{} ({}, length {}, error rate {:.0}%{}{})", sequence.id, sequence.start_utc, sequence.length, sequence.error_rate * 100.0,
+            if sequence.symbol_heavy { ", symbol-heavy" } else { "" }, format_input(sequence.input));
+        for keystroke in &sequence.keystrokes {
             if keystroke.k_type == KeystrokeType::Enter {
-                println!("\u{2523}\u{256E} [{}]  \u{2500} ({:>8}) \u{2500} {:?}", keystroke.seq, keystroke.timestamp, keystroke.k_type);
-                println!("\u{2503}\u{2570}\u{2500}\u{257C}[{}]", keystroke.response_size.expect("enter keystroke without response size"));
+                println!("\u{2523}\u{256E} [{}]  \u{2500} ({:>8}) \u{2500} {:?}{}", keystroke.seq, keystroke.latency_us, keystroke.k_type, format_level(keystroke.level()));
+                println!("\u{2503}\u{2570}\u{2500}\u{257C}[{}]{}", keystroke.response_size.expect("enter keystroke without response size"), format_guesses(&keystroke.guesses));
             } else {
-                println!("\u{2523}  [{}]  \u{2500} ({:>8}) \u{2500} {:?}", keystroke.seq, keystroke.timestamp, keystroke.k_type);
+                println!("\u{2523}  [{}]  \u{2500} ({:>8}) \u{2500} {:?}{}", keystroke.seq, keystroke.latency_us, keystroke.k_type, format_level(keystroke.level()));
             }
         }
         println!("\u{2523}\u{2501}");
@@ -105,11 +229,33 @@ fn print_keystrokes(session: &SshSession) {
     println!("\u{2503}");
 }
 
+/// Likely commands after a response size, e.g. ` ls (80%), id (40%)`; empty without guesses.
+fn format_guesses(guesses: &[containers::CommandGuess]) -> String {
+    if guesses.is_empty() {
+        return String::new();
+    }
+    format!(" {}", Colour::Yellow.paint(guesses.iter().map(|guess| guess.to_string()).collect::<Vec<_>>().join(", ")))
+}
+
+/// Prints a latency histogram as bars scaled to the fullest bucket.
+fn print_histogram(histogram: &[stats::Bucket]) {
+    let max = histogram.iter().map(|bucket| bucket.samples).max().unwrap_or(0).max(1);
+    let mut lower = 0;
+    for bucket in histogram {
+        let range = match bucket.upper_us {
+            Some(upper) => format!("{:>5}-{:<5}ms", lower / 1000, upper / 1000),
+            None => format!("{:>5}+     ms", lower / 1000),
+        };
+        println!("\u{2523}  {range} {:<30} {}", "\u{2588}".repeat(bucket.samples * 30 / max), bucket.samples);
+        lower = bucket.upper_us.unwrap_or(lower);
+    }
+}
+
 /// Saves the keystroke sequences to a specified file as JSON data.
 ///
 /// Data is saved as a simple JSON array of keystroke objects.
 /// Currently not used. Maybe if we add a flag to save sessions separately, this will be useful again. 
-pub fn _save_keystroke_sequences(sequences: &Vec<Vec<Keystroke>>, file_path: &Path) -> Result<(), serde_json::Error> {
+pub fn _save_keystroke_sequences(sequences: &Vec<KeystrokeSequence>, file_path: &Path) -> Result<(), serde_json::Error> {
     // Serialize the data to JSON string
     let serialized_data = serde_json::to_string(&sequences)?;
 
@@ -127,7 +273,7 @@ pub fn _save_keystroke_sequences(sequences: &Vec<Vec<Keystroke>>, file_path: &Pa
 /// Returns all data as JSON, which can be directly piped to jq, if printed. 
 ///
 /// Triggered by the `--json` flag.
-pub fn data_as_json(sessions: &HashMap<u32, SshSession>) -> Result<String, serde_json::Error> {
+pub fn data_as_json(sessions: &BTreeMap<u32, SshSession>) -> Result<String, serde_json::Error> {
     // Serialize the data to JSON string
     let serialized = serde_json::to_string(&sessions)?;
     Ok(serialized)
@@ -136,9 +282,9 @@ pub fn data_as_json(sessions: &HashMap<u32, SshSession>) -> Result<String, serde
 /// Returns all keystroke-related data as JSON.
 /// 
 /// Triggered by combination of `--json` and `-k`.
-pub fn keystrokes_as_json(sessions: &HashMap<u32, SshSession>) -> Result<String, serde_json::Error> {
+pub fn keystrokes_as_json(sessions: &BTreeMap<u32, SshSession>) -> Result<String, serde_json::Error> {
     // Bypass the SshSession struct and only collect the keystroke sequences.
-    let keystroke_only_map: HashMap<u32, &Vec<Vec<containers::Keystroke>>> = sessions
+    let keystroke_only_map: BTreeMap<u32, &Vec<containers::KeystrokeSequence>> = sessions
         .iter()
         .map(|(&stream_id, session)| (stream_id, &session.keystroke_data))
         .collect();
@@ -148,6 +294,300 @@ pub fn keystrokes_as_json(sessions: &HashMap<u32, SshSession>) -> Result<String,
     Ok(serialized)
 }
 
+/// Returns the sessions of several captures as JSON, keyed by capture and then stream; only
+/// their keystroke sequences if `keystrokes_only`.
+pub fn captures_as_json(captures: &BTreeMap<String, BTreeMap<u32, SshSession>>, keystrokes_only: bool) -> Result<String, serde_json::Error> {
+    if keystrokes_only {
+        let keystroke_only_map: BTreeMap<&String, BTreeMap<u32, &Vec<containers::KeystrokeSequence>>> = captures
+            .iter()
+            .map(|(capture, sessions)| (capture, sessions.iter().map(|(&stream_id, session)| (stream_id, &session.keystroke_data)).collect()))
+            .collect();
+        serde_json::to_string(&keystroke_only_map)
+    } else {
+        serde_json::to_string(captures)
+    }
+}
+
+/// Returns a session as JSON lines for `--jsonl`: one line with the session (only its keystroke
+/// sequences if `keystrokes_only`), followed by one line per finding if `events`.
+///
+/// Sessions finish in any order, so every line names its `record` type (`session` or `event`),
+/// the capture, and the stream.
+pub fn session_as_json_lines(session: &SshSession, capture: &str, keystrokes_only: bool, events: bool) -> Result<String, serde_json::Error> {
+    let line = |mut value: serde_json::Value, record: &str| {
+        if let Some(object) = value.as_object_mut() {
+            object.insert("record".to_string(), record.into());
+            object.insert("capture".to_string(), capture.into());
+            object.insert("stream".to_string(), session.stream.into());
+        }
+        serde_json::to_string(&value).map(|line| line + "\n")
+    };
+
+    let mut lines = if keystrokes_only {
+        line(serde_json::json!({ "keystroke_data": session.keystroke_data }), "session")?
+    } else {
+        line(serde_json::to_value(session)?, "session")?
+    };
+    if events {
+        for finding in &session.results {
+            lines.push_str(&line(serde_json::to_value(finding)?, "event")?);
+        }
+    }
+
+    Ok(lines)
+}
+
+/// Prints the changes in a server's posture between two captures.
+pub fn print_posture_changes(server: &str, old: &Posture, new: &Posture, changes: &[PostureChange]) {
+    println!("\n\u{250F}\u{2501}\u{2501}\u{2501}\u{2501} Posture of {}", Colour::Red.paint(server));
+    println!("\u{2503} Sessions    {} \u{2500}\u{257C} {}", old.sessions, new.sessions);
+
+    if changes.is_empty() {
+        println!("\u{2503} No changes");
+    }
+    for change in changes {
+        println!("\u{2523}\u{2501} {}", change.attribute);
+        for value in &change.removed {
+            println!("\u{2503}  {} {}", Colour::Red.paint("-"), value);
+        }
+        for value in &change.added {
+            println!("\u{2503}  {} {}", Colour::Green.paint("+"), value);
+        }
+    }
+    println!("\u{2517}\u{2501}\u{2501}\u{2501}\u{2501}");
+}
+
+/// Prints two sessions side by side, section by section, with the attributes that differ in red.
+/// `labels` name the sessions, e.g. `stream 3 of a.json`.
+pub fn print_session_comparison(labels: (&str, &str), comparisons: &[SessionComparison]) {
+    println!("\n\u{250F}\u{2501}\u{2501}\u{2501}\u{2501} {} \u{2500}\u{257C} {}", labels.0, labels.1);
+
+    let width = comparisons.iter().map(|comparison| comparison.attribute.chars().count()).max().unwrap_or_default();
+    let mut section = "";
+    for comparison in comparisons {
+        if comparison.section != section {
+            section = comparison.section;
+            println!("\u{2523}\u{2501} {}{}", section[..1].to_uppercase(), &section[1..]);
+        }
+        if comparison.changed {
+            println!("\u{2503} {} {} \u{2500}\u{257C} {}", Colour::Red.paint(format!("{:<width$}", comparison.attribute)), comparison.values.0, comparison.values.1);
+        } else {
+            println!("\u{2503} {:<width$} {}", comparison.attribute, comparison.values.0);
+        }
+    }
+
+    let changed = comparisons.iter().filter(|comparison| comparison.changed).count();
+    println!("\u{2503}\n\u{2503} {changed} of {} attributes differ", comparisons.len());
+    println!("\u{2517}\u{2501}\u{2501}\u{2501}\u{2501}");
+}
+
+/// Returns a session comparison as JSON.
+pub fn session_comparison_as_json(comparisons: &[SessionComparison]) -> Result<String, serde_json::Error> {
+    serde_json::to_string(comparisons)
+}
+
+/// Returns posture changes as JSON.
+pub fn posture_changes_as_json(changes: &[PostureChange]) -> Result<String, serde_json::Error> {
+    serde_json::to_string(changes)
+}
+
+/// Returns all keystrokes as CSV, one row per keystroke, with a header row.
+///
+/// Triggered by `--format csv`; meant for spreadsheets and dataframes.
+pub fn keystrokes_as_csv(sessions: &BTreeMap<u32, SshSession>) -> String {
+    let mut csv = String::from(KEYSTROKE_CSV_HEADER);
+    for session in sessions.values() {
+        push_keystroke_rows(&mut csv, "", session);
+    }
+
+    csv
+}
+
+/// Returns the keystrokes of several captures as CSV like [keystrokes_as_csv], with the capture
+/// in the first column.
+pub fn captures_keystrokes_as_csv(captures: &BTreeMap<String, BTreeMap<u32, SshSession>>) -> String {
+    let mut csv = format!("capture,{KEYSTROKE_CSV_HEADER}");
+    for (capture, sessions) in captures {
+        // Quoted, as capture paths may hold commas
+        let prefix = format!("\"{}\",", capture.replace('"', "\"\""));
+        for session in sessions.values() {
+            push_keystroke_rows(&mut csv, &prefix, session);
+        }
+    }
+
+    csv
+}
+
+const KEYSTROKE_CSV_HEADER: &str = "stream,sequence,seq,frame,timestamp,latency_us,type,confidence,response_size\n";
+
+/// Appends a row per keystroke of `session`, each starting with `prefix`.
+fn push_keystroke_rows(csv: &mut String, prefix: &str, session: &SshSession) {
+    for sequence in &session.keystroke_data {
+        for keystroke in &sequence.keystrokes {
+            csv.push_str(&format!("{prefix}{},{},{},{},{},{},{:?},{:.2},{}\n",
+                session.stream,
+                sequence.id,
+                keystroke.seq,
+                keystroke.frame.map_or(String::new(), |frame| frame.to_string()),
+                keystroke.timestamp,
+                keystroke.latency_us,
+                keystroke.k_type,
+                keystroke.confidence,
+                keystroke.response_size.map_or(String::new(), |size| size.to_string())));
+        }
+    }
+}
+
+/// Prints the outcome of each capture check.
+pub fn print_health_report(file: &str, report: &HealthReport) {
+    println!("\n\u{250F}\u{2501}\u{2501}\u{2501}\u{2501} Capture {}", Colour::Red.paint(file));
+    for check in &report.checks {
+        let status = match check.status {
+            Status::Pass => Colour::Green.paint("PASS"),
+            Status::Warn => Colour::Yellow.paint("WARN"),
+            Status::Fail => Colour::Red.paint("FAIL"),
+        };
+        println!("\u{2503} {status} {:<11} {}", check.name, check.details);
+    }
+    println!("\u{2517}\u{2501}\u{2501}\u{2501}\u{2501}");
+}
+
+/// Returns a capture health report as JSON.
+pub fn health_report_as_json(report: &HealthReport) -> Result<String, serde_json::Error> {
+    serde_json::to_string(report)
+}
+
+/// Prints the estimated clients behind each shared client IP.
+pub fn print_nat_reports(reports: &[NatReport]) {
+    if reports.is_empty() {
+        println!("No client IP with multiple sessions.");
+    }
+
+    for report in reports {
+        println!("\n\u{250F}\u{2501}\u{2501}\u{2501}\u{2501} Client {}", Colour::Red.paint(&report.client_ip));
+        println!("\u{2503} Sessions    {}", report.sessions);
+        println!("\u{2503} Clients     ~{}", report.estimated_clients);
+
+        for (index, group) in report.groups.iter().enumerate() {
+            println!("\u{2523}\u{2501} Client #{}", index + 1);
+            println!("\u{2503}  Version     {}", group.version);
+            println!("\u{2503}  HASSH       {}", group.hassh_c);
+            match group.typing_median_us {
+                Some(median) => println!("\u{2503}  Typing      {}ms median", median / 1000),
+                None => println!("\u{2503}  Typing      -"),
+            }
+            println!("\u{2503}  Concurrent  {}", group.concurrent);
+            println!("\u{2503}  Streams     {}", group.sessions.iter().map(|stream| stream.to_string()).collect::<Vec<_>>().join(", "));
+        }
+        println!("\u{2517}\u{2501}\u{2501}\u{2501}\u{2501}");
+    }
+}
+
+/// Prints the deprecated algorithms offered by each endpoint.
+pub fn print_audits(audits: &[EndpointAudit]) {
+    if audits.is_empty() {
+        println!("No deprecated algorithms offered.");
+    }
+
+    for audit in audits {
+        println!("\n\u{250F}\u{2501}\u{2501}\u{2501}\u{2501} {} {}", if audit.role == "server" { "Server" } else { "Client" }, Colour::Red.paint(&audit.endpoint));
+        println!("\u{2503} Versions    {}", audit.banners.iter().cloned().collect::<Vec<_>>().join(", "));
+        println!("\u{2503} Streams     {}", audit.streams.iter().map(|stream| stream.to_string()).collect::<Vec<_>>().join(", "));
+        for weakness in &audit.weaknesses {
+            let line = format!("{:<9} {weakness}", weakness.kind.to_string());
+            if weakness.negotiated {
+                println!("\u{2503} {}", Colour::Red.paint(line));
+            } else {
+                println!("\u{2503} {}", Colour::Yellow.paint(line));
+            }
+        }
+        println!("\u{2517}\u{2501}\u{2501}\u{2501}\u{2501}");
+    }
+}
+
+/// Prints the findings that changed since the previous run of the same capture.
+pub fn print_run_diff(diff: &RunDiff) {
+    println!("\n\u{250F}\u{2501}\u{2501}\u{2501}\u{2501} Changes since run of {} (v{})", diff.previous.0, diff.previous.1);
+    if diff.added.is_empty() && diff.removed.is_empty() {
+        println!("\u{2503} No changes in findings");
+    }
+    for key in &diff.added {
+        println!("\u{2503} {} Stream {} [{}] {}", Colour::Green.paint("+"), key.stream, key.seq, key.kind);
+    }
+    for key in &diff.removed {
+        println!("\u{2503} {} Stream {} [{}] {}", Colour::Red.paint("-"), key.stream, key.seq, key.kind);
+    }
+    println!("\u{2517}\u{2501}\u{2501}\u{2501}\u{2501}");
+}
+
+/// Prints linked session pairs, best first.
+pub fn print_links(links: &[Link]) {
+    if links.is_empty() {
+        println!("No linked sessions.");
+    }
+
+    for link in links {
+        let ((capture_a, stream_a), (capture_b, stream_b)) = &link.sessions;
+        println!("\n\u{250F}\u{2501}\u{2501}\u{2501}\u{2501} Score {}", Colour::Red.paint(format!("{:.2}", link.score)));
+        println!("\u{2503} {capture_a} stream {stream_a}");
+        println!("\u{2503} {capture_b} stream {stream_b}");
+        for (name, similarity) in &link.components {
+            println!("\u{2503}  {:<11} {:.2}", name, similarity);
+        }
+        println!("\u{2517}\u{2501}\u{2501}\u{2501}\u{2501}");
+    }
+}
+
+/// Returns linked session pairs as JSON.
+pub fn links_as_json(links: &[Link]) -> Result<String, serde_json::Error> {
+    serde_json::to_string(links)
+}
+
+/// Returns endpoint audits as JSON.
+pub fn audits_as_json(audits: &[EndpointAudit]) -> Result<String, serde_json::Error> {
+    serde_json::to_string(audits)
+}
+
+/// Returns NAT reports as JSON.
+pub fn nat_reports_as_json(reports: &[NatReport]) -> Result<String, serde_json::Error> {
+    serde_json::to_string(reports)
+}
+
+/// Prints the SSH streams of a capture, one row per stream.
+pub fn print_streams(streams: &[StreamSummary]) {
+    if streams.is_empty() {
+        println!("No SSH streams.");
+        return;
+    }
+
+    let columns = ["Stream", "Client", "Server", "Packets", "Start (UTC)", "Client banner", "Server banner"];
+    let banner = |banner: &Option<String>| banner.clone().unwrap_or_else(|| "-".to_string());
+    print_table(&Table {
+        columns: columns.iter().map(|column| column.to_string()).collect(),
+        rows: streams.iter()
+            .map(|stream| vec![stream.stream.to_string(), stream.client.clone(), stream.server.clone(), stream.packets.to_string(), stream.start_utc.clone(), banner(&stream.protocols.0), banner(&stream.protocols.1)])
+            .collect(),
+    });
+}
+
+pub fn streams_as_json(streams: &[StreamSummary]) -> Result<String, serde_json::Error> {
+    serde_json::to_string(streams)
+}
+
+/// Prints a query result as an aligned table.
+pub fn print_table(table: &Table) {
+    let widths: Vec<usize> = table.columns.iter().enumerate()
+        .map(|(index, column)| table.rows.iter().map(|row| row[index].chars().count()).chain([column.len()]).max().unwrap_or_default())
+        .collect();
+    let format_row = |row: &[String]| row.iter().zip(&widths).map(|(value, width)| format!("{value:<width$}")).collect::<Vec<_>>().join(" \u{2502} ");
+
+    println!("{}", Colour::Red.paint(format_row(&table.columns)));
+    for row in &table.rows {
+        println!("{}", format_row(row));
+    }
+    println!("({} rows)", table.rows.len());
+}
+
 /// Saves JSON data to a given file
 pub fn data_to_file(data: String, file_path: &Path) -> Result<(), io::Error> {
     let mut file = File::create(file_path)?;
@@ -179,3 +619,28 @@ pub fn print_banner() {
     println!(r"                              \ e \ ");
     println!(r"                               \ f \");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::core::tests::session;
+
+    #[test]
+    fn test_keystrokes_as_csv() {
+        let sessions = BTreeMap::from([(3, session())]);
+        assert_eq!("\
+stream,sequence,seq,frame,timestamp,latency_us,type,confidence,response_size
+3,1,2000,30,1706702404000000,0,Keystroke,1.00,
+3,1,2036,32,1706702404200000,200000,Delete,0.80,
+3,1,2072,34,1706702404500000,300000,Enter,1.00,512
+", keystrokes_as_csv(&sessions));
+
+        let captures = BTreeMap::from([("cases/a,\"b\".pcap".to_string(), sessions)]);
+        assert_eq!("\
+capture,stream,sequence,seq,frame,timestamp,latency_us,type,confidence,response_size
+\"cases/a,\"\"b\"\".pcap\",3,1,2000,30,1706702404000000,0,Keystroke,1.00,
+\"cases/a,\"\"b\"\".pcap\",3,1,2036,32,1706702404200000,200000,Delete,0.80,
+\"cases/a,\"\"b\"\".pcap\",3,1,2072,34,1706702404500000,300000,Enter,1.00,512
+", captures_keystrokes_as_csv(&captures));
+    }
+}
@@ -0,0 +1,323 @@
+//! Annotated pcapng export.
+//!
+//! Copies the analysed capture into a pcapng file in which every packet SSHniff classified (New
+//! Keys, the login prompt, key offers, keystrokes, returns, ...) carries a comment with the
+//! classification, so analysts can open the capture in Wireshark and filter on `frame.comment`.
+//! pcap captures are converted; pcapng captures are copied block by block, keeping their
+//! interfaces and existing comments. Packets are matched by frame number, i.e. their position in
+//! the capture, which is what tshark numbers them by.
+use crate::analyser::compression::CaptureFile;
+use crate::analyser::containers::KeystrokeType;
+use crate::analyser::core::SshSession;
+use super::catalog::Catalog;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const SECTION_HEADER: u32 = 0x0A0D0D0A;
+const INTERFACE_DESCRIPTION: u32 = 1;
+/// Obsolete Packet Block, still counted as a frame
+const PACKET: u32 = 2;
+const SIMPLE_PACKET: u32 = 3;
+const ENHANCED_PACKET: u32 = 6;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B3C4D;
+
+const OPT_ENDOFOPT: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const SHB_USERAPPL: u16 = 4;
+const IF_TSRESOL: u16 = 9;
+
+const PCAP_MICROSECONDS: u32 = 0xA1B2C3D4;
+const PCAP_NANOSECONDS: u32 = 0xA1B23C4D;
+
+/// Comments for the frames of a capture's sessions, by frame number: a finding's description, or
+/// a keystroke's type and command.
+pub fn annotations(sessions: &BTreeMap<u32, SshSession>, catalog: &Catalog) -> BTreeMap<u32, Vec<String>> {
+    let mut annotations: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+
+    for session in sessions.values() {
+        for finding in &session.results {
+            if let Some(frame) = finding.packet_ref.frame {
+                annotations.entry(frame).or_default()
+                    .push(format!("SSHniff stream {}: {} ({} confidence)", session.stream, catalog.describe(finding), finding.level()));
+            }
+        }
+
+        for sequence in &session.keystroke_data {
+            for keystroke in &sequence.keystrokes {
+                let frame = match keystroke.frame {
+                    Some(frame) => frame,
+                    None => continue,
+                };
+                let response = match (&keystroke.k_type, keystroke.response_size) {
+                    (KeystrokeType::Enter, Some(size)) => format!(", {size} bytes returned"),
+                    _ => String::new(),
+                };
+                annotations.entry(frame).or_default()
+                    .push(format!("SSHniff stream {}: {:?} keystroke of command #{}{response} ({} confidence)", session.stream, keystroke.k_type, sequence.id, keystroke.level()));
+            }
+        }
+    }
+
+    annotations
+}
+
+/// Writes `capture` as pcapng to `output`, with `annotations` attached to their frames as
+/// comments. Returns the number of frames annotated.
+///
+/// Compressed captures are decompressed first. Frames in Simple Packet Blocks, which cannot carry
+/// options, are copied without their comments.
+pub fn write_annotated(capture: &str, output: &Path, annotations: &BTreeMap<u32, Vec<String>>) -> io::Result<usize> {
+    let capture = CaptureFile::open(capture).map_err(io::Error::other)?;
+    let mut input = BufReader::new(File::open(capture.path())?);
+    let mut output = BufWriter::new(File::create(output)?);
+
+    let mut magic = [0u8; 4];
+    input.read_exact(&mut magic)?;
+    let annotated = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+        (SECTION_HEADER, _) => copy_pcapng(magic, &mut input, &mut output, annotations)?,
+        (PCAP_MICROSECONDS | PCAP_NANOSECONDS, _) => convert_pcap(u32::from_le_bytes(magic), false, &mut input, &mut output, annotations)?,
+        (_, PCAP_MICROSECONDS | PCAP_NANOSECONDS) => convert_pcap(u32::from_be_bytes(magic), true, &mut input, &mut output, annotations)?,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "not a pcap or pcapng capture")),
+    };
+
+    output.flush()?;
+    Ok(annotated)
+}
+
+/// Copies the blocks of a pcapng capture, adding comments to annotated Enhanced Packet Blocks.
+fn copy_pcapng(first: [u8; 4], input: &mut impl Read, output: &mut impl Write, annotations: &BTreeMap<u32, Vec<String>>) -> io::Result<usize> {
+    let mut big_endian = false;
+    let mut frame = 0;
+    let mut annotated = 0;
+    // The first block type was read to recognise the format
+    let mut first = Some(first);
+
+    loop {
+        let mut header = [0u8; 8];
+        let start = match first.take() {
+            Some(first) => {
+                header[..4].copy_from_slice(&first);
+                4
+            },
+            None => 0,
+        };
+        match input.read_exact(&mut header[start..]) {
+            Ok(()) => {},
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof && start == 0 => break,
+            Err(err) => return Err(err),
+        }
+
+        // The Section Header's type reads the same either way; its byte-order magic follows the length
+        let block_type = read_u32(&header[..4], big_endian);
+        let mut rest = vec![];
+        if block_type == SECTION_HEADER {
+            let mut magic = [0u8; 4];
+            input.read_exact(&mut magic)?;
+            big_endian = u32::from_be_bytes(magic) == BYTE_ORDER_MAGIC;
+            rest.extend(magic);
+        }
+        let length = read_u32(&header[4..], big_endian) as usize;
+        if length < 12 + rest.len() || !length.is_multiple_of(4) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid pcapng block length {length}")));
+        }
+        let read = rest.len();
+        rest.resize(length - 8, 0);
+        input.read_exact(&mut rest[read..])?;
+        let body = &rest[..rest.len() - 4];
+
+        match block_type {
+            ENHANCED_PACKET | PACKET | SIMPLE_PACKET => {
+                frame += 1;
+                match annotations.get(&frame) {
+                    Some(comments) if block_type == ENHANCED_PACKET => {
+                        write_block(output, big_endian, ENHANCED_PACKET, &with_comments(body, big_endian, comments)?)?;
+                        annotated += 1;
+                    },
+                    _ => {
+                        output.write_all(&header)?;
+                        output.write_all(&rest)?;
+                    },
+                }
+            },
+            _ => {
+                output.write_all(&header)?;
+                output.write_all(&rest)?;
+            },
+        }
+    }
+
+    Ok(annotated)
+}
+
+/// Enhanced Packet Block body with `comments` added to its options.
+fn with_comments(body: &[u8], big_endian: bool, comments: &[String]) -> io::Result<Vec<u8>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "truncated enhanced packet block");
+    let captured = read_u32(body.get(12..16).ok_or_else(invalid)?, big_endian) as usize;
+    let options_start = 20 + padded(captured);
+    if options_start > body.len() {
+        return Err(invalid());
+    }
+
+    // Existing options up to, not including, the end of options
+    let mut options_end = options_start;
+    while options_end + 4 <= body.len() {
+        let code = read_u16(&body[options_end..], big_endian);
+        let length = read_u16(&body[options_end + 2..], big_endian) as usize;
+        if code == OPT_ENDOFOPT {
+            break;
+        }
+        options_end = (options_end + 4 + padded(length)).min(body.len());
+    }
+
+    let mut rewritten = body[..options_end].to_vec();
+    for comment in comments {
+        write_option(&mut rewritten, big_endian, OPT_COMMENT, comment.as_bytes());
+    }
+    write_option(&mut rewritten, big_endian, OPT_ENDOFOPT, &[]);
+    Ok(rewritten)
+}
+
+/// Converts a pcap capture, whose 4-byte magic was already read, into a single pcapng section.
+fn convert_pcap(magic: u32, big_endian: bool, input: &mut impl Read, output: &mut impl Write, annotations: &BTreeMap<u32, Vec<String>>) -> io::Result<usize> {
+    let mut header = [0u8; 20];
+    input.read_exact(&mut header)?;
+    let snaplen = read_u32(&header[12..16], big_endian);
+    let linktype = read_u32(&header[16..20], big_endian);
+    let nanoseconds = magic == PCAP_NANOSECONDS;
+
+    // Written little-endian, whatever the byte order of the pcap
+    let mut section = vec![];
+    section.extend(BYTE_ORDER_MAGIC.to_le_bytes());
+    section.extend(1u16.to_le_bytes());
+    section.extend(0u16.to_le_bytes());
+    section.extend((-1i64).to_le_bytes());
+    write_option(&mut section, false, SHB_USERAPPL, format!("SSHniff {}", env!("CARGO_PKG_VERSION")).as_bytes());
+    write_option(&mut section, false, OPT_ENDOFOPT, &[]);
+    write_block(output, false, SECTION_HEADER, &section)?;
+
+    let mut interface = vec![];
+    interface.extend((linktype as u16).to_le_bytes());
+    interface.extend(0u16.to_le_bytes());
+    interface.extend(snaplen.to_le_bytes());
+    if nanoseconds {
+        write_option(&mut interface, false, IF_TSRESOL, &[9]);
+        write_option(&mut interface, false, OPT_ENDOFOPT, &[]);
+    }
+    write_block(output, false, INTERFACE_DESCRIPTION, &interface)?;
+
+    let mut frame = 0;
+    let mut annotated = 0;
+    let mut record = [0u8; 16];
+    loop {
+        match input.read_exact(&mut record) {
+            Ok(()) => {},
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+        }
+        frame += 1;
+        let (seconds, fraction) = (read_u32(&record[..4], big_endian) as u64, read_u32(&record[4..8], big_endian) as u64);
+        let (captured, original) = (read_u32(&record[8..12], big_endian), read_u32(&record[12..16], big_endian));
+        let mut data = vec![0u8; captured as usize];
+        input.read_exact(&mut data)?;
+
+        let timestamp = seconds * if nanoseconds { 1_000_000_000 } else { 1_000_000 } + fraction;
+        let mut packet = vec![];
+        packet.extend(0u32.to_le_bytes());
+        packet.extend(((timestamp >> 32) as u32).to_le_bytes());
+        packet.extend((timestamp as u32).to_le_bytes());
+        packet.extend(captured.to_le_bytes());
+        packet.extend(original.to_le_bytes());
+        packet.extend(&data);
+        packet.resize(20 + padded(data.len()), 0);
+        if let Some(comments) = annotations.get(&frame) {
+            for comment in comments {
+                write_option(&mut packet, false, OPT_COMMENT, comment.as_bytes());
+            }
+            write_option(&mut packet, false, OPT_ENDOFOPT, &[]);
+            annotated += 1;
+        }
+        write_block(output, false, ENHANCED_PACKET, &packet)?;
+    }
+
+    Ok(annotated)
+}
+
+fn write_block(output: &mut impl Write, big_endian: bool, block_type: u32, body: &[u8]) -> io::Result<()> {
+    let length = (12 + body.len()) as u32;
+    output.write_all(&write_u32(block_type, big_endian))?;
+    output.write_all(&write_u32(length, big_endian))?;
+    output.write_all(body)?;
+    output.write_all(&write_u32(length, big_endian))
+}
+
+fn write_option(buffer: &mut Vec<u8>, big_endian: bool, code: u16, value: &[u8]) {
+    let (code, length) = if big_endian { (code.to_be_bytes(), (value.len() as u16).to_be_bytes()) } else { (code.to_le_bytes(), (value.len() as u16).to_le_bytes()) };
+    buffer.extend(code);
+    buffer.extend(length);
+    buffer.extend(value);
+    buffer.resize(buffer.len() + padded(value.len()) - value.len(), 0);
+}
+
+/// Length rounded up to the 32-bit alignment of pcapng fields.
+fn padded(length: usize) -> usize {
+    length.div_ceil(4) * 4
+}
+
+fn read_u32(bytes: &[u8], big_endian: bool) -> u32 {
+    let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+    if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+}
+
+fn read_u16(bytes: &[u8], big_endian: bool) -> u16 {
+    let bytes = [bytes[0], bytes[1]];
+    if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) }
+}
+
+fn write_u32(value: u32, big_endian: bool) -> [u8; 4] {
+    if big_endian { value.to_be_bytes() } else { value.to_le_bytes() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pcapng_annotation() {
+        use std::collections::BTreeMap;
+
+        // pcap with two Ethernet frames of 6 and 3 bytes
+        let mut pcap = vec![];
+        for field in [0xa1b2c3d4u32, 0x0004_0002, 0, 0, 65535, 1] {
+            pcap.extend(field.to_le_bytes());
+        }
+        for (seconds, data) in [(1u32, &b"frame1"[..]), (2, &b"two"[..])] {
+            for field in [seconds, 500, data.len() as u32, data.len() as u32] {
+                pcap.extend(field.to_le_bytes());
+            }
+            pcap.extend(data);
+        }
+        let base = std::env::temp_dir().join(format!("sshniff-test-{}-annotate", std::process::id()));
+        let (input, converted, copied) = (base.with_extension("pcap"), base.with_extension("pcapng"), base.with_extension("copy.pcapng"));
+        std::fs::write(&input, &pcap).unwrap();
+
+        let annotations = BTreeMap::from([(2, vec!["SSHniff stream 0: Enter".to_string()])]);
+        assert_eq!(1, write_annotated(input.to_str().unwrap(), &converted, &annotations).unwrap());
+        let written = std::fs::read(&converted).unwrap();
+        assert_eq!(&0x0A0D0D0Au32.to_le_bytes(), &written[..4]);
+        let contains = |bytes: &[u8], text: &[u8]| bytes.windows(text.len()).any(|window| window == text);
+        assert!(contains(&written, b"SSHniff stream 0: Enter"));
+
+        // Copying a pcapng keeps the existing comments
+        let annotations = BTreeMap::from([(1, vec!["SSHniff stream 0: New Keys".to_string()]), (2, vec!["second".to_string()])]);
+        assert_eq!(2, write_annotated(converted.to_str().unwrap(), &copied, &annotations).unwrap());
+        let copy = std::fs::read(&copied).unwrap();
+        assert!(contains(&copy, b"SSHniff stream 0: New Keys") && contains(&copy, b"SSHniff stream 0: Enter") && contains(&copy, b"second"));
+        assert_eq!(written.len() + 36 + 12, copy.len());
+
+        for path in [input, converted, copied] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
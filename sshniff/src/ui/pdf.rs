@@ -0,0 +1,375 @@
+//! Paginated PDF reports for inclusion in incident-response and legal deliverables.
+//!
+//! The layout comes from [report], the same as for the HTML reports, followed by an appendix with
+//! the [Provenance] of the analysis. Every page is stamped with the SHA-256 of the capture and of
+//! the report body, so a printed page can be tied back to both.
+//!
+//! The writer only uses the standard Type 1 fonts, which every viewer has, so no fonts are
+//! embedded. Text is encoded as WinAnsi; characters outside of it are replaced with `?`.
+use crate::analyser::core::SshSession;
+use super::catalog::Catalog;
+use super::remediation::RemediationSet;
+use super::report::{self, Block, Provenance, Report, Row};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+
+/// A4 in points.
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 50.0;
+/// Space kept free above the bottom margin for the footer.
+const FOOTER_HEIGHT: f32 = 30.0;
+
+/// Column at which field values start.
+const VALUE_X: f32 = 200.0;
+
+/// Fonts by resource name; Courier is used for tables so their columns line up.
+const FONTS: [(&str, &str); 4] = [("F1", "Helvetica"), ("F2", "Helvetica-Bold"), ("F3", "Courier"), ("F4", "Courier-Bold")];
+
+/// Width of a Courier character relative to the font size.
+const COURIER_WIDTH: f32 = 0.6;
+/// Average width of a Helvetica character relative to the font size, for wrapping.
+const HELVETICA_WIDTH: f32 = 0.5;
+
+const TABLE_SIZE: f32 = 7.5;
+const TABLE_GAP: usize = 2;
+
+/// Indent of list items past their bullet.
+const BULLET_INDENT: f32 = 10.0;
+
+/// Renders a session (metadata, event timeline, remediation, keystroke sequences, provenance) as
+/// a PDF.
+pub fn session_as_pdf(session: &SshSession, capture: &str, provenance: &Provenance, catalog: &Catalog, remediation: &RemediationSet) -> Vec<u8> {
+    let mut report = report::session_report(session, capture, catalog, remediation);
+    report.sections.push(provenance.section());
+    report_as_pdf(&report, provenance)
+}
+
+/// Renders a [Report] as a PDF, stamping every page with hashes from `provenance`.
+pub fn report_as_pdf(report: &Report, provenance: &Provenance) -> Vec<u8> {
+    let mut layout = Layout::new();
+    layout.text("F2", 15.0, MARGIN, &report.title);
+    layout.space(6.0);
+
+    for section in &report.sections {
+        layout.space(10.0);
+        layout.ensure(40.0);
+        layout.text("F2", 12.0, MARGIN, &section.heading);
+        layout.space(4.0);
+        for block in &section.blocks {
+            layout.block(block);
+        }
+    }
+
+    let pages = layout.finish();
+
+    // The digest covers the body of every page, i.e. the content streams without their footers.
+    let mut hasher = Sha256::new();
+    for page in &pages {
+        hasher.update(page.as_bytes());
+    }
+    let digest = hex::encode(hasher.finalize());
+
+    let footers: Vec<String> = (1..=pages.len())
+        .map(|number| {
+            let mut footer = String::new();
+            let y = MARGIN;
+            show(&mut footer, "F1", 6.5, MARGIN, y + 9.0, &format!("Capture SHA-256 {}", provenance.capture_sha256));
+            show(&mut footer, "F1", 6.5, MARGIN, y, &format!("Report SHA-256 {digest}"));
+            show(&mut footer, "F1", 7.5, PAGE_WIDTH - MARGIN - 45.0, y, &format!("Page {number}/{}", pages.len()));
+            footer
+        })
+        .collect();
+
+    let info = [
+        ("Title", report.title.clone()),
+        ("Producer", format!("SSHniff {}", provenance.version)),
+        ("Subject", format!("Capture SHA-256 {}; report SHA-256 {digest}", provenance.capture_sha256)),
+        ("CreationDate", format!("D:{}Z", provenance.generated.replace(['-', ' ', ':'], ""))),
+    ];
+
+    write_document(&pages, &footers, &info)
+}
+
+/// Places text on pages, top to bottom, starting a new page when one is full.
+struct Layout {
+    pages: Vec<String>,
+    current: String,
+    y: f32,
+}
+
+impl Layout {
+    fn new() -> Self {
+        Self {
+            pages: Vec::new(),
+            current: String::new(),
+            y: PAGE_HEIGHT - MARGIN,
+        }
+    }
+
+    /// Starts a new page unless `height` still fits on the current one.
+    fn ensure(&mut self, height: f32) {
+        if self.y - height < MARGIN + FOOTER_HEIGHT {
+            self.new_page();
+        }
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(std::mem::take(&mut self.current));
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    fn space(&mut self, height: f32) {
+        self.y -= height;
+    }
+
+    /// Writes a line of text, moving down by its height.
+    fn text(&mut self, font: &str, size: f32, x: f32, text: &str) {
+        let height = size * 1.3;
+        self.ensure(height);
+        self.y -= height;
+        show(&mut self.current, font, size, x, self.y, text);
+    }
+
+    /// Writes text wrapped to the width between `x` and the right margin.
+    fn paragraph(&mut self, font: &str, size: f32, x: f32, text: &str) {
+        let columns = ((PAGE_WIDTH - MARGIN - x) / (size * HELVETICA_WIDTH)) as usize;
+        for line in wrap(text, columns) {
+            self.text(font, size, x, &line);
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        match block {
+            Block::Fields(fields) => {
+                let size = 8.5;
+                let columns = ((PAGE_WIDTH - MARGIN - VALUE_X) / (size * COURIER_WIDTH)) as usize;
+                for (name, value) in fields {
+                    let lines = wrap(value, columns);
+                    self.ensure(size * 1.3 * (lines.len() as f32));
+                    self.y -= size * 1.3;
+                    show(&mut self.current, "F2", size, MARGIN, self.y, name);
+                    for (index, line) in lines.iter().enumerate() {
+                        if index > 0 {
+                            self.y -= size * 1.3;
+                        }
+                        show(&mut self.current, "F3", size, VALUE_X, self.y, line);
+                    }
+                }
+                self.space(6.0);
+            },
+            Block::Table { header, rows } => {
+                self.table(header, rows);
+                self.space(6.0);
+            },
+            Block::Note(text) => {
+                self.paragraph("F1", 8.5, MARGIN, text);
+                self.space(3.0);
+            },
+            Block::Subheading { text, note } => {
+                self.space(4.0);
+                self.ensure(40.0);
+                self.text("F2", 10.0, MARGIN, text);
+                self.paragraph("F1", 8.5, MARGIN, note);
+                self.space(2.0);
+            },
+            Block::List(items) => {
+                for item in items {
+                    self.ensure(8.5 * 1.3);
+                    show(&mut self.current, "F1", 8.5, MARGIN, self.y - 8.5 * 1.3, "\u{2022}");
+                    self.paragraph("F1", 8.5, MARGIN + BULLET_INDENT, item);
+                }
+                self.space(3.0);
+            },
+        }
+    }
+
+    /// Writes a table in Courier, repeating the header on every page it spans. Cells too wide
+    /// for the page are wrapped within their column.
+    fn table(&mut self, header: &[String], rows: &[Row]) {
+        let available = ((PAGE_WIDTH - 2.0 * MARGIN) / (TABLE_SIZE * COURIER_WIDTH)) as usize;
+        let mut widths: Vec<usize> = header.iter().map(|name| name.chars().count()).collect();
+        for row in rows {
+            for (width, cell) in widths.iter_mut().zip(&row.cells) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        // Shrink the widest column until the table fits
+        while widths.iter().sum::<usize>() + TABLE_GAP * widths.len() > available {
+            match widths.iter_mut().max() {
+                Some(widest) if *widest > 8 => *widest -= 1,
+                _ => break,
+            }
+        }
+
+        let height = TABLE_SIZE * 1.3;
+        let header_lines = table_lines(header, &widths);
+        self.ensure(height * (header_lines.len() + 1) as f32);
+        self.table_lines("F4", &header_lines);
+
+        for row in rows {
+            let lines = table_lines(&row.cells, &widths);
+            if self.y - height * (lines.len() as f32) < MARGIN + FOOTER_HEIGHT {
+                self.new_page();
+                self.table_lines("F4", &header_lines);
+            }
+            self.table_lines(if row.emphasis { "F4" } else { "F3" }, &lines);
+        }
+    }
+
+    fn table_lines(&mut self, font: &str, lines: &[String]) {
+        for line in lines {
+            self.y -= TABLE_SIZE * 1.3;
+            show(&mut self.current, font, TABLE_SIZE, MARGIN, self.y, line);
+        }
+    }
+
+    /// Content streams of all pages.
+    fn finish(mut self) -> Vec<String> {
+        self.pages.push(self.current);
+        self.pages
+    }
+}
+
+/// Lays out the cells of a row as padded lines, wrapping each cell within its column.
+fn table_lines(cells: &[String], widths: &[usize]) -> Vec<String> {
+    let wrapped: Vec<Vec<String>> = cells.iter().zip(widths)
+        .map(|(cell, width)| wrap(cell, *width))
+        .collect();
+    let count = wrapped.iter().map(Vec::len).max().unwrap_or(0).max(1);
+
+    (0..count)
+        .map(|index| {
+            let mut line = String::new();
+            for (cell, width) in wrapped.iter().zip(widths) {
+                let part = cell.get(index).map_or("", String::as_str);
+                let _ = write!(line, "{part:<width$}{:gap$}", "", width = *width, gap = TABLE_GAP);
+            }
+            line.trim_end().to_string()
+        })
+        .collect()
+}
+
+/// Splits text into lines of at most `columns` characters, at spaces where possible.
+fn wrap(text: &str, columns: usize) -> Vec<String> {
+    let columns = columns.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split(' ') {
+        let mut word: Vec<char> = word.chars().collect();
+        // Break words that do not fit on a line of their own, e.g. hashes and signatures
+        while word.len() > columns {
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            lines.push(word.drain(..columns).collect());
+        }
+        let word: String = word.into_iter().collect();
+
+        if line.is_empty() {
+            line = word;
+        } else if line.chars().count() + 1 + word.chars().count() <= columns {
+            line.push(' ');
+            line.push_str(&word);
+        } else {
+            lines.push(std::mem::replace(&mut line, word));
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Appends a text-showing operation to a content stream.
+fn show(stream: &mut String, font: &str, size: f32, x: f32, y: f32, text: &str) {
+    let _ = writeln!(stream, "BT /{font} {size} Tf {x:.1} {y:.1} Td ({}) Tj ET", encode(text));
+}
+
+/// Encodes text as a WinAnsi PDF string literal body.
+fn encode(text: &str) -> String {
+    let mut encoded = String::new();
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                encoded.push('\\');
+                encoded.push(c);
+            },
+            ' '..='~' => encoded.push(c),
+            // Greek mu, as used for microseconds, shares a glyph with the micro sign
+            'μ' => encoded.push_str("\\265"),
+            // Outside Latin-1, at 0x95 in WinAnsi
+            '•' => encoded.push_str("\\225"),
+            // WinAnsi agrees with Latin-1 from 0xA0
+            '\u{a0}'..='\u{ff}' => {
+                let _ = write!(encoded, "\\{:03o}", c as u32);
+            },
+            _ => encoded.push('?'),
+        }
+    }
+    encoded
+}
+
+/// Assembles the PDF: catalog, page tree, fonts, one page with a body and a footer stream per
+/// page, the document information, and the cross-reference table.
+fn write_document(pages: &[String], footers: &[String], info: &[(&str, String)]) -> Vec<u8> {
+    const CATALOG: usize = 1;
+    const PAGES: usize = 2;
+    const FIRST_FONT: usize = 3;
+    let info_id = FIRST_FONT + FONTS.len();
+    let first_page = info_id + 1;
+    // Page, body stream, footer stream
+    let page_id = |index: usize| first_page + index * 3;
+
+    let mut objects: Vec<String> = Vec::new();
+    objects.push(format!("<< /Type /Catalog /Pages {PAGES} 0 R >>"));
+    objects.push(format!(
+        "<< /Type /Pages /Kids [{}] /Count {} >>",
+        (0..pages.len()).map(|index| format!("{} 0 R", page_id(index))).collect::<Vec<_>>().join(" "),
+        pages.len(),
+    ));
+    for (_, font) in FONTS {
+        objects.push(format!("<< /Type /Font /Subtype /Type1 /BaseFont /{font} /Encoding /WinAnsiEncoding >>"));
+    }
+    objects.push(format!(
+        "<< {} >>",
+        info.iter().map(|(key, value)| format!("/{key} ({})", encode(value))).collect::<Vec<_>>().join(" "),
+    ));
+
+    let fonts = FONTS.iter().enumerate()
+        .map(|(index, (name, _))| format!("/{name} {} 0 R", FIRST_FONT + index))
+        .collect::<Vec<_>>()
+        .join(" ");
+    for (index, (body, footer)) in pages.iter().zip(footers).enumerate() {
+        let id = page_id(index);
+        objects.push(format!(
+            "<< /Type /Page /Parent {PAGES} 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] /Resources << /Font << {fonts} >> >> /Contents [{} 0 R {} 0 R] >>",
+            id + 1,
+            id + 2,
+        ));
+        objects.push(stream(body));
+        objects.push(stream(footer));
+    }
+
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        let _ = writeln!(pdf, "{} 0 obj\n{object}\nendobj", index + 1);
+    }
+
+    let xref = pdf.len();
+    let _ = write!(pdf, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(pdf, "{offset:010} 00000 n ");
+    }
+    let _ = writeln!(pdf, "trailer\n<< /Size {} /Root {CATALOG} 0 R /Info {info_id} 0 R >>\nstartxref\n{xref}\n%%EOF", objects.len() + 1);
+
+    pdf.into_bytes()
+}
+
+fn stream(content: &str) -> String {
+    format!("<< /Length {} >>\nstream\n{content}\nendstream", content.len())
+}
@@ -0,0 +1,134 @@
+//! Progress reporting on STDERR while a capture is loaded and analysed.
+//!
+//! tshark takes minutes over multi-GB captures, so the packets read and streams found are
+//! reported as they come in, followed by the streams analysed. On a terminal the status line is
+//! redrawn in place; otherwise (e.g. redirected to a log file) a line is written now and then.
+use std::io::{self, IsTerminal, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Time between redraws of the status line on a terminal.
+pub const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Time between status lines when STDERR is not a terminal.
+pub const LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Status of the capture being processed; shared between the analysis threads.
+pub struct Progress {
+    enabled: bool,
+    terminal: bool,
+    /// Time the current capture was started
+    start: Mutex<Instant>,
+    /// Time of the last status written
+    last: Mutex<Instant>,
+    packets: AtomicUsize,
+    streams: AtomicUsize,
+    analysed: AtomicUsize,
+}
+
+impl Progress {
+    /// Reports progress if `enabled`; otherwise all calls are no-ops.
+    pub fn new(enabled: bool) -> Self {
+        Progress {
+            enabled,
+            terminal: io::stderr().is_terminal(),
+            start: Mutex::new(Instant::now()),
+            last: Mutex::new(Instant::now()),
+            packets: AtomicUsize::new(0),
+            streams: AtomicUsize::new(0),
+            analysed: AtomicUsize::new(0),
+        }
+    }
+
+    /// Starts over for the next capture.
+    pub fn reset(&self) {
+        *self.start.lock().unwrap() = Instant::now();
+        self.packets.store(0, Ordering::Relaxed);
+        self.streams.store(0, Ordering::Relaxed);
+        self.analysed.store(0, Ordering::Relaxed);
+    }
+
+    /// Records the packets read and SSH streams found so far.
+    pub fn read(&self, packets: usize, streams: usize) {
+        self.packets.store(packets, Ordering::Relaxed);
+        self.streams.store(streams, Ordering::Relaxed);
+        self.update();
+    }
+
+    /// Records the number of SSH streams to analyse once the capture has been read.
+    pub fn collected(&self, streams: usize) {
+        self.streams.store(streams, Ordering::Relaxed);
+    }
+
+    /// Records the analysis of one more stream, finished or skipped.
+    pub fn analysed(&self) {
+        self.analysed.fetch_add(1, Ordering::Relaxed);
+        self.update();
+    }
+
+    /// Writes the final status, ending the status line.
+    pub fn finish(&self) {
+        if !self.enabled {
+            return;
+        }
+        self.write(&format!("{} in {:.1}s", self.status(), self.start.lock().unwrap().elapsed().as_secs_f64()), true);
+    }
+
+    /// Current status, e.g. `Read 120000 packets, 12 SSH streams; analysed 3/12`.
+    pub fn status(&self) -> String {
+        let (packets, streams, analysed) = (self.packets.load(Ordering::Relaxed), self.streams.load(Ordering::Relaxed), self.analysed.load(Ordering::Relaxed));
+        let read = format!("Read {packets} packets, {streams} SSH streams");
+        if analysed == 0 {
+            read
+        } else {
+            format!("{read}; analysed {analysed}/{streams}")
+        }
+    }
+
+    /// Writes the status if the interval has passed since the last one.
+    fn update(&self) {
+        if !self.enabled {
+            return;
+        }
+        let interval = if self.terminal { REDRAW_INTERVAL } else { LOG_INTERVAL };
+        // Skip rather than wait if another thread is writing
+        let mut last = match self.last.try_lock() {
+            Ok(last) => last,
+            Err(_) => return,
+        };
+        if last.elapsed() < interval {
+            return;
+        }
+        *last = Instant::now();
+        self.write(&self.status(), false);
+    }
+
+    fn write(&self, status: &str, done: bool) {
+        let mut stderr = io::stderr().lock();
+        let _ = if self.terminal {
+            // Redraw in place: carriage return and clear the line
+            write!(stderr, "\r\x1b[2K{status}{}", if done { "\n" } else { "" })
+        } else {
+            writeln!(stderr, "{status}")
+        };
+        let _ = stderr.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        let progress = Progress::new(false);
+        progress.read(1200, 3);
+        assert_eq!("Read 1200 packets, 3 SSH streams", progress.status());
+        progress.collected(4);
+        progress.analysed();
+        assert_eq!("Read 1200 packets, 4 SSH streams; analysed 1/4", progress.status());
+        progress.reset();
+        assert_eq!("Read 0 packets, 0 SSH streams", progress.status());
+    }
+}
@@ -0,0 +1,185 @@
+//! Remediation guidance for findings.
+//!
+//! Guidance is attached to finding IDs, like [catalog](super::catalog) descriptions. The bundled
+//! guidance (`data/remediation/en.json`) covers authentication, forwarding, and host key findings;
+//! a file in the same format, given with `--remediation`, replaces or adds entries by ID. Texts
+//! are templates whose `{placeholders}` are filled in from the session; see [PLACEHOLDERS].
+use crate::analyser::containers::{Event, Finding};
+use crate::analyser::core::SshSession;
+use super::catalog::Catalog;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::OnceLock;
+
+/// The bundled guidance.
+const BUNDLED: &str = include_str!("../../data/remediation/en.json");
+
+/// Placeholders templates may use, and what they are replaced with.
+pub const PLACEHOLDERS: [(&str, &str); 9] = [
+    ("stream", "TCP stream number"),
+    ("client", "client address"),
+    ("server", "server address"),
+    ("details", "details of the session's first finding with the ID"),
+    ("count", "number of findings with the ID in the session"),
+    ("kex", "key exchange algorithm"),
+    ("cipher", "encryption algorithm"),
+    ("mac", "MAC algorithm"),
+    ("host_key", "server host key type and SHA256 fingerprint"),
+];
+
+/// Guidance for one finding ID, as written in a remediation file.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Remediation {
+    /// Finding ID the guidance applies to
+    pub finding: String,
+    /// One-line summary of what to do
+    pub summary: String,
+    /// Steps, in order
+    pub steps: Vec<String>,
+    /// Links to documentation
+    #[serde(default)]
+    pub references: Vec<String>,
+}
+
+/// Guidance rendered for a session.
+#[derive(Clone, Debug, Serialize)]
+pub struct Guidance {
+    pub finding: Event,
+    /// Number of findings with the ID in the session
+    pub count: usize,
+    pub summary: String,
+    pub steps: Vec<String>,
+    pub references: Vec<String>,
+}
+
+/// Guidance by finding ID.
+#[derive(Clone, Debug)]
+pub struct RemediationSet {
+    entries: BTreeMap<String, Remediation>,
+}
+
+impl RemediationSet {
+    /// The bundled guidance.
+    pub fn bundled() -> &'static RemediationSet {
+        static BUNDLED_SET: OnceLock<RemediationSet> = OnceLock::new();
+        BUNDLED_SET.get_or_init(|| Self::parse(BUNDLED).expect("bundled remediation guidance is valid"))
+    }
+
+    /// Bundled guidance with the entries of a remediation file replacing or adding to it.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| format!("Failed to read {path}: {err}"))?;
+        let custom = Self::parse(&contents).map_err(|err| format!("{path}: {err}"))?;
+        for id in custom.entries.keys().filter(|id| !Catalog::english().contains(id)) {
+            log::warn!("{path}: unknown finding ID '{id}'");
+        }
+
+        let mut set = Self::bundled().clone();
+        set.entries.extend(custom.entries);
+        Ok(set)
+    }
+
+    /// Parses a JSON array of [Remediation] entries, rejecting unknown placeholders.
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let entries: Vec<Remediation> = serde_json::from_str(contents).map_err(|err| err.to_string())?;
+
+        for entry in &entries {
+            let templates = [&entry.summary].into_iter().chain(&entry.steps);
+            for placeholder in templates.flat_map(|template| placeholders(template)) {
+                if !PLACEHOLDERS.iter().any(|(name, _)| *name == placeholder) {
+                    return Err(format!("{}: unknown placeholder {{{placeholder}}}", entry.finding));
+                }
+            }
+        }
+
+        Ok(Self { entries: entries.into_iter().map(|entry| (entry.finding.clone(), entry)).collect() })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Guidance for a finding ID, unrendered.
+    pub fn get(&self, kind: Event) -> Option<&Remediation> {
+        self.entries.get(&kind.to_string())
+    }
+
+    /// Guidance for each kind of finding in a session that has any, in order of first occurrence.
+    pub fn for_session(&self, session: &SshSession) -> Vec<Guidance> {
+        let mut kinds: Vec<(&Finding, usize)> = vec![];
+        for finding in &session.results {
+            match kinds.iter_mut().find(|(first, _)| first.kind == finding.kind) {
+                Some((_, count)) => *count += 1,
+                None => kinds.push((finding, 1)),
+            }
+        }
+
+        kinds.into_iter()
+            .filter_map(|(first, count)| {
+                let remediation = self.get(first.kind)?;
+                let values = values(session, first, count);
+                Some(Guidance {
+                    finding: first.kind,
+                    count,
+                    summary: render(&remediation.summary, &values),
+                    steps: remediation.steps.iter().map(|step| render(step, &values)).collect(),
+                    references: remediation.references.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Placeholder values for a session and its first finding of a kind.
+fn values(session: &SshSession, finding: &Finding, count: usize) -> Vec<(&'static str, String)> {
+    vec![
+        ("stream", session.stream.to_string()),
+        ("client", session.src.clone()),
+        ("server", session.dst.clone()),
+        ("details", finding.details.clone().unwrap_or_default()),
+        ("count", count.to_string()),
+        ("kex", session.algorithms.0.clone()),
+        ("cipher", session.algorithms.1.clone()),
+        ("mac", session.algorithms.2.clone()),
+        ("host_key", session.host_key.as_ref().map_or("an unknown key".to_string(), |host_key| format!("{} {}", host_key.key_type, host_key.sha256))),
+    ]
+}
+
+/// Replaces the `{placeholders}` of a template.
+pub fn render(template: &str, values: &[(&str, String)]) -> String {
+    let mut rendered = template.to_string();
+    for (name, value) in values {
+        rendered = rendered.replace(&format!("{{{name}}}"), value);
+    }
+    rendered
+}
+
+/// Names of the `{placeholders}` in a template.
+fn placeholders(template: &str) -> Vec<&str> {
+    template.split('{')
+        .skip(1)
+        .filter_map(|rest| rest.split_once('}').map(|(name, _)| name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::containers;
+
+    #[test]
+    fn test_remediation() {
+        assert!(RemediationSet::bundled().get(containers::Event::HostKeyChanged).is_some());
+        assert!(RemediationSet::bundled().get(containers::Event::Rekey).is_none());
+
+        let custom = RemediationSet::parse(r#"[{"finding": "WrongPassword", "summary": "Block {client}", "steps": ["{count} attempts"]}]"#).unwrap();
+        let entry = custom.get(containers::Event::WrongPassword).unwrap();
+        assert!(entry.references.is_empty());
+        assert_eq!("Block 10.0.0.1", render(&entry.summary, &[("client", "10.0.0.1".to_string())]));
+        assert!(RemediationSet::parse(r#"[{"finding": "WrongPassword", "summary": "Block {user}", "steps": []}]"#).is_err());
+    }
+}
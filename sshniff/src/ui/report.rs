@@ -0,0 +1,304 @@
+//! Format-neutral report layout shared by the HTML and PDF exporters.
+//!
+//! A session is laid out once as a [Report] of sections, field lists, and tables; each exporter
+//! only decides how to render those blocks.
+use crate::analyser::containers::{Confidence, KexInit, KeystrokeType};
+use crate::analyser::core::SshSession;
+use crate::analyser::options::AnalysisOptions;
+use crate::analyser::utils;
+use super::catalog::Catalog;
+use super::remediation::{Guidance, RemediationSet};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io;
+
+/// A report on a single session.
+pub struct Report {
+    pub title: String,
+    pub sections: Vec<Section>,
+}
+
+/// A headed part of a [Report].
+pub struct Section {
+    pub heading: String,
+    pub blocks: Vec<Block>,
+}
+
+/// Content of a [Section].
+pub enum Block {
+    /// Name/value pairs
+    Fields(Vec<(String, String)>),
+    /// Table with a header row
+    Table { header: Vec<String>, rows: Vec<Row> },
+    /// Secondary text, e.g. a summary line
+    Note(String),
+    /// Heading within a section, with secondary text
+    Subheading { text: String, note: String },
+    /// Bulleted items, e.g. remediation steps
+    List(Vec<String>),
+}
+
+/// Table row; emphasised rows stand out, e.g. the Enter closing a command.
+pub struct Row {
+    pub cells: Vec<String>,
+    pub emphasis: bool,
+}
+
+impl Row {
+    pub fn new(cells: Vec<String>) -> Self {
+        Self { cells, emphasis: false }
+    }
+}
+
+/// Where a report's data came from and how it was produced, for its appendix.
+pub struct Provenance {
+    pub capture: String,
+    /// SHA-256 of the capture file
+    pub capture_sha256: String,
+    /// Size of the capture file in bytes
+    pub capture_size: u64,
+    /// SSHniff version
+    pub version: String,
+    /// UTC time the report was generated
+    pub generated: String,
+    /// Analysis parameters as name/value pairs
+    pub parameters: Vec<(String, String)>,
+}
+
+impl Provenance {
+    /// Hashes the capture and records the options it was analysed with.
+    pub fn new(capture: &str, options: &AnalysisOptions) -> io::Result<Self> {
+        let mut hasher = Sha256::new();
+        let capture_size = io::copy(&mut File::open(capture)?, &mut hasher)?;
+
+        let thresholds = &options.thresholds;
+        let overrides = &options.overrides;
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "inferred".to_string());
+        let parameters = [
+            ("Skipped scans", format!("{:?}", options.skipped)),
+            ("Self-tuned thresholds", options.self_tune.to_string()),
+            ("Keystroke upper bound", thresholds.keystroke_upper_bound.to_string()),
+            ("Echo offset", thresholds.echo_offset.to_string()),
+            ("Prompt tolerance", thresholds.prompt_tolerance.to_string()),
+            ("Path change window / ratio / delta", format!("{} / {} / {}μs", thresholds.path_change_window, thresholds.path_change_ratio, thresholds.path_change_min_delta)),
+            ("Keystroke size", optional(overrides.keystroke_size.map(|size| size.to_string()))),
+            ("Prompt size", optional(overrides.prompt_size.map(|size| size.to_string()))),
+            ("MAC length", optional(overrides.mac_length.map(|length| length.to_string()))),
+            ("Obfuscated", optional(overrides.obfuscated.map(|obfuscated| obfuscated.to_string()))),
+            ("Idle split", options.idle_split.map_or("off".to_string(), |gap| format!("{gap}μs"))),
+        ];
+
+        Ok(Self {
+            capture: capture.to_string(),
+            capture_sha256: hex::encode(hasher.finalize()),
+            capture_size,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            generated: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            parameters: parameters.into_iter().map(|(name, value)| (name.to_string(), value)).collect(),
+        })
+    }
+
+    /// Appendix section listing the provenance and parameters.
+    pub fn section(&self) -> Section {
+        let fields = vec![
+            ("Capture".to_string(), self.capture.clone()),
+            ("Capture SHA-256".to_string(), self.capture_sha256.clone()),
+            ("Capture size".to_string(), format!("{} bytes", self.capture_size)),
+            ("SSHniff version".to_string(), self.version.clone()),
+            ("Generated (UTC)".to_string(), self.generated.clone()),
+        ];
+
+        Section {
+            heading: "Appendix: Provenance and Parameters".to_string(),
+            blocks: vec![Block::Fields(fields), Block::Fields(self.parameters.clone())],
+        }
+    }
+}
+
+/// Lays out a session: metadata, event timeline, remediation, and keystroke sequences. Findings
+/// are described from `catalog`, with guidance from `remediation`.
+pub fn session_report(session: &SshSession, capture: &str, catalog: &Catalog, remediation: &RemediationSet) -> Report {
+    let mut sections = vec![metadata(session), timeline(session, catalog)];
+    if let (Some(client), Some(server)) = &session.kexinit {
+        sections.push(kexinit(client, server));
+    }
+    if !session.advisories.0.is_empty() || !session.advisories.1.is_empty() {
+        sections.push(advisories(session));
+    }
+    let guidance = remediation.for_session(session);
+    if !guidance.is_empty() {
+        sections.push(remediation_section(&guidance, catalog));
+    }
+    if !session.keystroke_data.is_empty() {
+        sections.push(keystrokes(session));
+    }
+
+    Report {
+        title: format!("SSHniff: {} stream {}", capture, session.stream),
+        sections,
+    }
+}
+
+fn metadata(session: &SshSession) -> Section {
+    let mut blocks = vec![];
+    let fields = [
+        ("Duration (UTC)", format!("{} - {}", session.start_utc, session.end_utc)),
+        ("Client", format!("{} ({})", session.src, session.protocols.0)),
+        ("Server", format!("{} ({})", session.dst, session.protocols.1)),
+        ("HASSH client", session.hassh_c.clone()),
+        ("HASSH server", session.hassh_s.clone()),
+        ("Tags", session.tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>().join(", ")),
+        ("Severity", format!("{}/10", session.severity)),
+        ("KEX", session.algorithms.0.clone()),
+        ("Encryption", session.algorithms.1.clone()),
+        ("MAC", session.algorithms.2.clone()),
+        ("Compression", session.algorithms.3.clone()),
+        ("Host key", session.host_key.as_ref().map_or(String::new(), |host_key| format!("{} {} {}", host_key.key_type, host_key.sha256, host_key.md5))),
+        ("Bytes after login", format!("{} client / {} server", session.bytes.0, session.bytes.1)),
+        ("RTT", format!("{}μs client / {}μs server", session.jitter.0.mean_rtt, session.jitter.1.mean_rtt)),
+        ("Signature", session.signature.clone()),
+        ("Size confidence", format_size_confidence(session)),
+    ];
+
+    blocks.push(Block::Fields(fields.into_iter().map(|(name, value)| (name.to_string(), value)).collect()));
+    if let Some(sampled) = &session.sampled {
+        blocks.push(Block::Note(format!("Sampled after {} packets: {} / {} packets and {} / {} bytes (client / server) followed. {}", sampled.exact_packets, sampled.packets.0, sampled.packets.1, sampled.bytes.0, sampled.bytes.1, sampled.accuracy)));
+    }
+
+    Section {
+        heading: "Session".to_string(),
+        blocks,
+    }
+}
+
+fn timeline(session: &SshSession, catalog: &Catalog) -> Section {
+    let rows = session.results.iter()
+        .map(|finding| Row::new(vec![
+            finding.packet_ref.seq.to_string(),
+            utils::format_timestamp(finding.timestamp),
+            catalog.describe(finding),
+            format!("{:.0}%", finding.confidence * 100.0),
+        ]))
+        .collect();
+
+    Section {
+        heading: "Timeline of Events".to_string(),
+        blocks: vec![Block::Table { header: header(&["tcp.seq", "Time (UTC)", "Event", "Confidence"]), rows }],
+    }
+}
+
+/// Names and comma-separated algorithms of the lists of a KEXINIT. With `merge`, directional
+/// lists are given once where both directions agree, as they usually do.
+pub fn kexinit_lists(kexinit: &KexInit, merge: bool) -> Vec<(&'static str, String)> {
+    let mut lists = vec![("KEX", kexinit.kex.join(",")), ("Host key", kexinit.host_key.join(","))];
+    let directional = [
+        ("Encryption", "Encryption (CTS)", "Encryption (STC)", &kexinit.encryption),
+        ("MAC", "MAC (CTS)", "MAC (STC)", &kexinit.mac),
+        ("Compression", "Compression (CTS)", "Compression (STC)", &kexinit.compression),
+    ];
+    for (name, cts_name, stc_name, (client_to_server, server_to_client)) in directional {
+        if merge && client_to_server == server_to_client {
+            lists.push((name, client_to_server.join(",")));
+        } else {
+            lists.push((cts_name, client_to_server.join(",")));
+            lists.push((stc_name, server_to_client.join(",")));
+        }
+    }
+    lists
+}
+
+fn kexinit(client: &KexInit, server: &KexInit) -> Section {
+    let rows = kexinit_lists(client, false).into_iter().zip(kexinit_lists(server, false))
+        .map(|((name, client), (_, server))| Row::new(vec![name.to_string(), client, server]))
+        .collect();
+
+    Section {
+        heading: "Key Exchange Offers".to_string(),
+        blocks: vec![Block::Table { header: header(&["List", "Client", "Server"]), rows }],
+    }
+}
+
+fn advisories(session: &SshSession) -> Section {
+    let rows = [("Client", &session.advisories.0, &session.protocols.0), ("Server", &session.advisories.1, &session.protocols.1)].into_iter()
+        .flat_map(|(side, advisories, banner)| advisories.iter().map(move |advisory| Row {
+            cells: vec![
+                side.to_string(),
+                banner.clone(),
+                advisory.id.clone(),
+                format!("{:.1}", advisory.cvss),
+                format!("< {}", advisory.fixed),
+                advisory.summary.clone(),
+            ],
+            emphasis: advisory.cvss >= 7.0,
+        }))
+        .collect();
+
+    Section {
+        heading: "Known Vulnerabilities".to_string(),
+        blocks: vec![
+            Block::Note("Matched on the banner versions; distributions backport fixes without changing them.".to_string()),
+            Block::Table { header: header(&["Side", "Banner", "Advisory", "CVSS", "Fixed in", "Summary"]), rows },
+        ],
+    }
+}
+
+fn remediation_section(guidance: &[Guidance], catalog: &Catalog) -> Section {
+    let mut blocks = vec![];
+    for entry in guidance {
+        blocks.push(Block::Subheading {
+            text: entry.summary.clone(),
+            note: format!("({}, {}x)", catalog.event(entry.finding), entry.count),
+        });
+        blocks.push(Block::List(entry.steps.clone()));
+        if !entry.references.is_empty() {
+            blocks.push(Block::Note(format!("See {}", entry.references.join(", "))));
+        }
+    }
+
+    Section {
+        heading: "Remediation".to_string(),
+        blocks,
+    }
+}
+
+fn keystrokes(session: &SshSession) -> Section {
+    let mut blocks = vec![Block::Note(format!("Error rate {:.0}%", session.error_rate * 100.0))];
+
+    for sequence in &session.keystroke_data {
+        blocks.push(Block::Subheading {
+            text: format!("Command #{}", sequence.id),
+            note: format!("({}, length {}, error rate {:.0}%{}, {})", sequence.start_utc, sequence.length, sequence.error_rate * 100.0, if sequence.symbol_heavy { ", symbol-heavy" } else { "" }, sequence.input),
+        });
+
+        let rows = sequence.keystrokes.iter()
+            .map(|keystroke| Row {
+                cells: vec![
+                    keystroke.seq.to_string(),
+                    keystroke.latency_us.to_string(),
+                    format!("{:?}", keystroke.k_type),
+                    format!("{:.0}%", keystroke.confidence * 100.0),
+                    keystroke.response_size.map_or(String::new(), |size| size.to_string()),
+                    keystroke.guesses.iter().map(|guess| guess.to_string()).collect::<Vec<_>>().join(", "),
+                ],
+                emphasis: keystroke.k_type == KeystrokeType::Enter,
+            })
+            .collect();
+        blocks.push(Block::Table { header: header(&["tcp.seq", "Latency μs", "Type", "Confidence", "Response", "Likely command"]), rows });
+    }
+
+    Section {
+        heading: "Keystroke Sequences".to_string(),
+        blocks,
+    }
+}
+
+/// Keystroke, prompt, and login success sizes with the levels of their confidence.
+pub fn format_size_confidence(session: &SshSession) -> String {
+    let confidence = &session.size_confidence;
+    format!("keystroke {} ({}), prompt {} ({}), login success ({})", session.keystroke_size, Confidence::of(confidence.keystroke_size),
+        session.prompt_size, Confidence::of(confidence.prompt_size), Confidence::of(confidence.login_success))
+}
+
+fn header(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
@@ -0,0 +1,160 @@
+//! Syslog forwarding of sessions and findings.
+//!
+//! Messages follow RFC 5424 and carry the [CEF](super::formats) message of a session summary or
+//! timeline event as their body. Over TCP and TLS, messages are framed by octet counting
+//! (RFC 6587).
+use crate::analyser::core::SshSession;
+#[cfg(feature = "tls")]
+use crate::analyser::utils;
+use super::formats::{self, SiemFormat};
+use chrono::{SecondsFormat, Utc};
+use clap::ValueEnum;
+#[cfg(feature = "tls")]
+use native_tls::{TlsConnector, TlsStream};
+use std::io::{self, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+
+/// Facility `local0`.
+const FACILITY: u8 = 16;
+
+/// Severity `notice`.
+const SEVERITY: u8 = 5;
+
+/// How messages reach the syslog server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Transport {
+    Udp,
+    Tcp,
+    /// TCP with TLS (RFC 5425); the server certificate is verified. Needs the `tls` feature
+    Tls,
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+/// Open connection to a syslog server.
+pub struct SyslogSender {
+    connection: Connection,
+}
+
+impl SyslogSender {
+    /// Connects to `address` (`host:port`).
+    pub fn connect(address: &str, transport: Transport) -> io::Result<Self> {
+        let connection = match transport {
+            Transport::Udp => {
+                let target = address.to_socket_addrs()?.next()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{address} did not resolve")))?;
+                // Bind to the unspecified address of the target's family, so IPv6 servers work too.
+                let local = match target {
+                    SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+                    SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+                };
+                let socket = UdpSocket::bind(local)?;
+                socket.connect(target)?;
+                Connection::Udp(socket)
+            },
+            Transport::Tcp => Connection::Tcp(TcpStream::connect(address)?),
+            #[cfg(feature = "tls")]
+            Transport::Tls => {
+                let (host, _) = utils::split_endpoint(address);
+                let connector = TlsConnector::new().map_err(io::Error::other)?;
+                let stream = connector.connect(host, TcpStream::connect(address)?).map_err(io::Error::other)?;
+                Connection::Tls(Box::new(stream))
+            },
+            #[cfg(not(feature = "tls"))]
+            Transport::Tls => return Err(io::Error::new(io::ErrorKind::Unsupported, "TLS needs a build with the `tls` feature")),
+        };
+
+        Ok(Self { connection })
+    }
+
+    /// Sends the summary of a session, followed by one message per timeline event.
+    pub fn send_session(&mut self, session: &SshSession) -> io::Result<()> {
+        for message in formats::session_messages(session, SiemFormat::Cef) {
+            self.send(&message)?;
+        }
+        Ok(())
+    }
+
+    /// Sends a single message.
+    pub fn send(&mut self, message: &str) -> io::Result<()> {
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+        let line = format!("<{}>1 {timestamp} - sshniff {} - - {message}", FACILITY * 8 + SEVERITY, std::process::id());
+
+        match &mut self.connection {
+            Connection::Udp(socket) => socket.send(line.as_bytes()).map(|_| ()),
+            Connection::Tcp(stream) => write!(stream, "{} {line}", line.len()),
+            #[cfg(feature = "tls")]
+            Connection::Tls(stream) => write!(stream, "{} {line}", line.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    /// Checks the RFC 5424 header and returns the message body.
+    fn body(line: &str) -> &str {
+        let fields: Vec<&str> = line.splitn(8, ' ').collect();
+        assert_eq!(fields[0], "<133>1");
+        assert!(chrono::DateTime::parse_from_rfc3339(fields[1]).is_ok(), "{}", fields[1]);
+        assert_eq!(fields[2], "-");
+        assert_eq!(fields[3], "sshniff");
+        assert_eq!(fields[4], std::process::id().to_string());
+        assert_eq!(&fields[5..7], ["-", "-"]);
+        fields[7]
+    }
+
+    #[test]
+    fn test_udp_message() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut sender = SyslogSender::connect(&server.local_addr().unwrap().to_string(), Transport::Udp).unwrap();
+        sender.send("CEF:0|test").unwrap();
+
+        let mut buffer = [0u8; 512];
+        let length = server.recv(&mut buffer).unwrap();
+        let line = std::str::from_utf8(&buffer[..length]).unwrap();
+        assert_eq!(body(line), "CEF:0|test");
+    }
+
+    #[test]
+    fn test_udp_ipv6() {
+        let Ok(server) = UdpSocket::bind("[::1]:0") else { return };
+        let mut sender = SyslogSender::connect(&server.local_addr().unwrap().to_string(), Transport::Udp).unwrap();
+        sender.send("CEF:0|test").unwrap();
+
+        let mut buffer = [0u8; 512];
+        let length = server.recv(&mut buffer).unwrap();
+        assert_eq!(body(std::str::from_utf8(&buffer[..length]).unwrap()), "CEF:0|test");
+    }
+
+    #[test]
+    fn test_tcp_octet_counting() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut sender = SyslogSender::connect(&server.local_addr().unwrap().to_string(), Transport::Tcp).unwrap();
+        let (mut stream, _) = server.accept().unwrap();
+        sender.send("first").unwrap();
+        sender.send("second message").unwrap();
+        drop(sender);
+
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+
+        let mut rest = received.as_str();
+        for expected in ["first", "second message"] {
+            let (length, tail) = rest.split_once(' ').unwrap();
+            let length: usize = length.parse().unwrap();
+            let (line, tail) = tail.split_at(length);
+            assert_eq!(body(line), expected);
+            rest = tail;
+        }
+        assert!(rest.is_empty());
+    }
+}
@@ -0,0 +1,248 @@
+//! Interactive terminal viewer, behind the `tui` feature.
+//!
+//! Lists the sessions of a capture on the left and shows the selected one on the right: its
+//! metadata, the event timeline (followed by remediation guidance), and the keystroke sequences.
+//! The panes are laid out from the same [report] as the HTML and PDF exports, so the viewer shows
+//! the same data, without scrolling through the console dump of every stream.
+//!
+//! Keys: Up/Down (or k/j) select a session or scroll the focused pane, PageUp/PageDown scroll by a
+//! page, Home jumps to the top, Tab switches panes, and q or Esc quits.
+use crate::analyser::core::SshSession;
+use super::catalog::Catalog;
+use super::remediation::RemediationSet;
+use super::report::{self, Block, Row, Section};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{self, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::BTreeMap;
+use std::io;
+
+/// Width of the session list.
+const LIST_WIDTH: u16 = 36;
+
+/// Lines scrolled by PageUp/PageDown.
+const PAGE: usize = 10;
+
+/// Spaces between table columns.
+const COLUMN_GAP: usize = 2;
+
+/// Pane that arrow keys act on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Focus {
+    Sessions,
+    Timeline,
+    Keystrokes,
+}
+
+/// A session, rendered once up front.
+struct Entry {
+    label: String,
+    metadata: Vec<Line<'static>>,
+    timeline: Vec<Line<'static>>,
+    keystrokes: Vec<Line<'static>>,
+}
+
+struct Viewer {
+    entries: Vec<Entry>,
+    list: ListState,
+    focus: Focus,
+    /// Scroll offsets of the timeline and keystroke panes
+    scroll: (usize, usize),
+}
+
+/// Shows the sessions until the user quits. Does nothing without sessions.
+pub fn run(sessions: &BTreeMap<u32, SshSession>, capture: &str, catalog: &Catalog, remediation: &RemediationSet) -> io::Result<()> {
+    if sessions.is_empty() {
+        return Ok(());
+    }
+
+    let mut viewer = Viewer {
+        entries: sessions.values().map(|session| entry(session, capture, catalog, remediation)).collect(),
+        list: ListState::default().with_selected(Some(0)),
+        focus: Focus::Sessions,
+        scroll: (0, 0),
+    };
+
+    let mut terminal = ratatui::init();
+    let result = viewer.run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+impl Viewer {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !self.handle(key.code) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Applies a key press; false to quit.
+    fn handle(&mut self, key: KeyCode) -> bool {
+        let step: isize = match key {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Sessions => Focus::Timeline,
+                    Focus::Timeline => Focus::Keystrokes,
+                    Focus::Keystrokes => Focus::Sessions,
+                };
+                return true;
+            },
+            KeyCode::Up | KeyCode::Char('k') => -1,
+            KeyCode::Down | KeyCode::Char('j') => 1,
+            KeyCode::PageUp => -(PAGE as isize),
+            KeyCode::PageDown => PAGE as isize,
+            KeyCode::Home => isize::MIN,
+            _ => return true,
+        };
+
+        let entry = &self.entries[self.selected()];
+        match self.focus {
+            Focus::Sessions => {
+                self.list.select(Some(offset(self.selected(), step, self.entries.len())));
+                self.scroll = (0, 0);
+            },
+            Focus::Timeline => self.scroll.0 = offset(self.scroll.0, step, entry.timeline.len()),
+            Focus::Keystrokes => self.scroll.1 = offset(self.scroll.1, step, entry.keystrokes.len()),
+        }
+        true
+    }
+
+    fn selected(&self) -> usize {
+        self.list.selected().unwrap_or(0)
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, help] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [left, right] = Layout::horizontal([Constraint::Length(LIST_WIDTH), Constraint::Min(0)]).areas(main);
+
+        let items: Vec<ListItem> = self.entries.iter().map(|entry| ListItem::new(entry.label.clone())).collect();
+        let list = List::new(items)
+            .block(pane(" Sessions ", self.focus == Focus::Sessions))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, left, &mut self.list);
+
+        let entry = &self.entries[self.selected()];
+        let [metadata, timeline, keystrokes] = Layout::vertical([
+            Constraint::Length(entry.metadata.len() as u16 + 2),
+            Constraint::Percentage(40),
+            Constraint::Min(0),
+        ]).areas(right);
+        frame.render_widget(Paragraph::new(entry.metadata.clone()).block(pane(" Session ", false)), metadata);
+        scrolled(frame, " Timeline ", &entry.timeline, self.scroll.0, self.focus == Focus::Timeline, timeline);
+        scrolled(frame, " Keystrokes ", &entry.keystrokes, self.scroll.1, self.focus == Focus::Keystrokes, keystrokes);
+
+        let keys = " \u{2191}\u{2193}/jk select or scroll  PgUp/PgDn page  Home top  Tab switch pane  q quit";
+        frame.render_widget(Paragraph::new(keys).style(Style::default().fg(Color::DarkGray)), help);
+    }
+}
+
+/// Moves `position` by `step` within `0..len`; `isize::MIN` goes to the top.
+fn offset(position: usize, step: isize, len: usize) -> usize {
+    if step == isize::MIN {
+        return 0;
+    }
+    position.saturating_add_signed(step).min(len.saturating_sub(1))
+}
+
+fn pane(title: &str, focused: bool) -> widgets::Block<'_> {
+    let style = if focused { Style::default().fg(Color::Yellow) } else { Style::default() };
+    widgets::Block::bordered().title(title).border_style(style)
+}
+
+fn scrolled(frame: &mut Frame, title: &str, lines: &[Line<'static>], scroll: usize, focused: bool, area: Rect) {
+    let position = if lines.is_empty() { String::new() } else { format!(" {}/{} ", scroll + 1, lines.len()) };
+    let paragraph = Paragraph::new(lines.to_vec())
+        .block(pane(title, focused).title_bottom(Line::from(position).right_aligned()))
+        .scroll((scroll.min(u16::MAX as usize) as u16, 0));
+    frame.render_widget(paragraph, area);
+}
+
+/// Lays out a session's report into the panes.
+fn entry(session: &SshSession, capture: &str, catalog: &Catalog, remediation: &RemediationSet) -> Entry {
+    let report = report::session_report(session, capture, catalog, remediation);
+    let section = |heading: &str| report.sections.iter().find(|section| section.heading == heading);
+
+    let mut timeline = section("Timeline of Events").map_or(vec![], |section| lines(&section.blocks));
+    if let Some(remediation) = section("Remediation") {
+        timeline.push(Line::default());
+        timeline.push(heading(remediation));
+        timeline.extend(lines(&remediation.blocks));
+    }
+    let keystrokes = match section("Keystroke Sequences") {
+        Some(section) => lines(&section.blocks),
+        None => vec![Line::styled("No keystrokes", Style::default().add_modifier(Modifier::DIM))],
+    };
+
+    let severity = if session.severity > 0 { format!(" !{}", session.severity) } else { String::new() };
+    Entry {
+        label: format!("{:>4} {} \u{2192} {}{severity}", session.stream, session.src, session.dst),
+        metadata: section("Session").map_or(vec![], |section| lines(&section.blocks)),
+        timeline,
+        keystrokes,
+    }
+}
+
+fn heading(section: &Section) -> Line<'static> {
+    Line::styled(section.heading.clone(), Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED))
+}
+
+/// Renders blocks as plain terminal lines; tables are aligned in columns.
+fn lines(blocks: &[Block]) -> Vec<Line<'static>> {
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+    let dim = Style::default().add_modifier(Modifier::DIM);
+    let mut lines = vec![];
+
+    for block in blocks {
+        match block {
+            Block::Fields(fields) => {
+                let width = fields.iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+                for (name, value) in fields {
+                    lines.push(Line::from(vec![Span::styled(format!("{name:width$}  "), bold), Span::raw(value.clone())]));
+                }
+            },
+            Block::Table { header, rows } => {
+                let widths = column_widths(header, rows);
+                lines.push(Line::styled(table_line(header, &widths), bold.add_modifier(Modifier::UNDERLINED)));
+                for row in rows {
+                    let style = if row.emphasis { bold } else { Style::default() };
+                    lines.push(Line::styled(table_line(&row.cells, &widths), style));
+                }
+            },
+            Block::Note(text) => lines.push(Line::styled(text.clone(), dim)),
+            Block::Subheading { text, note } => {
+                lines.push(Line::default());
+                lines.push(Line::from(vec![Span::styled(text.clone(), bold), Span::styled(format!(" {note}"), dim)]));
+            },
+            Block::List(items) => lines.extend(items.iter().map(|item| Line::raw(format!("  - {item}")))),
+        }
+    }
+
+    lines
+}
+
+/// Widest cell of each column, header included.
+fn column_widths(header: &[String], rows: &[Row]) -> Vec<usize> {
+    header.iter()
+        .enumerate()
+        .map(|(column, name)| rows.iter()
+            .filter_map(|row| row.cells.get(column))
+            .chain([name])
+            .map(|cell| cell.chars().count())
+            .max()
+            .unwrap_or(0))
+        .collect()
+}
+
+fn table_line(cells: &[String], widths: &[usize]) -> String {
+    let line: Vec<String> = cells.iter().zip(widths).map(|(cell, &width)| format!("{cell:width$}")).collect();
+    line.join(&" ".repeat(COLUMN_GAP)).trim_end().to_string()
+}
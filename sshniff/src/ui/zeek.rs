@@ -0,0 +1,183 @@
+//! Zeek-style TSV logs.
+//!
+//! `ssh.log` follows the columns of Zeek's SSH analyzer (with the HASSH package), so it can sit
+//! next to Zeek's own logs. Everything SSHniff infers beyond that goes into `sshniff.log`, one
+//! row per finding and per command, keyed by the same uid and connection tuple.
+//!
+//! Zeek's uids are random, so the uid here is derived from the capture and stream instead; use
+//! the connection tuple and timestamps to correlate with Zeek's own logs.
+use crate::analyser::containers::Event;
+use crate::analyser::core::SshSession;
+use crate::analyser::utils;
+use std::collections::BTreeMap;
+
+const SSH_FIELDS: [(&str, &str); 21] = [
+    ("ts", "time"),
+    ("uid", "string"),
+    ("id.orig_h", "addr"),
+    ("id.orig_p", "port"),
+    ("id.resp_h", "addr"),
+    ("id.resp_p", "port"),
+    ("version", "count"),
+    ("auth_success", "bool"),
+    ("auth_attempts", "count"),
+    ("direction", "enum"),
+    ("client", "string"),
+    ("server", "string"),
+    ("cipher_alg", "string"),
+    ("mac_alg", "string"),
+    ("compression_alg", "string"),
+    ("kex_alg", "string"),
+    ("host_key_alg", "string"),
+    ("host_key", "string"),
+    ("hasshVersion", "string"),
+    ("hassh", "string"),
+    ("hasshServer", "string"),
+];
+
+const SSHNIFF_FIELDS: [(&str, &str); 10] = [
+    ("ts", "time"),
+    ("uid", "string"),
+    ("id.orig_h", "addr"),
+    ("id.orig_p", "port"),
+    ("id.resp_h", "addr"),
+    ("id.resp_p", "port"),
+    ("tcp_seq", "count"),
+    ("kind", "string"),
+    ("confidence", "double"),
+    ("details", "string"),
+];
+
+/// Returns the sessions as a Zeek `ssh.log`.
+pub fn ssh_log(sessions: &BTreeMap<u32, SshSession>, capture: &str) -> String {
+    let mut log = header("ssh", &SSH_FIELDS);
+
+    for session in sessions.values() {
+        let count = |events: &[Event]| session.results.iter().filter(|finding| events.contains(&finding.kind)).count();
+        let successes = count(&[Event::CorrectPassword, Event::AcceptedKey, Event::NoneAuth]);
+        let failures = count(&[Event::WrongPassword, Event::RejectedKey]);
+
+        let row = [
+            format_ts(utils::parse_session_time(&session.start_utc).unwrap_or_default()),
+            uid(capture, session.stream),
+            tuple(session),
+            "2".to_string(),
+            if successes > 0 { "T" } else { "F" }.to_string(),
+            (successes + failures).to_string(),
+            "-".to_string(),
+            field(&session.protocols.0),
+            field(&session.protocols.1),
+            field(&session.algorithms.1),
+            field(&session.algorithms.2),
+            field(&session.algorithms.3),
+            field(&session.algorithms.0),
+            session.host_key.as_ref().map_or("-".to_string(), |host_key| field(&host_key.key_type)),
+            session.host_key.as_ref().map_or("-".to_string(), |host_key| field(host_key.md5.trim_start_matches("MD5:"))),
+            "1.1".to_string(),
+            field(&session.hassh_c),
+            field(&session.hassh_s),
+        ];
+        log.push_str(&row.join("\t"));
+        log.push('\n');
+    }
+
+    log.push_str("#close\n");
+    log
+}
+
+/// Returns the findings and commands of the sessions as `sshniff.log`.
+pub fn sshniff_log(sessions: &BTreeMap<u32, SshSession>, capture: &str) -> String {
+    let mut log = header("sshniff", &SSHNIFF_FIELDS);
+
+    for session in sessions.values() {
+        let prefix = [uid(capture, session.stream), tuple(session)].join("\t");
+        let mut rows: Vec<(i64, String)> = Vec::new();
+
+        for finding in &session.results {
+            rows.push((finding.timestamp, format!("{}\t{}\t{}\t{:.2}\t{}", prefix, finding.packet_ref.seq, finding.kind, finding.confidence, finding.details.as_deref().map_or("-".to_string(), field))));
+        }
+        for sequence in &session.keystroke_data {
+            let response = sequence.keystrokes.last().and_then(|keystroke| keystroke.response_size).map_or("?".to_string(), |size| size.to_string());
+            let seq = sequence.keystrokes.first().map_or(0, |keystroke| keystroke.seq);
+            let details = format!("#{}, {} keystrokes, error rate {:.0}%, response {} bytes", sequence.id, sequence.keystrokes.len(), sequence.error_rate * 100.0, response);
+            rows.push((sequence.start, format!("{prefix}\t{seq}\tCommand\t1.00\t{}", field(&details))));
+        }
+
+        rows.sort_by_key(|(timestamp, _)| *timestamp);
+        for (timestamp, row) in rows {
+            log.push_str(&format!("{}\t{row}\n", format_ts(timestamp)));
+        }
+    }
+
+    log.push_str("#close\n");
+    log
+}
+
+/// Zeek log header for the given path and (name, type) fields.
+fn header(path: &str, fields: &[(&str, &str)]) -> String {
+    let names: Vec<&str> = fields.iter().map(|(name, _)| *name).collect();
+    let types: Vec<&str> = fields.iter().map(|(_, kind)| *kind).collect();
+
+    format!("#separator \\x09\n#set_separator\t,\n#empty_field\t(empty)\n#unset_field\t-\n#path\t{path}\n#fields\t{}\n#types\t{}\n", names.join("\t"), types.join("\t"))
+}
+
+/// Connection uid, stable for a capture and stream.
+fn uid(capture: &str, stream: u32) -> String {
+    format!("C{}", &utils::get_md5_hash(format!("{capture}:{stream}"))[..17])
+}
+
+/// `id.orig_h`, `id.orig_p`, `id.resp_h`, and `id.resp_p` of a session.
+fn tuple(session: &SshSession) -> String {
+    let split = |address: &str| {
+        let (ip, port) = utils::split_endpoint(address);
+        (ip.to_string(), port.unwrap_or("-").to_string())
+    };
+    let (orig_h, orig_p) = split(&session.src);
+    let (resp_h, resp_p) = split(&session.dst);
+
+    [orig_h, orig_p, resp_h, resp_p].join("\t")
+}
+
+/// Formats a UNIX timestamp (μs) as Zeek's `time`: seconds with microsecond precision.
+fn format_ts(timestamp: i64) -> String {
+    format!("{}.{:06}", timestamp.div_euclid(1_000_000), timestamp.rem_euclid(1_000_000))
+}
+
+/// Escapes a string field; empty values become Zeek's empty marker.
+fn field(value: &str) -> String {
+    if value.is_empty() {
+        return "(empty)".to_string();
+    }
+    value.replace('\\', "\\\\").replace('\t', "\\x09").replace('\n', "\\x0a")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyser::core::tests::session;
+
+    #[test]
+    fn test_logs() {
+        let sessions = BTreeMap::from([(3, session())]);
+        let tuple = "Cb14a1c5839c90c5b4\t192.168.1.10\t51514\t10.0.0.1\t22";
+
+        assert_eq!(format!("\
+#separator \\x09\n#set_separator\t,\n#empty_field\t(empty)\n#unset_field\t-\n#path\tssh
+#fields\tts\tuid\tid.orig_h\tid.orig_p\tid.resp_h\tid.resp_p\tversion\tauth_success\tauth_attempts\tdirection\tclient\tserver\tcipher_alg\tmac_alg\tcompression_alg\tkex_alg\thost_key_alg\thost_key\thasshVersion\thassh\thasshServer
+#types\ttime\tstring\taddr\tport\taddr\tport\tcount\tbool\tcount\tenum\tstring\tstring\tstring\tstring\tstring\tstring\tstring\tstring\tstring\tstring\tstring
+1706702400.000000\t{tuple}\t2\tF\t1\t-\tSSH-2.0-OpenSSH_9.6\tSSH-2.0-OpenSSH_8.9p1 Ubuntu-3ubuntu0.6\tchacha20-poly1305@openssh.com\thmac-sha2-256\tnone\tcurve25519-sha256\t-\t-\t1.1\tec7378c1a92f5a8dde7e8b7a1ddf33d1\tb12d2871a1189eff20364cf5333619ee
+#close
+"), ssh_log(&sessions, "capture.pcapng"));
+
+        // Rows are in time order, with tabs, newlines, and backslashes in the details escaped
+        assert_eq!(format!("\
+#separator \\x09\n#set_separator\t,\n#empty_field\t(empty)\n#unset_field\t-\n#path\tsshniff
+#fields\tts\tuid\tid.orig_h\tid.orig_p\tid.resp_h\tid.resp_p\ttcp_seq\tkind\tconfidence\tdetails
+#types\ttime\tstring\taddr\tport\taddr\tport\tcount\tstring\tdouble\tstring
+1706702402.500000\t{tuple}\t1342\tWrongPassword\t0.50\t-
+1706702404.000000\t{tuple}\t2000\tCommand\t1.00\t#1, 3 keystrokes, error rate 25%, response 512 bytes
+1706702409.000000\t{tuple}\t3718\tPortForwarding\t0.75\tto \"db|main\"=5432\\x09via C:\\\\tmp <x> & y\\x0anext
+#close
+"), sshniff_log(&sessions, "capture.pcapng"));
+    }
+}